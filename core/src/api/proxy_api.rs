@@ -4,10 +4,8 @@
 
 use flutter_rust_bridge::frb;
 use once_cell::sync::Lazy;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-#[cfg(test)]
-use std::sync::Arc;
-use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 use tokio::task;
@@ -15,20 +13,32 @@ use tokio::task;
 use crate::frb_generated::StreamSink;
 use crate::mcp::auth::McpAuthTokenManager;
 use crate::mcp::manager::{self, McpRuntimeConfig, McpRuntimeStatus};
+use crate::models::alert::{Alert, AlertRule, AlertRuleInput};
 use crate::models::breakpoint::{BreakpointRule, BreakpointRuleInput, RequestEdit};
+use crate::models::rewrite::{RewriteRule, RewriteRuleInput, RuleMatchMode};
+use crate::models::throttle::ThrottleConfig;
+use crate::models::upstream_tls::UpstreamTlsOverride;
 use crate::models::{
-    HttpMethod, HttpTransaction, PaginatedTransactions, TransactionFilter, TransactionState,
+    BodySearchHit, BodySource, ConnectionTranscript, ExportTimezone, ExtractedField, FilterExpr,
+    H2cUpgradeMode, HostActivity, HttpMethod, HttpTransaction, PaginatedTransactions,
+    QueryRedaction, StatusCount, TransactionFilter, TransactionState, WebSocketExportFormat,
 };
 use crate::platform::{self, CertTrustStatus};
-use crate::proxy::breakpoints;
+use crate::proxy::{alerts, breakpoints, rewrite};
 use crate::storage::{self, TransactionFilterExt};
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
 
 // Global traffic stream sink
 static TRAFFIC_SINK: Mutex<Option<StreamSink<HttpTransaction>>> = Mutex::new(None);
+static ALERT_SINK: Mutex<Option<StreamSink<Alert>>> = Mutex::new(None);
 static STREAM_FILTER: Lazy<RwLock<TransactionFilter>> =
     Lazy::new(|| RwLock::new(TransactionFilter::default()));
+/// Text bodies up to this size stay inline in the UI stream (0 = always strip)
+static STREAM_BODY_PREVIEW_BYTES: AtomicUsize = AtomicUsize::new(0);
+/// Skip non-terminal updates in the UI stream (hooks and MCP still get them)
+static STREAM_FINAL_STATE_ONLY: AtomicBool = AtomicBool::new(false);
 static MCP_TRANSACTION_CHANNEL: Lazy<broadcast::Sender<HttpTransaction>> = Lazy::new(|| {
     let (tx, _rx) = broadcast::channel(512);
     tx
@@ -41,6 +51,14 @@ static ACTIVE_SERVER_TASK: AtomicU64 = AtomicU64::new(0);
 static CURRENT_PROXY_CONFIG: Lazy<RwLock<(u16, String)>> =
     Lazy::new(|| RwLock::new((9090, "127.0.0.1".to_string())));
 
+/// Callback invoked for every transaction update; see [`register_transaction_hook`].
+pub type TransactionHook = dyn Fn(&HttpTransaction) + Send + Sync;
+
+type RegisteredHook = (u64, Arc<TransactionHook>);
+
+static TRANSACTION_HOOKS: Lazy<RwLock<Vec<RegisteredHook>>> = Lazy::new(|| RwLock::new(Vec::new()));
+static NEXT_HOOK_ID: AtomicU64 = AtomicU64::new(1);
+
 #[cfg(test)]
 type TestTransactionObserver = dyn Fn(&HttpTransaction) + Send + Sync;
 
@@ -137,6 +155,109 @@ pub struct ProxyConfig {
     pub enable_https: bool,
     /// Path to store certificates (e.g. CA)
     pub storage_path: String,
+    /// Capture responses only; request bodies are streamed through uncaptured
+    /// and request breakpoints are skipped
+    pub response_only_mode: bool,
+    /// Stream responses larger than this many bytes instead of buffering them
+    /// (None = same as the body capture cap; never above it)
+    pub stream_threshold_bytes: Option<u32>,
+    /// Keep at most this many bytes of each request and response body
+    /// (None = 512 KiB)
+    pub body_capture_limit_bytes: Option<u32>,
+    /// Headers stripped before forwarding upstream, on top of those named in the
+    /// request's Connection header (None = standard hop-by-hop set)
+    pub hop_by_hop_headers: Option<Vec<String>>,
+    /// Record raw per-connection transcripts (heavy; for protocol debugging)
+    pub record_transcripts: bool,
+    /// Close pooled upstream connections idle longer than this (None = 30s)
+    pub upstream_idle_timeout_secs: Option<u32>,
+    /// Only intercept TLS whose SNI matches one of these patterns
+    /// (`host`, `*.suffix` or `*`); everything else is tunneled. None = all.
+    pub sni_intercept_include: Option<Vec<String>>,
+    /// Never intercept TLS whose SNI matches one of these patterns
+    pub sni_intercept_exclude: Option<Vec<String>>,
+    /// When the upstream replies with non-HTTP bytes, keep the first few KB
+    /// as the response body instead of discarding them
+    pub capture_invalid_responses: bool,
+    /// Text bodies up to this many bytes are included inline in the live
+    /// traffic stream; larger or binary bodies are fetched on demand. 0 = none.
+    pub stream_body_preview_bytes: u32,
+    /// Only send completed/failed transactions to the live traffic stream,
+    /// skipping pending and breakpointed updates. Less UI work on slow devices,
+    /// at the cost of not seeing requests in flight.
+    pub stream_final_state_only: bool,
+    /// Host patterns allowed to target the proxy's own address. Other requests
+    /// that would loop back into the proxy are refused with 421.
+    pub self_loop_allowlist: Option<Vec<String>>,
+    /// Persist only transaction metadata (no headers or bodies) to the database;
+    /// full details stay available from memory until evicted from the ring buffer
+    pub lean_storage: bool,
+    /// Drop transactions older than this from the in-memory buffer (they stay
+    /// in the database). None = evict by count only.
+    pub ring_max_age_secs: Option<u32>,
+    /// Answer TRACE requests with 405 instead of forwarding them. TRACE reflects
+    /// the request (cookies, auth headers) back to the caller and enables
+    /// cross-site tracing, so it is blocked by default.
+    pub block_trace: bool,
+    /// Only allow CONNECT tunnels to well-known TLS ports (443, 8443, ...)
+    pub connect_tls_ports_only: bool,
+    /// Reject (400) requests whose Host is duplicated, malformed, or disagrees
+    /// with an absolute-form target, instead of picking one interpretation
+    pub strict_host_validation: bool,
+    /// Reject (400) requests with ambiguous framing such as bare LF line endings
+    /// or both Content-Length and Transfer-Encoding. Either way such requests
+    /// get a "potential request smuggling" security warning.
+    pub reject_ambiguous_framing: bool,
+    /// Transactions a previous run left Pending or Breakpointed (e.g. after a
+    /// crash) are marked Failed once older than this, at startup and
+    /// periodically. None = never.
+    pub abandoned_transaction_secs: Option<u32>,
+    /// Store an entropy score for each captured body (high = likely compressed
+    /// or encrypted, low = text)
+    pub body_entropy: bool,
+    /// Add `X-Cheddar-*` headers with the proxy's timing and upstream address
+    /// to intercepted responses, for client-side debugging
+    pub debug_timing_headers: bool,
+    /// Tunnel a CONNECT without interception when no certificate can be
+    /// issued for its host, instead of dropping the connection
+    pub tunnel_on_cert_failure: bool,
+    /// Store JA3/JA4 fingerprints of each intercepted client's TLS ClientHello
+    pub capture_client_fingerprint: bool,
+    /// How query strings are kept in stored paths (tokens and PII often live
+    /// there). Upstream requests are never modified.
+    pub query_redaction: QueryRedaction,
+    /// MaxMind databases (Country/City and/or ASN `.mmdb` files) used to tag
+    /// each transaction with its server's country and network. None = off.
+    pub geoip_db_paths: Option<Vec<String>>,
+    /// Send a keepalive ping to the upstream of any WebSocket idle this many
+    /// seconds (None = off; can also be enabled per connection)
+    pub websocket_keepalive_secs: Option<u32>,
+    /// Fail a request with 504 "Upstream response timeout" when the upstream
+    /// sends no response headers within this many seconds (0 = wait forever)
+    pub response_header_timeout_secs: Option<u32>,
+    /// Offer HTTP/2 to HTTPS upstreams. Clients keep using HTTP/1.1 with the
+    /// proxy; method, path, headers, status, body and timing are captured as
+    /// usual, h2 framing details (priorities, push, trailers) are not.
+    pub enable_h2: bool,
+    /// Note non-standard message syntax (bare LF line endings, folded or
+    /// whitespace-padded header values, duplicated critical headers) on each
+    /// transaction, for testing how strict clients and servers are
+    pub record_conformance_notes: bool,
+    /// Resume upstream TLS sessions (tickets/session IDs) as rustls does by
+    /// default. Turn off to make every new upstream connection pay for a full
+    /// handshake when measuring handshake cost.
+    pub upstream_tls_resumption: bool,
+    /// Store request bodies (forwarded either way). Off keeps only their size
+    /// and SHA-256, e.g. when only responses matter.
+    pub capture_request_bodies: bool,
+    /// Store response bodies (forwarded either way). Off keeps only their size
+    /// and SHA-256, e.g. when auditing what an app sends.
+    pub capture_response_bodies: bool,
+    /// Handling of `Upgrade: h2c` (cleartext HTTP/2) offers from clients
+    pub h2c_upgrade: H2cUpgradeMode,
+    /// Simulate a slow network (bandwidth per connection and added latency).
+    /// None = full speed; can be changed while running with [`set_throttle`].
+    pub throttle: Option<ThrottleConfig>,
 }
 
 impl Default for ProxyConfig {
@@ -146,6 +267,40 @@ impl Default for ProxyConfig {
             bind_address: "127.0.0.1".to_string(),
             enable_https: false,
             storage_path: "./".to_string(),
+            response_only_mode: false,
+            stream_threshold_bytes: None,
+            body_capture_limit_bytes: None,
+            hop_by_hop_headers: None,
+            record_transcripts: false,
+            upstream_idle_timeout_secs: None,
+            sni_intercept_include: None,
+            sni_intercept_exclude: None,
+            capture_invalid_responses: false,
+            stream_body_preview_bytes: 0,
+            stream_final_state_only: false,
+            self_loop_allowlist: None,
+            lean_storage: false,
+            ring_max_age_secs: None,
+            block_trace: true,
+            connect_tls_ports_only: false,
+            strict_host_validation: false,
+            reject_ambiguous_framing: false,
+            abandoned_transaction_secs: Some(3600),
+            body_entropy: false,
+            debug_timing_headers: false,
+            tunnel_on_cert_failure: true,
+            capture_client_fingerprint: false,
+            query_redaction: QueryRedaction::Off,
+            geoip_db_paths: None,
+            websocket_keepalive_secs: None,
+            response_header_timeout_secs: Some(60),
+            enable_h2: false,
+            record_conformance_notes: false,
+            upstream_tls_resumption: true,
+            capture_request_bodies: true,
+            capture_response_bodies: true,
+            h2c_upgrade: H2cUpgradeMode::Downgrade,
+            throttle: None,
         }
     }
 }
@@ -238,6 +393,7 @@ pub async fn enable_mcp_server(
         allow_writes: allow_writes.unwrap_or(false),
         require_approval: require_approval.unwrap_or(true),
         socket_path: None,
+        bind_retry: Default::default(),
     };
     manager::start_runtime(config)
         .await
@@ -310,9 +466,27 @@ pub fn create_traffic_stream(sink: StreamSink<HttpTransaction>) -> Result<(), St
     Ok(())
 }
 
-/// Update the live stream filter to reduce UI load
+/// Initialize the stream of fired alerts
+#[frb(sync)]
+pub fn create_alert_stream(sink: StreamSink<Alert>) -> Result<(), String> {
+    let mut guard = ALERT_SINK.lock().map_err(|e| e.to_string())?;
+    *guard = Some(sink);
+    Ok(())
+}
+
+/// Only stream completed/failed transactions to the UI (no in-flight updates)
+#[frb(sync)]
+pub fn set_stream_final_state_only(enabled: bool) {
+    STREAM_FINAL_STATE_ONLY.store(enabled, Ordering::Relaxed);
+}
+
+/// Update the live stream filter to reduce UI load. Fails on an invalid
+/// `host_regex`/`path_regex`, leaving the current filter in place.
 #[frb(sync)]
 pub fn update_stream_filter(filter: Option<TransactionFilter>) -> Result<bool, String> {
+    if let Some(filter) = &filter {
+        filter.validate().map_err(|e| e.to_string())?;
+    }
     set_stream_filter(filter);
     Ok(true)
 }
@@ -337,8 +511,72 @@ pub fn reset_test_transaction_observer() {
     guard.take();
 }
 
+/// Register a callback that runs on every captured transaction update (pending,
+/// breakpointed, completed, failed) — e.g. to forward traffic to an external SIEM.
+///
+/// Hooks are fire-and-forget: they are called inline on the proxy's request path and
+/// their outcome is ignored, so they must return quickly and never block. Spawn a task
+/// or hand off to a channel for any I/O. Returns an id for [`unregister_transaction_hook`].
+#[frb(ignore)]
+pub fn register_transaction_hook(hook: Box<TransactionHook>) -> u64 {
+    let id = NEXT_HOOK_ID.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mut hooks) = TRANSACTION_HOOKS.write() {
+        hooks.push((id, Arc::from(hook)));
+    }
+    id
+}
+
+/// Remove a hook added with [`register_transaction_hook`]. Returns false if unknown.
+#[frb(ignore)]
+pub fn unregister_transaction_hook(id: u64) -> bool {
+    let Ok(mut hooks) = TRANSACTION_HOOKS.write() else {
+        return false;
+    };
+    let before = hooks.len();
+    hooks.retain(|(hook_id, _)| *hook_id != id);
+    hooks.len() != before
+}
+
+fn run_transaction_hooks(tx: &HttpTransaction) {
+    // Clone the list so hooks can (un)register hooks without deadlocking.
+    let hooks: Vec<Arc<TransactionHook>> = match TRANSACTION_HOOKS.read() {
+        Ok(hooks) => hooks.iter().map(|(_, hook)| hook.clone()).collect(),
+        Err(_) => return,
+    };
+    for hook in hooks {
+        // A panicking hook must not take down the proxy task or skip the rest.
+        if let Err(panic) = std::panic::catch_unwind(AssertUnwindSafe(|| hook(tx))) {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("non-string panic payload");
+            tracing::error!("Transaction hook panicked on {}: {}", tx.id, message);
+        }
+    }
+}
+
 /// Internal helper to send transaction to sink
 pub fn send_transaction_to_sink(tx: HttpTransaction) {
+    if crate::proxy::quiet_hosts::is_quiet(&tx.host) {
+        return;
+    }
+
+    run_transaction_hooks(&tx);
+    for alert in alerts::evaluate(&tx) {
+        send_alert_to_sink(alert);
+    }
+
+    let _ = MCP_TRANSACTION_CHANNEL.send(tx.clone());
+
+    let terminal = matches!(
+        tx.state,
+        TransactionState::Completed | TransactionState::Failed
+    );
+    if !terminal && STREAM_FINAL_STATE_ONLY.load(Ordering::Relaxed) {
+        return;
+    }
+
     #[cfg(test)]
     let observer = {
         let guard = TEST_TRANSACTION_OBSERVER.lock().unwrap();
@@ -349,22 +587,43 @@ pub fn send_transaction_to_sink(tx: HttpTransaction) {
         callback(&tx);
     }
 
-    let _ = MCP_TRANSACTION_CHANNEL.send(tx.clone());
-
     if !stream_filter_allows(&tx) {
         return;
     }
     if let Ok(guard) = TRAFFIC_SINK.lock() {
         if let Some(sink) = &*guard {
-            // Strip large bodies for the UI stream to save memory (lazy load later)
-            let mut light_tx = tx;
-            light_tx.request_body = None;
-            light_tx.response_body = None;
-            let _ = sink.add(light_tx);
+            let preview_bytes = STREAM_BODY_PREVIEW_BYTES.load(Ordering::Relaxed);
+            let _ = sink.add(strip_stream_bodies(tx, preview_bytes));
+        }
+    }
+}
+
+/// Internal helper to publish a fired alert
+pub fn send_alert_to_sink(alert: Alert) {
+    tracing::info!("Alert '{}': {}", alert.rule_name, alert.message);
+    if let Ok(guard) = ALERT_SINK.lock() {
+        if let Some(sink) = &*guard {
+            let _ = sink.add(alert);
         }
     }
 }
 
+/// Strip bodies for the UI stream to save memory (lazy load later). Text bodies
+/// no larger than `preview_bytes` are kept so small JSON needs no follow-up fetch.
+fn strip_stream_bodies(mut tx: HttpTransaction, preview_bytes: usize) -> HttpTransaction {
+    let keep = |body: &Option<Vec<u8>>| {
+        body.as_deref()
+            .is_some_and(|b| b.len() <= preview_bytes && std::str::from_utf8(b).is_ok())
+    };
+    if !keep(&tx.request_body) {
+        tx.request_body = None;
+    }
+    if !keep(&tx.response_body) {
+        tx.response_body = None;
+    }
+    tx
+}
+
 /// Subscribe to live transactions for non-FRB consumers (e.g., MCP).
 #[frb(ignore)]
 pub(crate) fn subscribe_transaction_events() -> broadcast::Receiver<HttpTransaction> {
@@ -378,6 +637,15 @@ fn stream_filter_allows(tx: &HttpTransaction) -> bool {
         .unwrap_or(true)
 }
 
+/// Live stream filter currently applied
+#[frb(ignore)]
+pub(crate) fn current_stream_filter() -> TransactionFilter {
+    STREAM_FILTER
+        .read()
+        .map(|filter| filter.clone())
+        .unwrap_or_default()
+}
+
 fn set_stream_filter(filter: Option<TransactionFilter>) {
     if let Ok(mut guard) = STREAM_FILTER.write() {
         *guard = filter.unwrap_or_default();
@@ -404,6 +672,8 @@ pub async fn start_proxy(config: ProxyConfig) -> Result<bool, String> {
     }
 
     PROXY_RUNNING.store(true, Ordering::SeqCst);
+    STREAM_BODY_PREVIEW_BYTES.store(config.stream_body_preview_bytes as usize, Ordering::Relaxed);
+    set_stream_final_state_only(config.stream_final_state_only);
     // Store current config for status queries
     {
         let mut current = CURRENT_PROXY_CONFIG.write().unwrap();
@@ -415,6 +685,19 @@ pub async fn start_proxy(config: ProxyConfig) -> Result<bool, String> {
         selected_port
     );
     storage::init_transaction_store(&config.storage_path).map_err(|e| e.to_string())?;
+    let store_config = storage::store_config().map_err(|e| e.to_string())?;
+    storage::set_store_config(storage::StoreConfig {
+        lean: config.lean_storage,
+        ring_max_age_secs: config.ring_max_age_secs.map(u64::from),
+        ..store_config
+    })
+    .map_err(|e| e.to_string())?;
+    let geoip_db_paths = config.geoip_db_paths.clone().unwrap_or_default();
+    if let Err(err) = crate::proxy::geoip::configure_geoip(&geoip_db_paths) {
+        tracing::warn!("GeoIP enrichment disabled: {}", err);
+        let _ = crate::proxy::geoip::configure_geoip(&[]);
+    }
+    crate::proxy::throttle::set_throttle(config.throttle);
 
     // Spawn the real proxy server
     // We clone the config elements manually because ProxyConfig might not be Clone
@@ -424,6 +707,34 @@ pub async fn start_proxy(config: ProxyConfig) -> Result<bool, String> {
     let port = selected_port;
     let enable_https = config.enable_https;
     let storage_path = config.storage_path.clone();
+    let response_only_mode = config.response_only_mode;
+    let stream_threshold_bytes = config.stream_threshold_bytes.map(|v| v as usize);
+    let body_capture_limit_bytes = config.body_capture_limit_bytes.map(|v| v as usize);
+    let hop_by_hop_headers = config.hop_by_hop_headers.clone();
+    let record_transcripts = config.record_transcripts;
+    let upstream_idle_timeout_secs = config.upstream_idle_timeout_secs.map(u64::from);
+    let sni_intercept_include = config.sni_intercept_include.clone().unwrap_or_default();
+    let sni_intercept_exclude = config.sni_intercept_exclude.clone().unwrap_or_default();
+    let capture_invalid_responses = config.capture_invalid_responses;
+    let self_loop_allowlist = config.self_loop_allowlist.clone().unwrap_or_default();
+    let block_trace = config.block_trace;
+    let connect_tls_ports_only = config.connect_tls_ports_only;
+    let strict_host_validation = config.strict_host_validation;
+    let reject_ambiguous_framing = config.reject_ambiguous_framing;
+    let abandoned_transaction_secs = config.abandoned_transaction_secs.map(u64::from);
+    let body_entropy = config.body_entropy;
+    let debug_timing_headers = config.debug_timing_headers;
+    let tunnel_on_cert_failure = config.tunnel_on_cert_failure;
+    let capture_client_fingerprint = config.capture_client_fingerprint;
+    let query_redaction = config.query_redaction;
+    let websocket_keepalive_secs = config.websocket_keepalive_secs.map(u64::from);
+    let response_header_timeout_secs = config.response_header_timeout_secs.map(u64::from);
+    let enable_h2 = config.enable_h2;
+    let record_conformance_notes = config.record_conformance_notes;
+    let upstream_tls_resumption = config.upstream_tls_resumption;
+    let capture_request_bodies = config.capture_request_bodies;
+    let capture_response_bodies = config.capture_response_bodies;
+    let h2c_upgrade = config.h2c_upgrade;
 
     tokio::spawn(async move {
         let server_config = crate::proxy::server::ProxyConfig {
@@ -431,6 +742,34 @@ pub async fn start_proxy(config: ProxyConfig) -> Result<bool, String> {
             port,
             enable_https,
             storage_path,
+            response_only_mode,
+            stream_threshold_bytes,
+            body_capture_limit_bytes,
+            hop_by_hop_headers,
+            record_transcripts,
+            upstream_idle_timeout_secs,
+            sni_intercept_include,
+            sni_intercept_exclude,
+            capture_invalid_responses,
+            self_loop_allowlist,
+            block_trace,
+            connect_tls_ports_only,
+            strict_host_validation,
+            reject_ambiguous_framing,
+            abandoned_transaction_secs,
+            body_entropy,
+            debug_timing_headers,
+            tunnel_on_cert_failure,
+            capture_client_fingerprint,
+            query_redaction,
+            websocket_keepalive_secs,
+            response_header_timeout_secs,
+            enable_h2,
+            record_conformance_notes,
+            upstream_tls_resumption,
+            capture_request_bodies,
+            capture_response_bodies,
+            h2c_upgrade,
         };
 
         if let Err(e) = crate::proxy::server::run_server(server_config).await {
@@ -506,6 +845,33 @@ pub fn get_root_ca_pem(storage_path: String) -> Result<String, String> {
         .map_err(|e| e.to_string())
 }
 
+/// Leaf certificate cache counters for the running proxy
+#[derive(Debug, Clone)]
+#[frb]
+pub struct CertCacheStats {
+    pub size: u32,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Inspect the running proxy's per-host certificate cache.
+/// Returns None when the proxy is stopped or HTTPS interception is disabled.
+#[frb(sync)]
+pub fn get_cert_cache_stats() -> Option<CertCacheStats> {
+    crate::proxy::server::cert_cache_stats().map(|stats| CertCacheStats {
+        size: stats.size as u32,
+        hits: stats.hits,
+        misses: stats.misses,
+    })
+}
+
+/// Drop all cached leaf certificates so they are regenerated on the next
+/// CONNECT, without restarting the proxy. Returns the number of entries cleared.
+#[frb(sync)]
+pub fn clear_cert_cache() -> u32 {
+    crate::proxy::server::clear_cert_cache() as u32
+}
+
 /// Create a mock HTTP transaction for testing
 /// This will be replaced with real traffic from the proxy
 #[frb(sync)]
@@ -574,6 +940,151 @@ pub async fn query_transactions(
     Ok(result)
 }
 
+/// Fetch paginated transactions matching an AND/OR/NOT filter expression
+#[frb]
+pub async fn query_transactions_expr(
+    expr: FilterExpr,
+    page: u32,
+    page_size: u32,
+) -> Result<PaginatedTransactions, String> {
+    let mut result = crate::storage::query_transactions_expr(&expr, page, page_size)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Strip bodies for list view to save memory
+    for item in &mut result.items {
+        item.request_body = None;
+        item.response_body = None;
+    }
+
+    Ok(result)
+}
+
+/// Set the page size used when a query passes 0, and the cap applied to all
+/// paginated queries (UI and MCP alike). Requires an initialized store.
+#[frb(sync)]
+pub fn set_query_page_limits(default_page_size: u32, max_page_size: u32) -> Result<(), String> {
+    let current = crate::storage::store_config().map_err(|e| e.to_string())?;
+    crate::storage::set_store_config(crate::storage::StoreConfig {
+        default_page_size,
+        max_page_size,
+        ..current
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Set host patterns whose traffic is forwarded but never persisted or streamed
+/// (e.g. `*.google-analytics.com`). Independent of the TLS bypass list.
+#[frb(sync)]
+pub fn set_quiet_hosts(patterns: Vec<String>) {
+    crate::proxy::quiet_hosts::set_quiet_hosts(patterns);
+}
+
+/// Get the configured quiet host patterns
+#[frb(sync)]
+pub fn get_quiet_hosts() -> Vec<String> {
+    crate::proxy::quiet_hosts::quiet_hosts()
+}
+
+/// Throttle proxied traffic to simulate a slow network, or pass None to go
+/// back to full speed. Applies to requests started after the call.
+#[frb(sync)]
+pub fn set_throttle(config: Option<ThrottleConfig>) {
+    crate::proxy::throttle::set_throttle(config);
+}
+
+/// Get the current throttle settings (None = full speed)
+#[frb(sync)]
+pub fn get_throttle() -> Option<ThrottleConfig> {
+    crate::proxy::throttle::throttle()
+}
+
+/// Set per-host TLS settings (ALPN, SNI, certificate validation) for upstream
+/// connections. The first override whose pattern matches the host applies.
+#[frb(sync)]
+pub fn set_upstream_tls_overrides(overrides: Vec<UpstreamTlsOverride>) -> Result<(), String> {
+    crate::proxy::upstream_tls::set_upstream_tls_overrides(overrides).map_err(|e| e.to_string())
+}
+
+/// Get the configured upstream TLS overrides, in match order
+#[frb(sync)]
+pub fn get_upstream_tls_overrides() -> Vec<UpstreamTlsOverride> {
+    crate::proxy::upstream_tls::upstream_tls_overrides()
+}
+
+/// Serve a captured transaction's response to every matching request
+/// (same method, scheme, host, port and path) without contacting the upstream.
+/// Fails if only part of the response body was captured. With
+/// `compress_mock_responses`, a plain stored body is compressed (gzip, br or
+/// deflate) for clients whose `Accept-Encoding` allows it.
+#[frb]
+pub async fn serve_recorded_response(
+    transaction_id: String,
+    compress_mock_responses: bool,
+) -> Result<(), String> {
+    let tx = crate::storage::get_transaction_by_id(&transaction_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Transaction not found".to_string())?;
+    crate::proxy::playback::arm_recording(&tx, compress_mock_responses).map_err(|e| e.to_string())
+}
+
+/// Stop serving a recorded response. Returns false if it was not being served.
+#[frb(sync)]
+pub fn stop_serving_recorded_response(transaction_id: String) -> bool {
+    crate::proxy::playback::disarm_recording(&transaction_id)
+}
+
+/// Ids of transactions whose responses are currently served from recordings
+#[frb(sync)]
+pub fn list_recorded_responses() -> Vec<String> {
+    crate::proxy::playback::armed_recordings()
+}
+
+/// Stop serving all recorded responses
+#[frb(sync)]
+pub fn clear_recorded_responses() {
+    crate::proxy::playback::clear_recordings();
+}
+
+/// Render a transaction as a self-contained HTML page (headers plus formatted
+/// bodies) for sharing. When `output_path` is given the page is also written there.
+#[frb]
+pub async fn render_transaction_html(
+    id: String,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    let tx = crate::storage::get_transaction_by_id(&id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Transaction not found".to_string())?;
+    let html = storage::render_transaction_html(&tx);
+    if let Some(path) = output_path {
+        std::fs::write(&path, &html).map_err(|e| format!("Failed to write {path}: {e}"))?;
+    }
+    Ok(html)
+}
+
+/// Render a captured request as a cURL command that reproduces it, like a
+/// browser's "Copy as cURL".
+#[frb]
+pub async fn transaction_to_curl(id: String) -> Result<String, String> {
+    let tx = crate::storage::get_transaction_by_id(&id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Transaction not found".to_string())?;
+    Ok(storage::curl_command(&tx))
+}
+
+/// Fetch several transactions (full details) in one call, in the order of
+/// `ids`. Unknown ids are left out.
+#[frb]
+pub async fn fetch_transactions(ids: Vec<String>) -> Result<Vec<HttpTransaction>, String> {
+    crate::storage::get_transactions_by_ids(&ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 /// Fetch a single transaction by ID (full details including body)
 /// Fetch a single transaction by ID (full details including body)
 #[frb]
@@ -584,6 +1095,56 @@ pub async fn fetch_transaction(id: String) -> Result<HttpTransaction, String> {
     res.ok_or_else(|| "Transaction not found".to_string())
 }
 
+/// Chunk size used by [`stream_response_body`] when none is given
+const DEFAULT_BODY_CHUNK_BYTES: u32 = 64 * 1024;
+
+/// Stream the stored response body of a transaction to the UI in chunks, so
+/// large bodies can be written out progressively instead of crossing the
+/// bridge as one allocation. The stream closes after the last chunk; returns
+/// the number of bytes sent. Stops early if the Dart side cancels.
+#[frb]
+pub async fn stream_response_body(
+    id: String,
+    chunk_bytes: Option<u32>,
+    sink: StreamSink<Vec<u8>>,
+) -> Result<u64, String> {
+    let reader = storage::open_response_body(&id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Transaction not found".to_string())?;
+    let chunk_bytes = chunk_bytes.unwrap_or(DEFAULT_BODY_CHUNK_BYTES).max(1) as usize;
+    pump_chunks(reader, chunk_bytes, |chunk| sink.add(chunk).is_ok())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Read `reader` to the end in chunks of at most `chunk_bytes`, handing each
+/// to `emit` until it returns false. Returns the number of bytes emitted.
+async fn pump_chunks<R>(
+    mut reader: R,
+    chunk_bytes: usize,
+    mut emit: impl FnMut(Vec<u8>) -> bool,
+) -> std::io::Result<u64>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut sent = 0u64;
+    let mut buf = vec![0u8; chunk_bytes];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        if !emit(buf[..n].to_vec()) {
+            break;
+        }
+        sent += n as u64;
+    }
+    Ok(sent)
+}
+
 /// Fetch a transaction with bodies preserved for MCP/detail views.
 pub async fn get_transaction_detail(id: &str) -> Result<Option<HttpTransaction>, String> {
     crate::storage::get_transaction_by_id(id)
@@ -620,18 +1181,29 @@ pub async fn query_transactions_with_time_range(
 }
 
 /// Export transactions to a HAR file on disk.
+/// When `compress` is set (or the path ends in `.gz`) the file is gzip-compressed;
+/// a `.gz` suffix is appended if `compress` is set and the path lacks one.
+/// `filter` can combine tags, pins and other fields to export a curated subset.
 #[frb]
 pub async fn export_har_file(
     output_path: String,
-    filter: Option<TransactionFilter>,
+    filter: Option<FilterExpr>,
+    compress: bool,
+    timezone: Option<ExportTimezone>,
 ) -> Result<u64, String> {
-    let effective_filter = filter.unwrap_or_default();
-    let transactions = storage::list_transactions(&effective_filter)
-        .await
-        .map_err(|e| e.to_string())?;
-    let count = storage::export_har_to_path(transactions, &output_path)
+    let output_path = if compress && !storage::is_gzip_path(&output_path) {
+        format!("{output_path}.gz")
+    } else {
+        output_path
+    };
+    let effective_filter = filter.unwrap_or(FilterExpr::And(Vec::new()));
+    let transactions = storage::list_transactions_expr(&effective_filter)
         .await
         .map_err(|e| e.to_string())?;
+    let count =
+        storage::export_har_to_path(transactions, &output_path, timezone.unwrap_or_default())
+            .await
+            .map_err(|e| e.to_string())?;
     Ok(count as u64)
 }
 
@@ -654,11 +1226,10 @@ pub async fn list_transactions_page(
         .map_err(|e| e.to_string())
 }
 
-/// Import transactions from a HAR file.
+/// Import transactions from a HAR file (plain or gzip-compressed).
 #[frb]
 pub async fn import_har_file(input_path: String) -> Result<u64, String> {
-    let data = std::fs::read_to_string(&input_path).map_err(|e| e.to_string())?;
-    let transactions = storage::import_har_from_str(&data).map_err(|e| e.to_string())?;
+    let transactions = storage::import_har_from_path(&input_path).map_err(|e| e.to_string())?;
     let mut count = 0u64;
     for mut tx in transactions {
         tx.state = TransactionState::Completed;
@@ -681,7 +1252,42 @@ pub async fn prune_old_transactions(days: Option<u32>) -> Result<u64, String> {
         .map_err(|e| e.to_string())
 }
 
-/// Clear all transactions from the database (manual wipe)
+/// Count how many transactions `prune_old_transactions` would delete, without
+/// deleting anything. Pinned transactions are never counted.
+#[frb]
+pub async fn preview_prune(days: Option<u32>) -> Result<u64, String> {
+    let prune_days = days.unwrap_or(5);
+    storage::prune_preview(prune_days)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Pin or unpin a transaction; pinned transactions survive pruning.
+/// Returns false if no transaction has this ID.
+#[frb]
+pub async fn set_transaction_pinned(id: String, pinned: bool) -> Result<bool, String> {
+    storage::set_transaction_pinned(&id, pinned)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Replace the tags of a transaction. Tags are trimmed, blanks dropped and
+/// duplicates removed. Returns false if no transaction has this ID.
+#[frb]
+pub async fn set_transaction_tags(id: String, tags: Vec<String>) -> Result<bool, String> {
+    let mut cleaned: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !cleaned.iter().any(|t| t == tag) {
+            cleaned.push(tag.to_string());
+        }
+    }
+    storage::set_transaction_tags(&id, cleaned)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Clear all transactions and WebSocket messages from the database (manual wipe)
 #[frb]
 pub async fn clear_all_transactions() -> Result<u64, String> {
     storage::clear_all_transactions()
@@ -689,6 +1295,55 @@ pub async fn clear_all_transactions() -> Result<u64, String> {
         .map_err(|e| e.to_string())
 }
 
+/// Import the transactions captured in another Cheddar database file (e.g.
+/// from another machine), with their WebSocket messages. Transactions whose id
+/// already exists are skipped. Returns the number of transactions imported.
+#[frb]
+pub async fn merge_database(other_db_path: String) -> Result<u64, String> {
+    storage::merge_database(&other_db_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Transaction counts per status code, ascending by code, without loading the
+/// transactions. Those without a response are counted under status 0. Both
+/// time bounds are optional (ms since epoch, inclusive).
+#[frb]
+pub async fn get_status_breakdown(
+    filter: Option<TransactionFilter>,
+    start_time_ms: Option<i64>,
+    end_time_ms: Option<i64>,
+) -> Result<Vec<StatusCount>, String> {
+    let time_range = match (start_time_ms, end_time_ms) {
+        (None, None) => None,
+        (start, end) => Some((start.unwrap_or(i64::MIN), end.unwrap_or(i64::MAX))),
+    };
+    let breakdown = storage::status_breakdown(&filter.unwrap_or_default(), time_range)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(breakdown
+        .into_iter()
+        .map(|(status, count)| StatusCount { status, count })
+        .collect())
+}
+
+/// Hosts seen in captured traffic with request counts and last-seen time,
+/// most recently active first
+#[frb]
+pub async fn list_recent_hosts(limit: u32) -> Result<Vec<HostActivity>, String> {
+    let hosts = storage::list_recent_hosts(limit)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(hosts
+        .into_iter()
+        .map(|(host, count, last_seen_ms)| HostActivity {
+            host,
+            count,
+            last_seen_ms,
+        })
+        .collect())
+}
+
 /// Get total transaction count in the database
 #[frb]
 pub async fn get_transaction_count() -> Result<u64, String> {
@@ -718,6 +1373,95 @@ pub async fn get_slow_transactions(
     Ok(results)
 }
 
+/// Pull one field out of the JSON bodies of matching transactions, e.g. every
+/// returned user id via the pointer `/user/id`. Bodies without the field are skipped.
+#[frb]
+pub async fn extract_field(
+    filter: Option<TransactionFilter>,
+    json_pointer: String,
+    source: BodySource,
+) -> Result<Vec<ExtractedField>, String> {
+    if !json_pointer.is_empty() && !json_pointer.starts_with('/') {
+        return Err(format!(
+            "Invalid JSON pointer '{json_pointer}': must be empty or start with '/'"
+        ));
+    }
+    storage::extract_json_field(&filter.unwrap_or_default(), &json_pointer, source)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Transactions whose request or response body contains `query`
+/// (case-insensitive, at least 3 characters), newest first, each with a
+/// snippet of the text around the match
+#[frb]
+pub async fn search_bodies(query: String, limit: u32) -> Result<Vec<BodySearchHit>, String> {
+    storage::search_bodies(&query, limit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Rewrite rule APIs
+#[frb(sync)]
+pub fn list_rewrite_rules() -> Result<Vec<RewriteRule>, String> {
+    Ok(rewrite::list_rewrite_rules())
+}
+
+#[frb(sync)]
+pub fn add_rewrite_rule(input: RewriteRuleInput) -> Result<RewriteRule, String> {
+    Ok(rewrite::add_rewrite_rule(input))
+}
+
+#[frb(sync)]
+pub fn remove_rewrite_rule(id: String) -> Result<bool, String> {
+    Ok(rewrite::remove_rewrite_rule(&id))
+}
+
+/// Set rewrite rule evaluation order: `ids` first (highest priority first),
+/// then any unlisted rules in their current order
+#[frb(sync)]
+pub fn reorder_rules(ids: Vec<String>) -> Result<(), String> {
+    rewrite::reorder_rewrite_rules(&ids).map_err(|e| e.to_string())
+}
+
+/// Whether only the first matching rewrite rule applies, or all of them
+#[frb(sync)]
+pub fn set_rewrite_match_mode(mode: RuleMatchMode) {
+    rewrite::set_rewrite_match_mode(mode);
+}
+
+#[frb(sync)]
+pub fn get_rewrite_match_mode() -> RuleMatchMode {
+    rewrite::rewrite_match_mode()
+}
+
+/// Alert rule APIs
+#[frb(sync)]
+pub fn list_alert_rules() -> Result<Vec<AlertRule>, String> {
+    Ok(alerts::list_alert_rules())
+}
+
+#[frb(sync)]
+pub fn add_alert_rule(input: AlertRuleInput) -> Result<AlertRule, String> {
+    Ok(alerts::add_alert_rule(input))
+}
+
+#[frb(sync)]
+pub fn remove_alert_rule(id: String) -> Result<bool, String> {
+    Ok(alerts::remove_alert_rule(&id))
+}
+
+/// Alerts fired by the alert rules, newest first
+#[frb(sync)]
+pub fn get_alerts() -> Vec<Alert> {
+    alerts::alerts()
+}
+
+#[frb(sync)]
+pub fn clear_alerts() {
+    alerts::clear_alerts();
+}
+
 /// Breakpoint rule APIs
 #[frb(sync)]
 pub fn list_breakpoint_rules() -> Result<Vec<BreakpointRule>, String> {
@@ -726,7 +1470,7 @@ pub fn list_breakpoint_rules() -> Result<Vec<BreakpointRule>, String> {
 
 #[frb(sync)]
 pub fn add_breakpoint_rule(input: BreakpointRuleInput) -> Result<BreakpointRule, String> {
-    Ok(breakpoints::add_breakpoint_rule(input))
+    breakpoints::add_breakpoint_rule(input).map_err(|e| e.to_string())
 }
 
 #[frb(sync)]
@@ -793,19 +1537,53 @@ pub struct ReplayResult {
     pub success: bool,
     /// Error message if replay failed
     pub error: Option<String>,
+    /// File with the raw bytes exchanged with the upstream (wire capture only)
+    pub wire_capture_path: Option<String>,
+    /// Target of a redirect (or next page) on another origin that was not followed
+    pub blocked_redirect: Option<String>,
+}
+
+impl From<crate::replay::ReplayResult> for ReplayResult {
+    fn from(result: crate::replay::ReplayResult) -> Self {
+        Self {
+            transaction_id: result.transaction_id,
+            status_code: result.status_code,
+            success: result.success,
+            error: result.error,
+            wire_capture_path: result.wire_capture_path,
+            blocked_redirect: result.blocked_redirect,
+        }
+    }
 }
 
 /// Replay a previously captured HTTP request
 ///
 /// This function retrieves the original transaction, applies any overrides,
 /// makes a new HTTP request, and captures the response as a new transaction.
+/// With `capture_wire`, the request bypasses the proxy and the raw bytes
+/// exchanged with the upstream are written to `wire_capture_path`. With
+/// `bypass_cache`, conditional headers are dropped so the server cannot
+/// answer 304 Not Modified. With `dedup_window_ms`, a replay identical to one
+/// made within that window increments its `occurrence_count` instead of
+/// creating a new transaction. With `preserve_header_order`, headers go out in
+/// the captured order and casing rather than being normalized. With
+/// `timeout_ms`, a replay not finished in time is recorded as failed. With
+/// `redirect_same_host_only`, a redirect to another host is not followed and
+/// is reported in `blocked_redirect`.
 #[frb]
+#[allow(clippy::too_many_arguments)]
 pub async fn replay_request(
     transaction_id: String,
     method_override: Option<String>,
     path_override: Option<String>,
     headers_override: Option<std::collections::HashMap<String, String>>,
     body_override: Option<Vec<u8>>,
+    capture_wire: Option<bool>,
+    bypass_cache: Option<bool>,
+    dedup_window_ms: Option<u32>,
+    preserve_header_order: Option<bool>,
+    timeout_ms: Option<u32>,
+    redirect_same_host_only: Option<bool>,
 ) -> Result<ReplayResult, String> {
     use crate::models::HttpMethod;
     use crate::replay::{replay_request as do_replay, ReplayParams};
@@ -832,28 +1610,102 @@ pub async fn replay_request(
         headers: headers_override,
         body: body_override,
         accept_invalid_certs: false,
+        content_encoding: Default::default(),
+        capture_wire: capture_wire.unwrap_or(false),
+        bypass_cache: bypass_cache.unwrap_or(false),
+        dedup_window: dedup_window_ms.map(|ms| std::time::Duration::from_millis(ms.into())),
+        base_url: None,
+        preserve_header_order: preserve_header_order.unwrap_or(false),
+        timeout_ms: timeout_ms.map(u64::from),
+        redirect_same_host_only: redirect_same_host_only.unwrap_or(false),
+        drop_credentials: false,
     };
 
     let result = do_replay(&transaction_id, params).await?;
 
-    Ok(ReplayResult {
-        transaction_id: result.transaction_id,
-        status_code: result.status_code,
-        success: result.success,
-        error: result.error,
-    })
+    Ok(result.into())
+}
+
+/// Replay captured requests in order, preserving the original gaps between
+/// their start times (each gap capped at 30s).
+#[frb]
+pub async fn replay_sequence(transaction_ids: Vec<String>) -> Result<Vec<ReplayResult>, String> {
+    let results =
+        crate::replay::replay_sequence(&transaction_ids, crate::replay::ReplayParams::default())
+            .await?;
+
+    Ok(results.into_iter().map(ReplayResult::from).collect())
+}
+
+/// Follow a captured response's next-page hint (Link header or JSON `next`
+/// URL/cursor), replaying each page as a new transaction. Stops when a page
+/// has no next hint or after `max_pages` requests (default 20, at most 500).
+/// A next page on another origin is not fetched; its URL is returned as the
+/// last result's `blocked_redirect`.
+#[frb]
+pub async fn replay_all_pages(
+    transaction_id: String,
+    max_pages: Option<u32>,
+) -> Result<Vec<ReplayResult>, String> {
+    let max_pages = max_pages.map_or(crate::replay::DEFAULT_MAX_PAGES, |n| n as usize);
+    let results = crate::replay::replay_all_pages(&transaction_id, max_pages).await?;
+
+    Ok(results.into_iter().map(ReplayResult::from).collect())
+}
+
+/// Saved replay environments (base URL and default headers per deployment)
+#[frb]
+pub fn list_replay_environments() -> Result<Vec<crate::models::ReplayEnvironment>, String> {
+    storage::list_environments().map_err(|e| e.to_string())
+}
+
+/// Save a replay environment, replacing any existing one with the same name
+#[frb]
+pub fn save_replay_environment(
+    environment: crate::models::ReplayEnvironment,
+) -> Result<(), String> {
+    if environment.name.trim().is_empty() {
+        return Err("Environment name must not be empty".to_string());
+    }
+    reqwest::Url::parse(&environment.base_url)
+        .map_err(|e| format!("Invalid base URL {}: {}", environment.base_url, e))?;
+    storage::save_environment(environment).map_err(|e| e.to_string())
+}
+
+/// Delete a saved replay environment. Returns whether it existed.
+#[frb]
+pub fn delete_replay_environment(name: String) -> Result<bool, String> {
+    storage::delete_environment(&name).map_err(|e| e.to_string())
+}
+
+/// Replay a captured request against a saved environment, sending it to the
+/// environment's base URL with its headers merged over the captured ones.
+/// Captured cookies and authorization go along unless the environment has
+/// `drop_credentials` set.
+#[frb]
+pub async fn replay_in_environment(
+    transaction_id: String,
+    environment_name: String,
+) -> Result<ReplayResult, String> {
+    let result = crate::replay::replay_in_environment(&transaction_id, &environment_name).await?;
+    Ok(result.into())
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 // WebSocket message APIs
 // ─────────────────────────────────────────────────────────────────────────────
 
-use crate::models::WebSocketMessage;
+use crate::models::{WebSocketMessage, WebSocketStats};
 
-/// Get all WebSocket messages for a connection
+/// Get a connection's WebSocket messages in capture order, skipping `offset`
+/// (default 0) and returning at most `limit` (default all)
 #[frb(sync)]
-pub fn get_websocket_messages(connection_id: String) -> Vec<WebSocketMessage> {
-    storage::get_websocket_messages(&connection_id)
+pub fn get_websocket_messages(
+    connection_id: String,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> Vec<WebSocketMessage> {
+    storage::get_websocket_messages(&connection_id, offset.unwrap_or(0), limit)
 }
 
 /// Get the count of WebSocket messages for a connection
@@ -862,28 +1714,109 @@ pub fn get_websocket_message_count(connection_id: String) -> u64 {
     storage::get_websocket_message_count(&connection_id) as u64
 }
 
+/// Get message rate and byte counters for a WebSocket connection
+#[frb(sync)]
+pub fn get_websocket_stats(connection_id: String) -> Option<WebSocketStats> {
+    storage::get_websocket_stats(&connection_id)
+}
+
+/// Get the raw transcript for a connection (a transaction's `transcript_id`).
+/// Only populated while the proxy runs with `record_transcripts` enabled.
+#[frb(sync)]
+pub fn get_connection_transcript(connection_id: String) -> Option<ConnectionTranscript> {
+    storage::get_connection_transcript(&connection_id)
+}
+
 /// Clear all WebSocket messages for a connection
 #[frb(sync)]
 pub fn clear_websocket_messages(connection_id: String) {
     storage::clear_websocket_messages(&connection_id);
 }
 
+/// Save a WebSocket connection's captured messages to a file, as NDJSON or a
+/// readable text transcript. Returns the number of messages written.
+#[frb]
+pub async fn export_websocket_messages(
+    connection_id: String,
+    path: String,
+    format: WebSocketExportFormat,
+    timezone: Option<ExportTimezone>,
+) -> Result<u64, String> {
+    storage::export_websocket_messages(&connection_id, &path, format, timezone.unwrap_or_default())
+        .map(|count| count as u64)
+        .map_err(|e| e.to_string())
+}
+
+/// Decode binary WebSocket frames as `message_type` using a protobuf
+/// descriptor set file (`protoc --descriptor_set_out`). Without a path, frames
+/// are decoded schema-less (field numbers and wire types only).
+#[frb]
+pub async fn set_websocket_protobuf_schema(
+    descriptor_set_path: Option<String>,
+    message_type: Option<String>,
+) -> Result<(), String> {
+    let descriptor_set = match descriptor_set_path {
+        Some(path) => Some(
+            tokio::fs::read(&path)
+                .await
+                .map_err(|e| format!("Failed to read {path}: {e}"))?,
+        ),
+        None => None,
+    };
+    crate::proxy::websocket::set_protobuf_schema(descriptor_set.as_deref(), message_type.as_deref())
+        .map_err(|e| e.to_string())
+}
+
 /// Clear all WebSocket messages
 #[frb(sync)]
 pub fn clear_all_websocket_messages() {
     storage::clear_all_websocket_messages();
 }
 
+/// Send a captured WebSocket message again on its connection, in the
+/// direction it was captured, with `edited_payload` replacing the original if
+/// given. The connection must still be open. Returns the injected message.
+#[frb(sync)]
+pub fn replay_websocket_message(
+    message_id: String,
+    edited_payload: Option<Vec<u8>>,
+) -> Result<WebSocketMessage, String> {
+    crate::proxy::websocket::replay_message(&message_id, edited_payload).map_err(|e| e.to_string())
+}
+
+/// Ping the upstream of an open WebSocket whenever it has been idle for
+/// `interval_secs`, overriding the global setting. None or 0 turns it off.
+#[frb(sync)]
+pub fn set_websocket_keepalive(connection_id: String, interval_secs: Option<u32>) {
+    crate::proxy::websocket::set_connection_keepalive(&connection_id, interval_secs);
+}
+
+/// Cap how many replays and Composer requests may be in flight at once
+/// (default 100). Requests beyond the cap queue for up to `queue_timeout_ms`
+/// (default 60s) and then fail.
+#[frb(sync)]
+pub fn set_max_concurrent_replays(max_concurrent_replays: u32, queue_timeout_ms: Option<u32>) {
+    crate::replay::set_limits(
+        max_concurrent_replays as usize,
+        queue_timeout_ms
+            .map(|ms| std::time::Duration::from_millis(ms.into()))
+            .unwrap_or(crate::replay::DEFAULT_QUEUE_TIMEOUT),
+    );
+}
+
 /// Send a new HTTP request directly (not a replay)
 ///
 /// This allows the Composer to send requests without needing an existing
-/// captured transaction. The request will be tracked as a new transaction.
+/// captured transaction. The request will be tracked as a new transaction,
+/// failed if it has not completed within `timeout_ms` when given.
 #[frb]
 pub async fn send_direct_request(
     url: String,
     method: String,
     headers: std::collections::HashMap<String, String>,
     body: Option<Vec<u8>>,
+    capture_wire: Option<bool>,
+    timeout_ms: Option<u32>,
 ) -> Result<ReplayResult, String> {
     use crate::replay::{send_direct_request as do_send, DirectRequestParams};
 
@@ -892,14 +1825,260 @@ pub async fn send_direct_request(
         method,
         headers,
         body,
+        capture_wire: capture_wire.unwrap_or(false),
+        timeout_ms: timeout_ms.map(u64::from),
     };
 
     let result = do_send(params).await?;
 
-    Ok(ReplayResult {
-        transaction_id: result.transaction_id,
-        status_code: result.status_code,
-        success: result.success,
-        error: result.error,
-    })
+    Ok(result.into())
+}
+
+/// Parse a pasted curl command (`-X`, `-H`, `-d`/`--data-binary`, `-u`, ...)
+/// and send it like [`send_direct_request`].
+#[frb]
+pub async fn send_curl_request(command: String) -> Result<ReplayResult, String> {
+    let params = crate::replay::parse_curl_command(&command)?;
+    let result = crate::replay::send_direct_request(params).await?;
+
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    fn registered_hooks_receive_transactions_until_removed() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let make_hook = |tag: &'static str| {
+            let seen = seen.clone();
+            Box::new(move |tx: &HttpTransaction| {
+                if tx.host == "hook.test" {
+                    seen.lock().unwrap().push((tag, tx.id.clone()));
+                }
+            })
+        };
+        let first = register_transaction_hook(make_hook("first"));
+        let second = register_transaction_hook(make_hook("second"));
+
+        let tx = create_mock_transaction("GET".into(), "hook.test".into(), "/".into(), Some(200));
+        send_transaction_to_sink(tx.clone());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("first", tx.id.clone()), ("second", tx.id.clone())]
+        );
+
+        assert!(unregister_transaction_hook(first));
+        assert!(!unregister_transaction_hook(first));
+        send_transaction_to_sink(tx.clone());
+        assert_eq!(seen.lock().unwrap().len(), 3);
+        assert_eq!(seen.lock().unwrap()[2].0, "second");
+
+        assert!(unregister_transaction_hook(second));
+    }
+
+    #[test]
+    fn panicking_hook_does_not_skip_later_hooks() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let panicking = register_transaction_hook(Box::new(|tx: &HttpTransaction| {
+            if tx.host == "hook-panic.test" {
+                panic!("hook failed");
+            }
+        }));
+        let recorder = {
+            let seen = seen.clone();
+            register_transaction_hook(Box::new(move |tx: &HttpTransaction| {
+                if tx.host == "hook-panic.test" {
+                    seen.lock().unwrap().push(tx.id.clone());
+                }
+            }))
+        };
+
+        let tx = create_mock_transaction(
+            "GET".into(),
+            "hook-panic.test".into(),
+            "/".into(),
+            Some(200),
+        );
+        send_transaction_to_sink(tx.clone());
+        assert_eq!(*seen.lock().unwrap(), vec![tx.id.clone()]);
+
+        assert!(unregister_transaction_hook(panicking));
+        assert!(unregister_transaction_hook(recorder));
+    }
+
+    #[test]
+    fn stream_keeps_small_text_bodies_inline() {
+        let mut small =
+            create_mock_transaction("GET".into(), "a.test".into(), "/".into(), Some(200));
+        small.response_body = Some(br#"{"ok":true}"#.to_vec());
+        let small = strip_stream_bodies(small, 1024);
+        assert_eq!(small.response_body.as_deref(), Some(&br#"{"ok":true}"#[..]));
+
+        let mut large =
+            create_mock_transaction("GET".into(), "a.test".into(), "/".into(), Some(200));
+        large.response_body = Some(format!("[{}]", "1,".repeat(1024)).into_bytes());
+        assert!(strip_stream_bodies(large, 1024).response_body.is_none());
+
+        let mut binary =
+            create_mock_transaction("GET".into(), "a.test".into(), "/".into(), Some(200));
+        binary.response_body = Some(vec![0xff, 0xfe, 0x00]);
+        assert!(strip_stream_bodies(binary.clone(), 1024)
+            .response_body
+            .is_none());
+
+        let mut text = binary;
+        text.response_body = Some(b"ok".to_vec());
+        assert!(strip_stream_bodies(text, 0).response_body.is_none());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn curl_export_pipes_binary_bodies_and_trusts_intercepted_tls() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let mut tx = create_mock_transaction(
+            "POST".into(),
+            "upload.test".into(),
+            "/blob".into(),
+            Some(201),
+        );
+        tx.was_intercepted = true;
+        tx.request_headers
+            .insert("Content-Type".into(), "application/octet-stream".into());
+        tx.request_body = Some(vec![0xff, 0x00, 0x10]);
+        let id = tx.id.clone();
+        storage::persist_transaction(tx).await.unwrap();
+
+        let curl = transaction_to_curl(id).await.unwrap();
+        assert!(curl.starts_with("printf '%s' '/wAQ' | base64 --decode | curl -X POST"));
+        assert!(curl.contains("'https://upload.test/blob'"));
+        assert!(curl.contains("--insecure"));
+        assert!(curl.contains("-H 'Content-Type: application/octet-stream'"));
+        assert!(curl.ends_with("--data-binary @-"));
+
+        assert!(transaction_to_curl("missing".into()).await.is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn har_export_keeps_only_tagged_transactions() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let mut ids = Vec::new();
+        for path in ["/a", "/b", "/c", "/d"] {
+            let tx =
+                create_mock_transaction("GET".into(), "tags.test".into(), path.into(), Some(200));
+            ids.push(tx.id.clone());
+            storage::persist_transaction(tx).await.unwrap();
+        }
+        assert!(
+            set_transaction_tags(ids[0].clone(), vec![" bug-123 ".into(), "bug-123".into()])
+                .await
+                .unwrap()
+        );
+        assert!(
+            set_transaction_tags(ids[2].clone(), vec!["bug-123".into(), "flaky".into()])
+                .await
+                .unwrap()
+        );
+        assert!(set_transaction_tags(ids[3].clone(), vec!["bug-456".into()])
+            .await
+            .unwrap());
+        assert!(
+            !set_transaction_tags("missing".into(), vec!["bug-123".into()])
+                .await
+                .unwrap()
+        );
+        set_transaction_pinned(ids[1].clone(), true).await.unwrap();
+
+        let tagged = FilterExpr::Leaf(TransactionFilter {
+            tag: Some("bug-123".into()),
+            ..Default::default()
+        });
+        let har_path = storage_dir.path().join("bug-123.har");
+        let count = export_har_file(
+            har_path.to_str().unwrap().to_string(),
+            Some(tagged.clone()),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 2);
+
+        let har: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&har_path).unwrap()).unwrap();
+        let mut urls: Vec<&str> = har["log"]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["request"]["url"].as_str().unwrap())
+            .collect();
+        urls.sort();
+        assert_eq!(urls.len(), 2);
+        assert!(urls[0].ends_with("tags.test/a"));
+        assert!(urls[1].ends_with("tags.test/c"));
+
+        // Tagged OR pinned
+        let curated = FilterExpr::Or(vec![
+            tagged,
+            FilterExpr::Leaf(TransactionFilter {
+                pinned: Some(true),
+                ..Default::default()
+            }),
+        ]);
+        let count = export_har_file(
+            har_path.to_str().unwrap().to_string(),
+            Some(curated),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn stored_response_body_streams_in_order() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let body: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut tx =
+            create_mock_transaction("GET".into(), "large.test".into(), "/".into(), Some(200));
+        tx.response_body = Some(body.clone());
+        let id = tx.id.clone();
+        storage::persist_transaction(tx).await.unwrap();
+        // Reopen so the body comes from SQLite rather than the in-memory ring
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let reader = storage::open_response_body(&id).await.unwrap().unwrap();
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        let sent = pump_chunks(reader, 64 * 1024, |chunk| {
+            chunks.push(chunk);
+            true
+        })
+        .await
+        .unwrap();
+        assert_eq!(sent, body.len() as u64);
+        assert!(chunks.len() >= 4);
+        assert!(chunks.iter().all(|c| c.len() <= 64 * 1024));
+        assert_eq!(chunks.concat(), body);
+
+        // A consumer that stops listening ends the stream
+        let reader = storage::open_response_body(&id).await.unwrap().unwrap();
+        let sent = pump_chunks(reader, 1024, |_| false).await.unwrap();
+        assert_eq!(sent, 0);
+
+        assert!(storage::open_response_body("missing")
+            .await
+            .unwrap()
+            .is_none());
+    }
 }
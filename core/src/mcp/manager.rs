@@ -3,7 +3,10 @@
 //! Manages the lifecycle of the MCP server (start, stop, status).
 //! Uses the rmcp SDK for MCP protocol implementation.
 
+#[cfg(any(unix, windows))]
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Result;
 use once_cell::sync::Lazy;
@@ -20,6 +23,60 @@ pub struct McpRuntimeConfig {
     pub socket_path: Option<PathBuf>,
     pub allow_writes: bool,
     pub require_approval: bool,
+    pub bind_retry: BindRetryPolicy,
+}
+
+/// How often to retry binding the MCP listener (e.g. after an unclean shutdown
+/// left a stale socket behind). The delay doubles after each failed attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct BindRetryPolicy {
+    pub attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for BindRetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            base_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Run `bind` until it succeeds or the policy's attempts are exhausted.
+/// `bind` receives the 1-based attempt number.
+#[cfg(any(unix, windows))]
+async fn bind_with_retry<T, F, Fut>(policy: BindRetryPolicy, mut bind: F) -> Result<T>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    let attempts = policy.attempts.max(1);
+    let mut delay = policy.base_delay;
+    let mut attempt = 1;
+    loop {
+        match bind(attempt).await {
+            Ok(listener) => {
+                if attempt > 1 {
+                    tracing::info!("Bound MCP listener on attempt {attempt}");
+                }
+                return Ok(listener);
+            }
+            Err(err) if attempt < attempts => {
+                tracing::warn!(
+                    "Binding MCP listener failed (attempt {attempt}/{attempts}): {err}; retrying in {delay:?}"
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to bind MCP listener after {attempt} attempt(s): {err}"
+                ));
+            }
+        }
+    }
 }
 
 impl McpRuntimeConfig {
@@ -92,9 +149,6 @@ mod platform_runtime {
             .ensure_token()
             .map_err(|e| anyhow!("Failed to initialize MCP token: {e}"))?;
         let socket_path = config.socket_path();
-        if socket_path.exists() {
-            let _ = fs::remove_file(&socket_path);
-        }
 
         tracing::info!(
             "Binding MCP Unix socket at {} (auto_start_proxy={})",
@@ -102,7 +156,13 @@ mod platform_runtime {
             config.auto_start_proxy
         );
 
-        let listener = UnixListener::bind(&socket_path)?;
+        let listener = match bind_socket(&socket_path, config.bind_retry).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                *LAST_ERROR.lock().await = Some(err.to_string());
+                return Err(err);
+            }
+        };
 
         let server_config = McpServerConfig {
             storage_path: config.storage_path.clone(),
@@ -142,6 +202,20 @@ mod platform_runtime {
         Ok(current_status_locked().await)
     }
 
+    /// Bind the Unix socket, removing a stale socket file before each attempt.
+    pub(super) async fn bind_socket(
+        socket_path: &Path,
+        retry: BindRetryPolicy,
+    ) -> Result<UnixListener> {
+        bind_with_retry(retry, |_| async move {
+            if socket_path.exists() {
+                let _ = fs::remove_file(socket_path);
+            }
+            UnixListener::bind(socket_path)
+        })
+        .await
+    }
+
     pub(super) async fn stop_runtime() -> Result<McpRuntimeStatus> {
         let mut guard = RUNTIME.lock().await;
         if let Some(handle) = guard.take() {
@@ -355,7 +429,17 @@ mod platform_runtime {
             .ensure_token()
             .map_err(|e| anyhow!("Failed to initialize MCP token: {e}"))?;
 
-        let listener = TcpListener::bind((IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).await?;
+        let bound = bind_with_retry(config.bind_retry, |_| {
+            TcpListener::bind((IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        })
+        .await;
+        let listener = match bound {
+            Ok(listener) => listener,
+            Err(err) => {
+                *LAST_ERROR.lock().await = Some(err.to_string());
+                return Err(err);
+            }
+        };
         let addr = listener.local_addr()?;
 
         tracing::info!(
@@ -555,10 +639,50 @@ pub fn notify_token_rotated() {
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
-#[cfg(all(test, windows))]
+#[cfg(test)]
 mod tests {
     use super::*;
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stale_socket_is_removed_before_bind() {
+        let storage = tempfile::tempdir().unwrap();
+        let socket_path = storage.path().join("stale.sock");
+        // A listener that goes away without unlinking leaves its socket file behind.
+        drop(std::os::unix::net::UnixListener::bind(&socket_path).unwrap());
+        assert!(socket_path.exists());
+        assert!(tokio::net::UnixStream::connect(&socket_path).await.is_err());
+
+        let listener = platform_runtime::bind_socket(&socket_path, BindRetryPolicy::default())
+            .await
+            .expect("bind succeeds over stale socket");
+        tokio::net::UnixStream::connect(&socket_path)
+            .await
+            .expect("new listener accepts connections");
+        drop(listener);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[tokio::test]
+    async fn bind_retry_gives_up_after_configured_attempts() {
+        let policy = BindRetryPolicy {
+            attempts: 3,
+            base_delay: Duration::from_millis(1),
+        };
+        let mut seen = Vec::new();
+        let result: Result<()> = bind_with_retry(policy, |attempt| {
+            seen.push(attempt);
+            async { Err(std::io::Error::from(std::io::ErrorKind::AddrInUse)) }
+        })
+        .await;
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("after 3 attempt(s)"));
+        assert_eq!(seen, vec![1, 2, 3]);
+    }
+
+    #[cfg(windows)]
     #[tokio::test]
     async fn windows_runtime_reports_tcp_endpoint() {
         let storage = tempfile::tempdir().unwrap();
@@ -568,6 +692,7 @@ mod tests {
             socket_path: None,
             allow_writes: false,
             require_approval: true,
+            bind_retry: BindRetryPolicy::default(),
         };
 
         let status = start_runtime(config).await.expect("start runtime");
@@ -17,9 +17,13 @@ use tokio::sync::Mutex;
 
 use crate::api::proxy_api::{self, ProxyConfig};
 use crate::models::breakpoint::{BreakpointRuleInput, RequestEdit};
-use crate::models::TransactionFilter;
+use crate::models::{
+    ExportTimezone, FilterExpr, HttpTransaction, PathNormalization, TransactionFilter,
+};
 use crate::platform::{self, CertTrustStatus};
 use crate::proxy::breakpoints;
+use crate::replay::ReplayContentEncoding;
+use crate::storage::TransactionFilterExt;
 
 const ROOT_CA_COMMON_NAME: &str = "Cheddar Proxy CA";
 
@@ -108,16 +112,41 @@ pub struct TransactionsQueryParams {
     /// Filter by maximum status code
     #[serde(default)]
     pub status_max: Option<u16>,
+    /// Filter by SHA-256 of the request or response body (hex)
+    #[serde(default)]
+    pub body_sha256: Option<String>,
+    /// Filter by minimum response size in bytes
+    #[serde(default)]
+    pub min_response_size: Option<u64>,
+    /// Filter by maximum response size in bytes
+    #[serde(default)]
+    pub max_response_size: Option<u64>,
+    /// Filter by exact JSON-RPC method name
+    #[serde(default)]
+    pub jsonrpc_method: Option<String>,
+    /// Filter by client TLS fingerprint (JA3 hash or JA4)
+    #[serde(default)]
+    pub client_fingerprint: Option<String>,
+    /// Only pinned (true) or unpinned (false) transactions
+    #[serde(default)]
+    pub pinned: Option<bool>,
+    /// Only transactions carrying this exact tag
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Filter by host matching this regex (case-insensitive)
+    #[serde(default)]
+    pub host_regex: Option<String>,
+    /// Filter by path matching this regex, e.g. "^/api/v\\d+/users/\\d+"
+    #[serde(default)]
+    pub path_regex: Option<String>,
     /// Page number (0-indexed)
     #[serde(default)]
     pub page: Option<u32>,
-    /// Number of items per page (max 100)
+    /// Number of items per page (defaults and cap come from the store's paging policy)
     #[serde(rename = "pageSize", default)]
     pub page_size: Option<u32>,
 }
 
-const MAX_PAGE_SIZE: u32 = 100;
-
 impl TransactionsQueryParams {
     /// Parse start_time string into milliseconds timestamp
     fn parse_start_time(&self) -> Result<i64, String> {
@@ -177,6 +206,15 @@ impl TransactionsQueryParams {
             && self.path_contains.is_none()
             && self.status_min.is_none()
             && self.status_max.is_none()
+            && self.body_sha256.is_none()
+            && self.min_response_size.is_none()
+            && self.max_response_size.is_none()
+            && self.jsonrpc_method.is_none()
+            && self.client_fingerprint.is_none()
+            && self.pinned.is_none()
+            && self.tag.is_none()
+            && self.host_regex.is_none()
+            && self.path_regex.is_none()
         {
             return None;
         }
@@ -189,6 +227,15 @@ impl TransactionsQueryParams {
             path_contains: self.path_contains.clone(),
             status_min: self.status_min,
             status_max: self.status_max,
+            body_sha256: self.body_sha256.clone(),
+            min_response_size: self.min_response_size,
+            max_response_size: self.max_response_size,
+            jsonrpc_method: self.jsonrpc_method.clone(),
+            client_fingerprint: self.client_fingerprint.clone(),
+            pinned: self.pinned,
+            tag: self.tag.clone(),
+            host_regex: self.host_regex.clone(),
+            path_regex: self.path_regex.clone(),
         })
     }
 }
@@ -248,6 +295,15 @@ impl SlowRequestsParams {
             path_contains: self.path_contains.clone(),
             status_min: self.status_min,
             status_max: self.status_max,
+            body_sha256: None,
+            min_response_size: None,
+            max_response_size: None,
+            jsonrpc_method: None,
+            client_fingerprint: None,
+            pinned: None,
+            tag: None,
+            host_regex: None,
+            path_regex: None,
         })
     }
 }
@@ -267,6 +323,12 @@ pub struct BreakpointAddParams {
     /// Match requests where path contains this string
     #[serde(default)]
     pub path_contains: Option<String>,
+    /// Match requests whose host matches this regex (case-insensitive)
+    #[serde(default)]
+    pub host_regex: Option<String>,
+    /// Match requests whose path matches this regex, e.g. "^/api/v\\d+/users/\\d+"
+    #[serde(default)]
+    pub path_regex: Option<String>,
 }
 
 fn default_true() -> bool {
@@ -296,6 +358,8 @@ impl BreakpointAddParams {
                 }),
             host_contains: self.host_contains.clone(),
             path_contains: self.path_contains.clone(),
+            host_regex: self.host_regex.clone(),
+            path_regex: self.path_regex.clone(),
         }
     }
 }
@@ -383,21 +447,94 @@ pub struct ReplayRequestParams {
     /// Optional: Allow invalid TLS certificates (default: false)
     #[serde(default)]
     pub allow_insecure_tls: bool,
+    /// Optional: When the body is not actually encoded as its Content-Encoding
+    /// header claims, "recompress" it to match (default) or "strip" the header
+    #[serde(default)]
+    pub content_encoding: ReplayContentEncoding,
+    /// Optional: Strip If-None-Match/If-Modified-Since and send no-cache so the
+    /// server returns a full response rather than 304 (default: false)
+    #[serde(default)]
+    pub bypass_cache: bool,
+    /// Optional: Count a replay identical (request and response) to one made
+    /// within this many milliseconds on that transaction instead of storing it
+    #[serde(default)]
+    pub dedup_window_ms: Option<u64>,
+    /// Optional: Send headers in the captured order and casing (default: false)
+    #[serde(default)]
+    pub preserve_header_order: bool,
+    /// Optional: Fail the replay if it takes longer than this many milliseconds
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Optional: Don't follow redirects to a different host, e.g. so an
+    /// authenticated request cannot leak its credentials (default: false)
+    #[serde(default)]
+    pub redirect_same_host_only: bool,
+}
+
+/// Parameters for replaying a sequence of requests
+#[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
+pub struct ReplaySequenceParams {
+    /// Transaction IDs to replay, in order
+    pub ids: Vec<String>,
+    /// Optional: Allow invalid TLS certificates (default: false)
+    #[serde(default)]
+    pub allow_insecure_tls: bool,
 }
 
 /// Parameters for HAR export
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct HarExportParams {
-    /// File path to export HAR to
+    /// File path to export HAR to (a `.gz` suffix writes gzip-compressed output)
+    pub path: String,
+    /// Render startedDateTime with this UTC offset in minutes (e.g. 330 for +05:30). Default: UTC
+    #[serde(rename = "utcOffsetMinutes", default)]
+    pub utc_offset_minutes: Option<i32>,
+    /// Only export transactions carrying this exact tag
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Only export pinned transactions
+    #[serde(rename = "pinnedOnly", default)]
+    pub pinned_only: bool,
+}
+
+/// Parameters for capture_session
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct CaptureSessionParams {
+    /// File path to write the session's HAR to
     pub path: String,
-    // Filter could be added but requires simple types
+    /// Only capture hosts containing this string
+    #[serde(rename = "hostContains", default)]
+    pub host_contains: Option<String>,
+    /// Stop after this many finished transactions (default: 20, capped at the
+    /// configured max page size, 100 unless changed)
+    #[serde(rename = "maxTransactions", default)]
+    pub max_transactions: Option<u32>,
+    /// Stop after this many seconds even if fewer were captured (default: 60, max: 600)
+    #[serde(rename = "timeoutSecs", default)]
+    pub timeout_secs: Option<u64>,
 }
 
+const DEFAULT_SESSION_TRANSACTIONS: u32 = 20;
+const DEFAULT_SESSION_TIMEOUT_SECS: u64 = 60;
+const MAX_SESSION_TIMEOUT_SECS: u64 = 600;
+/// How often a capture session checks the store for new transactions
+const SESSION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
 /// Parameters for HAR import
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct HarImportParams {
-    /// File path to import HAR from
+    /// File path to import HAR from (`.har.gz` files are decompressed automatically)
+    pub path: String,
+}
+
+/// Parameters for certificate export
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct CertificateExportParams {
+    /// File path to write the root CA certificate (PEM) to
     pub path: String,
+    /// Also write a DER-encoded `.crt` copy next to the PEM (for Windows/Android installs)
+    #[serde(rename = "includeDer", default)]
+    pub include_der: bool,
 }
 
 /// Parameters for listing WebSocket connections
@@ -406,7 +543,7 @@ pub struct WebSocketConnectionsParams {
     /// Page number (0-indexed)
     #[serde(default)]
     pub page: Option<u32>,
-    /// Number of items per page (max 100)
+    /// Number of items per page (defaults and cap come from the store's paging policy)
     #[serde(rename = "pageSize", default)]
     pub page_size: Option<u32>,
 }
@@ -433,8 +570,69 @@ pub struct ListDomainsParams {
     /// Maximum number of domains to return (default: 100, max: 500)
     #[serde(default)]
     pub limit: Option<u32>,
+    /// "count" (default) for the busiest domains first, "recent" for the most
+    /// recently contacted first
+    #[serde(default, rename = "sortBy")]
+    pub sort_by: Option<String>,
+}
+
+/// Parameters for search_traffic tool
+#[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
+pub struct SearchTrafficParams {
+    /// Text to find in request or response bodies (case-insensitive, at
+    /// least 3 characters)
+    pub query: String,
+    /// Maximum number of matches to return, newest first (default: 20, max: 100)
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+/// Parameters for status_breakdown tool
+#[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
+pub struct StatusBreakdownParams {
+    /// Start of the window. ISO 8601 or "today". Default: all stored traffic
+    #[serde(rename = "startTime", default)]
+    pub start_time: Option<String>,
+    /// End of the window. Defaults to now
+    #[serde(rename = "endTime", default)]
+    pub end_time: Option<String>,
+    /// Only include hosts containing this string
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+/// Parameters for error_summary tool
+#[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
+pub struct ErrorSummaryParams {
+    /// Start time for the summary window (REQUIRED). ISO 8601 or "today".
+    #[serde(rename = "startTime")]
+    pub start_time: Option<String>,
+    /// End time for the summary window. Defaults to now.
+    #[serde(rename = "endTime", default)]
+    pub end_time: Option<String>,
+    /// Only include hosts containing this string
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Maximum number of failing endpoints to return (default: 10, max: 50)
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// Collapse duplicate slashes when grouping endpoints (default: false)
+    #[serde(rename = "collapseSlashes", default)]
+    pub collapse_slashes: bool,
+    /// Group endpoints case-insensitively by path (default: false)
+    #[serde(rename = "caseInsensitivePaths", default)]
+    pub case_insensitive_paths: bool,
 }
 
+/// Most recent errors scanned when building an error summary
+const ERROR_SUMMARY_SCAN_LIMIT: u32 = 2000;
+/// Sample transaction ids kept per status code
+const ERROR_SAMPLE_IDS: usize = 3;
+/// Distinct response snippets reported
+const ERROR_SNIPPET_COUNT: usize = 5;
+/// Characters kept from an error response body
+const ERROR_SNIPPET_CHARS: usize = 200;
+
 // ============================================================================
 // Response Types (for outputSchema support)
 // ============================================================================
@@ -481,6 +679,14 @@ pub struct CertificateInfo {
     pub trust_status: String,
 }
 
+/// Response from export_certificate tool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateExportResponse {
+    /// Files that were written
+    pub paths: Vec<String>,
+}
+
 /// Response from server_stats tool
 #[derive(Debug, Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -512,6 +718,112 @@ pub struct DomainInfo {
     pub host: String,
     /// Number of requests to this domain
     pub request_count: u64,
+    /// When this domain was last contacted (ISO 8601)
+    pub last_seen: String,
+}
+
+/// Response from search_traffic tool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SearchTrafficResponse {
+    /// Matching transactions, newest first
+    pub matches: Vec<TrafficMatch>,
+    /// Number of matches returned
+    pub total: usize,
+}
+
+/// A transaction whose body contains the search text
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficMatch {
+    /// Transaction ID, for transaction_detail
+    pub id: String,
+    pub method: String,
+    pub url: String,
+    /// HTTP status, if a response arrived
+    pub status: Option<u16>,
+    /// When the request started (ISO 8601)
+    pub started_at: String,
+    /// Body text around the match
+    pub snippet: String,
+}
+
+/// Response from status_breakdown tool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusBreakdownResponse {
+    /// Transactions counted
+    pub total: u64,
+    /// Counts per status code, ascending; status 0 means no response
+    pub statuses: Vec<StatusBucket>,
+}
+
+/// Transactions sharing a status code
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusBucket {
+    pub status: u16,
+    pub count: u64,
+}
+
+/// Response from error_summary tool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorSummaryResponse {
+    /// Transactions in the window (after the host filter)
+    pub total_requests: u64,
+    /// Transactions with a 4xx or 5xx status in the window
+    pub total_errors: u64,
+    /// Endpoints ordered by 5xx count, then 4xx count
+    pub top_endpoints: Vec<FailingEndpoint>,
+    /// Sample transaction ids per status code
+    pub error_classes: Vec<ErrorClassSamples>,
+    /// Most common error response body prefixes
+    pub common_snippets: Vec<ErrorSnippet>,
+}
+
+/// Failure counts for a single method + host + path
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FailingEndpoint {
+    pub method: String,
+    pub host: String,
+    /// Path without query string
+    pub path: String,
+    pub server_errors: u64,
+    pub client_errors: u64,
+}
+
+/// Transactions sharing a status code
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorClassSamples {
+    pub status: u16,
+    pub count: u64,
+    pub sample_ids: Vec<String>,
+}
+
+/// A response body prefix seen across error responses
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorSnippet {
+    pub snippet: String,
+    pub count: u64,
+}
+
+/// Response from capture_session tool
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureSessionResponse {
+    /// HAR file written
+    pub path: String,
+    /// Finished transactions captured (and exported)
+    pub captured: usize,
+    /// Whether the session ended on the timeout rather than the transaction count
+    pub timed_out: bool,
+    /// Whether the proxy had to be started for this session
+    pub started_proxy: bool,
+    /// Ids of the exported transactions, oldest first
+    pub transaction_ids: Vec<String>,
 }
 
 // ============================================================================
@@ -560,6 +872,13 @@ impl CheddarProxyServer {
         Ok(())
     }
 
+    async fn load_transaction(&self, id: &str) -> Result<HttpTransaction, McpError> {
+        let tx = proxy_api::get_transaction_detail(id).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to fetch transaction: {e}"), None)
+        })?;
+        tx.ok_or_else(|| McpError::invalid_params("Transaction not found", None))
+    }
+
     fn ensure_write_allowed(&self, action: &str) -> Result<(), McpError> {
         if !self.config.allow_writes {
             return Err(McpError::invalid_request(
@@ -578,6 +897,177 @@ impl CheddarProxyServer {
     }
 }
 
+/// Write the CA PEM to `path`, plus a DER copy with a `.crt` extension when requested.
+fn write_certificate_files(
+    pem: &str,
+    path: &str,
+    include_der: bool,
+) -> Result<Vec<String>, String> {
+    let pem_path = PathBuf::from(path);
+    if let Some(parent) = pem_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&pem_path, pem).map_err(|e| e.to_string())?;
+    let mut written = vec![pem_path.to_string_lossy().to_string()];
+
+    if include_der {
+        let der = rustls_pemfile::certs(&mut pem.as_bytes())
+            .next()
+            .ok_or_else(|| "root CA PEM contains no certificate".to_string())?
+            .map_err(|e| e.to_string())?;
+        let mut der_path = pem_path.with_extension("crt");
+        if der_path == pem_path {
+            der_path = pem_path.with_extension("der");
+        }
+        std::fs::write(&der_path, der.as_ref()).map_err(|e| e.to_string())?;
+        written.push(der_path.to_string_lossy().to_string());
+    }
+
+    Ok(written)
+}
+
+/// Aggregate error transactions (newest first) into a triage summary.
+fn summarize_errors(
+    errors: &[HttpTransaction],
+    total_requests: u64,
+    total_errors: u64,
+    limit: usize,
+    normalization: PathNormalization,
+) -> ErrorSummaryResponse {
+    use std::collections::HashMap;
+
+    let mut endpoints: HashMap<(String, String, String), FailingEndpoint> = HashMap::new();
+    let mut classes: HashMap<u16, ErrorClassSamples> = HashMap::new();
+    let mut snippets: HashMap<String, u64> = HashMap::new();
+
+    for tx in errors {
+        let Some(status) = tx.status_code.filter(|s| *s >= 400) else {
+            continue;
+        };
+        let path = tx.grouping_path(normalization);
+        let endpoint = endpoints
+            .entry((tx.method.to_string(), tx.host.clone(), path.clone()))
+            .or_insert_with(|| FailingEndpoint {
+                method: tx.method.to_string(),
+                host: tx.host.clone(),
+                path,
+                server_errors: 0,
+                client_errors: 0,
+            });
+        if status >= 500 {
+            endpoint.server_errors += 1;
+        } else {
+            endpoint.client_errors += 1;
+        }
+
+        let class = classes.entry(status).or_insert_with(|| ErrorClassSamples {
+            status,
+            count: 0,
+            sample_ids: Vec::new(),
+        });
+        class.count += 1;
+        if class.sample_ids.len() < ERROR_SAMPLE_IDS {
+            class.sample_ids.push(tx.id.clone());
+        }
+
+        if let Some(body) = tx.response_body.as_deref() {
+            let text = String::from_utf8_lossy(body);
+            let snippet: String = text.trim().chars().take(ERROR_SNIPPET_CHARS).collect();
+            if !snippet.is_empty() {
+                *snippets.entry(snippet).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut top_endpoints: Vec<FailingEndpoint> = endpoints.into_values().collect();
+    top_endpoints.sort_by(|a, b| {
+        (b.server_errors, b.client_errors)
+            .cmp(&(a.server_errors, a.client_errors))
+            .then_with(|| a.host.cmp(&b.host))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    top_endpoints.truncate(limit);
+
+    let mut error_classes: Vec<ErrorClassSamples> = classes.into_values().collect();
+    error_classes.sort_by(|a, b| b.count.cmp(&a.count).then(a.status.cmp(&b.status)));
+
+    let mut common_snippets: Vec<ErrorSnippet> = snippets
+        .into_iter()
+        .map(|(snippet, count)| ErrorSnippet { snippet, count })
+        .collect();
+    common_snippets.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.snippet.cmp(&b.snippet))
+    });
+    common_snippets.truncate(ERROR_SNIPPET_COUNT);
+
+    ErrorSummaryResponse {
+        total_requests,
+        total_errors,
+        top_endpoints,
+        error_classes,
+        common_snippets,
+    }
+}
+
+/// Wait until `target` finished transactions matching `filter` have been stored
+/// since now (or `timeout` passes), then export them as HAR to `path`.
+async fn run_capture_session(
+    filter: &TransactionFilter,
+    target: u32,
+    timeout: std::time::Duration,
+    path: &str,
+) -> Result<CaptureSessionResponse, String> {
+    use crate::models::TransactionState;
+
+    let since = chrono::Utc::now().timestamp_millis();
+    let deadline = tokio::time::Instant::now() + timeout;
+    let (mut captured, timed_out) = loop {
+        let stored =
+            crate::storage::list_transactions_with_time_range(filter, since, i64::MAX, None)
+                .await
+                .map_err(|e| e.to_string())?;
+        let finished: Vec<HttpTransaction> = stored
+            .into_iter()
+            .filter(|tx| {
+                matches!(
+                    tx.state,
+                    TransactionState::Completed | TransactionState::Failed
+                )
+            })
+            .collect();
+        if finished.len() >= target as usize {
+            break (finished, false);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            break (finished, true);
+        }
+        tokio::time::sleep(SESSION_POLL_INTERVAL).await;
+    };
+
+    if captured.is_empty() {
+        return Err(format!(
+            "no matching transactions finished within {}s",
+            timeout.as_secs()
+        ));
+    }
+    // Newest first from the store; keep the earliest `target` in capture order.
+    captured.reverse();
+    captured.truncate(target as usize);
+    let transaction_ids = captured.iter().map(|tx| tx.id.clone()).collect();
+    let count = crate::storage::export_har_to_path(captured, path, ExportTimezone::default())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(CaptureSessionResponse {
+        path: path.to_string(),
+        captured: count,
+        timed_out,
+        started_proxy: false,
+        transaction_ids,
+    })
+}
+
 fn cert_status_to_str(status: CertTrustStatus) -> &'static str {
     match status {
         CertTrustStatus::Trusted => "trusted",
@@ -586,6 +1076,19 @@ fn cert_status_to_str(status: CertTrustStatus) -> &'static str {
     }
 }
 
+/// Every rule that changes how traffic is handled, for `proxy://rules`.
+/// Recorded responses are the proxy's mocks: transactions whose stored
+/// response is served in place of the upstream.
+fn rules_snapshot() -> serde_json::Value {
+    serde_json::json!({
+        "breakpoints": crate::proxy::breakpoints::list_breakpoint_rules(),
+        "rewrites": crate::proxy::rewrite::list_rewrite_rules(),
+        "rewriteMatchMode": crate::proxy::rewrite::rewrite_match_mode(),
+        "alerts": crate::proxy::alerts::list_alert_rules(),
+        "recordedResponses": crate::proxy::playback::armed_recordings(),
+    })
+}
+
 // ============================================================================
 // Tool Implementations
 // ============================================================================
@@ -728,6 +1231,26 @@ impl CheddarProxyServer {
 
         Ok(CallToolResult::success(vec![Content::text(pem)]))
     }
+
+    #[tool(
+        description = "Write the root CA certificate to a file (PEM, optionally also DER .crt) so it can be installed manually",
+        annotations(read_only_hint = false, destructive_hint = true)
+    )]
+    async fn export_certificate(
+        &self,
+        params: Parameters<CertificateExportParams>,
+    ) -> Result<Json<CertificateExportResponse>, McpError> {
+        self.ensure_write_allowed("export_certificate")?;
+        let p = params.0;
+
+        let pem = proxy_api::get_root_ca_pem(self.config.storage_path_as_string())
+            .map_err(|e| McpError::internal_error(format!("Failed to read root CA: {e}"), None))?;
+        let paths = write_certificate_files(&pem, &p.path, p.include_der).map_err(|e| {
+            McpError::internal_error(format!("Failed to export certificate: {e}"), None)
+        })?;
+
+        Ok(Json(CertificateExportResponse { paths }))
+    }
     // ========================================================================
     // System & Stats
     // ========================================================================
@@ -754,7 +1277,7 @@ impl CheddarProxyServer {
     // ========================================================================
 
     #[tool(
-        description = "Query captured HTTP transactions with time-bounded filtering. REQUIRES startTime parameter (ISO 8601 format or 'today'). Supports filtering by method, host, path, status codes, with pagination (max 100 per page).",
+        description = "Query captured HTTP transactions with time-bounded filtering. REQUIRES startTime parameter (ISO 8601 format or 'today'). Supports filtering by method, host, path, status codes, response size, with pagination (page size capped by the store's paging policy, 100 by default).",
         annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn transactions_list(
@@ -772,8 +1295,14 @@ impl CheddarProxyServer {
             .map_err(|e| McpError::invalid_params(e, None))?;
 
         let page = p.page.unwrap_or(0);
-        let page_size = p.page_size.unwrap_or(50).min(MAX_PAGE_SIZE);
+        // 0 lets the store apply its configured default; it also clamps to its max.
+        let page_size = p.page_size.unwrap_or(0);
         let filter = p.to_filter();
+        if let Some(filter) = &filter {
+            filter
+                .validate()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        }
 
         // Query with time bounds
         let result = proxy_api::query_transactions_with_time_range(
@@ -796,15 +1325,82 @@ impl CheddarProxyServer {
         &self,
         params: Parameters<TransactionDetailParams>,
     ) -> Result<CallToolResult, McpError> {
-        let tx = proxy_api::get_transaction_detail(&params.0.id)
+        let tx = self.load_transaction(&params.0.id).await?;
+        let json = serde_json::to_string_pretty(&tx).unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    #[tool(
+        description = "Get a cURL command that reproduces a captured request. Binary bodies are piped in via base64; text bodies over 16 KiB are truncated with a note.",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
+    async fn get_curl(
+        &self,
+        params: Parameters<TransactionDetailParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let tx = self.load_transaction(&params.0.id).await?;
+        Ok(CallToolResult::success(vec![Content::text(
+            crate::storage::curl_command(&tx),
+        )]))
+    }
+
+    #[tool(
+        description = "Get a JavaScript fetch() snippet that reproduces a captured request. Request bodies over 16 KiB are truncated with a note.",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
+    async fn get_fetch(
+        &self,
+        params: Parameters<TransactionDetailParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let tx = self.load_transaction(&params.0.id).await?;
+        Ok(CallToolResult::success(vec![Content::text(
+            crate::storage::fetch_snippet(&tx),
+        )]))
+    }
+
+    #[tool(
+        description = "Find transactions whose request or response body contains a text (case-insensitive, at least 3 characters), newest first. Returns each match's id, method, URL, status and a snippet around the match; fetch full bodies with transaction_detail.",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
+    async fn search_traffic(
+        &self,
+        params: Parameters<SearchTrafficParams>,
+    ) -> Result<Json<SearchTrafficResponse>, McpError> {
+        let p = params.0;
+        if p.query.chars().count() < crate::storage::MIN_BODY_SEARCH_LEN {
+            return Err(McpError::invalid_params(
+                format!(
+                    "query must be at least {} characters",
+                    crate::storage::MIN_BODY_SEARCH_LEN
+                ),
+                None,
+            ));
+        }
+        let limit = p.limit.unwrap_or(20).clamp(1, 100);
+        let hits = proxy_api::search_bodies(p.query, limit)
             .await
             .map_err(|e| {
-                McpError::internal_error(format!("Failed to fetch transaction: {e}"), None)
+                McpError::internal_error(format!("Failed to search traffic: {e}"), None)
             })?;
 
-        let tx = tx.ok_or_else(|| McpError::invalid_params("Transaction not found", None))?;
-        let json = serde_json::to_string_pretty(&tx).unwrap_or_default();
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+        let matches: Vec<TrafficMatch> = hits
+            .into_iter()
+            .map(|hit| TrafficMatch {
+                id: hit.transaction.id.clone(),
+                method: hit.transaction.method.to_string(),
+                url: hit.transaction.full_url(),
+                status: hit.transaction.status_code,
+                started_at: chrono::DateTime::from_timestamp_millis(
+                    hit.transaction.timing.start_time,
+                )
+                .unwrap_or_default()
+                .to_rfc3339(),
+                snippet: hit.snippet,
+            })
+            .collect();
+        let total = matches.len();
+
+        Ok(Json(SearchTrafficResponse { matches, total }))
     }
 
     #[tool(
@@ -816,7 +1412,13 @@ impl CheddarProxyServer {
         params: Parameters<SlowRequestsParams>,
     ) -> Result<CallToolResult, McpError> {
         let p = params.0;
-        let slow = proxy_api::get_slow_transactions(p.to_filter(), p.threshold_ms, p.limit)
+        let filter = p.to_filter();
+        if let Some(filter) = &filter {
+            filter
+                .validate()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        }
+        let slow = proxy_api::get_slow_transactions(filter, p.threshold_ms, p.limit)
             .await
             .map_err(|e| {
                 McpError::internal_error(format!("Failed to query slow requests: {e}"), None)
@@ -827,7 +1429,7 @@ impl CheddarProxyServer {
     }
 
     #[tool(
-        description = "List unique domains/hosts contacted with request counts. Useful for privacy auditing to see what servers an app communicates with.",
+        description = "List unique domains/hosts contacted with request counts and last-seen time. sortBy: 'count' (default) or 'recent'. Useful for privacy auditing to see what servers an app communicates with.",
         annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn list_domains(
@@ -836,15 +1438,26 @@ impl CheddarProxyServer {
     ) -> Result<Json<ListDomainsResponse>, McpError> {
         let capped_limit = params.0.limit.unwrap_or(100).clamp(1, 500);
 
-        let hosts = crate::storage::list_unique_hosts(capped_limit)
-            .await
-            .map_err(|e| McpError::internal_error(format!("Failed to list domains: {e}"), None))?;
+        let hosts = match params.0.sort_by.as_deref() {
+            None | Some("count") => crate::storage::list_unique_hosts(capped_limit).await,
+            Some("recent") => crate::storage::list_recent_hosts(capped_limit).await,
+            Some(other) => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown sortBy '{other}', expected 'count' or 'recent'"),
+                    None,
+                ))
+            }
+        }
+        .map_err(|e| McpError::internal_error(format!("Failed to list domains: {e}"), None))?;
 
         let domains: Vec<DomainInfo> = hosts
             .into_iter()
-            .map(|(host, request_count)| DomainInfo {
+            .map(|(host, request_count, last_seen_ms)| DomainInfo {
                 host,
                 request_count,
+                last_seen: chrono::DateTime::from_timestamp_millis(last_seen_ms)
+                    .unwrap_or_default()
+                    .to_rfc3339(),
             })
             .collect();
         let total = domains.len();
@@ -852,6 +1465,116 @@ impl CheddarProxyServer {
         Ok(Json(ListDomainsResponse { domains, total }))
     }
 
+    #[tool(
+        description = "Count transactions per HTTP status code (status 0 = no response) for a quick health overview. Optional startTime/endTime (ISO 8601 or 'today') and host substring filter.",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
+    async fn status_breakdown(
+        &self,
+        params: Parameters<StatusBreakdownParams>,
+    ) -> Result<Json<StatusBreakdownResponse>, McpError> {
+        let p = params.0;
+        let parse = |s: &str| {
+            TransactionsQueryParams::parse_time_string(s)
+                .map_err(|e| McpError::invalid_params(e, None))
+        };
+        let time_range = match (p.start_time.as_deref(), p.end_time.as_deref()) {
+            (None, None) => None,
+            (start, end) => Some((
+                start.map(parse).transpose()?.unwrap_or(i64::MIN),
+                end.map(parse)
+                    .transpose()?
+                    .unwrap_or_else(|| chrono::Utc::now().timestamp_millis()),
+            )),
+        };
+        let filter = TransactionFilter {
+            host_contains: p.host,
+            ..Default::default()
+        };
+        let breakdown = crate::storage::status_breakdown(&filter, time_range)
+            .await
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to count statuses: {e}"), None)
+            })?;
+
+        let statuses: Vec<StatusBucket> = breakdown
+            .into_iter()
+            .map(|(status, count)| StatusBucket { status, count })
+            .collect();
+        let total = statuses.iter().map(|s| s.count).sum();
+        Ok(Json(StatusBreakdownResponse { total, statuses }))
+    }
+
+    #[tool(
+        description = "Summarize failing traffic for triage. REQUIRES startTime (ISO 8601 or 'today'). Returns top failing endpoints by 5xx/4xx count, sample transaction ids per status code, and common error response snippets. Optional host substring filter; collapseSlashes and caseInsensitivePaths merge path variants into one endpoint.",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
+    async fn error_summary(
+        &self,
+        params: Parameters<ErrorSummaryParams>,
+    ) -> Result<Json<ErrorSummaryResponse>, McpError> {
+        let p = params.0;
+        let start_ms = p
+            .start_time
+            .as_deref()
+            .ok_or_else(|| McpError::invalid_params("startTime is required", None))
+            .and_then(|s| {
+                TransactionsQueryParams::parse_time_string(s)
+                    .map_err(|e| McpError::invalid_params(e, None))
+            })?;
+        let end_ms = match p.end_time.as_deref() {
+            Some(s) => TransactionsQueryParams::parse_time_string(s)
+                .map_err(|e| McpError::invalid_params(e, None))?,
+            None => chrono::Utc::now().timestamp_millis(),
+        };
+        let limit = p.limit.unwrap_or(10).clamp(1, 50) as usize;
+
+        let all_filter = TransactionFilter {
+            host_contains: p.host.clone(),
+            ..Default::default()
+        };
+        let error_filter = TransactionFilter {
+            status_min: Some(400),
+            ..all_filter.clone()
+        };
+        let query_err = |e: anyhow::Error| {
+            McpError::internal_error(format!("Failed to query errors: {e}"), None)
+        };
+        let all =
+            crate::storage::query_transactions_with_time_range(&all_filter, start_ms, end_ms, 0, 1)
+                .await
+                .map_err(query_err)?;
+        let error_total = crate::storage::query_transactions_with_time_range(
+            &error_filter,
+            start_ms,
+            end_ms,
+            0,
+            1,
+        )
+        .await
+        .map_err(query_err)?
+        .total;
+        let errors = crate::storage::list_transactions_with_time_range(
+            &error_filter,
+            start_ms,
+            end_ms,
+            Some(ERROR_SUMMARY_SCAN_LIMIT),
+        )
+        .await
+        .map_err(query_err)?;
+
+        Ok(Json(summarize_errors(
+            &errors,
+            all.total,
+            error_total,
+            limit,
+            PathNormalization {
+                collapse_slashes: p.collapse_slashes,
+                lowercase: p.case_insensitive_paths,
+            },
+        )))
+    }
+
     // ========================================================================
     // Breakpoints (Phase 2)
     // ========================================================================
@@ -876,7 +1599,8 @@ impl CheddarProxyServer {
     ) -> Result<CallToolResult, McpError> {
         self.ensure_write_allowed("add_breakpoint_rule")?;
         let input = params.0.to_input();
-        let created = breakpoints::add_breakpoint_rule(input);
+        let created = breakpoints::add_breakpoint_rule(input)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
         let json = serde_json::to_string_pretty(&created).unwrap_or_default();
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
@@ -988,6 +1712,15 @@ impl CheddarProxyServer {
             headers: p.headers,
             body: p.body.map(|s| s.into_bytes()),
             accept_invalid_certs: p.allow_insecure_tls,
+            content_encoding: p.content_encoding,
+            capture_wire: false,
+            bypass_cache: p.bypass_cache,
+            dedup_window: p.dedup_window_ms.map(std::time::Duration::from_millis),
+            base_url: None,
+            preserve_header_order: p.preserve_header_order,
+            timeout_ms: p.timeout_ms,
+            redirect_same_host_only: p.redirect_same_host_only,
+            drop_credentials: false,
         };
 
         let result = replay_request(&p.id, replay_params)
@@ -995,13 +1728,19 @@ impl CheddarProxyServer {
             .map_err(|e| McpError::internal_error(format!("Replay failed: {e}"), None))?;
 
         if result.success {
-            Ok(CallToolResult::success(vec![Content::text(format!(
+            let mut text = format!(
                 "Request replayed successfully. New transaction ID: {}, Status: {}",
                 result.transaction_id,
                 result
                     .status_code
                     .map_or("pending".to_string(), |c| c.to_string())
-            ))]))
+            );
+            if let Some(location) = &result.blocked_redirect {
+                text.push_str(&format!(
+                    ". Redirect to another host not followed: {location}"
+                ));
+            }
+            Ok(CallToolResult::success(vec![Content::text(text)]))
         } else {
             Ok(CallToolResult::success(vec![Content::text(format!(
                 "Request replay failed. Transaction ID: {}, Error: {}",
@@ -1011,6 +1750,52 @@ impl CheddarProxyServer {
         }
     }
 
+    #[tool(
+        description = "Replay captured requests in the given order, waiting between them as long as the original requests were apart (gaps capped at 30s). Useful for reproducing timing-sensitive bugs.",
+        annotations(read_only_hint = false, destructive_hint = true)
+    )]
+    async fn replay_sequence(
+        &self,
+        params: Parameters<ReplaySequenceParams>,
+    ) -> Result<CallToolResult, McpError> {
+        self.ensure_write_allowed("replay_sequence")?;
+        let p = params.0;
+        if p.ids.is_empty() {
+            return Err(McpError::invalid_params("ids must not be empty", None));
+        }
+
+        let replay_params = crate::replay::ReplayParams {
+            accept_invalid_certs: p.allow_insecure_tls,
+            ..Default::default()
+        };
+        let results = crate::replay::replay_sequence(&p.ids, replay_params)
+            .await
+            .map_err(|e| McpError::internal_error(format!("Replay failed: {e}"), None))?;
+
+        let lines: Vec<String> = p
+            .ids
+            .iter()
+            .zip(&results)
+            .map(
+                |(original, result)| match (&result.status_code, &result.error) {
+                    (Some(status), _) => {
+                        format!("{original} -> {} (status {status})", result.transaction_id)
+                    }
+                    (None, error) => format!(
+                        "{original} -> {} (failed: {})",
+                        result.transaction_id,
+                        error.as_deref().unwrap_or("unknown error")
+                    ),
+                },
+            )
+            .collect();
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Replayed {} request(s):\n{}",
+            results.len(),
+            lines.join("\n")
+        ))]))
+    }
+
     // ========================================================================
     // HAR Export/Import (Phase 2)
     // ========================================================================
@@ -1024,7 +1809,17 @@ impl CheddarProxyServer {
         params: Parameters<HarExportParams>,
     ) -> Result<CallToolResult, McpError> {
         let p = params.0;
-        let count = proxy_api::export_har_file(p.path.clone(), None)
+        let timezone = p
+            .utc_offset_minutes
+            .map(|minutes| ExportTimezone::Offset { minutes });
+        let filter = (p.tag.is_some() || p.pinned_only).then(|| {
+            FilterExpr::Leaf(TransactionFilter {
+                tag: p.tag.clone(),
+                pinned: p.pinned_only.then_some(true),
+                ..Default::default()
+            })
+        });
+        let count = proxy_api::export_har_file(p.path.clone(), filter, false, timezone)
             .await
             .map_err(|e| McpError::internal_error(format!("Failed to export HAR: {e}"), None))?;
 
@@ -1034,6 +1829,51 @@ impl CheddarProxyServer {
         ))]))
     }
 
+    #[tool(
+        description = "Run a focused capture session: start the proxy if needed, narrow the live stream to a host, wait for N finished transactions or a timeout, then export them to a HAR file and return a summary",
+        annotations(read_only_hint = false, destructive_hint = true)
+    )]
+    async fn capture_session(
+        &self,
+        params: Parameters<CaptureSessionParams>,
+    ) -> Result<Json<CaptureSessionResponse>, McpError> {
+        self.ensure_write_allowed("capture_session")?;
+        let p = params.0;
+        let target = p
+            .max_transactions
+            .unwrap_or(DEFAULT_SESSION_TRANSACTIONS)
+            .max(1);
+        let target = crate::storage::clamp_page_size(target);
+        let timeout = std::time::Duration::from_secs(
+            p.timeout_secs
+                .unwrap_or(DEFAULT_SESSION_TIMEOUT_SECS)
+                .min(MAX_SESSION_TIMEOUT_SECS),
+        );
+
+        let started_proxy = !proxy_api::get_proxy_status().is_running;
+        if started_proxy {
+            let mut config = proxy_api::create_default_config();
+            config.storage_path = self.config.storage_path_as_string();
+            proxy_api::start_proxy(config).await.map_err(|e| {
+                McpError::internal_error(format!("Failed to start proxy: {e}"), None)
+            })?;
+        }
+
+        let filter = TransactionFilter {
+            host_contains: p.host_contains.filter(|h| !h.trim().is_empty()),
+            ..Default::default()
+        };
+        let previous_filter = proxy_api::current_stream_filter();
+        let _ = proxy_api::update_stream_filter(Some(filter.clone()));
+        let result = run_capture_session(&filter, target, timeout, &p.path).await;
+        let _ = proxy_api::update_stream_filter(Some(previous_filter));
+
+        let mut response = result
+            .map_err(|e| McpError::internal_error(format!("Capture session failed: {e}"), None))?;
+        response.started_proxy = started_proxy;
+        Ok(Json(response))
+    }
+
     #[tool(
         description = "Import transactions from a HAR (HTTP Archive) file at the specified path",
         annotations(read_only_hint = false, destructive_hint = true)
@@ -1068,7 +1908,7 @@ impl CheddarProxyServer {
     ) -> Result<CallToolResult, McpError> {
         let p = params.0;
         let page = p.page.unwrap_or(0);
-        let page_size = p.page_size.unwrap_or(50).min(MAX_PAGE_SIZE);
+        let page_size = crate::storage::clamp_page_size(p.page_size.unwrap_or(0));
 
         // Query WebSocket transactions from storage
         let connections = crate::storage::get_websocket_connections(page, page_size);
@@ -1081,6 +1921,8 @@ impl CheddarProxyServer {
                     "path": tx.path,
                     "timestamp": tx.timestamp_ms,
                     "messageCount": crate::storage::get_websocket_message_count(&tx.id),
+                    "messagesPerSecond": crate::storage::get_websocket_stats(&tx.id)
+                        .map(|stats| stats.messages_per_second()),
                 })
             }).collect::<Vec<_>>(),
             "page": page,
@@ -1105,14 +1947,9 @@ impl CheddarProxyServer {
         let limit = p.limit.unwrap_or(100).min(MAX_WS_MESSAGE_LIMIT);
         let offset = p.offset.unwrap_or(0);
 
-        // Get all messages and apply pagination
-        let all_messages = crate::storage::get_websocket_messages(&p.connection_id);
-        let total = all_messages.len();
-        let messages: Vec<_> = all_messages
-            .into_iter()
-            .skip(offset as usize)
-            .take(limit as usize)
-            .collect();
+        let total = crate::storage::get_websocket_message_count(&p.connection_id);
+        let messages =
+            crate::storage::get_websocket_messages(&p.connection_id, offset, Some(limit));
 
         use base64::Engine;
         let result = serde_json::json!({
@@ -1167,6 +2004,44 @@ impl CheddarProxyServer {
             serde_json::to_string_pretty(&result).unwrap_or_default(),
         )]))
     }
+
+    #[tool(
+        description = "Get throughput stats for a WebSocket connection: messages per second, message and byte counts per direction, and the largest message size. Helps spot chatty or runaway connections.",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
+    async fn websocket_stats(
+        &self,
+        params: Parameters<WebSocketMessagesParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let connection_id = params.0.connection_id;
+        let Some(stats) = crate::storage::get_websocket_stats(&connection_id) else {
+            return Err(McpError::invalid_params(
+                format!("No WebSocket messages captured for connection {connection_id}"),
+                None,
+            ));
+        };
+
+        let result = serde_json::json!({
+            "connectionId": stats.connection_id,
+            "messageCount": stats.message_count(),
+            "messagesPerSecond": stats.messages_per_second(),
+            "clientToServer": {
+                "messages": stats.client_to_server_messages,
+                "bytes": stats.client_to_server_bytes,
+            },
+            "serverToClient": {
+                "messages": stats.server_to_client_messages,
+                "bytes": stats.server_to_client_bytes,
+            },
+            "maxMessageSize": stats.max_message_size,
+            "firstMessageAt": stats.first_message_at,
+            "lastMessageAt": stats.last_message_at,
+        });
+
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::to_string_pretty(&result).unwrap_or_default(),
+        )]))
+    }
 }
 
 // ============================================================================
@@ -1257,6 +2132,19 @@ impl ServerHandler for CheddarProxyServer {
                     },
                     annotations: None,
                 },
+                Annotated {
+                    raw: RawResource {
+                        uri: "proxy://rules".into(),
+                        name: "Active Rules".into(),
+                        title: None,
+                        description: Some("Breakpoint, rewrite and alert rules plus recorded responses served as mocks".into()),
+                        mime_type: Some("application/json".into()),
+                        size: None,
+                        icons: None,
+                        meta: None,
+                    },
+                    annotations: None,
+                },
             ],
             ..Default::default()
         }))
@@ -1329,6 +2217,15 @@ impl ServerHandler for CheddarProxyServer {
                 });
             }
 
+            if uri == "proxy://rules" {
+                return Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(
+                        serde_json::to_string_pretty(&rules_snapshot()).unwrap_or_default(),
+                        uri,
+                    )],
+                });
+            }
+
             // Handle transaction/{id} pattern
             if let Some(id) = uri.strip_prefix("proxy://transaction/") {
                 let tx = proxy_api::get_transaction_detail(id).await.map_err(|e| {
@@ -1379,4 +2276,265 @@ mod tests {
         assert_eq!(filter.method, Some(HttpMethod::Get));
         assert_eq!(filter.host_contains, Some("example.com".into()));
     }
+
+    #[tokio::test]
+    async fn export_certificate_writes_pem_and_der() {
+        let storage = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let server = CheddarProxyServer::new(McpServerConfig {
+            storage_path: storage.path().to_path_buf(),
+            allow_writes: true,
+            require_approval: false,
+            ..Default::default()
+        });
+
+        let pem_path = out.path().join("cheddar.pem");
+        let Json(response) = server
+            .export_certificate(Parameters(CertificateExportParams {
+                path: pem_path.to_string_lossy().to_string(),
+                include_der: true,
+            }))
+            .await
+            .expect("export succeeds");
+        assert_eq!(response.paths.len(), 2);
+
+        let ca =
+            crate::proxy::cert_manager::CertManager::new(storage.path().to_str().unwrap()).unwrap();
+        let expected_der = rustls_pemfile::certs(&mut ca.ca_cert_pem.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        let exported_pem = std::fs::read(&pem_path).unwrap();
+        let exported_der = rustls_pemfile::certs(&mut exported_pem.as_slice())
+            .next()
+            .expect("PEM contains a certificate")
+            .unwrap();
+        assert_eq!(exported_der, expected_der);
+
+        let der_file = std::fs::read(out.path().join("cheddar.crt")).unwrap();
+        assert_eq!(der_file, expected_der.as_ref());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn capture_session_waits_for_matching_transactions_and_exports() {
+        use crate::models::{HttpMethod, TransactionState};
+
+        let storage = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage.path().to_str().unwrap()).unwrap();
+
+        let producer = tokio::spawn(async {
+            let hosts = ["cdn.other.com", "api.example.com", "api.example.com"];
+            for (i, host) in hosts.into_iter().enumerate() {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                let mut tx = HttpTransaction::new(
+                    HttpMethod::Get,
+                    "https",
+                    host,
+                    443,
+                    &format!("/step{i}"),
+                    Default::default(),
+                );
+                crate::storage::persist_transaction(tx.clone())
+                    .await
+                    .unwrap();
+                tx.state = TransactionState::Completed;
+                tx.status_code = Some(200);
+                crate::storage::persist_transaction(tx).await.unwrap();
+            }
+        });
+
+        let filter = TransactionFilter {
+            host_contains: Some("example.com".to_string()),
+            ..Default::default()
+        };
+        let har_path = storage.path().join("session.har");
+        let har = har_path.to_string_lossy().to_string();
+        let response = run_capture_session(&filter, 2, std::time::Duration::from_secs(10), &har)
+            .await
+            .expect("session completes");
+        producer.await.unwrap();
+
+        assert_eq!(response.captured, 2);
+        assert!(!response.timed_out);
+        let exported: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&har_path).unwrap()).unwrap();
+        let urls: Vec<&str> = exported["log"]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["request"]["url"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://api.example.com/step1",
+                "https://api.example.com/step2"
+            ]
+        );
+
+        let short =
+            run_capture_session(&filter, 5, std::time::Duration::from_millis(300), &har).await;
+        assert!(
+            short.is_err(),
+            "nothing new arrives after the first session"
+        );
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn get_curl_reproduces_post_with_body() {
+        use crate::models::HttpMethod;
+
+        let storage = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage.path().to_str().unwrap()).unwrap();
+
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "https",
+            "api.example.com",
+            443,
+            "/orders",
+            [("Content-Type".to_string(), "application/json".to_string())].into(),
+        );
+        tx.request_body = Some(br#"{"note":"it's here"}"#.to_vec());
+        let id = tx.id.clone();
+        crate::storage::persist_transaction(tx).await.unwrap();
+
+        let server = CheddarProxyServer::new(McpServerConfig {
+            storage_path: storage.path().to_path_buf(),
+            ..Default::default()
+        });
+        let result = server
+            .get_curl(Parameters(TransactionDetailParams { id }))
+            .await
+            .expect("curl succeeds");
+        let curl = &result.content[0].as_text().expect("text content").text;
+
+        assert!(curl.starts_with("curl -X POST 'https://api.example.com/orders'"));
+        assert!(curl.contains("-H 'Content-Type: application/json'"));
+        assert!(curl.contains(r#"--data-binary '{"note":"it'\''s here"}'"#));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn error_summary_surfaces_failing_endpoint() {
+        use crate::models::{HttpMethod, TransactionState};
+
+        let storage = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage.path().to_str().unwrap()).unwrap();
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let cases = [
+            ("/health", 200, None),
+            ("/health", 200, None),
+            ("/orders?page=1", 500, Some("upstream timeout")),
+            ("/orders?page=2", 500, Some("upstream timeout")),
+            ("/orders", 200, None),
+            ("/login", 401, Some("bad token")),
+        ];
+        for (i, (path, status, body)) in cases.iter().enumerate() {
+            let mut tx = HttpTransaction::new(
+                HttpMethod::Get,
+                "https",
+                "api.example.com",
+                443,
+                path,
+                Default::default(),
+            );
+            tx.id = format!("tx-{i}");
+            tx.status_code = Some(*status);
+            tx.state = TransactionState::Completed;
+            tx.timing.start_time = now - 1_000 + i as i64;
+            tx.response_body = body.map(|b| b.as_bytes().to_vec());
+            crate::storage::persist_transaction(tx).await.unwrap();
+        }
+
+        let server = CheddarProxyServer::new(McpServerConfig {
+            storage_path: storage.path().to_path_buf(),
+            ..Default::default()
+        });
+        let Json(summary) = server
+            .error_summary(Parameters(ErrorSummaryParams {
+                start_time: Some(
+                    chrono::DateTime::from_timestamp_millis(now - 60_000)
+                        .unwrap()
+                        .to_rfc3339(),
+                ),
+                host: Some("example".into()),
+                ..Default::default()
+            }))
+            .await
+            .expect("summary succeeds");
+
+        assert_eq!(summary.total_requests, 6);
+        assert_eq!(summary.total_errors, 3);
+        let top = &summary.top_endpoints[0];
+        assert_eq!(top.path, "/orders");
+        assert_eq!(top.server_errors, 2);
+        assert_eq!(summary.error_classes[0].status, 500);
+        assert_eq!(summary.error_classes[0].sample_ids.len(), 2);
+        assert_eq!(summary.common_snippets[0].snippet, "upstream timeout");
+        assert_eq!(summary.common_snippets[0].count, 2);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn rules_resource_lists_breakpoint_rules() {
+        let rule = breakpoints::add_breakpoint_rule(BreakpointRuleInput {
+            host_contains: Some("rules.example.com".into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let snapshot = rules_snapshot();
+        breakpoints::remove_breakpoint_rule(&rule.id);
+
+        let listed = snapshot["breakpoints"].as_array().unwrap();
+        let entry = listed
+            .iter()
+            .find(|r| r["id"] == rule.id.as_str())
+            .expect("new rule is listed");
+        assert_eq!(entry["host_contains"], "rules.example.com");
+        assert!(snapshot["rewrites"].is_array());
+        assert!(snapshot["recordedResponses"].is_array());
+    }
+
+    #[test]
+    fn error_summary_groups_normalized_paths() {
+        use crate::models::HttpMethod;
+
+        let errors: Vec<HttpTransaction> = ["/A//b", "/a/b?x=1"]
+            .iter()
+            .map(|path| {
+                let mut tx = HttpTransaction::new(
+                    HttpMethod::Get,
+                    "https",
+                    "api.example.com",
+                    443,
+                    path,
+                    Default::default(),
+                );
+                tx.status_code = Some(500);
+                tx
+            })
+            .collect();
+
+        let raw = summarize_errors(&errors, 2, 2, 10, PathNormalization::default());
+        assert_eq!(raw.top_endpoints.len(), 2);
+
+        let normalized = summarize_errors(
+            &errors,
+            2,
+            2,
+            10,
+            PathNormalization {
+                collapse_slashes: true,
+                lowercase: true,
+            },
+        );
+        assert_eq!(normalized.top_endpoints.len(), 1);
+        assert_eq!(normalized.top_endpoints[0].path, "/a/b");
+        assert_eq!(normalized.top_endpoints[0].server_errors, 2);
+    }
 }
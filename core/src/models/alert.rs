@@ -0,0 +1,53 @@
+//! Models for alert rules evaluated against captured traffic.
+
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// What makes a finished transaction fire an alert.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub enum AlertCondition {
+    /// The response status is within `min..=max` (e.g. 500..=599)
+    StatusBetween { min: u16, max: u16 },
+    /// The transaction took longer than `ms` milliseconds in total
+    SlowerThan { ms: u32 },
+    /// The request failed without a response (connection error, timeout)
+    Failed,
+}
+
+/// Input payload for creating alert rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct AlertRuleInput {
+    pub name: String,
+    pub enabled: bool,
+    pub host_contains: Option<String>,
+    pub path_contains: Option<String>,
+    pub condition: AlertCondition,
+}
+
+/// Alert rule stored on the backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct AlertRule {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub host_contains: Option<String>,
+    pub path_contains: Option<String>,
+    pub condition: AlertCondition,
+}
+
+/// A rule firing for one transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct Alert {
+    pub id: String,
+    pub rule_id: String,
+    pub rule_name: String,
+    pub transaction_id: String,
+    /// When the alert fired (Unix millis)
+    pub fired_at: i64,
+    /// e.g. "GET https://api.example.com/orders returned 503"
+    pub message: String,
+}
@@ -13,6 +13,12 @@ pub struct BreakpointRuleInput {
     pub method: Option<HttpMethod>,
     pub host_contains: Option<String>,
     pub path_contains: Option<String>,
+    /// Regex the host must match (case-insensitive), e.g. `^api\.`
+    #[serde(default)]
+    pub host_regex: Option<String>,
+    /// Regex the path must match, e.g. `^/api/v\d+/users/\d+`
+    #[serde(default)]
+    pub path_regex: Option<String>,
 }
 
 impl Default for BreakpointRuleInput {
@@ -22,6 +28,8 @@ impl Default for BreakpointRuleInput {
             method: None,
             host_contains: None,
             path_contains: None,
+            host_regex: None,
+            path_regex: None,
         }
     }
 }
@@ -35,6 +43,10 @@ pub struct BreakpointRule {
     pub method: Option<HttpMethod>,
     pub host_contains: Option<String>,
     pub path_contains: Option<String>,
+    #[serde(default)]
+    pub host_regex: Option<String>,
+    #[serde(default)]
+    pub path_regex: Option<String>,
 }
 
 /// Request edits applied when resuming a breakpoint.
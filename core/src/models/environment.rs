@@ -0,0 +1,25 @@
+//! Named replay environments (e.g. staging, prod) that retarget a captured
+//! request at another deployment.
+
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A deployment to replay captured requests against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct ReplayEnvironment {
+    /// Unique name shown in the UI (e.g. "staging")
+    pub name: String,
+    /// Scheme, host, optional port and optional path prefix that replace the
+    /// captured request's origin (e.g. `https://staging.example.com/api`)
+    pub base_url: String,
+    /// Headers added to every replay, replacing captured headers of the same
+    /// name (case-insensitive). Typically auth for the environment.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Don't forward the captured `Cookie` and `Authorization` headers to this
+    /// environment. Without it they are sent unless `headers` replaces them.
+    #[serde(default)]
+    pub drop_credentials: bool,
+}
@@ -0,0 +1,64 @@
+//! Export options
+//!
+//! Settings shared by the file exporters (HAR, etc.). Captured timestamps are
+//! always stored as UTC milliseconds; these only affect how they are rendered.
+
+use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// Timezone used for wall-clock timestamps in exported files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[frb]
+pub enum ExportTimezone {
+    /// Coordinated Universal Time (`Z` suffix)
+    #[default]
+    Utc,
+    /// The machine's local timezone, including daylight saving for each timestamp
+    Local,
+    /// A fixed offset from UTC in minutes (e.g. 330 for +05:30)
+    Offset { minutes: i32 },
+}
+
+/// File format for exported WebSocket sessions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[frb]
+pub enum WebSocketExportFormat {
+    /// One JSON object per message (NDJSON)
+    #[default]
+    Jsonl,
+    /// Human-readable transcript, one line per message
+    Text,
+}
+
+impl ExportTimezone {
+    /// Reject fixed offsets outside chrono's range (strictly within ±24 hours).
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let ExportTimezone::Offset { minutes } = *self {
+            minutes
+                .checked_mul(60)
+                .and_then(FixedOffset::east_opt)
+                .ok_or_else(|| anyhow::anyhow!("UTC offset out of range: {minutes} minutes"))?;
+        }
+        Ok(())
+    }
+
+    /// Format a Unix timestamp (ms) as RFC 3339 with this zone's offset.
+    pub fn format_millis(&self, millis: i64) -> String {
+        let utc = Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .unwrap_or_else(Utc::now);
+        match self {
+            ExportTimezone::Utc => utc.to_rfc3339(),
+            ExportTimezone::Local => utc.with_timezone(&Local).to_rfc3339(),
+            ExportTimezone::Offset { minutes } => {
+                let offset = FixedOffset::east_opt(minutes * 60)
+                    .unwrap_or_else(|| FixedOffset::east_opt(0).expect("zero offset"));
+                DateTime::<FixedOffset>::from(utc)
+                    .with_timezone(&offset)
+                    .to_rfc3339()
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! gRPC-Web message framing
+//!
+//! Splits captured gRPC-Web bodies into length-prefixed frames and decodes the
+//! trailer frame that gRPC-Web carries in the body instead of HTTP trailers.
+
+use base64::{engine::general_purpose, Engine as _};
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// Flag bit marking a frame whose payload is the trailer block
+const TRAILER_FLAG: u8 = 0x80;
+/// Flag bit marking a compressed message payload
+const COMPRESSED_FLAG: u8 = 0x01;
+
+/// One length-prefixed gRPC-Web frame
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct GrpcFrame {
+    /// Trailer frame (headers such as grpc-status) rather than a message
+    pub is_trailer: bool,
+    /// Payload compressed with the negotiated grpc-encoding
+    pub compressed: bool,
+    /// Declared payload length (may exceed `payload` if the capture was truncated)
+    pub length: u32,
+    pub payload: Vec<u8>,
+    /// Parsed `name: value` lines of a trailer frame
+    pub trailers: Vec<(String, String)>,
+}
+
+/// Whether a Content-Type is gRPC-Web, and if so whether it is the base64 `-text` variant
+pub fn grpc_web_kind(content_type: &str) -> Option<bool> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    let subtype = essence.strip_prefix("application/grpc-web")?;
+    let subtype = subtype.split('+').next().unwrap_or("");
+    match subtype {
+        "" => Some(false),
+        "-text" => Some(true),
+        _ => None,
+    }
+}
+
+/// Decode a gRPC-Web body into frames. `text` bodies are base64 first; they may be
+/// a concatenation of separately padded chunks, one per flushed write.
+pub fn decode_grpc_web(body: &[u8], text: bool) -> Vec<GrpcFrame> {
+    let decoded;
+    let bytes = if text {
+        decoded = decode_base64_chunks(body);
+        decoded.as_slice()
+    } else {
+        body
+    };
+
+    let mut frames = Vec::new();
+    let mut rest = bytes;
+    while rest.len() >= 5 {
+        let flags = rest[0];
+        let length = u32::from_be_bytes([rest[1], rest[2], rest[3], rest[4]]);
+        let end = (5 + length as usize).min(rest.len());
+        let payload = rest[5..end].to_vec();
+        rest = &rest[end..];
+
+        let is_trailer = flags & TRAILER_FLAG != 0;
+        let trailers = if is_trailer {
+            parse_trailers(&payload)
+        } else {
+            Vec::new()
+        };
+        frames.push(GrpcFrame {
+            is_trailer,
+            compressed: flags & COMPRESSED_FLAG != 0,
+            length,
+            payload,
+            trailers,
+        });
+    }
+    frames
+}
+
+fn decode_base64_chunks(body: &[u8]) -> Vec<u8> {
+    let clean: Vec<u8> = body
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let mut out = Vec::with_capacity(clean.len() * 3 / 4);
+    let mut start = 0;
+    let mut i = 0;
+    while i < clean.len() {
+        if clean[i] == b'=' {
+            while i < clean.len() && clean[i] == b'=' {
+                i += 1;
+            }
+            if let Ok(chunk) = general_purpose::STANDARD.decode(&clean[start..i]) {
+                out.extend_from_slice(&chunk);
+            }
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    if start < clean.len() {
+        if let Ok(chunk) = general_purpose::STANDARD_NO_PAD.decode(&clean[start..]) {
+            out.extend_from_slice(&chunk);
+        }
+    }
+    out
+}
+
+fn parse_trailers(payload: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(payload)
+        .split("\r\n")
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_grpc_web_text_with_trailer_frame() {
+        let message = b"\x0a\x05hello";
+        let trailer = b"grpc-status: 5\r\ngrpc-message: not found\r\n";
+        let mut data_frame = vec![0x00];
+        data_frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        data_frame.extend_from_slice(message);
+        let mut trailer_frame = vec![0x80];
+        trailer_frame.extend_from_slice(&(trailer.len() as u32).to_be_bytes());
+        trailer_frame.extend_from_slice(trailer);
+
+        // Servers base64 each flush separately, so padding can appear mid-body.
+        let body = format!(
+            "{}{}",
+            general_purpose::STANDARD.encode(&data_frame),
+            general_purpose::STANDARD.encode(&trailer_frame)
+        );
+        assert_eq!(grpc_web_kind("application/grpc-web-text+proto"), Some(true));
+
+        let frames = decode_grpc_web(body.as_bytes(), true);
+        assert_eq!(frames.len(), 2);
+        assert!(!frames[0].is_trailer);
+        assert_eq!(frames[0].payload, message);
+        assert!(frames[1].is_trailer);
+        assert_eq!(
+            frames[1].trailers,
+            vec![
+                ("grpc-status".to_string(), "5".to_string()),
+                ("grpc-message".to_string(), "not found".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! JSON-RPC 2.0 over HTTP
+//!
+//! Recognizes JSON-RPC request and response bodies (single calls and batches)
+//! so transactions can be labelled and filtered by RPC method.
+
+use serde_json::Value;
+
+/// One call from a JSON-RPC request body
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcCall {
+    pub method: String,
+    /// The `id` rendered as JSON text (`1`, `"abc"`); `None` for notifications
+    pub id: Option<String>,
+}
+
+/// One reply from a JSON-RPC response body
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcReply {
+    pub id: Option<String>,
+    /// `error.code` when the call failed
+    pub error_code: Option<i64>,
+}
+
+/// Parse the calls in a JSON-RPC request body. Empty if the body isn't JSON-RPC.
+pub fn parse_jsonrpc_request(body: &[u8]) -> Vec<JsonRpcCall> {
+    messages(body)
+        .into_iter()
+        .filter_map(|msg| {
+            let method = msg.get("method")?.as_str()?.to_string();
+            Some(JsonRpcCall {
+                method,
+                id: msg.get("id").map(Value::to_string),
+            })
+        })
+        .collect()
+}
+
+/// Parse the replies in a JSON-RPC response body. Empty if the body isn't JSON-RPC.
+pub fn parse_jsonrpc_response(body: &[u8]) -> Vec<JsonRpcReply> {
+    messages(body)
+        .into_iter()
+        .filter(|msg| msg.get("result").is_some() || msg.get("error").is_some())
+        .map(|msg| JsonRpcReply {
+            id: msg
+                .get("id")
+                .filter(|id| !id.is_null())
+                .map(Value::to_string),
+            error_code: msg
+                .get("error")
+                .and_then(|e| e.get("code"))
+                .and_then(Value::as_i64),
+        })
+        .collect()
+}
+
+/// Objects carrying `"jsonrpc": "2.0"`, from a single message or a batch array.
+fn messages(body: &[u8]) -> Vec<Value> {
+    let Ok(value) = serde_json::from_slice::<Value>(body) else {
+        return Vec::new();
+    };
+    let items = match value {
+        Value::Array(items) => items,
+        single => vec![single],
+    };
+    items
+        .into_iter()
+        .filter(|msg| msg.get("jsonrpc").and_then(Value::as_str) == Some("2.0"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{HttpMethod, HttpTransaction};
+    use std::collections::HashMap;
+
+    #[test]
+    fn links_jsonrpc_request_to_response_by_id() {
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "https",
+            "rpc.example.com",
+            443,
+            "/",
+            HashMap::new(),
+        );
+        tx.request_body = Some(
+            br#"{"jsonrpc":"2.0","method":"eth_getBalance","params":["0x1"],"id":7}"#.to_vec(),
+        );
+        // Batched replies may arrive in any order; only the matching id counts.
+        tx.response_body = Some(
+            br#"[{"jsonrpc":"2.0","result":"0x0","id":3},
+                 {"jsonrpc":"2.0","error":{"code":-32602,"message":"bad params"},"id":7}]"#
+                .to_vec(),
+        );
+        tx.refresh_jsonrpc();
+
+        assert_eq!(tx.jsonrpc_method.as_deref(), Some("eth_getBalance"));
+        assert_eq!(tx.jsonrpc_id.as_deref(), Some("7"));
+        assert_eq!(tx.jsonrpc_error_code, Some(-32602));
+
+        tx.request_body = Some(br#"{"method":"not-rpc","id":1}"#.to_vec());
+        tx.refresh_jsonrpc();
+        assert_eq!(tx.jsonrpc_method, None);
+    }
+}
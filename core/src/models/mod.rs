@@ -2,9 +2,25 @@
 //!
 //! These models are shared between Rust and Flutter via flutter_rust_bridge.
 
+pub mod alert;
 pub mod breakpoint;
+pub mod environment;
+pub mod export;
+pub mod grpc;
+pub mod jsonrpc;
+pub mod pagination;
+pub mod protobuf;
+pub mod rewrite;
+pub mod throttle;
 pub mod transaction;
+pub mod transcript;
+pub mod upstream_tls;
 pub mod websocket;
 
+pub use environment::*;
+pub use export::*;
+pub use grpc::*;
+pub use pagination::*;
 pub use transaction::*;
+pub use transcript::*;
 pub use websocket::*;
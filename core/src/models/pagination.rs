@@ -0,0 +1,166 @@
+//! API pagination hints
+//!
+//! Recognizes the common ways a response points at the next page: an RFC 8288
+//! `Link: <...>; rel="next"` header, a `next` URL in a JSON body, or an opaque
+//! cursor/page token in a JSON body that goes back as a query parameter.
+
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where the next-page hint was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub enum PaginationStyle {
+    /// `Link` response header with `rel="next"`
+    LinkHeader,
+    /// A next-page URL in the JSON body
+    BodyUrl,
+    /// A cursor or page token in the JSON body
+    BodyCursor,
+}
+
+/// How to fetch the page after this response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct PaginationInfo {
+    pub style: PaginationStyle,
+    /// Next page URL, absolute or relative to the request URL
+    pub next_url: Option<String>,
+    /// Opaque cursor for [`PaginationStyle::BodyCursor`]
+    pub next_cursor: Option<String>,
+    /// Query parameter that carries `next_cursor` on the next request
+    pub cursor_param: Option<String>,
+}
+
+/// JSON paths (as key sequences) holding a next-page URL
+const URL_KEYS: &[&[&str]] = &[
+    &["next"],
+    &["next_page_url"],
+    &["nextPageUrl"],
+    &["links", "next"],
+    &["_links", "next", "href"],
+    &["paging", "next"],
+    &["pagination", "next"],
+    &["meta", "next"],
+];
+
+/// JSON paths holding a cursor, with the query parameter it is sent back in
+const CURSOR_KEYS: &[(&[&str], &str)] = &[
+    (&["next_cursor"], "cursor"),
+    (&["nextCursor"], "cursor"),
+    (&["cursor", "next"], "cursor"),
+    (&["pagination", "next_cursor"], "cursor"),
+    (&["meta", "next_cursor"], "cursor"),
+    (&["response_metadata", "next_cursor"], "cursor"),
+    (&["paging", "cursors", "after"], "after"),
+    (&["nextPageToken"], "pageToken"),
+    (&["next_page_token"], "page_token"),
+];
+
+/// Detect a next-page hint. The `Link` header wins over the body; an empty or
+/// null `next` in the body means this is the last page.
+pub fn detect_pagination<'a>(
+    mut headers: impl Iterator<Item = (&'a str, &'a str)>,
+    content_type: Option<&str>,
+    body: Option<&[u8]>,
+) -> Option<PaginationInfo> {
+    let link_next = headers
+        .find(|(name, _)| name.eq_ignore_ascii_case("link"))
+        .and_then(|(_, value)| link_rel_next(value));
+    if let Some(url) = link_next {
+        return Some(PaginationInfo {
+            style: PaginationStyle::LinkHeader,
+            next_url: Some(url),
+            next_cursor: None,
+            cursor_param: None,
+        });
+    }
+
+    let is_json = content_type.is_some_and(|ct| ct.to_ascii_lowercase().contains("json"));
+    if !is_json {
+        return None;
+    }
+    let json: Value = serde_json::from_slice(body?).ok()?;
+    let lookup = |path: &[&str]| {
+        path.iter()
+            .try_fold(&json, |value, key| value.get(key))
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    if let Some(url) = URL_KEYS.iter().find_map(|path| lookup(path)) {
+        // A bare `next` sometimes holds a cursor rather than a URL.
+        if url.contains('/') || url.contains('?') {
+            return Some(PaginationInfo {
+                style: PaginationStyle::BodyUrl,
+                next_url: Some(url),
+                next_cursor: None,
+                cursor_param: None,
+            });
+        }
+    }
+    CURSOR_KEYS.iter().find_map(|(path, param)| {
+        lookup(path).map(|cursor| PaginationInfo {
+            style: PaginationStyle::BodyCursor,
+            next_url: None,
+            next_cursor: Some(cursor),
+            cursor_param: Some(param.to_string()),
+        })
+    })
+}
+
+/// The `rel="next"` target of a `Link` header value
+fn link_rel_next(value: &str) -> Option<String> {
+    value.split(',').find_map(|link| {
+        let (target, params) = link.trim().split_once('>')?;
+        let target = target.trim().strip_prefix('<')?;
+        let is_next = params.split(';').any(|param| {
+            let Some((key, rel)) = param.split_once('=') else {
+                return false;
+            };
+            key.trim().eq_ignore_ascii_case("rel")
+                && rel
+                    .trim()
+                    .trim_matches('"')
+                    .split_ascii_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case("next"))
+        });
+        is_next.then(|| target.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_link_header_and_body_hints() {
+        let link = r#"<https://api.test/items?page=1>; rel="prev", <https://api.test/items?page=3>; rel="next""#;
+        let info = detect_pagination([("Link", link)].into_iter(), None, None).unwrap();
+        assert_eq!(info.style, PaginationStyle::LinkHeader);
+        assert_eq!(
+            info.next_url.as_deref(),
+            Some("https://api.test/items?page=3")
+        );
+
+        let body = br#"{"data":[],"links":{"next":"/items?page=2"}}"#;
+        let info =
+            detect_pagination(std::iter::empty(), Some("application/json"), Some(body)).unwrap();
+        assert_eq!(info.style, PaginationStyle::BodyUrl);
+        assert_eq!(info.next_url.as_deref(), Some("/items?page=2"));
+
+        let body = br#"{"items":[],"response_metadata":{"next_cursor":"dXNlcjpVMDYx"}}"#;
+        let info =
+            detect_pagination(std::iter::empty(), Some("application/json"), Some(body)).unwrap();
+        assert_eq!(info.style, PaginationStyle::BodyCursor);
+        assert_eq!(info.next_cursor.as_deref(), Some("dXNlcjpVMDYx"));
+        assert_eq!(info.cursor_param.as_deref(), Some("cursor"));
+
+        let last = br#"{"data":[],"next":null,"next_cursor":""}"#;
+        assert!(
+            detect_pagination(std::iter::empty(), Some("application/json"), Some(last)).is_none()
+        );
+    }
+}
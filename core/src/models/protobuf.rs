@@ -0,0 +1,454 @@
+//! Protobuf wire-format decoding for binary payloads.
+//!
+//! Without a schema, the wire format still reveals field numbers and wire
+//! types, so payloads are shown as a list of `{field, wire_type, value}`
+//! entries with length-delimited values guessed as text, nested message or
+//! bytes. With a `FileDescriptorSet` (as produced by
+//! `protoc --descriptor_set_out`), fields get their names and declared types.
+
+use anyhow::{anyhow, bail};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Nested messages deeper than this are shown as bytes
+const MAX_DEPTH: usize = 16;
+
+// FieldDescriptorProto.Type values (descriptor.proto)
+const TYPE_DOUBLE: i32 = 1;
+const TYPE_FLOAT: i32 = 2;
+const TYPE_INT64: i32 = 3;
+const TYPE_INT32: i32 = 5;
+const TYPE_FIXED64: i32 = 6;
+const TYPE_FIXED32: i32 = 7;
+const TYPE_BOOL: i32 = 8;
+const TYPE_STRING: i32 = 9;
+const TYPE_MESSAGE: i32 = 11;
+const TYPE_BYTES: i32 = 12;
+const TYPE_UINT32: i32 = 13;
+const TYPE_ENUM: i32 = 14;
+const TYPE_SFIXED32: i32 = 15;
+const TYPE_SFIXED64: i32 = 16;
+const TYPE_SINT32: i32 = 17;
+const TYPE_SINT64: i32 = 18;
+/// FieldDescriptorProto.Label LABEL_REPEATED
+const LABEL_REPEATED: i32 = 3;
+
+/// One field as it appears on the wire
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl WireValue<'_> {
+    fn wire_type_name(&self) -> &'static str {
+        match self {
+            WireValue::Varint(_) => "varint",
+            WireValue::Fixed64(_) => "i64",
+            WireValue::Bytes(_) => "len",
+            WireValue::Fixed32(_) => "i32",
+        }
+    }
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn read_fixed<const N: usize>(buf: &[u8], pos: &mut usize) -> Option<[u8; N]> {
+    let bytes = buf.get(*pos..*pos + N)?.try_into().ok()?;
+    *pos += N;
+    Some(bytes)
+}
+
+/// Split `buf` into (field number, value) pairs. Returns None unless the whole
+/// buffer is valid wire format (groups are not supported).
+pub fn parse_wire_fields(buf: &[u8]) -> Option<Vec<(u32, WireValue<'_>)>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = read_varint(buf, &mut pos)?;
+        let number = u32::try_from(key >> 3).ok().filter(|n| *n > 0)?;
+        let value = match key & 0x7 {
+            0 => WireValue::Varint(read_varint(buf, &mut pos)?),
+            1 => WireValue::Fixed64(u64::from_le_bytes(read_fixed(buf, &mut pos)?)),
+            2 => {
+                let len = usize::try_from(read_varint(buf, &mut pos)?).ok()?;
+                let bytes = buf.get(pos..pos.checked_add(len)?)?;
+                pos += len;
+                WireValue::Bytes(bytes)
+            }
+            5 => WireValue::Fixed32(u32::from_le_bytes(read_fixed(buf, &mut pos)?)),
+            _ => return None,
+        };
+        fields.push((number, value));
+    }
+    Some(fields)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn printable_text(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    text.chars()
+        .all(|c| !c.is_control() || c.is_whitespace())
+        .then_some(text)
+}
+
+/// Decode a payload with no schema. Returns None if it is not protobuf.
+pub fn decode_without_schema(buf: &[u8]) -> Option<Value> {
+    if buf.is_empty() {
+        return None;
+    }
+    decode_fields_without_schema(buf, 0)
+}
+
+fn decode_fields_without_schema(buf: &[u8], depth: usize) -> Option<Value> {
+    let fields = parse_wire_fields(buf)?;
+    let entries = fields
+        .into_iter()
+        .map(|(number, value)| {
+            let decoded = match value {
+                WireValue::Varint(v) => json!(v),
+                WireValue::Fixed64(v) => json!(v),
+                WireValue::Fixed32(v) => json!(v),
+                WireValue::Bytes(bytes) => guess_length_delimited(bytes, depth),
+            };
+            json!({
+                "field": number,
+                "wire_type": value.wire_type_name(),
+                "value": decoded,
+            })
+        })
+        .collect();
+    Some(Value::Array(entries))
+}
+
+/// Length-delimited values may be strings, nested messages or raw bytes.
+fn guess_length_delimited(bytes: &[u8], depth: usize) -> Value {
+    if let Some(text) = printable_text(bytes) {
+        return json!(text);
+    }
+    if depth + 1 < MAX_DEPTH && !bytes.is_empty() {
+        if let Some(nested) = decode_fields_without_schema(bytes, depth + 1) {
+            return json!({ "message": nested });
+        }
+    }
+    json!({ "hex": hex(bytes) })
+}
+
+#[derive(Debug, Clone)]
+struct FieldDescriptor {
+    name: String,
+    number: u32,
+    kind: i32,
+    /// Fully qualified message/enum name without the leading dot
+    type_name: Option<String>,
+    repeated: bool,
+}
+
+/// Message types loaded from a `FileDescriptorSet`, keyed by full name
+/// (`package.Message`, `package.Outer.Inner`).
+#[derive(Debug, Clone, Default)]
+pub struct DescriptorPool {
+    messages: HashMap<String, Vec<FieldDescriptor>>,
+}
+
+impl DescriptorPool {
+    /// Parse a serialized `google.protobuf.FileDescriptorSet`
+    pub fn from_descriptor_set(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut pool = DescriptorPool::default();
+        let files =
+            parse_wire_fields(bytes).ok_or_else(|| anyhow!("not a serialized descriptor set"))?;
+        for (number, value) in files {
+            if let (1, WireValue::Bytes(file)) = (number, value) {
+                pool.add_file(file)?;
+            }
+        }
+        if pool.messages.is_empty() {
+            bail!("descriptor set contains no message types");
+        }
+        Ok(pool)
+    }
+
+    /// Whether `message_type` (with or without a leading dot) is known
+    pub fn contains(&self, message_type: &str) -> bool {
+        self.messages
+            .contains_key(message_type.trim_start_matches('.'))
+    }
+
+    fn add_file(&mut self, file: &[u8]) -> anyhow::Result<()> {
+        let fields = parse_wire_fields(file).ok_or_else(|| anyhow!("malformed file descriptor"))?;
+        let package = fields
+            .iter()
+            .find_map(|(n, v)| match (n, v) {
+                (2, WireValue::Bytes(b)) => std::str::from_utf8(b).ok(),
+                _ => None,
+            })
+            .unwrap_or_default()
+            .to_string();
+        for (number, value) in &fields {
+            if let (4, WireValue::Bytes(message)) = (number, value) {
+                self.add_message(&package, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_message(&mut self, scope: &str, message: &[u8]) -> anyhow::Result<()> {
+        let parts =
+            parse_wire_fields(message).ok_or_else(|| anyhow!("malformed message descriptor"))?;
+        let name = parts
+            .iter()
+            .find_map(|(n, v)| match (n, v) {
+                (1, WireValue::Bytes(b)) => std::str::from_utf8(b).ok(),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("message descriptor without a name"))?;
+        let full_name = if scope.is_empty() {
+            name.to_string()
+        } else {
+            format!("{scope}.{name}")
+        };
+
+        let mut fields = Vec::new();
+        for (number, value) in &parts {
+            match (number, value) {
+                (2, WireValue::Bytes(field)) => fields.push(parse_field_descriptor(field)?),
+                (3, WireValue::Bytes(nested)) => self.add_message(&full_name, nested)?,
+                _ => {}
+            }
+        }
+        self.messages.insert(full_name, fields);
+        Ok(())
+    }
+
+    /// Decode `buf` as `message_type` into a JSON object keyed by field name.
+    /// Fields missing from the schema are keyed by their number.
+    pub fn decode(&self, message_type: &str, buf: &[u8]) -> Option<Value> {
+        self.decode_message(message_type.trim_start_matches('.'), buf, 0)
+    }
+
+    fn decode_message(&self, message_type: &str, buf: &[u8], depth: usize) -> Option<Value> {
+        let schema = self.messages.get(message_type)?;
+        let mut out = Map::new();
+        for (number, value) in parse_wire_fields(buf)? {
+            let Some(field) = schema.iter().find(|f| f.number == number) else {
+                let raw = match value {
+                    WireValue::Varint(v) => json!(v),
+                    WireValue::Fixed64(v) => json!(v),
+                    WireValue::Fixed32(v) => json!(v),
+                    WireValue::Bytes(bytes) => guess_length_delimited(bytes, depth),
+                };
+                push_value(&mut out, &number.to_string(), raw, true);
+                continue;
+            };
+            let values = match (&value, field.kind) {
+                // Packed repeated scalars arrive as one length-delimited blob.
+                (WireValue::Bytes(bytes), kind)
+                    if field.repeated
+                        && !matches!(kind, TYPE_STRING | TYPE_BYTES | TYPE_MESSAGE) =>
+                {
+                    decode_packed(bytes, kind)?
+                }
+                _ => vec![self.typed_value(field, value, depth)?],
+            };
+            for decoded in values {
+                push_value(&mut out, &field.name, decoded, field.repeated);
+            }
+        }
+        Some(Value::Object(out))
+    }
+
+    fn typed_value(
+        &self,
+        field: &FieldDescriptor,
+        value: WireValue,
+        depth: usize,
+    ) -> Option<Value> {
+        Some(match (field.kind, value) {
+            (TYPE_MESSAGE, WireValue::Bytes(bytes)) => {
+                let nested = field.type_name.as_deref()?;
+                if depth + 1 >= MAX_DEPTH {
+                    json!({ "hex": hex(bytes) })
+                } else {
+                    self.decode_message(nested, bytes, depth + 1)?
+                }
+            }
+            (TYPE_STRING, WireValue::Bytes(bytes)) => json!(String::from_utf8_lossy(bytes)),
+            (TYPE_BYTES, WireValue::Bytes(bytes)) => json!({ "hex": hex(bytes) }),
+            (kind, WireValue::Varint(v)) => varint_value(kind, v),
+            (kind, WireValue::Fixed64(v)) => fixed64_value(kind, v),
+            (kind, WireValue::Fixed32(v)) => fixed32_value(kind, v),
+            (_, WireValue::Bytes(bytes)) => json!({ "hex": hex(bytes) }),
+        })
+    }
+}
+
+fn parse_field_descriptor(bytes: &[u8]) -> anyhow::Result<FieldDescriptor> {
+    let parts = parse_wire_fields(bytes).ok_or_else(|| anyhow!("malformed field descriptor"))?;
+    let mut field = FieldDescriptor {
+        name: String::new(),
+        number: 0,
+        kind: 0,
+        type_name: None,
+        repeated: false,
+    };
+    for (number, value) in parts {
+        match (number, value) {
+            (1, WireValue::Bytes(b)) => field.name = String::from_utf8_lossy(b).into_owned(),
+            (3, WireValue::Varint(v)) => field.number = v as u32,
+            (4, WireValue::Varint(v)) => field.repeated = v as i32 == LABEL_REPEATED,
+            (5, WireValue::Varint(v)) => field.kind = v as i32,
+            (6, WireValue::Bytes(b)) => {
+                field.type_name = Some(
+                    String::from_utf8_lossy(b)
+                        .trim_start_matches('.')
+                        .to_string(),
+                )
+            }
+            _ => {}
+        }
+    }
+    if field.name.is_empty() || field.number == 0 {
+        bail!("field descriptor without a name or number");
+    }
+    Ok(field)
+}
+
+fn push_value(out: &mut Map<String, Value>, key: &str, value: Value, repeated: bool) {
+    if !repeated {
+        out.insert(key.to_string(), value);
+        return;
+    }
+    match out.entry(key.to_string()).or_insert_with(|| json!([])) {
+        Value::Array(items) => items.push(value),
+        other => *other = json!([other.clone(), value]),
+    }
+}
+
+fn decode_packed(bytes: &[u8], kind: i32) -> Option<Vec<Value>> {
+    let mut values = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        values.push(match kind {
+            TYPE_DOUBLE | TYPE_FIXED64 | TYPE_SFIXED64 => {
+                fixed64_value(kind, u64::from_le_bytes(read_fixed(bytes, &mut pos)?))
+            }
+            TYPE_FLOAT | TYPE_FIXED32 | TYPE_SFIXED32 => {
+                fixed32_value(kind, u32::from_le_bytes(read_fixed(bytes, &mut pos)?))
+            }
+            _ => varint_value(kind, read_varint(bytes, &mut pos)?),
+        });
+    }
+    Some(values)
+}
+
+fn varint_value(kind: i32, v: u64) -> Value {
+    match kind {
+        TYPE_INT32 | TYPE_ENUM => json!(v as i32),
+        TYPE_INT64 => json!(v as i64),
+        TYPE_UINT32 => json!(v as u32),
+        TYPE_BOOL => json!(v != 0),
+        TYPE_SINT32 => json!(((v >> 1) as i32) ^ -((v & 1) as i32)),
+        TYPE_SINT64 => json!(((v >> 1) as i64) ^ -((v & 1) as i64)),
+        _ => json!(v),
+    }
+}
+
+fn fixed64_value(kind: i32, v: u64) -> Value {
+    match kind {
+        TYPE_DOUBLE => json!(f64::from_bits(v)),
+        TYPE_SFIXED64 => json!(v as i64),
+        _ => json!(v),
+    }
+}
+
+fn fixed32_value(kind: i32, v: u32) -> Value {
+    match kind {
+        TYPE_FLOAT => json!(f32::from_bits(v)),
+        TYPE_SFIXED32 => json!(v as i32),
+        _ => json!(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_field_numbers_and_wire_types_without_schema() {
+        // field 1: varint 150, field 2: "testing", field 3: nested { field 1: 1 }
+        let frame = [
+            0x08, 0x96, 0x01, 0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g', 0x1a, 0x02,
+            0x08, 0x01,
+        ];
+        let decoded = decode_without_schema(&frame).expect("valid protobuf");
+        let pairs: Vec<(u64, &str)> = decoded
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| {
+                (
+                    f["field"].as_u64().unwrap(),
+                    f["wire_type"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(pairs, vec![(1, "varint"), (2, "len"), (3, "len")]);
+        assert_eq!(decoded[0]["value"], 150);
+        assert_eq!(decoded[1]["value"], "testing");
+        assert_eq!(decoded[2]["value"]["message"][0]["value"], 1);
+
+        assert!(decode_without_schema(&[0xff, 0xff]).is_none());
+    }
+
+    fn len_field(number: u8, bytes: &[u8]) -> Vec<u8> {
+        let mut out = vec![(number << 3) | 2, bytes.len() as u8];
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    fn field_descriptor(name: &str, number: u8, label: u8, kind: u8) -> Vec<u8> {
+        let mut field = len_field(1, name.as_bytes());
+        field.extend([3 << 3, number, 4 << 3, label, 5 << 3, kind]);
+        field
+    }
+
+    #[test]
+    fn decodes_named_fields_with_descriptor_set() {
+        // package chat; message Msg { string text = 1; repeated sint32 ids = 2; }
+        let mut message = len_field(1, b"Msg");
+        message.extend(len_field(
+            2,
+            &field_descriptor("text", 1, 1, TYPE_STRING as u8),
+        ));
+        message.extend(len_field(
+            2,
+            &field_descriptor("ids", 2, LABEL_REPEATED as u8, TYPE_SINT32 as u8),
+        ));
+        let mut file = len_field(2, b"chat");
+        file.extend(len_field(4, &message));
+        let set = len_field(1, &file);
+
+        let pool = DescriptorPool::from_descriptor_set(&set).expect("descriptor parses");
+        assert!(pool.contains(".chat.Msg"));
+        // text: "hi", ids packed: [-1, 2] (zigzag 1, 4), unknown field 9: 7
+        let frame = [0x0a, 0x02, b'h', b'i', 0x12, 0x02, 0x01, 0x04, 0x48, 0x07];
+        let decoded = pool.decode("chat.Msg", &frame).expect("decodes");
+        assert_eq!(decoded, json!({"text": "hi", "ids": [-1, 2], "9": [7]}));
+    }
+}
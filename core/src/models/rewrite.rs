@@ -0,0 +1,54 @@
+//! Models for rewrite rules applied to proxied traffic.
+
+use crate::models::HttpMethod;
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// What a rewrite rule does to matching traffic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub enum RewriteAction {
+    /// Answer CORS preflights with 204 and add permissive CORS headers to
+    /// responses. `allow_origin` None echoes the request's Origin (or `*`).
+    Cors { allow_origin: Option<String> },
+    /// Set a response header, replacing any value the upstream sent
+    SetResponseHeader { name: String, value: String },
+}
+
+/// How a rule registry treats several rules matching the same request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[frb]
+pub enum RuleMatchMode {
+    /// Only the highest-priority matching rule applies
+    #[default]
+    FirstMatch,
+    /// Every matching rule applies, in priority order; on conflicting edits
+    /// the higher-priority rule wins
+    AllMatching,
+}
+
+/// Input payload for creating rewrite rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct RewriteRuleInput {
+    pub enabled: bool,
+    pub method: Option<HttpMethod>,
+    pub host_contains: Option<String>,
+    pub path_contains: Option<String>,
+    pub action: RewriteAction,
+    /// Higher priorities are evaluated first; equal priorities keep insertion order
+    pub priority: i32,
+}
+
+/// Rewrite rule stored on the backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct RewriteRule {
+    pub id: String,
+    pub enabled: bool,
+    pub method: Option<HttpMethod>,
+    pub host_contains: Option<String>,
+    pub path_contains: Option<String>,
+    pub action: RewriteAction,
+    pub priority: i32,
+}
@@ -0,0 +1,17 @@
+//! Models for simulating slow networks.
+
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// Bandwidth and latency limits applied to proxied traffic. A zero limit
+/// leaves that direction unthrottled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct ThrottleConfig {
+    /// Download (upstream to client) bandwidth in kilobits per second
+    pub down_kbps: u32,
+    /// Upload (client to upstream) bandwidth in kilobits per second
+    pub up_kbps: u32,
+    /// Extra delay before each response is passed on, in milliseconds
+    pub added_latency_ms: u32,
+}
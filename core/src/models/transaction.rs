@@ -2,6 +2,9 @@
 //!
 //! Represents a single HTTP request/response pair captured by the proxy.
 
+use super::grpc::{decode_grpc_web, grpc_web_kind, GrpcFrame};
+use super::jsonrpc::{parse_jsonrpc_request, parse_jsonrpc_response};
+use super::pagination::{detect_pagination, PaginationInfo};
 use chrono::Utc;
 use flutter_rust_bridge::frb;
 use serde::{Deserialize, Serialize};
@@ -64,6 +67,72 @@ impl std::str::FromStr for HttpMethod {
     }
 }
 
+/// How the client wrote the request target (RFC 9112 section 3.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub enum RequestTargetForm {
+    /// `/path?query`; the host comes from the Host header
+    Origin,
+    /// `http://host/path`, as sent to an explicitly configured proxy
+    Absolute,
+    /// `host:port`, used by CONNECT
+    Authority,
+}
+
+/// Stand-in for query values removed by [`QueryRedaction::MaskValues`]
+pub const REDACTED_QUERY_VALUE: &str = "***";
+
+/// How the query string of a captured path is stored. Only the stored copy is
+/// affected; the upstream always receives the request as sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[frb]
+pub enum QueryRedaction {
+    /// Store the query string as sent
+    #[default]
+    Off,
+    /// Keep parameter names, replace every value with `***`
+    MaskValues,
+    /// Drop the query string entirely
+    Drop,
+}
+
+impl QueryRedaction {
+    /// `path` with its query string redacted according to this mode
+    pub fn apply(self, path: &str) -> String {
+        let Some((base, query)) = path.split_once('?') else {
+            return path.to_string();
+        };
+        match self {
+            QueryRedaction::Off => path.to_string(),
+            QueryRedaction::Drop => base.to_string(),
+            QueryRedaction::MaskValues => {
+                let masked: Vec<String> = query
+                    .split('&')
+                    .map(|pair| match pair.split_once('=') {
+                        Some((name, _)) => format!("{name}={REDACTED_QUERY_VALUE}"),
+                        None => pair.to_string(),
+                    })
+                    .collect();
+                format!("{base}?{}", masked.join("&"))
+            }
+        }
+    }
+}
+
+/// What the proxy does with a client's `Upgrade: h2c` offer to switch a
+/// cleartext connection to HTTP/2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[frb]
+pub enum H2cUpgradeMode {
+    /// Remove `Upgrade: h2c` and `HTTP2-Settings` so the exchange stays on
+    /// HTTP/1.1 and is captured normally
+    #[default]
+    Downgrade,
+    /// Pass the offer upstream; if accepted (101), relay the HTTP/2 bytes
+    /// uncaptured. HTTPS requests are always downgraded, h2c being cleartext-only.
+    Tunnel,
+}
+
 /// State of an HTTP transaction
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[frb]
@@ -84,6 +153,10 @@ pub enum TransactionState {
 pub struct TransactionTiming {
     /// When the request started
     pub start_time: i64, // milliseconds since epoch
+    /// Time between receiving the request and starting to connect upstream
+    /// (breakpoint pauses, connection-limit waits) in ms
+    #[serde(default)]
+    pub blocked_ms: Option<u32>,
     /// DNS lookup duration in ms
     pub dns_lookup_ms: Option<u32>,
     /// TCP connection duration in ms
@@ -104,6 +177,7 @@ impl Default for TransactionTiming {
     fn default() -> Self {
         Self {
             start_time: Utc::now().timestamp_millis(),
+            blocked_ms: None,
             dns_lookup_ms: None,
             tcp_connect_ms: None,
             tls_handshake_ms: None,
@@ -136,9 +210,19 @@ pub struct HttpTransaction {
 
     /// Request path (including query string)
     pub path: String,
+    /// Whether `path` holds a redacted query string (see [`QueryRedaction`]),
+    /// so it differs from what was sent upstream
+    #[serde(default)]
+    pub query_redacted: bool,
 
     /// HTTP version (e.g., "HTTP/1.1")
     pub http_version: String,
+    /// HTTP version the client used for this request
+    #[serde(default)]
+    pub client_http_version: Option<String>,
+    /// HTTP version the upstream answered this request with
+    #[serde(default)]
+    pub upstream_http_version: Option<String>,
 
     /// Current state of the transaction
     pub state: TransactionState,
@@ -146,10 +230,20 @@ pub struct HttpTransaction {
     // Request data
     /// Request headers
     pub request_headers: HashMap<String, String>,
+    /// Request headers in the order and casing they were forwarded, repeats
+    /// included (empty for transactions captured before this was recorded)
+    #[serde(default)]
+    pub request_header_list: Vec<(String, String)>,
     /// Request body (if any)
     pub request_body: Option<Vec<u8>>,
     /// Request content type
     pub request_content_type: Option<String>,
+    /// Hex SHA-256 of the full request body (computed before capture truncation)
+    #[serde(default)]
+    pub request_body_sha256: Option<String>,
+    /// Decoded fields of an `application/x-www-form-urlencoded` request body
+    #[serde(default)]
+    pub form_fields: Vec<(String, String)>,
 
     // Response data
     /// HTTP status code
@@ -158,10 +252,53 @@ pub struct HttpTransaction {
     pub status_message: Option<String>,
     /// Response headers
     pub response_headers: Option<HashMap<String, String>>,
+    /// Response headers in the order and casing they were received, repeats
+    /// such as `Set-Cookie` included (empty for transactions captured before
+    /// this was recorded)
+    #[serde(default)]
+    pub response_header_list: Vec<(String, String)>,
     /// Response body (if any)
     pub response_body: Option<Vec<u8>>,
     /// Response content type
     pub response_content_type: Option<String>,
+    /// Hex SHA-256 of the full response body (computed before capture truncation)
+    #[serde(default)]
+    pub response_body_sha256: Option<String>,
+    /// `Content-Encoding` removed from the stored response body (e.g. `gzip`).
+    /// `None` means the body is stored as received.
+    #[serde(default)]
+    pub response_body_encoding: Option<String>,
+    /// Decoded frames of a gRPC-Web response body
+    #[serde(default)]
+    pub grpc_frames: Vec<GrpcFrame>,
+    /// grpc-status from the trailer frame (or trailers-only response headers)
+    #[serde(default)]
+    pub grpc_status: Option<i32>,
+    /// grpc-message accompanying `grpc_status`
+    #[serde(default)]
+    pub grpc_message: Option<String>,
+    /// Shannon entropy (bits per byte, 0-8) of the start of the request body.
+    /// Near 8 suggests compressed or encrypted data, below ~5 text.
+    #[serde(default)]
+    pub request_body_entropy: Option<f32>,
+    /// Shannon entropy of the start of the response body, see `request_body_entropy`
+    #[serde(default)]
+    pub response_body_entropy: Option<f32>,
+    /// Server-Timing metrics reported by the upstream: (name, duration ms, description)
+    #[serde(default)]
+    pub server_timing: Vec<(String, Option<f64>, Option<String>)>,
+    /// JSON-RPC method of the request body (the first call of a batch)
+    #[serde(default)]
+    pub jsonrpc_method: Option<String>,
+    /// JSON-RPC id of that call, as JSON text
+    #[serde(default)]
+    pub jsonrpc_id: Option<String>,
+    /// `error.code` of the response entry whose id matches `jsonrpc_id`
+    #[serde(default)]
+    pub jsonrpc_error_code: Option<i64>,
+    /// Next-page hint found in the response, for paginated APIs
+    #[serde(default)]
+    pub pagination: Option<PaginationInfo>,
 
     // Metadata
     /// Timing information
@@ -172,18 +309,83 @@ pub struct HttpTransaction {
     pub has_breakpoint: bool,
     /// Notes or tags for this transaction
     pub notes: Option<String>,
+    /// Pinned transactions are kept when old traffic is pruned
+    #[serde(default)]
+    pub pinned: bool,
+    /// Free-form labels (e.g. a ticket ID) used to curate exports
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Identical replays collapsed into this transaction (see replay dedup)
+    #[serde(default = "default_occurrence_count")]
+    pub occurrence_count: u32,
 
     // Connection metadata
     /// Server IP address (resolved from DNS)
     pub server_ip: Option<String>,
+    /// Country and network of `server_ip`, when GeoIP databases are configured
+    #[serde(default)]
+    pub server_geo: Option<GeoInfo>,
     /// TLS protocol version (e.g., "TLS 1.3")
     pub tls_version: Option<String>,
     /// TLS cipher suite (e.g., "TLS_AES_256_GCM_SHA384")
     pub tls_cipher: Option<String>,
+    /// Whether the upstream TLS handshake resumed an earlier session
+    #[serde(default)]
+    pub tls_resumed: Option<bool>,
     /// Whether an existing connection was reused (keep-alive)
     pub connection_reused: bool,
+    /// Whether the upstream connection was taken from the idle pool
+    #[serde(default)]
+    pub upstream_connection_reused: bool,
+    /// Connection transcript id, set when transcripts are being recorded
+    #[serde(default)]
+    pub transcript_id: Option<String>,
+    /// User from credentials embedded in the request URL (password redacted)
+    #[serde(default)]
+    pub url_credentials: Option<String>,
+    /// Request-target form the client used
+    #[serde(default)]
+    pub request_target_form: Option<RequestTargetForm>,
+    /// Security problems spotted while proxying, e.g. ambiguous request
+    /// framing that could desync the client and upstream
+    #[serde(default)]
+    pub security_warnings: Vec<String>,
+    /// Non-standard but tolerated message syntax in the request or response
+    /// head (bare LF, obs-fold, padded header values, duplicated headers),
+    /// recorded when `record_conformance_notes` is on
+    #[serde(default)]
+    pub conformance_notes: Vec<String>,
+    /// Rules and proxy features that acted on the request, in the order they
+    /// did, as `kind:detail` (`rewrite:<rule id>`, `mock:<recording id>`, ...)
+    #[serde(default)]
+    pub applied_actions: Vec<String>,
+    /// JA3 hash (MD5) of the client's TLS ClientHello, when fingerprinting is on
+    #[serde(default)]
+    pub client_ja3: Option<String>,
+    /// JA4 fingerprint of the client's TLS ClientHello
+    #[serde(default)]
+    pub client_ja4: Option<String>,
+    /// True when the request was decrypted by the proxy's TLS interception
+    /// (MITM); false for plain HTTP and for CONNECT tunnels relayed untouched
+    #[serde(default)]
+    pub was_intercepted: bool,
     /// Whether this is a WebSocket upgrade connection
     pub is_websocket: bool,
+    /// How the client's `Upgrade: h2c` offer was handled; None if it made none
+    #[serde(default)]
+    pub h2c_upgrade: Option<H2cUpgradeMode>,
+}
+
+/// Where a server address is located, from GeoIP/ASN databases
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2 country code
+    pub country: Option<String>,
+    /// Autonomous system number
+    pub asn: Option<u32>,
+    /// Organization operating the autonomous system
+    pub org: Option<String>,
 }
 
 /// Filter options for querying or streaming transactions
@@ -200,6 +402,83 @@ pub struct TransactionFilter {
     pub status_min: Option<u16>,
     /// Maximum HTTP status (inclusive)
     pub status_max: Option<u16>,
+    /// Hex SHA-256 matching either the request or response body hash
+    pub body_sha256: Option<String>,
+    /// Minimum response size in bytes (inclusive)
+    pub min_response_size: Option<u64>,
+    /// Maximum response size in bytes (inclusive)
+    pub max_response_size: Option<u64>,
+    /// Exact JSON-RPC method name
+    pub jsonrpc_method: Option<String>,
+    /// Client TLS fingerprint, matching either the JA3 hash or the JA4
+    pub client_fingerprint: Option<String>,
+    /// Only pinned (`true`) or unpinned (`false`) transactions
+    pub pinned: Option<bool>,
+    /// Exact tag the transaction must carry
+    pub tag: Option<String>,
+    /// Regex the host must match (case-insensitive)
+    pub host_regex: Option<String>,
+    /// Regex the path (including query) must match
+    pub path_regex: Option<String>,
+}
+
+/// Boolean combination of [`TransactionFilter`]s, e.g.
+/// `And([Or([Leaf(host a), Leaf(host b)]), Leaf(status >= 400)])`.
+/// A flat filter is a single `Leaf`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub enum FilterExpr {
+    /// Every child matches (an empty list matches everything)
+    And(Vec<FilterExpr>),
+    /// At least one child matches (an empty list matches nothing)
+    Or(Vec<FilterExpr>),
+    /// The child does not match
+    Not(Box<FilterExpr>),
+    /// All set fields of the filter match
+    Leaf(TransactionFilter),
+}
+
+impl From<TransactionFilter> for FilterExpr {
+    fn from(filter: TransactionFilter) -> Self {
+        FilterExpr::Leaf(filter)
+    }
+}
+
+/// How request paths are normalized when grouping transactions by endpoint.
+/// Only affects grouping; forwarded and stored paths are never rewritten.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[frb]
+pub struct PathNormalization {
+    /// Collapse runs of `/` into a single slash (`//users` -> `/users`)
+    pub collapse_slashes: bool,
+    /// Lowercase the path; leave off for case-sensitive APIs
+    pub lowercase: bool,
+}
+
+/// Which captured body an analysis runs against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub enum BodySource {
+    Request,
+    Response,
+}
+
+/// A scalar value pulled out of a transaction's JSON body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct ExtractedField {
+    pub transaction_id: String,
+    /// Strings are returned as-is; numbers, booleans and null as their JSON text
+    pub value: String,
+}
+
+/// A transaction whose request or response body contains a search text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct BodySearchHit {
+    pub transaction: HttpTransaction,
+    /// The body text around the match, `…` marking where it was cut
+    pub snippet: String,
 }
 
 /// Paginated response returned to Flutter
@@ -212,6 +491,25 @@ pub struct PaginatedTransactions {
     pub items: Vec<HttpTransaction>,
 }
 
+/// Number of transactions that ended with one status code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct StatusCount {
+    /// HTTP status, or 0 for transactions without a response
+    pub status: u16,
+    pub count: u64,
+}
+
+/// A host that traffic was seen for, with when it was last contacted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct HostActivity {
+    pub host: String,
+    pub count: u64,
+    /// `started_at` of the host's most recent transaction (ms since epoch)
+    pub last_seen_ms: i64,
+}
+
 impl HttpTransaction {
     /// Create a new transaction for an incoming request
     pub fn new(
@@ -229,28 +527,172 @@ impl HttpTransaction {
             host: host.to_string(),
             port,
             path: path.to_string(),
+            query_redacted: false,
             http_version: "HTTP/1.1".to_string(),
+            client_http_version: None,
+            upstream_http_version: None,
             state: TransactionState::Pending,
             request_headers: headers,
+            request_header_list: Vec::new(),
             request_body: None,
             request_content_type: None,
+            request_body_sha256: None,
+            form_fields: Vec::new(),
             status_code: None,
             status_message: None,
             response_headers: None,
+            response_header_list: Vec::new(),
             response_body: None,
             response_content_type: None,
+            response_body_sha256: None,
+            response_body_encoding: None,
+            grpc_frames: Vec::new(),
+            grpc_status: None,
+            grpc_message: None,
+            request_body_entropy: None,
+            response_body_entropy: None,
+            server_timing: Vec::new(),
+            jsonrpc_method: None,
+            jsonrpc_id: None,
+            jsonrpc_error_code: None,
+            pagination: None,
             timing: TransactionTiming::default(),
             response_size: None,
             has_breakpoint: false,
             notes: None,
+            pinned: false,
+            tags: Vec::new(),
+            occurrence_count: 1,
             server_ip: None,
+            server_geo: None,
             tls_version: None,
             tls_cipher: None,
+            tls_resumed: None,
             connection_reused: false,
+            upstream_connection_reused: false,
+            transcript_id: None,
+            url_credentials: None,
+            request_target_form: None,
+            security_warnings: Vec::new(),
+            conformance_notes: Vec::new(),
+            applied_actions: Vec::new(),
+            client_ja3: None,
+            client_ja4: None,
+            was_intercepted: false,
             is_websocket: false,
+            h2c_upgrade: None,
         }
     }
 
+    /// Re-derive `form_fields` from the captured request body
+    pub fn refresh_form_fields(&mut self) {
+        let is_form = self.request_content_type.as_deref().is_some_and(|ct| {
+            ct.split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        });
+        self.form_fields = match (&self.request_body, is_form) {
+            (Some(body), true) => parse_form_urlencoded(body),
+            _ => Vec::new(),
+        };
+    }
+
+    /// Re-derive the JSON-RPC method and id from the request body, and link the
+    /// response entry carrying the same id
+    pub fn refresh_jsonrpc(&mut self) {
+        let call = self
+            .request_body
+            .as_deref()
+            .and_then(|body| parse_jsonrpc_request(body).into_iter().next());
+        let Some(call) = call else {
+            self.jsonrpc_method = None;
+            self.jsonrpc_id = None;
+            self.jsonrpc_error_code = None;
+            return;
+        };
+        self.jsonrpc_error_code = self.response_body.as_deref().and_then(|body| {
+            parse_jsonrpc_response(body)
+                .into_iter()
+                .find(|reply| call.id.is_some() && reply.id == call.id)
+                .and_then(|reply| reply.error_code)
+        });
+        self.jsonrpc_method = Some(call.method);
+        self.jsonrpc_id = call.id;
+    }
+
+    /// Re-parse `server_timing` from the response's Server-Timing header
+    pub fn refresh_server_timing(&mut self) {
+        self.server_timing = self
+            .response_headers
+            .iter()
+            .flatten()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("server-timing"))
+            .flat_map(|(_, value)| parse_server_timing(value))
+            .collect();
+    }
+
+    /// Re-detect `pagination` from the response's Link header or JSON body
+    pub fn refresh_pagination(&mut self) {
+        let headers = self
+            .response_headers
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        self.pagination = detect_pagination(
+            headers,
+            self.response_content_type.as_deref(),
+            self.response_body.as_deref(),
+        );
+    }
+
+    /// Compute the entropy of both bodies from their first [`ENTROPY_SAMPLE_BYTES`]
+    pub fn refresh_body_entropy(&mut self) {
+        let sample = |body: &Option<Vec<u8>>| {
+            body.as_deref()
+                .filter(|b| !b.is_empty())
+                .map(|b| shannon_entropy(&b[..b.len().min(ENTROPY_SAMPLE_BYTES)]))
+        };
+        self.request_body_entropy = sample(&self.request_body);
+        self.response_body_entropy = sample(&self.response_body);
+    }
+
+    /// Re-derive gRPC-Web frames and status from the captured response
+    pub fn refresh_grpc_frames(&mut self) {
+        let Some(text) = self
+            .response_content_type
+            .as_deref()
+            .and_then(grpc_web_kind)
+        else {
+            return;
+        };
+        self.grpc_frames = self
+            .response_body
+            .as_deref()
+            .map(|body| decode_grpc_web(body, text))
+            .unwrap_or_default();
+
+        let trailer = |name: &str| {
+            self.grpc_frames
+                .iter()
+                .filter(|f| f.is_trailer)
+                .flat_map(|f| f.trailers.iter())
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+                .or_else(|| {
+                    self.response_headers.as_ref().and_then(|headers| {
+                        headers
+                            .iter()
+                            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                            .map(|(_, v)| v.clone())
+                    })
+                })
+        };
+        self.grpc_status = trailer("grpc-status").and_then(|s| s.parse().ok());
+        self.grpc_message = trailer("grpc-message");
+    }
+
     /// Get the full URL
     #[frb(sync)]
     pub fn full_url(&self) -> String {
@@ -264,6 +706,31 @@ impl HttpTransaction {
         format!("{}://{}{}{}", self.scheme, self.host, port_str, self.path)
     }
 
+    /// Path (without query string) used as the endpoint grouping key
+    pub fn grouping_path(&self, normalization: PathNormalization) -> String {
+        let path = self.path.split('?').next().unwrap_or("");
+        let mut key = String::with_capacity(path.len());
+        for c in path.chars() {
+            if normalization.collapse_slashes && c == '/' && key.ends_with('/') {
+                continue;
+            }
+            key.push(c);
+        }
+        if normalization.lowercase {
+            key = key.to_lowercase();
+        }
+        key
+    }
+
+    /// Decoded name/value pairs of the (stored, possibly redacted) query string
+    #[frb(sync)]
+    pub fn query_params(&self) -> Vec<(String, String)> {
+        self.path
+            .split_once('?')
+            .map(|(_, query)| parse_form_urlencoded(query.as_bytes()))
+            .unwrap_or_default()
+    }
+
     /// Get duration as formatted string
     #[frb(sync)]
     pub fn duration_str(&self) -> String {
@@ -285,3 +752,222 @@ impl HttpTransaction {
         }
     }
 }
+
+/// Bytes of each body sampled for the entropy score
+pub const ENTROPY_SAMPLE_BYTES: usize = 4 * 1024;
+
+/// Shannon entropy of `data` in bits per byte (0.0 for empty input, at most 8.0)
+fn default_occurrence_count() -> u32 {
+    1
+}
+
+pub fn shannon_entropy(data: &[u8]) -> f32 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0u32; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    entropy as f32
+}
+
+/// Decode an `application/x-www-form-urlencoded` body into name/value pairs,
+/// keeping order and duplicates like the query string does.
+pub fn parse_form_urlencoded(body: &[u8]) -> Vec<(String, String)> {
+    body.split(|b| *b == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, |b| *b == b'=');
+            let name = percent_decode(parts.next().unwrap_or_default(), true);
+            let value = percent_decode(parts.next().unwrap_or_default(), true);
+            (name, value)
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes, keeping malformed ones as written. `plus_as_space`
+/// applies form and query-string encoding, where `+` stands for a space.
+/// Decoded bytes that are not UTF-8 are replaced.
+pub(crate) fn percent_decode(input: &[u8], plus_as_space: bool) -> String {
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'+' if plus_as_space => out.push(b' '),
+            b'%' if i + 2 < input.len() => match (hex(input[i + 1]), hex(input[i + 2])) {
+                (Some(high), Some(low)) => {
+                    out.push(high << 4 | low);
+                    i += 3;
+                    continue;
+                }
+                _ => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Parse a `Server-Timing` header value (`db;dur=53, cache;desc="Cache Read"`)
+/// into (name, duration ms, description) metrics.
+pub fn parse_server_timing(value: &str) -> Vec<(String, Option<f64>, Option<String>)> {
+    value
+        .split(',')
+        .filter_map(|metric| {
+            let mut params = metric.split(';').map(str::trim);
+            let name = params.next().filter(|n| !n.is_empty())?.to_string();
+            let mut duration = None;
+            let mut description = None;
+            for param in params {
+                let Some((key, value)) = param.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "dur" if duration.is_none() => duration = value.parse().ok(),
+                    "desc" if description.is_none() => description = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            Some((name, duration, description))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use sha2::{Digest, Sha256};
+    use std::io::Write;
+
+    #[test]
+    fn compressed_body_has_high_entropy_and_text_low() {
+        // Hex digests vary enough that gzip output looks like random bytes.
+        let mut text = String::new();
+        let mut digest = Sha256::digest(b"seed");
+        while text.len() < 64 * 1024 {
+            text.push_str(&format!("{digest:x}\n"));
+            digest = Sha256::digest(digest);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "https",
+            "example.com",
+            443,
+            "/upload",
+            HashMap::new(),
+        );
+        tx.request_body = Some(
+            b"{\"name\": \"Ada Lovelace\", \"role\": \"analyst\", \"active\": true}".repeat(50),
+        );
+        tx.response_body = Some(compressed);
+        tx.refresh_body_entropy();
+
+        let request = tx.request_body_entropy.expect("request entropy");
+        let response = tx.response_body_entropy.expect("response entropy");
+        assert!(request < 5.0, "plain text entropy {request}");
+        assert!(response > 7.5, "compressed entropy {response}");
+        assert_eq!(shannon_entropy(b""), 0.0);
+        assert_eq!(shannon_entropy(b"aaaa"), 0.0);
+    }
+
+    #[test]
+    fn parses_server_timing_header() {
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "api.example.com",
+            443,
+            "/",
+            HashMap::new(),
+        );
+        tx.response_headers = Some(HashMap::from([(
+            "Server-Timing".to_string(),
+            "db;dur=53, app;dur=47.2".to_string(),
+        )]));
+        tx.refresh_server_timing();
+        assert_eq!(
+            tx.server_timing,
+            vec![
+                ("db".to_string(), Some(53.0), None),
+                ("app".to_string(), Some(47.2), None),
+            ]
+        );
+
+        assert_eq!(
+            parse_server_timing(r#"cache;desc="Cache Read";dur=2.5, miss"#),
+            vec![
+                (
+                    "cache".to_string(),
+                    Some(2.5),
+                    Some("Cache Read".to_string())
+                ),
+                ("miss".to_string(), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_decode_handles_plus_malformed_escapes_and_utf8() {
+        assert_eq!(percent_decode(b"a+b%20c", true), "a b c");
+        assert_eq!(percent_decode(b"a+b%20c", false), "a+b c");
+        assert_eq!(percent_decode(b"100%+%zz%4", true), "100% %zz%4");
+        assert_eq!(percent_decode(b"caf%C3%A9", true), "café");
+    }
+
+    #[test]
+    fn parses_form_urlencoded_request_body() {
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "https",
+            "example.com",
+            443,
+            "/login",
+            HashMap::new(),
+        );
+        tx.request_content_type = Some("application/x-www-form-urlencoded; charset=UTF-8".into());
+        tx.request_body = Some(b"a=1&b=hello%20world".to_vec());
+        tx.refresh_form_fields();
+
+        assert_eq!(
+            tx.form_fields,
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "hello world".to_string()),
+            ]
+        );
+
+        tx.request_content_type = Some("application/json".into());
+        tx.refresh_form_fields();
+        assert!(tx.form_fields.is_empty());
+    }
+
+    #[test]
+    fn query_redaction_masks_or_drops_query() {
+        let path = "/cb?code=abc&flag&state=xyz";
+        assert_eq!(QueryRedaction::Off.apply(path), path);
+        assert_eq!(
+            QueryRedaction::MaskValues.apply(path),
+            "/cb?code=***&flag&state=***"
+        );
+        assert_eq!(QueryRedaction::Drop.apply(path), "/cb");
+        assert_eq!(QueryRedaction::Drop.apply("/plain"), "/plain");
+    }
+}
@@ -0,0 +1,44 @@
+//! Connection transcript models
+//!
+//! Raw bytes exchanged on a proxied connection, recorded when transcripts are
+//! enabled for low-level protocol debugging.
+
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// Which leg of the proxied connection a chunk travelled on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub enum TranscriptDirection {
+    /// Read from the client
+    ClientToProxy,
+    /// Written to the client
+    ProxyToClient,
+    /// Written to the upstream server
+    ProxyToUpstream,
+    /// Read from the upstream server
+    UpstreamToProxy,
+}
+
+/// A single read or write on a connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct TranscriptChunk {
+    pub direction: TranscriptDirection,
+    /// Unix timestamp in milliseconds
+    pub timestamp_ms: i64,
+    /// Bytes as lowercase hex
+    pub data_hex: String,
+}
+
+/// Everything recorded for one client connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct ConnectionTranscript {
+    pub connection_id: String,
+    pub chunks: Vec<TranscriptChunk>,
+    /// Bytes recorded so far (before hex encoding)
+    pub captured_bytes: u64,
+    /// Whether later bytes were dropped because the per-connection cap was hit
+    pub truncated: bool,
+}
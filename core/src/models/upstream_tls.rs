@@ -0,0 +1,19 @@
+//! Models for per-host TLS settings used when connecting to upstream servers.
+
+use flutter_rust_bridge::frb;
+use serde::{Deserialize, Serialize};
+
+/// TLS handshake settings for upstreams whose host matches `host_pattern`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[frb]
+pub struct UpstreamTlsOverride {
+    /// Exact hostname, `*.suffix` (subdomains only) or `*`; case-insensitive
+    pub host_pattern: String,
+    /// ALPN protocols to offer (e.g. `["http/1.1"]`). None keeps the default.
+    pub alpn_protocols: Option<Vec<String>>,
+    /// Send the host in the SNI extension. Disable for servers reached by IP
+    /// that reject or misroute unexpected names.
+    pub send_sni: bool,
+    /// Skip certificate chain and hostname validation for this host only
+    pub accept_invalid_certs: bool,
+}
@@ -38,6 +38,18 @@ impl WebSocketOpcode {
         }
     }
 
+    /// Raw opcode nibble as sent on the wire
+    pub fn to_u8(self) -> u8 {
+        match self {
+            WebSocketOpcode::Continuation => 0,
+            WebSocketOpcode::Text => 1,
+            WebSocketOpcode::Binary => 2,
+            WebSocketOpcode::Close => 8,
+            WebSocketOpcode::Ping => 9,
+            WebSocketOpcode::Pong => 10,
+        }
+    }
+
     /// Convert to string representation
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -110,6 +122,21 @@ pub struct WebSocketMessage {
 
     /// Whether this is the final frame in a message
     pub is_final: bool,
+
+    /// Sent by the proxy itself (keepalive ping) rather than forwarded
+    #[serde(default)]
+    pub injected: bool,
+
+    /// Protobuf view of a binary payload, as JSON: an object keyed by field
+    /// name when a schema is configured, otherwise a list of
+    /// `{field, wire_type, value}` entries. None if the payload is not protobuf.
+    #[serde(default)]
+    pub decoded_view: Option<String>,
+
+    /// Arrived compressed with `permessage-deflate`; `payload` holds the
+    /// inflated bytes
+    #[serde(default)]
+    pub compressed: bool,
 }
 
 impl WebSocketMessage {
@@ -132,6 +159,9 @@ impl WebSocketMessage {
             timestamp: Utc::now().timestamp_millis(),
             is_fragmented: false,
             is_final,
+            injected: false,
+            decoded_view: None,
+            compressed: false,
         }
     }
 
@@ -197,3 +227,66 @@ impl WebSocketMessage {
         }
     }
 }
+
+/// Running throughput counters for one WebSocket connection.
+/// Counts every captured frame, including those evicted from the message buffer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[frb]
+pub struct WebSocketStats {
+    pub connection_id: String,
+    pub client_to_server_messages: u64,
+    pub server_to_client_messages: u64,
+    pub client_to_server_bytes: u64,
+    pub server_to_client_bytes: u64,
+    /// Largest single payload seen in either direction
+    pub max_message_size: u64,
+    pub first_message_at: i64,
+    pub last_message_at: i64,
+}
+
+impl WebSocketStats {
+    pub fn new(connection_id: String) -> Self {
+        Self {
+            connection_id,
+            client_to_server_messages: 0,
+            server_to_client_messages: 0,
+            client_to_server_bytes: 0,
+            server_to_client_bytes: 0,
+            max_message_size: 0,
+            first_message_at: 0,
+            last_message_at: 0,
+        }
+    }
+
+    /// Fold a captured message into the counters
+    pub fn record(&mut self, msg: &WebSocketMessage) {
+        match msg.direction {
+            MessageDirection::ClientToServer => {
+                self.client_to_server_messages += 1;
+                self.client_to_server_bytes += msg.payload_length;
+            }
+            MessageDirection::ServerToClient => {
+                self.server_to_client_messages += 1;
+                self.server_to_client_bytes += msg.payload_length;
+            }
+        }
+        self.max_message_size = self.max_message_size.max(msg.payload_length);
+        if self.first_message_at == 0 {
+            self.first_message_at = msg.timestamp;
+        }
+        self.last_message_at = self.last_message_at.max(msg.timestamp);
+    }
+
+    /// Total messages in both directions
+    #[frb(sync)]
+    pub fn message_count(&self) -> u64 {
+        self.client_to_server_messages + self.server_to_client_messages
+    }
+
+    /// Average message rate between the first and last message (at least a 1s window)
+    #[frb(sync)]
+    pub fn messages_per_second(&self) -> f64 {
+        let window_ms = (self.last_message_at - self.first_message_at).max(1000);
+        self.message_count() as f64 * 1000.0 / window_ms as f64
+    }
+}
@@ -0,0 +1,172 @@
+use super::{CertTrustStatus, PlatformProxyAdapter};
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::process::Command;
+use tracing::warn;
+
+/// System CA directory maintained by `update-ca-certificates`
+const SYSTEM_CERTS_DIR: &str = "/etc/ssl/certs";
+
+/// Proxy configuration through GNOME's `org.gnome.system.proxy` settings, which
+/// GNOME apps, Chromium and most GTK software follow. Certificate trust is read
+/// from the system CA directory and the user's NSS database (Chromium, and
+/// Firefox when configured to use it).
+pub struct LinuxProxyAdapter;
+
+impl LinuxProxyAdapter {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    fn run_gsettings(args: &[&str]) -> Result<()> {
+        let output = Command::new("gsettings")
+            .arg("set")
+            .args(args)
+            .output()
+            .context("failed to run gsettings (is this a GNOME desktop?)")?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow!(
+            "gsettings set {:?} failed: {}",
+            args,
+            stderr.trim()
+        ))
+    }
+
+    fn normalize_host(host: &str) -> String {
+        let trimmed = host.trim();
+        let stripped = trimmed
+            .strip_prefix("http://")
+            .or_else(|| trimmed.strip_prefix("https://"))
+            .unwrap_or(trimmed);
+        stripped
+            .split('/')
+            .next()
+            .unwrap_or(stripped)
+            .trim()
+            .to_string()
+    }
+
+    /// Whether a PEM file under the system CA directory has `common_name` as a
+    /// subject CN. `None` if the directory can't be read.
+    fn system_store_has(common_name: &str) -> Option<bool> {
+        let entries = std::fs::read_dir(SYSTEM_CERTS_DIR).ok()?;
+        let found = entries.flatten().any(|entry| {
+            let path = entry.path();
+            let is_pem = path
+                .extension()
+                .is_some_and(|ext| ext == "pem" || ext == "crt");
+            is_pem && Self::pem_file_has(&path, common_name)
+        });
+        Some(found)
+    }
+
+    fn pem_file_has(path: &Path, common_name: &str) -> bool {
+        let Ok(pem) = std::fs::read(path) else {
+            return false;
+        };
+        let found = rustls_pemfile::certs(&mut pem.as_slice())
+            .flatten()
+            .any(|der| {
+                x509_parser::parse_x509_certificate(der.as_ref())
+                    .map(|(_, cert)| {
+                        cert.subject()
+                            .iter_common_name()
+                            .any(|cn| cn.as_str() == Ok(common_name))
+                    })
+                    .unwrap_or(false)
+            });
+        found
+    }
+
+    /// Whether the user's NSS database trusts `common_name` as a CA for TLS
+    /// servers. `None` if `certutil` or the database is missing.
+    fn nss_db_trusts(common_name: &str) -> Option<bool> {
+        let home = std::env::var_os("HOME")?;
+        let db = Path::new(&home).join(".pki/nssdb");
+        if !db.is_dir() {
+            return None;
+        }
+        let output = Command::new("certutil")
+            .arg("-d")
+            .arg(format!("sql:{}", db.display()))
+            .arg("-L")
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(nss_listing_trusts(
+            &String::from_utf8_lossy(&output.stdout),
+            common_name,
+        ))
+    }
+}
+
+/// Whether `certutil -L` output lists `common_name` with the `C` (trusted CA)
+/// flag in its SSL trust attributes. Lines end in `SSL,S/MIME,JAR/XPI` flags.
+fn nss_listing_trusts(listing: &str, common_name: &str) -> bool {
+    listing.lines().any(|line| {
+        let Some((nickname, flags)) = line.trim_end().rsplit_once(char::is_whitespace) else {
+            return false;
+        };
+        let ssl_flags = flags.split(',').next().unwrap_or_default();
+        nickname.trim() == common_name && ssl_flags.contains('C')
+    })
+}
+
+impl PlatformProxyAdapter for LinuxProxyAdapter {
+    fn enable_system_proxy(&self, host: &str, port: u16) -> Result<()> {
+        let host = format!("'{}'", Self::normalize_host(host));
+        let port = port.to_string();
+        for scheme in [
+            "org.gnome.system.proxy.http",
+            "org.gnome.system.proxy.https",
+        ] {
+            Self::run_gsettings(&[scheme, "host", &host])?;
+            Self::run_gsettings(&[scheme, "port", &port])?;
+        }
+        Self::run_gsettings(&["org.gnome.system.proxy", "mode", "'manual'"])
+    }
+
+    fn disable_system_proxy(&self) -> Result<()> {
+        Self::run_gsettings(&["org.gnome.system.proxy", "mode", "'none'"])
+    }
+
+    fn detect_certificate_trust(&self, common_name: &str) -> Result<CertTrustStatus> {
+        let system = Self::system_store_has(common_name);
+        let nss = Self::nss_db_trusts(common_name);
+        if system.is_none() {
+            warn!("Could not read {SYSTEM_CERTS_DIR} to check certificate trust");
+        }
+        Ok(match (system, nss) {
+            (Some(true), _) | (_, Some(true)) => CertTrustStatus::Trusted,
+            (None, None) => CertTrustStatus::Unknown,
+            _ => CertTrustStatus::NotTrusted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nss_listing_requires_ssl_ca_trust() {
+        let listing = "\
+Certificate Nickname                                         Trust Attributes
+                                                             SSL,S/MIME,JAR/XPI
+
+Cheddar Proxy CA                                             CT,,
+Some Client Cert                                             u,u,u
+Untrusted Root                                               ,C,
+";
+        assert!(nss_listing_trusts(listing, "Cheddar Proxy CA"));
+        assert!(!nss_listing_trusts(listing, "Untrusted Root"));
+        assert!(!nss_listing_trusts(listing, "Some Client Cert"));
+        assert!(!nss_listing_trusts(listing, "Missing"));
+    }
+}
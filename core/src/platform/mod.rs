@@ -3,16 +3,20 @@
 use anyhow::Result;
 use flutter_rust_bridge::frb;
 
+#[cfg(target_os = "linux")]
+mod linux;
 #[cfg(target_os = "macos")]
 mod mac;
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
 mod noop;
 #[cfg(target_os = "windows")]
 mod windows;
 
+#[cfg(target_os = "linux")]
+use linux::LinuxProxyAdapter as PlatformImpl;
 #[cfg(target_os = "macos")]
 use mac::MacProxyAdapter as PlatformImpl;
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
 use noop::NoopProxyAdapter as PlatformImpl;
 #[cfg(target_os = "windows")]
 use windows::WindowsProxyAdapter as PlatformImpl;
@@ -0,0 +1,153 @@
+//! Alert rules: passive monitoring of captured traffic.
+//!
+//! Rules are evaluated against every finished transaction as it is published,
+//! whatever path finished it. Each match is appended to a bounded in-memory alerts log and published to
+//! the alert stream.
+
+use crate::models::alert::{Alert, AlertCondition, AlertRule, AlertRuleInput};
+use crate::models::{HttpTransaction, TransactionState};
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Oldest alerts are dropped once the log holds this many
+pub const MAX_ALERT_LOG_ENTRIES: usize = 1000;
+
+static RULES: Lazy<RwLock<Vec<AlertRule>>> = Lazy::new(|| RwLock::new(Vec::new()));
+static ALERT_LOG: Lazy<RwLock<VecDeque<Alert>>> = Lazy::new(|| RwLock::new(VecDeque::new()));
+
+pub fn list_alert_rules() -> Vec<AlertRule> {
+    RULES.read().map(|r| r.clone()).unwrap_or_default()
+}
+
+pub fn add_alert_rule(input: AlertRuleInput) -> AlertRule {
+    let rule = AlertRule {
+        id: Uuid::new_v4().to_string(),
+        name: input.name,
+        enabled: input.enabled,
+        host_contains: input.host_contains,
+        path_contains: input.path_contains,
+        condition: input.condition,
+    };
+    if let Ok(mut rules) = RULES.write() {
+        rules.push(rule.clone());
+    }
+    rule
+}
+
+pub fn remove_alert_rule(id: &str) -> bool {
+    let Ok(mut rules) = RULES.write() else {
+        return false;
+    };
+    let before = rules.len();
+    rules.retain(|rule| rule.id != id);
+    before != rules.len()
+}
+
+/// Fired alerts, newest first
+pub fn alerts() -> Vec<Alert> {
+    ALERT_LOG
+        .read()
+        .map(|log| log.iter().rev().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn clear_alerts() {
+    if let Ok(mut log) = ALERT_LOG.write() {
+        log.clear();
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn clear_alert_rules() {
+    if let Ok(mut rules) = RULES.write() {
+        rules.clear();
+    }
+}
+
+fn rule_matches(rule: &AlertRule, tx: &HttpTransaction) -> bool {
+    let contains = |needle: &Option<String>, haystack: &str| {
+        needle.as_ref().is_none_or(|n| {
+            haystack
+                .to_ascii_lowercase()
+                .contains(&n.to_ascii_lowercase())
+        })
+    };
+    rule.enabled
+        && contains(&rule.host_contains, &tx.host)
+        && contains(&rule.path_contains, &tx.path)
+}
+
+/// Why `condition` holds for `tx`, or None if it does not.
+fn condition_reason(condition: &AlertCondition, tx: &HttpTransaction) -> Option<String> {
+    match condition {
+        AlertCondition::StatusBetween { min, max } => tx
+            .status_code
+            .filter(|status| (*min..=*max).contains(status))
+            .map(|status| format!("returned {status}")),
+        AlertCondition::SlowerThan { ms } => tx
+            .timing
+            .total_ms
+            .filter(|total| total > ms)
+            .map(|total| format!("took {total} ms (limit {ms} ms)")),
+        AlertCondition::Failed => (tx.state == TransactionState::Failed).then(|| {
+            match tx.notes.as_deref().filter(|n| !n.is_empty()) {
+                Some(note) => format!("failed: {note}"),
+                None => "failed".to_string(),
+            }
+        }),
+    }
+}
+
+/// Evaluate the rules against a finished transaction and log any alerts it fires.
+/// Pending and breakpointed updates are ignored, and a rule that already fired
+/// for the transaction (e.g. before a later note or GeoIP update) is skipped.
+pub(crate) fn evaluate(tx: &HttpTransaction) -> Vec<Alert> {
+    if !matches!(
+        tx.state,
+        TransactionState::Completed | TransactionState::Failed
+    ) {
+        return Vec::new();
+    }
+    let already_fired: Vec<String> = ALERT_LOG
+        .read()
+        .map(|log| {
+            log.iter()
+                .filter(|alert| alert.transaction_id == tx.id)
+                .map(|alert| alert.rule_id.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let Ok(rules) = RULES.read() else {
+        return Vec::new();
+    };
+    let fired: Vec<Alert> = rules
+        .iter()
+        .filter(|rule| !already_fired.contains(&rule.id) && rule_matches(rule, tx))
+        .filter_map(|rule| {
+            let reason = condition_reason(&rule.condition, tx)?;
+            Some(Alert {
+                id: Uuid::new_v4().to_string(),
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                transaction_id: tx.id.clone(),
+                fired_at: chrono::Utc::now().timestamp_millis(),
+                message: format!("{} {} {}", tx.method.to_string(), tx.full_url(), reason),
+            })
+        })
+        .collect();
+    drop(rules);
+
+    if !fired.is_empty() {
+        if let Ok(mut log) = ALERT_LOG.write() {
+            for alert in &fired {
+                if log.len() == MAX_ALERT_LOG_ENTRIES {
+                    log.pop_front();
+                }
+                log.push_back(alert.clone());
+            }
+        }
+    }
+    fired
+}
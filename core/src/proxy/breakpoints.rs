@@ -1,8 +1,10 @@
 use crate::api::proxy_api::send_transaction_to_sink;
 use crate::models::breakpoint::{BreakpointRule, BreakpointRuleInput, RequestEdit};
 use crate::models::{HttpMethod, HttpTransaction, TransactionState};
+use crate::storage::host_regex_pattern;
 use anyhow::{anyhow, Context};
 use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Mutex, RwLock};
@@ -78,8 +80,21 @@ pub enum BreakpointAction {
 
 type PendingBreakpoint = oneshot::Sender<BreakpointAction>;
 
+/// A rule with its regexes compiled once, when it is added.
+struct ActiveRule {
+    rule: BreakpointRule,
+    host_regex: Option<Regex>,
+    path_regex: Option<Regex>,
+}
+
+fn compile_rule_regex(field: &str, pattern: Option<&str>) -> anyhow::Result<Option<Regex>> {
+    pattern
+        .map(|pattern| Regex::new(pattern).with_context(|| format!("invalid {field}")))
+        .transpose()
+}
+
 pub struct BreakpointManager {
-    rules: RwLock<Vec<BreakpointRule>>,
+    rules: RwLock<Vec<ActiveRule>>,
     pending: Mutex<HashMap<String, PendingBreakpoint>>,
 }
 
@@ -98,30 +113,51 @@ impl BreakpointManager {
     }
 
     pub fn list_rules(&self) -> Vec<BreakpointRule> {
-        self.rules.read().unwrap().clone()
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .map(|active| active.rule.clone())
+            .collect()
     }
 
-    pub fn add_rule(&self, input: BreakpointRuleInput) -> BreakpointRule {
-        let mut rules = self.rules.write().unwrap();
+    /// Add a rule; fails if either regex does not compile.
+    pub fn add_rule(&self, input: BreakpointRuleInput) -> anyhow::Result<BreakpointRule> {
+        let host_regex = compile_rule_regex(
+            "host_regex",
+            input
+                .host_regex
+                .as_deref()
+                .map(host_regex_pattern)
+                .as_deref(),
+        )?;
+        let path_regex = compile_rule_regex("path_regex", input.path_regex.as_deref())?;
         let rule = BreakpointRule {
             id: Uuid::new_v4().to_string(),
             enabled: input.enabled,
             method: input.method,
             host_contains: input.host_contains,
             path_contains: input.path_contains,
+            host_regex: input.host_regex,
+            path_regex: input.path_regex,
         };
-        rules.push(rule.clone());
-        rule
+        self.rules.write().unwrap().push(ActiveRule {
+            rule: rule.clone(),
+            host_regex,
+            path_regex,
+        });
+        Ok(rule)
     }
 
     pub fn remove_rule(&self, id: &str) -> bool {
         let mut rules = self.rules.write().unwrap();
         let before = rules.len();
-        rules.retain(|rule| rule.id != id);
+        rules.retain(|active| active.rule.id != id);
         before != rules.len()
     }
 
-    fn rule_matches(rule: &BreakpointRule, ctx: &BreakpointContext) -> bool {
+    fn rule_matches(active: &ActiveRule, ctx: &BreakpointContext) -> bool {
+        let rule = &active.rule;
         if !rule.enabled {
             return false;
         }
@@ -148,15 +184,31 @@ impl BreakpointManager {
                 return false;
             }
         }
+        if let Some(re) = &active.host_regex {
+            if !re.is_match(&ctx.host) {
+                return false;
+            }
+        }
+        if let Some(re) = &active.path_regex {
+            if !re.is_match(&ctx.path) {
+                return false;
+            }
+        }
         true
     }
 
     pub fn should_break(&self, ctx: &BreakpointContext) -> bool {
+        self.matching_rule_id(ctx).is_some()
+    }
+
+    /// Id of the first rule that pauses the request
+    pub fn matching_rule_id(&self, ctx: &BreakpointContext) -> Option<String> {
         self.rules
             .read()
             .unwrap()
             .iter()
-            .any(|rule| Self::rule_matches(rule, ctx))
+            .find(|rule| Self::rule_matches(rule, ctx))
+            .map(|active| active.rule.id.clone())
     }
 
     pub async fn wait_for_decision(
@@ -204,10 +256,11 @@ pub async fn maybe_pause_request(
     ctx: BreakpointContext,
 ) -> anyhow::Result<Option<RequestEdit>> {
     let manager = BreakpointManager::instance();
-    if !manager.should_break(&ctx) {
+    let Some(rule_id) = manager.matching_rule_id(&ctx) else {
         return Ok(None);
-    }
+    };
 
+    tx.applied_actions.push(format!("breakpoint:{rule_id}"));
     tx.state = TransactionState::Breakpointed;
     tx.has_breakpoint = true;
     send_transaction_to_sink(tx.clone());
@@ -236,7 +289,7 @@ pub fn list_breakpoint_rules() -> Vec<BreakpointRule> {
     BreakpointManager::instance().list_rules()
 }
 
-pub fn add_breakpoint_rule(input: BreakpointRuleInput) -> BreakpointRule {
+pub fn add_breakpoint_rule(input: BreakpointRuleInput) -> anyhow::Result<BreakpointRule> {
     BreakpointManager::instance().add_rule(input)
 }
 
@@ -267,3 +320,41 @@ pub fn reset_for_tests() {
         manager.reset();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx(host: &str, path: &str) -> BreakpointContext {
+        BreakpointContext {
+            method: HttpMethod::Get,
+            host: host.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn regex_rules_match_and_reject_bad_patterns() {
+        let manager = BreakpointManager::default();
+        manager
+            .add_rule(BreakpointRuleInput {
+                host_regex: Some(r"^api\.".to_string()),
+                path_regex: Some(r"^/api/v\d+/users/\d+$".to_string()),
+                ..BreakpointRuleInput::default()
+            })
+            .expect("valid rule");
+
+        assert!(manager.should_break(&ctx("API.example.com", "/api/v2/users/42")));
+        assert!(!manager.should_break(&ctx("api.example.com", "/api/v2/users/me")));
+        assert!(!manager.should_break(&ctx("www.example.com", "/api/v2/users/42")));
+
+        let err = manager
+            .add_rule(BreakpointRuleInput {
+                path_regex: Some("/users/(".to_string()),
+                ..BreakpointRuleInput::default()
+            })
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("invalid path_regex"));
+        assert_eq!(manager.list_rules().len(), 1);
+    }
+}
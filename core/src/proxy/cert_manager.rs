@@ -15,6 +15,14 @@ use time::{Duration, OffsetDateTime};
 
 const MAX_SERVER_CONFIG_CACHE: usize = 256;
 
+/// Snapshot of the per-host leaf certificate cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeafCacheStats {
+    pub size: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
 pub struct CertManager {
     _storage_path: PathBuf,
     pub ca_cert_pem: String,
@@ -121,6 +129,11 @@ impl CertManager {
             }
         }
 
+        self.server_configs
+            .lock()
+            .map_err(|_| anyhow!("CertManager cache poisoned"))?
+            .misses += 1;
+
         let (cert_chain, key_der) = self.issue_leaf_cert(host)?;
 
         let mut config = ServerConfig::builder()
@@ -139,6 +152,33 @@ impl CertManager {
         Ok(arc)
     }
 
+    /// Current size and hit/miss counters of the leaf cache
+    pub fn cache_stats(&self) -> LeafCacheStats {
+        let cache = self
+            .server_configs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        LeafCacheStats {
+            size: cache.map.len(),
+            hits: cache.hits,
+            misses: cache.misses,
+        }
+    }
+
+    /// Drop every cached leaf so the next handshake per host issues a fresh
+    /// certificate. Handshakes already holding a config are unaffected.
+    /// Returns the number of entries removed.
+    pub fn clear_cache(&self) -> usize {
+        let mut cache = self
+            .server_configs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let removed = cache.map.len();
+        cache.map.clear();
+        cache.order.clear();
+        removed
+    }
+
     fn issue_leaf_cert(
         &self,
         host: &str,
@@ -194,6 +234,8 @@ impl CertManager {
 struct ServerConfigCache {
     map: HashMap<String, Arc<ServerConfig>>,
     order: VecDeque<String>,
+    hits: u64,
+    misses: u64,
 }
 
 impl ServerConfigCache {
@@ -201,12 +243,15 @@ impl ServerConfigCache {
         Self {
             map: HashMap::new(),
             order: VecDeque::new(),
+            hits: 0,
+            misses: 0,
         }
     }
 
     fn get(&mut self, key: &str) -> Option<Arc<ServerConfig>> {
         if let Some(cfg) = self.map.get(key) {
             let cfg = cfg.clone();
+            self.hits += 1;
             self.promote(key);
             Some(cfg)
         } else {
@@ -261,4 +306,29 @@ mod tests {
         let manager_again = CertManager::new(&path).expect("reuse existing");
         assert_eq!(manager_again.ca_cert_pem, original_pem);
     }
+
+    #[test]
+    fn clearing_cache_forces_new_leaf() {
+        let dir = tempdir().unwrap();
+        let manager = CertManager::new(dir.path().to_str().unwrap()).unwrap();
+
+        let first = manager.server_config_for_host("example.com").unwrap();
+        let cached = manager.server_config_for_host("example.com").unwrap();
+        assert!(Arc::ptr_eq(&first, &cached));
+        assert_eq!(
+            manager.cache_stats(),
+            LeafCacheStats {
+                size: 1,
+                hits: 1,
+                misses: 1
+            }
+        );
+
+        assert_eq!(manager.clear_cache(), 1);
+        assert_eq!(manager.cache_stats().size, 0);
+
+        let rebuilt = manager.server_config_for_host("example.com").unwrap();
+        assert!(!Arc::ptr_eq(&first, &rebuilt));
+        assert_eq!(manager.cache_stats().misses, 2);
+    }
 }
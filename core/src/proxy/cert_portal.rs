@@ -0,0 +1,207 @@
+//! Root CA download page for installing the certificate on devices
+//!
+//! Phones install a CA by opening it in the browser, so plain HTTP requests
+//! through the proxy to [`PORTAL_HOST`] are answered by the proxy itself: `/`
+//! is a short install guide for the visitor's platform, `/cert.pem` and
+//! `/cert.crt` are the CA certificate as PEM and DER.
+
+use rustls::pki_types::CertificateDer;
+
+/// Host name answered by the proxy instead of being forwarded
+pub const PORTAL_HOST: &str = "cheddar.proxy";
+
+/// Whether a request to `host` is for the download page
+pub(crate) fn is_portal_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case(PORTAL_HOST)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Platform {
+    Ios,
+    Android,
+    Desktop,
+}
+
+impl Platform {
+    fn from_user_agent(user_agent: Option<&str>) -> Self {
+        let ua = user_agent.unwrap_or_default().to_ascii_lowercase();
+        if ua.contains("iphone") || ua.contains("ipad") || ua.contains("ipod") {
+            Platform::Ios
+        } else if ua.contains("android") {
+            Platform::Android
+        } else {
+            Platform::Desktop
+        }
+    }
+}
+
+/// The full HTTP/1.1 response for a portal request. `ca_cert_pem` is None
+/// when HTTPS interception (and so the CA) is off.
+pub(crate) fn portal_response(
+    path: &str,
+    user_agent: Option<&str>,
+    ca_cert_pem: Option<&str>,
+) -> Vec<u8> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let Some(pem) = ca_cert_pem else {
+        return response(
+            503,
+            "Service Unavailable",
+            "text/plain; charset=utf-8",
+            b"HTTPS interception is off, so there is no CA certificate to install.".to_vec(),
+        );
+    };
+    match path {
+        "" | "/" => response(
+            200,
+            "OK",
+            "text/html; charset=utf-8",
+            install_page(Platform::from_user_agent(user_agent)).into_bytes(),
+        ),
+        "/cert" | "/cert.pem" => download(
+            200,
+            "OK",
+            "application/x-pem-file",
+            Some("cheddar-proxy-ca.pem"),
+            pem.as_bytes().to_vec(),
+        ),
+        "/cert.crt" | "/cert.cer" | "/cert.der" => match pem_to_der(pem) {
+            Some(der) => download(
+                200,
+                "OK",
+                "application/x-x509-ca-cert",
+                Some("cheddar-proxy-ca.crt"),
+                der.to_vec(),
+            ),
+            None => response(
+                500,
+                "Internal Server Error",
+                "text/plain; charset=utf-8",
+                b"The CA certificate could not be read.".to_vec(),
+            ),
+        },
+        _ => response(
+            404,
+            "Not Found",
+            "text/plain; charset=utf-8",
+            b"Not found. Open / for install instructions.".to_vec(),
+        ),
+    }
+}
+
+fn pem_to_der(pem: &str) -> Option<CertificateDer<'static>> {
+    rustls_pemfile::certs(&mut pem.as_bytes()).next()?.ok()
+}
+
+fn response(code: u16, reason: &str, content_type: &str, body: Vec<u8>) -> Vec<u8> {
+    download(code, reason, content_type, None, body)
+}
+
+/// A response, offered as a file named `filename` when given
+fn download(
+    code: u16,
+    reason: &str,
+    content_type: &str,
+    filename: Option<&str>,
+    body: Vec<u8>,
+) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {code} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\n",
+        body.len()
+    );
+    if let Some(filename) = filename {
+        response.push_str(&format!(
+            "Content-Disposition: attachment; filename=\"{filename}\"\r\n"
+        ));
+    }
+    response.push_str("\r\n");
+    let mut bytes = response.into_bytes();
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+fn install_page(platform: Platform) -> String {
+    let (link, steps) = match platform {
+        Platform::Ios => (
+            "/cert.crt",
+            "<li>Tap the button above and allow the configuration profile download.</li>\
+             <li>Open Settings &rsaquo; Profile Downloaded and tap Install.</li>\
+             <li>Go to Settings &rsaquo; General &rsaquo; About &rsaquo; Certificate Trust \
+             Settings and turn on full trust for Cheddar Proxy CA.</li>",
+        ),
+        Platform::Android => (
+            "/cert.crt",
+            "<li>Tap the button above to download the certificate.</li>\
+             <li>Open Settings &rsaquo; Security &rsaquo; Encryption &amp; credentials \
+             &rsaquo; Install a certificate &rsaquo; CA certificate.</li>\
+             <li>Pick the downloaded cheddar-proxy-ca.crt. Apps only trust user CAs \
+             if their network security config allows it.</li>",
+        ),
+        Platform::Desktop => (
+            "/cert.pem",
+            "<li>Download the certificate with the button above.</li>\
+             <li>macOS: open it in Keychain Access, then set Trust to Always Trust.</li>\
+             <li>Windows: open it, choose Install Certificate and place it in \
+             Trusted Root Certification Authorities.</li>\
+             <li>Linux: copy it to /usr/local/share/ca-certificates/ as a .crt file \
+             and run update-ca-certificates; Firefox needs it imported separately.</li>",
+        ),
+    };
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>Install the Cheddar Proxy CA</title></head>\
+         <body style=\"font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em\">\
+         <h1>Install the Cheddar Proxy CA</h1>\
+         <p>To inspect HTTPS traffic from this device, trust the proxy's root certificate.</p>\
+         <p><a href=\"{link}\" style=\"font-size: 1.2em\">Download certificate</a></p>\
+         <ol>{steps}</ol>\
+         <p>Other formats: <a href=\"/cert.pem\">PEM</a> &middot; <a href=\"/cert.crt\">DER</a></p>\
+         </body></html>\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::cert_manager::CertManager;
+
+    fn split(response: Vec<u8>) -> (String, Vec<u8>) {
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("response head");
+        (
+            String::from_utf8_lossy(&response[..end]).into_owned(),
+            response[end + 4..].to_vec(),
+        )
+    }
+
+    #[test]
+    fn serves_ca_as_pem_and_der_with_install_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = CertManager::new(dir.path().to_str().unwrap()).unwrap();
+        let pem = Some(manager.ca_cert_pem.as_str());
+
+        let (head, body) = split(portal_response("/cert.pem", None, pem));
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+        assert!(head.contains("Content-Type: application/x-pem-file\r\n"));
+        assert_eq!(body, manager.ca_cert_pem.as_bytes());
+
+        let (head, body) = split(portal_response("/cert.crt?x=1", None, pem));
+        assert!(head.contains("Content-Type: application/x-x509-ca-cert\r\n"));
+        assert_eq!(body, manager.test_ca_der().as_ref());
+
+        let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X)";
+        let (head, body) = split(portal_response("/", Some(iphone), pem));
+        assert!(head.contains("Content-Type: text/html"));
+        let page = String::from_utf8_lossy(&body);
+        assert!(page.contains("Certificate Trust"), "{page}");
+
+        let (head, _) = split(portal_response("/cert.pem", None, None));
+        assert!(head.starts_with("HTTP/1.1 503"));
+        let (head, _) = split(portal_response("/other", None, pem));
+        assert!(head.starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,283 @@
+//! HTTP content codings the proxy can produce and undo itself
+//!
+//! Used wherever the proxy synthesizes a body (replays, canned responses) and
+//! must make it agree with a `Content-Encoding` header, and to store captured
+//! response bodies decoded. gzip and deflate go through flate2, `br` through
+//! the brotli crate.
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{Read, Write};
+
+/// Brotli quality for canned bodies: well below the maximum (11), which is
+/// too slow to run per request
+const BROTLI_QUALITY: u32 = 5;
+/// Brotli window size (log2), the encoder's usual default
+const BROTLI_LGWIN: u32 = 22;
+
+/// A content coding the proxy can encode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCoding {
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+impl ContentCoding {
+    /// Parse a single coding token (`gzip`, `x-gzip`, `deflate`, `br`).
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "br" => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Value for the `Content-Encoding` header
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// Whether `data` already starts with this coding's framing. Brotli has no
+    /// magic bytes, so a `br` body is always assumed to be encoded.
+    pub fn is_encoded(&self, data: &[u8]) -> bool {
+        match self {
+            Self::Gzip => data.starts_with(&[0x1f, 0x8b]),
+            Self::Deflate => {
+                data.len() >= 2
+                    && data[0] & 0x0f == 8
+                    && (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 == 0
+            }
+            Self::Brotli => true,
+        }
+    }
+
+    pub fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut encoder =
+                        brotli::CompressorWriter::new(&mut out, 4096, BROTLI_QUALITY, BROTLI_LGWIN);
+                    encoder.write_all(data)?;
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    /// Decode `data`, keeping at most `limit` bytes of output so a small
+    /// compressed body cannot expand without bound. A truncated stream (e.g. a
+    /// capped capture) yields whatever decoded before the cut.
+    ///
+    /// gzip input may be several concatenated members (RFC 1952 section 2.2),
+    /// which are decoded back to back. `deflate` is meant to be zlib-wrapped,
+    /// but some servers send raw DEFLATE; both are accepted.
+    pub fn decode(&self, data: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => read_capped(MultiGzDecoder::new(data), limit),
+            Self::Deflate if self.is_encoded(data) => read_capped(ZlibDecoder::new(data), limit),
+            Self::Deflate => read_capped(DeflateDecoder::new(data), limit),
+            Self::Brotli => read_capped(brotli::Decompressor::new(data, 4096), limit),
+        }
+    }
+}
+
+fn read_capped(reader: impl Read, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut reader = reader.take(limit as u64);
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(out),
+            Ok(n) => out.extend_from_slice(&buf[..n]),
+            Err(_) if !out.is_empty() => return Ok(out),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Undo a `Content-Encoding` header value (codings are removed last to first).
+/// Returns `None` when a coding is unsupported (e.g. `zstd`) or the body does
+/// not decode, in which case the body should be kept as received.
+pub fn decode_body(content_encoding: &str, data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut body = data.to_vec();
+    for token in content_encoding.rsplit(',') {
+        let token = token.trim();
+        if token.is_empty() || token.eq_ignore_ascii_case("identity") {
+            continue;
+        }
+        body = ContentCoding::from_token(token)?
+            .decode(&body, limit)
+            .ok()?;
+    }
+    Some(body)
+}
+
+/// Pick the coding to use for a client's `Accept-Encoding` header.
+///
+/// Honours q-values (`q=0` refuses a coding) and on ties prefers gzip, then
+/// br, then deflate. Returns `None` when the client accepts nothing we can
+/// produce.
+pub fn negotiate(accept_encoding: Option<&str>) -> Option<ContentCoding> {
+    let mut gzip = None;
+    let mut deflate = None;
+    let mut brotli = None;
+    let mut wildcard = None;
+    for item in accept_encoding?.split(',') {
+        let mut parts = item.split(';');
+        let token = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match ContentCoding::from_token(token) {
+            Some(ContentCoding::Gzip) => gzip = Some(quality),
+            Some(ContentCoding::Deflate) => deflate = Some(quality),
+            Some(ContentCoding::Brotli) => brotli = Some(quality),
+            None if token == "*" => wildcard = Some(quality),
+            None => {}
+        }
+    }
+
+    // A wildcard only covers codings the client did not list explicitly.
+    let ranked = [
+        (ContentCoding::Gzip, gzip),
+        (ContentCoding::Brotli, brotli),
+        (ContentCoding::Deflate, deflate),
+    ];
+    let mut best: Option<(ContentCoding, f32)> = None;
+    for (coding, quality) in ranked {
+        let quality = quality.or(wildcard).unwrap_or(0.0);
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((coding, quality));
+        }
+    }
+    best.map(|(coding, _)| coding)
+}
+
+/// Compress a canned response body for the client, returning the body and the
+/// `Content-Encoding` to send (`None` leaves the body plain).
+pub fn encode_for_client(
+    body: &[u8],
+    accept_encoding: Option<&str>,
+) -> std::io::Result<(Vec<u8>, Option<ContentCoding>)> {
+    match negotiate(accept_encoding) {
+        Some(coding) if !body.is_empty() => Ok((coding.encode(body)?, Some(coding))),
+        _ => Ok((body.to_vec(), None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+
+    #[test]
+    fn canned_body_is_gzipped_only_when_client_accepts_it() {
+        let body = br#"{"mock":true}"#;
+
+        let (encoded, coding) = encode_for_client(body, Some("br;q=0.5, gzip;q=0.8")).unwrap();
+        assert_eq!(coding, Some(ContentCoding::Gzip));
+        let mut decoded = Vec::new();
+        GzDecoder::new(encoded.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+
+        let (plain, coding) = encode_for_client(body, Some("identity")).unwrap();
+        assert_eq!(coding, None);
+        assert_eq!(plain, body);
+
+        let (plain, coding) = encode_for_client(body, None).unwrap();
+        assert_eq!(coding, None);
+        assert_eq!(plain, body);
+    }
+
+    #[test]
+    fn canned_body_is_brotli_encoded_when_preferred() {
+        let body = br#"{"mock":true}"#;
+        let (encoded, coding) = encode_for_client(body, Some("gzip;q=0.5, br")).unwrap();
+        assert_eq!(coding, Some(ContentCoding::Brotli));
+        assert_eq!(decode_body("br", &encoded, usize::MAX).unwrap(), body);
+    }
+
+    #[test]
+    fn captured_bodies_decode_within_the_cap() {
+        let body: Vec<u8> = (0..5000)
+            .flat_map(|i| format!("{i},").into_bytes())
+            .collect();
+        let gzip = ContentCoding::Gzip.encode(&body).unwrap();
+        assert_eq!(decode_body("gzip", &gzip, usize::MAX).unwrap(), body);
+        assert_eq!(decode_body("gzip", &gzip, 10).unwrap(), &body[..10]);
+
+        let zlib = ContentCoding::Deflate.encode(&body).unwrap();
+        assert_eq!(decode_body("Deflate", &zlib, usize::MAX).unwrap(), body);
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(&body).unwrap();
+        let raw = raw.finish().unwrap();
+        assert_eq!(decode_body("deflate", &raw, usize::MAX).unwrap(), body);
+
+        // Stacked codings are undone in reverse order.
+        let both = ContentCoding::Gzip.encode(&zlib).unwrap();
+        assert_eq!(
+            decode_body("deflate, gzip", &both, usize::MAX).unwrap(),
+            body
+        );
+
+        // A capture cut mid-stream still yields the decodable prefix.
+        let partial = decode_body("gzip", &gzip[..gzip.len() / 2], usize::MAX).unwrap();
+        assert!(body.starts_with(&partial));
+
+        assert_eq!(decode_body("zstd", &gzip, usize::MAX), None);
+        let br = ContentCoding::Brotli.encode(&body).unwrap();
+        assert_eq!(decode_body("br", &br, 10).unwrap(), &body[..10]);
+        assert_eq!(decode_body("gzip", b"not gzip", usize::MAX), None);
+        assert_eq!(decode_body("identity", b"plain", 100).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn multi_member_gzip_decodes_every_member() {
+        let mut stream = ContentCoding::Gzip.encode(b"first member, ").unwrap();
+        stream.extend(ContentCoding::Gzip.encode(b"second member").unwrap());
+        assert_eq!(
+            decode_body("gzip", &stream, usize::MAX).unwrap(),
+            b"first member, second member"
+        );
+        // The cap applies across members.
+        assert_eq!(
+            decode_body("gzip", &stream, 20).unwrap(),
+            b"first member, second"
+        );
+    }
+
+    #[test]
+    fn negotiation_respects_q_values() {
+        assert_eq!(
+            negotiate(Some("gzip;q=0.2, deflate;q=0.9")),
+            Some(ContentCoding::Deflate)
+        );
+        assert_eq!(
+            negotiate(Some("gzip;q=0, *;q=0.5")),
+            Some(ContentCoding::Brotli)
+        );
+        assert_eq!(negotiate(Some("br, gzip")), Some(ContentCoding::Gzip));
+        assert_eq!(negotiate(Some("gzip;q=0")), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Short-lived cache of upstream DNS lookups
+//!
+//! Upstream hosts are resolved explicitly so the lookup can be timed apart from
+//! the TCP connect. Results are kept briefly so a burst of new connections to
+//! the same origin doesn't pay for (or report) the lookup again.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long a resolved address list is reused
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+struct CachedAddrs {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+}
+
+/// Resolved addresses keyed by lowercase `host:port`.
+pub struct DnsCache {
+    entries: Mutex<HashMap<(String, u16), CachedAddrs>>,
+    ttl: Duration,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Addresses for `host:port` if they were resolved within the TTL.
+    pub fn get(&self, host: &str, port: u16) -> Option<Vec<SocketAddr>> {
+        let mut entries = self.entries.lock().ok()?;
+        let key = (host.to_ascii_lowercase(), port);
+        match entries.get(&key) {
+            Some(cached) if cached.resolved_at.elapsed() < self.ttl => Some(cached.addrs.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, host: &str, port: u16, addrs: Vec<SocketAddr>) {
+        if addrs.is_empty() {
+            return;
+        }
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, cached| cached.resolved_at.elapsed() < self.ttl);
+            entries.insert(
+                (host.to_ascii_lowercase(), port),
+                CachedAddrs {
+                    addrs,
+                    resolved_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    /// Resolve `host:port`, returning the addresses and whether they came from
+    /// the cache.
+    pub async fn resolve(&self, host: &str, port: u16) -> std::io::Result<(Vec<SocketAddr>, bool)> {
+        if let Some(addrs) = self.get(host, port) {
+            return Ok((addrs, true));
+        }
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addrs.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no addresses found for {host}"),
+            ));
+        }
+        self.insert(host, port, addrs.clone());
+        Ok((addrs, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn entries_expire_after_ttl() {
+        let cache = DnsCache::new(Duration::from_secs(30));
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        cache.insert("Example.test", 8080, vec![addr]);
+
+        assert_eq!(cache.get("example.test", 8080), Some(vec![addr]));
+        assert_eq!(cache.get("example.test", 443), None);
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert_eq!(cache.get("example.test", 8080), None);
+    }
+
+    #[tokio::test]
+    async fn resolve_reports_cache_hits() {
+        let cache = DnsCache::new(DEFAULT_TTL);
+        let (first, cached) = cache.resolve("127.0.0.1", 9).await.unwrap();
+        assert!(!cached);
+        let (second, cached) = cache.resolve("127.0.0.1", 9).await.unwrap();
+        assert!(cached);
+        assert_eq!(first, second);
+    }
+}
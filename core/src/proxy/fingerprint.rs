@@ -0,0 +1,411 @@
+//! JA3/JA4 fingerprints of a client's TLS ClientHello.
+//!
+//! Both summarize the offered TLS version, cipher suites and extensions, so
+//! requests from the same TLS stack (a browser build, curl, a bot library)
+//! share a fingerprint regardless of headers like User-Agent. GREASE values
+//! (RFC 8701) are ignored since clients pick them at random.
+//!
+//! The acceptor consumes the ClientHello, so [`read_client_hello`] reads the
+//! first record itself and [`PrefixedStream`] hands it back to the handshake.
+
+use super::sni::Reader;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+/// Fingerprints computed from one ClientHello
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientFingerprint {
+    /// JA3 string: `version,ciphers,extensions,groups,point_formats`
+    pub ja3: String,
+    /// MD5 of [`Self::ja3`], the form JA3 is usually shared in
+    pub ja3_hash: String,
+    /// JA4 (TLS over TCP), e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`
+    pub ja4: String,
+}
+
+/// Fields of a ClientHello that the fingerprints are built from
+#[derive(Default)]
+struct ClientHello {
+    legacy_version: u16,
+    cipher_suites: Vec<u16>,
+    /// Extension types in the order sent
+    extensions: Vec<u16>,
+    supported_groups: Vec<u16>,
+    ec_point_formats: Vec<u8>,
+    signature_algorithms: Vec<u16>,
+    supported_versions: Vec<u16>,
+    first_alpn: Option<Vec<u8>>,
+}
+
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+/// Fingerprint a TLS record holding a ClientHello. `None` if it is not one.
+pub(crate) fn fingerprint_client_hello(record: &[u8]) -> Option<ClientFingerprint> {
+    let hello = parse_client_hello(record)?;
+    let ja3 = ja3_string(&hello);
+    let ja3_hash = format!("{:x}", Md5::digest(ja3.as_bytes()));
+    Some(ClientFingerprint {
+        ja3,
+        ja3_hash,
+        ja4: ja4_string(&hello),
+    })
+}
+
+fn parse_client_hello(record: &[u8]) -> Option<ClientHello> {
+    let mut r = Reader(record);
+    if r.u8()? != 0x16 {
+        return None;
+    }
+    r.skip(2)?;
+    let record_len = r.u16()? as usize;
+    let mut r = Reader(r.take(record_len.min(r.0.len()))?);
+    if r.u8()? != 0x01 {
+        return None;
+    }
+    r.skip(3)?;
+
+    let mut hello = ClientHello {
+        legacy_version: r.u16()?,
+        ..Default::default()
+    };
+    r.skip(32)?; // random
+    let session_id_len = r.u8()? as usize;
+    r.skip(session_id_len)?;
+    let cipher_suites_len = r.u16()? as usize;
+    let mut suites = Reader(r.take(cipher_suites_len)?);
+    while !suites.0.is_empty() {
+        hello.cipher_suites.push(suites.u16()?);
+    }
+    let compression_len = r.u8()? as usize;
+    r.skip(compression_len)?;
+    if r.0.is_empty() {
+        return Some(hello);
+    }
+
+    let extensions_len = r.u16()? as usize;
+    let mut extensions = Reader(r.take(extensions_len)?);
+    while !extensions.0.is_empty() {
+        let ext_type = extensions.u16()?;
+        let ext_len = extensions.u16()? as usize;
+        let mut data = Reader(extensions.take(ext_len)?);
+        hello.extensions.push(ext_type);
+        match ext_type {
+            EXT_SUPPORTED_GROUPS => hello.supported_groups = u16_list(&mut data)?,
+            EXT_SIGNATURE_ALGORITHMS => hello.signature_algorithms = u16_list(&mut data)?,
+            EXT_EC_POINT_FORMATS => {
+                let len = data.u8()? as usize;
+                hello.ec_point_formats = data.take(len)?.to_vec();
+            }
+            EXT_SUPPORTED_VERSIONS => {
+                let len = data.u8()? as usize;
+                let mut versions = Reader(data.take(len)?);
+                while !versions.0.is_empty() {
+                    hello.supported_versions.push(versions.u16()?);
+                }
+            }
+            EXT_ALPN => {
+                let list_len = data.u16()? as usize;
+                let mut protocols = Reader(data.take(list_len)?);
+                if !protocols.0.is_empty() {
+                    let len = protocols.u8()? as usize;
+                    hello.first_alpn = Some(protocols.take(len)?.to_vec());
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(hello)
+}
+
+/// A list prefixed by its 16-bit byte length
+fn u16_list(data: &mut Reader) -> Option<Vec<u16>> {
+    let len = data.u16()? as usize;
+    let mut list = Reader(data.take(len)?);
+    let mut values = Vec::with_capacity(len / 2);
+    while !list.0.is_empty() {
+        values.push(list.u16()?);
+    }
+    Some(values)
+}
+
+fn ja3_string(hello: &ClientHello) -> String {
+    let join = |values: &[u16]| {
+        values
+            .iter()
+            .filter(|v| !is_grease(**v))
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join("-")
+    };
+    let point_formats = hello
+        .ec_point_formats
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join("-");
+    format!(
+        "{},{},{},{},{}",
+        hello.legacy_version,
+        join(&hello.cipher_suites),
+        join(&hello.extensions),
+        join(&hello.supported_groups),
+        point_formats
+    )
+}
+
+fn ja4_string(hello: &ClientHello) -> String {
+    let version = hello
+        .supported_versions
+        .iter()
+        .copied()
+        .filter(|v| !is_grease(*v))
+        .max()
+        .unwrap_or(hello.legacy_version);
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let sni = if hello.extensions.contains(&EXT_SERVER_NAME) {
+        'd'
+    } else {
+        'i'
+    };
+    let ciphers: Vec<u16> = hello
+        .cipher_suites
+        .iter()
+        .copied()
+        .filter(|v| !is_grease(*v))
+        .collect();
+    let extensions: Vec<u16> = hello
+        .extensions
+        .iter()
+        .copied()
+        .filter(|v| !is_grease(*v))
+        .collect();
+    let alpn = match hello.first_alpn.as_deref() {
+        Some([first, .., last]) | Some([first @ last]) => {
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                format!("{}{}", *first as char, *last as char)
+            } else {
+                let (first, last) = (format!("{first:02x}"), format!("{last:02x}"));
+                format!("{}{}", &first[..1], &last[1..])
+            }
+        }
+        _ => "00".to_string(),
+    };
+    let prefix = format!(
+        "t{version}{sni}{:02}{:02}{alpn}",
+        ciphers.len().min(99),
+        extensions.len().min(99)
+    );
+
+    let mut sorted_ciphers = ciphers;
+    sorted_ciphers.sort_unstable();
+    let mut sorted_extensions: Vec<u16> = extensions
+        .into_iter()
+        .filter(|e| *e != EXT_SERVER_NAME && *e != EXT_ALPN)
+        .collect();
+    sorted_extensions.sort_unstable();
+    let mut extension_part = hex_list(&sorted_extensions);
+    if !hello.signature_algorithms.is_empty() {
+        extension_part.push('_');
+        extension_part.push_str(&hex_list(&hello.signature_algorithms));
+    }
+    format!(
+        "{prefix}_{}_{}",
+        truncated_sha256(&hex_list(&sorted_ciphers)),
+        truncated_sha256(&extension_part)
+    )
+}
+
+fn hex_list(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{v:04x}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// First 12 hex digits of the SHA-256 of `input`, or zeros for an empty list
+fn truncated_sha256(input: &str) -> String {
+    if input.is_empty() {
+        return "0".repeat(12);
+    }
+    format!("{:x}", Sha256::digest(input.as_bytes()))[..12].to_string()
+}
+
+/// Read the client's first TLS record (normally the whole ClientHello). Stops
+/// early if the bytes are not a handshake record; whatever was read is
+/// returned so it can be handed on unchanged.
+pub(crate) async fn read_client_hello<S>(client: &mut S) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut record = vec![0u8; 5];
+    client.read_exact(&mut record).await?;
+    if record[0] != 0x16 {
+        return Ok(record);
+    }
+    let len = u16::from_be_bytes([record[3], record[4]]) as usize;
+    record.resize(5 + len, 0);
+    client.read_exact(&mut record[5..]).await?;
+    Ok(record)
+}
+
+/// A stream that yields `prefix` before reading from `inner`
+pub(crate) struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    offset: usize,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    pub(crate) fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            offset: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefixedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.offset < self.prefix.len() {
+            let remaining = &self.prefix[self.offset..];
+            let n = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..n]);
+            self.offset += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, data)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    /// Wrap `body` in a 16-bit length prefix
+    fn vec16(body: &[u8]) -> Vec<u8> {
+        let mut out = (body.len() as u16).to_be_bytes().to_vec();
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn extension(ext_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = ext_type.to_be_bytes().to_vec();
+        out.extend(vec16(data));
+        out
+    }
+
+    /// A fixed ClientHello with GREASE in the ciphers, extensions, groups and
+    /// versions, so the expected fingerprints never change.
+    fn known_client_hello() -> Vec<u8> {
+        let mut extensions = Vec::new();
+        extensions.extend(extension(0x1a1a, &[]));
+        let mut server_name = vec![0u8];
+        server_name.extend(vec16(b"example.com"));
+        extensions.extend(extension(EXT_SERVER_NAME, &vec16(&server_name)));
+        extensions.extend(extension(
+            EXT_SUPPORTED_GROUPS,
+            &vec16(&[0x2a, 0x2a, 0x00, 0x1d, 0x00, 0x17]),
+        ));
+        extensions.extend(extension(EXT_EC_POINT_FORMATS, &[1, 0]));
+        extensions.extend(extension(
+            EXT_SIGNATURE_ALGORITHMS,
+            &vec16(&[0x04, 0x03, 0x08, 0x04]),
+        ));
+        extensions.extend(extension(EXT_ALPN, &vec16(b"\x02h2\x08http/1.1")));
+        extensions.extend(extension(
+            EXT_SUPPORTED_VERSIONS,
+            &[6, 0x3a, 0x3a, 0x03, 0x04, 0x03, 0x03],
+        ));
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend([7u8; 32]);
+        hello.push(0); // session id
+        hello.extend(vec16(&[0x0a, 0x0a, 0x13, 0x01, 0x13, 0x02, 0xc0, 0x2f]));
+        hello.extend([1, 0]); // null compression
+        hello.extend(vec16(&extensions));
+
+        let mut handshake = vec![0x01];
+        handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(hello);
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend(vec16(&handshake));
+        record
+    }
+
+    #[test]
+    fn known_client_hello_has_stable_ja3_and_ja4() {
+        let fingerprint = fingerprint_client_hello(&known_client_hello()).expect("parses");
+        assert_eq!(
+            fingerprint.ja3,
+            "771,4865-4866-49199,0-10-11-13-16-43,29-23,0"
+        );
+        assert_eq!(fingerprint.ja3_hash, "c9e264cb3675678ee364e81f3b6da7ad");
+        assert_eq!(fingerprint.ja4, "t13d0306h2_40b44b994229_fb71836bce29");
+
+        assert_eq!(fingerprint_client_hello(b"GET / HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn read_client_hello_is_replayed_to_the_handshake() {
+        let hello = known_client_hello();
+        let (mut client, mut server) = duplex(4096);
+        client.write_all(&hello).await.unwrap();
+        client.write_all(b"after").await.unwrap();
+        drop(client);
+
+        let record = read_client_hello(&mut server).await.unwrap();
+        assert_eq!(record, hello);
+        let mut replayed = Vec::new();
+        PrefixedStream::new(record, server)
+            .read_to_end(&mut replayed)
+            .await
+            .unwrap();
+        assert_eq!(&replayed[..hello.len()], hello.as_slice());
+        assert_eq!(&replayed[hello.len()..], b"after");
+    }
+}
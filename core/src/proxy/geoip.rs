@@ -0,0 +1,96 @@
+//! GeoIP/ASN enrichment of upstream server addresses.
+//!
+//! When MaxMind databases are configured, completed transactions are annotated
+//! with the country and network operator of the server they talked to. The
+//! lookup runs in a background task after the transaction has been persisted,
+//! so it never delays the response to the client.
+
+use crate::models::{GeoInfo, HttpTransaction};
+use maxminddb::{geoip2, Reader};
+use once_cell::sync::Lazy;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+/// Source of geo/network data for an IP address.
+pub(crate) trait GeoLookup: Send + Sync {
+    fn lookup(&self, ip: IpAddr) -> Option<GeoInfo>;
+}
+
+static GEO_LOOKUP: Lazy<RwLock<Option<Arc<dyn GeoLookup>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Country (or City) and ASN databases in MaxMind DB format. Either may be absent.
+struct MaxMindLookup {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoLookup for MaxMindLookup {
+    fn lookup(&self, ip: IpAddr) -> Option<GeoInfo> {
+        let country = self
+            .country
+            .as_ref()
+            .and_then(|db| db.lookup::<geoip2::Country>(ip).ok())
+            .and_then(|record| record.country.and_then(|c| c.iso_code))
+            .map(str::to_string);
+        let asn = self
+            .asn
+            .as_ref()
+            .and_then(|db| db.lookup::<geoip2::Asn>(ip).ok());
+        let info = GeoInfo {
+            country,
+            asn: asn.as_ref().and_then(|a| a.autonomous_system_number),
+            org: asn
+                .and_then(|a| a.autonomous_system_organization)
+                .map(str::to_string),
+        };
+        (info.country.is_some() || info.asn.is_some()).then_some(info)
+    }
+}
+
+/// Load MaxMind databases (e.g. GeoLite2-Country and GeoLite2-ASN) from `paths`.
+/// Each file is classified by its embedded database type. An empty list turns
+/// enrichment off.
+pub fn configure_geoip(paths: &[String]) -> anyhow::Result<()> {
+    if paths.is_empty() {
+        set_lookup(None);
+        return Ok(());
+    }
+    let mut lookup = MaxMindLookup {
+        country: None,
+        asn: None,
+    };
+    for path in paths {
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| anyhow::anyhow!("opening GeoIP database {path}: {e}"))?;
+        let kind = reader.metadata.database_type.to_ascii_lowercase();
+        if kind.contains("asn") {
+            lookup.asn = Some(reader);
+        } else if kind.contains("country") || kind.contains("city") {
+            lookup.country = Some(reader);
+        } else {
+            anyhow::bail!("unsupported GeoIP database type {kind:?} in {path}");
+        }
+    }
+    set_lookup(Some(Arc::new(lookup)));
+    Ok(())
+}
+
+pub(crate) fn set_lookup(lookup: Option<Arc<dyn GeoLookup>>) {
+    if let Ok(mut current) = GEO_LOOKUP.write() {
+        *current = lookup;
+    }
+}
+
+/// Whether `tx` should be enriched once it has been persisted.
+pub(crate) fn wants_enrichment(tx: &HttpTransaction) -> bool {
+    tx.server_geo.is_none()
+        && tx.server_ip.is_some()
+        && GEO_LOOKUP.read().map(|l| l.is_some()).unwrap_or(false)
+}
+
+/// Geo information for `tx`'s server address, if a lookup is configured and knows it.
+pub(crate) fn lookup_server(tx: &HttpTransaction) -> Option<GeoInfo> {
+    let ip: IpAddr = tx.server_ip.as_deref()?.parse().ok()?;
+    let lookup = GEO_LOOKUP.read().ok()?.clone()?;
+    lookup.lookup(ip)
+}
@@ -2,9 +2,25 @@
 //!
 //! This module contains the core proxy server that intercepts HTTP traffic.
 
+pub mod alerts;
 pub mod breakpoints;
 pub mod cert_manager;
+pub mod cert_portal;
+pub mod content_coding;
+pub mod dns_cache;
+mod fingerprint;
+pub mod geoip;
+pub mod playback;
+pub mod quiet_hosts;
+pub mod rewrite;
 pub mod server;
+mod sni;
+mod sse;
+pub mod throttle;
+pub(crate) mod transcript;
+mod upstream_h2;
+pub mod upstream_pool;
+pub mod upstream_tls;
 pub mod websocket;
 
 pub use server::*;
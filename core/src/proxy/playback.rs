@@ -0,0 +1,283 @@
+//! Record/replay ("VCR") mode.
+//!
+//! Captured transactions can be armed for playback: while armed, requests with the
+//! same method, scheme, host, port and path never reach the network. The upstream
+//! connection is replaced by a [`RecordedResponse`] that feeds back the stored
+//! response head and body as if the server had just sent them.
+
+use crate::models::HttpTransaction;
+use crate::proxy::content_coding;
+use crate::proxy::server::MAX_BODY_CAPTURE_BYTES;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Headers that describe the original wire framing, rebuilt for the stored body.
+const FRAMING_HEADERS: &[&str] = &["content-length", "transfer-encoding", "connection"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RecordingKey {
+    method: String,
+    scheme: String,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl RecordingKey {
+    fn new(method: &str, scheme: &str, host: &str, port: u16, path: &str) -> Self {
+        Self {
+            method: method.to_ascii_uppercase(),
+            scheme: scheme.to_ascii_lowercase(),
+            host: host.to_ascii_lowercase(),
+            port,
+            path: path.to_string(),
+        }
+    }
+}
+
+struct Recording {
+    transaction_id: String,
+    response: Arc<StoredResponse>,
+    raw_response: Arc<Vec<u8>>,
+    /// Compress a plain body per the client's `Accept-Encoding`
+    compress: bool,
+}
+
+/// A captured response ready to be served: everything but the framing headers
+struct StoredResponse {
+    /// Status line and headers, each ending in CRLF, without Content-Length
+    head: String,
+    body: Vec<u8>,
+    /// The body is served without a `Content-Encoding`
+    plain: bool,
+}
+
+impl StoredResponse {
+    /// Serialize with `body` (the stored one or an encoded copy) and a fresh
+    /// Content-Length
+    fn to_raw(&self, content_encoding: Option<&str>, body: &[u8]) -> Vec<u8> {
+        let mut head = self.head.clone();
+        if let Some(coding) = content_encoding {
+            head.push_str(&format!("Content-Encoding: {coding}\r\n"));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        let mut raw = head.into_bytes();
+        raw.extend_from_slice(body);
+        raw
+    }
+}
+
+static RECORDINGS: Lazy<RwLock<HashMap<RecordingKey, Recording>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Serve `tx`'s stored response for every matching request until stopped.
+/// Replaces any recording already armed for the same request. Refused when
+/// only part of the response body was stored (capture capped or off).
+///
+/// With `compress`, a body stored plain is gzip/br/deflate-encoded for each
+/// client that accepts it, as a real server would; otherwise it is served as
+/// stored.
+pub fn arm_recording(tx: &HttpTransaction, compress: bool) -> anyhow::Result<()> {
+    let response = stored_response_for(tx)
+        .ok_or_else(|| anyhow::anyhow!("transaction {} has no recorded response", tx.id))?;
+    if !body_is_complete(tx) {
+        anyhow::bail!(
+            "transaction {} has only part of its response body stored; serving it would cut the response short",
+            tx.id
+        );
+    }
+    let raw_response = response.to_raw(None, &response.body);
+    let key = RecordingKey::new(
+        &tx.method.to_string(),
+        &tx.scheme,
+        &tx.host,
+        tx.port,
+        &tx.path,
+    );
+    RECORDINGS
+        .write()
+        .map_err(|_| anyhow::anyhow!("recording registry poisoned"))?
+        .insert(
+            key,
+            Recording {
+                transaction_id: tx.id.clone(),
+                response: Arc::new(response),
+                raw_response: Arc::new(raw_response),
+                compress,
+            },
+        );
+    Ok(())
+}
+
+/// Stop serving the recording made from `transaction_id`. Returns whether one was armed.
+pub fn disarm_recording(transaction_id: &str) -> bool {
+    let Ok(mut recordings) = RECORDINGS.write() else {
+        return false;
+    };
+    let before = recordings.len();
+    recordings.retain(|_, r| r.transaction_id != transaction_id);
+    before != recordings.len()
+}
+
+/// Stop serving all recordings
+pub fn clear_recordings() {
+    if let Ok(mut recordings) = RECORDINGS.write() {
+        recordings.clear();
+    }
+}
+
+/// Ids of transactions currently served from recordings
+pub fn armed_recordings() -> Vec<String> {
+    RECORDINGS
+        .read()
+        .map(|r| r.values().map(|rec| rec.transaction_id.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// The recorded response for a request, if one is armed.
+pub(crate) fn recorded_response_for(
+    method: &str,
+    scheme: &str,
+    host: &str,
+    port: u16,
+    path: &str,
+) -> Option<RecordedResponse> {
+    let recordings = RECORDINGS.read().ok()?;
+    let recording = recordings.get(&RecordingKey::new(method, scheme, host, port, path))?;
+    Some(RecordedResponse {
+        transaction_id: recording.transaction_id.clone(),
+        response: recording.response.clone(),
+        data: recording.raw_response.clone(),
+        pos: 0,
+        compress: recording.compress,
+    })
+}
+
+/// Whether the stored response body is the whole body the server sent. A body
+/// stored as received is checked against the hash of the full body; a decoded
+/// one (or one captured before hashes were kept) only against the capture cap.
+fn body_is_complete(tx: &HttpTransaction) -> bool {
+    let body = tx.response_body.as_deref().unwrap_or_default();
+    match (&tx.response_body_sha256, &tx.response_body_encoding) {
+        (Some(hash), None) => format!("{:x}", Sha256::digest(body)) == *hash,
+        _ => {
+            tx.response_size.unwrap_or(0) <= MAX_BODY_CAPTURE_BYTES as u64
+                && body.len() < MAX_BODY_CAPTURE_BYTES
+        }
+    }
+}
+
+/// Rebuild an HTTP/1.1 response from a captured transaction. The stored body is
+/// served as-is with a fresh Content-Length; if it was stored decoded, the
+/// `Content-Encoding` header is dropped to match. Headers keep their received
+/// order and repeats (several `Set-Cookie`s) when the capture recorded them.
+fn stored_response_for(tx: &HttpTransaction) -> Option<StoredResponse> {
+    let status = tx.status_code?;
+    let body = tx.response_body.as_deref().unwrap_or_default();
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status,
+        tx.status_message.as_deref().unwrap_or_default()
+    );
+    let headers: Vec<_> = if tx.response_header_list.is_empty() {
+        let mut headers: Vec<_> = tx.response_headers.iter().flatten().collect();
+        headers.sort();
+        headers
+    } else {
+        tx.response_header_list
+            .iter()
+            .map(|(n, v)| (n, v))
+            .collect()
+    };
+    let headers: Vec<_> = headers
+        .into_iter()
+        .filter(|(name, _)| !FRAMING_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+        .filter(|(name, _)| {
+            tx.response_body_encoding.is_none() || !name.eq_ignore_ascii_case("content-encoding")
+        })
+        .collect();
+    let plain = !headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("content-encoding"));
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+
+    Some(StoredResponse {
+        head,
+        body: body.to_vec(),
+        plain,
+    })
+}
+
+/// Upstream stand-in that replays a stored response; request bytes are discarded.
+pub(crate) struct RecordedResponse {
+    transaction_id: String,
+    response: Arc<StoredResponse>,
+    data: Arc<Vec<u8>>,
+    pos: usize,
+    compress: bool,
+}
+
+impl RecordedResponse {
+    pub(crate) fn transaction_id(&self) -> &str {
+        &self.transaction_id
+    }
+
+    /// If the recording was armed with compression, compress a plain stored
+    /// body with the coding the client's `Accept-Encoding` prefers, as the
+    /// original server likely did. Bodies stored with their `Content-Encoding`
+    /// are served untouched.
+    pub(crate) fn encode_for_client(&mut self, accept_encoding: Option<&str>) {
+        if !self.compress || !self.response.plain || self.pos > 0 {
+            return;
+        }
+        match content_coding::encode_for_client(&self.response.body, accept_encoding) {
+            Ok((body, Some(coding))) => {
+                self.data = Arc::new(self.response.to_raw(Some(coding.as_str()), &body));
+            }
+            Ok((_, None)) => {}
+            Err(err) => tracing::debug!("Could not compress recorded response: {}", err),
+        }
+    }
+}
+
+impl AsyncRead for RecordedResponse {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let remaining = &this.data[this.pos..];
+        let n = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for RecordedResponse {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
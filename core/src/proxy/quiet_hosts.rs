@@ -0,0 +1,41 @@
+//! Quiet hosts: traffic that is forwarded normally but kept out of the capture.
+//!
+//! Unlike the bypass list (which tunnels TLS instead of intercepting it), quiet
+//! hosts are still proxied and intercepted; their transactions are simply never
+//! persisted or streamed. Useful for analytics beacons and crash reporters.
+
+use crate::proxy::sni::pattern_matches;
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+
+static QUIET_HOSTS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Replace the quiet host patterns. Patterns are exact hostnames, `*.suffix`
+/// (subdomains only) or `*`; matching is case-insensitive.
+pub fn set_quiet_hosts(patterns: Vec<String>) {
+    let patterns = patterns
+        .into_iter()
+        .map(|p| p.trim().to_ascii_lowercase())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if let Ok(mut guard) = QUIET_HOSTS.write() {
+        *guard = patterns;
+    }
+}
+
+/// Currently configured quiet host patterns
+pub fn quiet_hosts() -> Vec<String> {
+    QUIET_HOSTS.read().map(|g| g.clone()).unwrap_or_default()
+}
+
+/// Whether transactions for `host` should be left out of the capture
+pub fn is_quiet(host: &str) -> bool {
+    let Ok(patterns) = QUIET_HOSTS.read() else {
+        return false;
+    };
+    if patterns.is_empty() {
+        return false;
+    }
+    let host = host.to_ascii_lowercase();
+    patterns.iter().any(|p| pattern_matches(p, &host))
+}
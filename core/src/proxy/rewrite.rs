@@ -0,0 +1,358 @@
+//! Rewrite rules: edits the proxy applies to matching traffic on the fly.
+//!
+//! CORS injection is for developing a local frontend against an API that does
+//! not send CORS headers: preflights are answered by the proxy and never reach
+//! the upstream, other responses get the headers added.
+//!
+//! Rules are kept sorted by priority (highest first, ties in insertion order).
+//! The registry's [`RuleMatchMode`] decides whether only the first matching rule
+//! applies or every matching rule does.
+
+use crate::models::rewrite::{RewriteAction, RewriteRule, RewriteRuleInput, RuleMatchMode};
+use crate::models::HttpMethod;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Methods advertised on responses with injected CORS headers
+const DEFAULT_ALLOW_METHODS: &str = "GET, POST, PUT, PATCH, DELETE, OPTIONS";
+/// How long browsers may cache an injected preflight answer
+const PREFLIGHT_MAX_AGE_SECS: &str = "86400";
+
+#[derive(Default)]
+struct Registry {
+    rules: Vec<RewriteRule>,
+    mode: RuleMatchMode,
+}
+
+static RULES: Lazy<RwLock<Registry>> = Lazy::new(|| RwLock::new(Registry::default()));
+
+/// Rules in evaluation order
+pub fn list_rewrite_rules() -> Vec<RewriteRule> {
+    RULES.read().map(|r| r.rules.clone()).unwrap_or_default()
+}
+
+pub fn rewrite_match_mode() -> RuleMatchMode {
+    RULES.read().map(|r| r.mode).unwrap_or_default()
+}
+
+pub fn set_rewrite_match_mode(mode: RuleMatchMode) {
+    if let Ok(mut registry) = RULES.write() {
+        registry.mode = mode;
+    }
+}
+
+/// Put the rules in the order given by `ids` (first = highest priority) by
+/// reassigning priorities. Rules not listed keep their relative order after them.
+pub fn reorder_rewrite_rules(ids: &[String]) -> anyhow::Result<()> {
+    let mut registry = RULES
+        .write()
+        .map_err(|_| anyhow::anyhow!("rewrite rules poisoned"))?;
+    if let Some(unknown) = ids
+        .iter()
+        .find(|id| !registry.rules.iter().any(|r| &r.id == *id))
+    {
+        anyhow::bail!("unknown rewrite rule {unknown}");
+    }
+    let rank = |rule: &RewriteRule| ids.iter().position(|id| *id == rule.id);
+    registry
+        .rules
+        .sort_by_key(|rule| rank(rule).unwrap_or(usize::MAX));
+    let count = registry.rules.len() as i32;
+    for (i, rule) in registry.rules.iter_mut().enumerate() {
+        rule.priority = count - i as i32;
+    }
+    Ok(())
+}
+
+pub fn add_rewrite_rule(input: RewriteRuleInput) -> RewriteRule {
+    let rule = RewriteRule {
+        id: Uuid::new_v4().to_string(),
+        enabled: input.enabled,
+        method: input.method,
+        host_contains: input.host_contains,
+        path_contains: input.path_contains,
+        action: input.action,
+        priority: input.priority,
+    };
+    if let Ok(mut registry) = RULES.write() {
+        let at = registry
+            .rules
+            .iter()
+            .position(|r| r.priority < rule.priority)
+            .unwrap_or(registry.rules.len());
+        registry.rules.insert(at, rule.clone());
+    }
+    rule
+}
+
+pub fn remove_rewrite_rule(id: &str) -> bool {
+    let Ok(mut registry) = RULES.write() else {
+        return false;
+    };
+    let before = registry.rules.len();
+    registry.rules.retain(|rule| rule.id != id);
+    before != registry.rules.len()
+}
+
+/// Remove every rule and restore first-match evaluation
+pub fn clear_rewrite_rules() {
+    if let Ok(mut registry) = RULES.write() {
+        *registry = Registry::default();
+    }
+}
+
+fn rule_matches(rule: &RewriteRule, method: HttpMethod, host: &str, path: &str) -> bool {
+    let contains = |needle: &Option<String>, haystack: &str| {
+        needle.as_ref().is_none_or(|n| {
+            haystack
+                .to_ascii_lowercase()
+                .contains(&n.to_ascii_lowercase())
+        })
+    };
+    rule.enabled
+        && rule.method.is_none_or(|m| m == method)
+        && contains(&rule.host_contains, host)
+        && contains(&rule.path_contains, path)
+}
+
+/// Ids and actions of the rules that apply to a request, highest priority first
+fn matching_actions(method: HttpMethod, host: &str, path: &str) -> Vec<(String, RewriteAction)> {
+    let Ok(registry) = RULES.read() else {
+        return Vec::new();
+    };
+    let matching = registry
+        .rules
+        .iter()
+        .filter(|rule| rule_matches(rule, method, host, path))
+        .map(|rule| (rule.id.clone(), rule.action.clone()));
+    match registry.mode {
+        RuleMatchMode::FirstMatch => matching.take(1).collect(),
+        RuleMatchMode::AllMatching => matching.collect(),
+    }
+}
+
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// `Access-Control-Allow-Origin` (plus credentials when echoing a specific origin).
+/// An echoed origin depends on the request, so `Vary: Origin` is added to
+/// whatever `upstream_vary` the response already had.
+fn origin_headers(
+    allow_origin: Option<String>,
+    request_headers: &HashMap<String, String>,
+    upstream_vary: Option<&str>,
+) -> Vec<(String, String)> {
+    let echoed = allow_origin.is_none();
+    let origin = allow_origin
+        .or_else(|| header(request_headers, "origin").map(str::to_string))
+        .unwrap_or_else(|| "*".to_string());
+    let credentials = origin != "*";
+    let mut headers = vec![("Access-Control-Allow-Origin".to_string(), origin)];
+    if credentials {
+        headers.push((
+            "Access-Control-Allow-Credentials".to_string(),
+            "true".to_string(),
+        ));
+    }
+    if echoed {
+        headers.push(("Vary".to_string(), vary_with_origin(upstream_vary)));
+    }
+    headers
+}
+
+/// Lowercased, sorted header names for a CORS list. With credentials a `*`
+/// there is taken literally, so the names have to be spelled out.
+fn header_names(headers: &HashMap<String, String>) -> String {
+    let mut names: Vec<String> = headers
+        .keys()
+        .map(|name| name.to_ascii_lowercase())
+        .filter(|name| !name.starts_with("access-control-"))
+        .collect();
+    names.sort();
+    names.dedup();
+    names.join(", ")
+}
+
+fn vary_with_origin(upstream_vary: Option<&str>) -> String {
+    match upstream_vary.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(vary)
+            if vary == "*"
+                || vary
+                    .split(',')
+                    .any(|name| name.trim().eq_ignore_ascii_case("origin")) =>
+        {
+            vary.to_string()
+        }
+        Some(vary) => format!("{}, Origin", vary),
+        None => "Origin".to_string(),
+    }
+}
+
+/// Headers produced by rewrite rules, with the ids of the rules that applied
+#[derive(Debug, Default)]
+pub(crate) struct Rewrite {
+    pub headers: Vec<(String, String)>,
+    pub rule_ids: Vec<String>,
+}
+
+/// Headers for a 204 answer to a CORS preflight, if a CORS rule covers the
+/// request the preflight announces. Other OPTIONS requests are left alone.
+pub(crate) fn cors_preflight_headers(
+    method: HttpMethod,
+    host: &str,
+    path: &str,
+    request_headers: &HashMap<String, String>,
+) -> Option<Rewrite> {
+    if method != HttpMethod::Options {
+        return None;
+    }
+    let requested = header(request_headers, "access-control-request-method")?;
+    let requested_method = requested.parse::<HttpMethod>().ok()?;
+    let (rule_id, allow_origin) = matching_actions(requested_method, host, path)
+        .into_iter()
+        .find_map(|(id, action)| match action {
+            RewriteAction::Cors { allow_origin } => Some((id, allow_origin)),
+            _ => None,
+        })?;
+
+    let mut headers = origin_headers(allow_origin, request_headers, None);
+    headers.push((
+        "Access-Control-Allow-Methods".to_string(),
+        requested.trim().to_string(),
+    ));
+    headers.push((
+        "Access-Control-Allow-Headers".to_string(),
+        header(request_headers, "access-control-request-headers")
+            .unwrap_or("*")
+            .to_string(),
+    ));
+    headers.push((
+        "Access-Control-Max-Age".to_string(),
+        PREFLIGHT_MAX_AGE_SECS.to_string(),
+    ));
+    Some(Rewrite {
+        headers,
+        rule_ids: vec![rule_id],
+    })
+}
+
+/// Headers to set on a proxied response, replacing any the upstream sent.
+/// When several rules set the same header, the higher-priority one wins.
+/// `upstream_headers` are the response's own headers: CORS extends their
+/// `Vary` and, when credentials are allowed, exposes them by name.
+pub(crate) fn response_headers(
+    method: HttpMethod,
+    host: &str,
+    path: &str,
+    request_headers: &HashMap<String, String>,
+    upstream_headers: &HashMap<String, String>,
+) -> Rewrite {
+    let mut rewrite = Rewrite::default();
+    for (id, action) in matching_actions(method, host, path) {
+        let edits = match action {
+            RewriteAction::Cors { allow_origin } => {
+                let upstream_vary = header(upstream_headers, "vary");
+                let mut cors = origin_headers(allow_origin, request_headers, upstream_vary);
+                let credentials = cors
+                    .iter()
+                    .any(|(name, _)| name == "Access-Control-Allow-Credentials");
+                let (allow_headers, expose_headers) = if credentials {
+                    (
+                        header_names(request_headers),
+                        header_names(upstream_headers),
+                    )
+                } else {
+                    ("*".to_string(), "*".to_string())
+                };
+                cors.push((
+                    "Access-Control-Allow-Methods".to_string(),
+                    DEFAULT_ALLOW_METHODS.to_string(),
+                ));
+                if !allow_headers.is_empty() {
+                    cors.push(("Access-Control-Allow-Headers".to_string(), allow_headers));
+                }
+                if !expose_headers.is_empty() {
+                    cors.push(("Access-Control-Expose-Headers".to_string(), expose_headers));
+                }
+                cors
+            }
+            RewriteAction::SetResponseHeader { name, value } => vec![(name, value)],
+        };
+        for (name, value) in edits {
+            if !rewrite
+                .headers
+                .iter()
+                .any(|(n, _)| n.eq_ignore_ascii_case(&name))
+            {
+                rewrite.headers.push((name, value));
+            }
+        }
+        rewrite.rule_ids.push(id);
+    }
+    rewrite
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    fn set_header_rule(name: &str, value: &str, priority: i32) -> RewriteRule {
+        add_rewrite_rule(RewriteRuleInput {
+            enabled: true,
+            method: None,
+            host_contains: Some("example.com".to_string()),
+            path_contains: None,
+            action: RewriteAction::SetResponseHeader {
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+            priority,
+        })
+    }
+
+    #[test]
+    #[serial]
+    fn overlapping_rules_apply_in_priority_order() {
+        clear_rewrite_rules();
+        let low = set_header_rule("X-Env", "low", 1);
+        let high = set_header_rule("X-Env", "high", 5);
+        set_header_rule("X-Extra", "yes", 0);
+        let ids: Vec<_> = list_rewrite_rules().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids[..2], [high.id.clone(), low.id.clone()]);
+
+        let apply = || {
+            response_headers(
+                HttpMethod::Get,
+                "api.example.com",
+                "/",
+                &HashMap::new(),
+                &HashMap::new(),
+            )
+            .headers
+        };
+        // First match wins: only the highest-priority rule applies.
+        assert_eq!(apply(), vec![("X-Env".to_string(), "high".to_string())]);
+
+        // All matching: every rule applies, higher priority wins the conflict.
+        set_rewrite_match_mode(RuleMatchMode::AllMatching);
+        assert_eq!(
+            apply(),
+            vec![
+                ("X-Env".to_string(), "high".to_string()),
+                ("X-Extra".to_string(), "yes".to_string()),
+            ]
+        );
+
+        reorder_rewrite_rules(std::slice::from_ref(&low.id)).unwrap();
+        assert_eq!(apply()[0], ("X-Env".to_string(), "low".to_string()));
+        assert!(reorder_rewrite_rules(&["missing".to_string()]).is_err());
+        clear_rewrite_rules();
+    }
+}
@@ -4,25 +4,47 @@
 
 use crate::api::proxy_api::{is_running_internal, send_transaction_to_sink};
 use crate::models::breakpoint::RequestEdit;
-use crate::models::{HttpMethod, HttpTransaction, TransactionState};
+use crate::models::{
+    percent_decode, H2cUpgradeMode, HttpMethod, HttpTransaction, QueryRedaction, RequestTargetForm,
+    TransactionState,
+};
 use crate::proxy::breakpoints::{self, BreakpointContext};
-use crate::proxy::cert_manager::CertManager;
+use crate::proxy::cert_manager::{CertManager, LeafCacheStats};
+use crate::proxy::cert_portal;
+use crate::proxy::content_coding;
+use crate::proxy::dns_cache::{self, DnsCache};
+use crate::proxy::fingerprint::{self, ClientFingerprint, PrefixedStream};
+use crate::proxy::geoip;
+use crate::proxy::playback::{self, RecordedResponse};
+use crate::proxy::quiet_hosts;
+use crate::proxy::rewrite;
+use crate::proxy::sni::{self, SniPolicy};
+use crate::proxy::sse::EventStreamParser;
+use crate::proxy::throttle::{ConnectionThrottle, Throttled};
+use crate::proxy::transcript::Recorded;
+use crate::proxy::upstream_h2;
+use crate::proxy::upstream_pool::{self, ConnectionPool, PoolKey};
+use crate::proxy::upstream_tls;
+use crate::proxy::websocket::PerMessageDeflate;
 use crate::storage;
 use anyhow::{anyhow, Context};
-#[cfg(test)]
+use base64::Engine;
 use once_cell::sync::Lazy;
+use rustls::client::Resumption;
 use rustls::pki_types::ServerName;
-use rustls::{ClientConfig, RootCertStore};
+use rustls::{ClientConfig, HandshakeKind, RootCertStore};
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, VecDeque};
 #[cfg(test)]
 use std::future::Future;
 use std::io;
 use std::mem;
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
-#[cfg(test)]
 use std::sync::Mutex;
 use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
 use thiserror::Error;
 #[cfg(test)]
 use tokio::io::DuplexStream;
@@ -34,8 +56,67 @@ use webpki_roots::TLS_SERVER_ROOTS;
 
 const MAX_HEADER_BYTES: usize = 64 * 1024;
 const MAX_HEADER_COUNT: usize = 128;
-const MAX_BODY_CAPTURE_BYTES: usize = 512 * 1024;
+pub(crate) const MAX_BODY_CAPTURE_BYTES: usize = 512 * 1024;
 const MAX_REQUEST_BODY_BYTES: usize = 32 * 1024 * 1024; // 32MB hard cap on inbound bodies
+const MAX_INVALID_RESPONSE_CAPTURE_BYTES: usize = 4 * 1024;
+/// How often a streaming `text/event-stream` response is re-sent to the UI
+const SSE_EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Idle keep-alive connections to upstream servers, shared by all client connections.
+static UPSTREAM_POOL: Lazy<ConnectionPool<PooledUpstream>> = Lazy::new(|| {
+    ConnectionPool::new(
+        upstream_pool::MAX_IDLE_PER_ORIGIN,
+        upstream_pool::DEFAULT_IDLE_TIMEOUT,
+    )
+});
+
+/// Recent upstream DNS lookups, so reconnects don't re-resolve.
+static UPSTREAM_DNS: Lazy<DnsCache> = Lazy::new(|| DnsCache::new(dns_cache::DEFAULT_TTL));
+
+/// Certificate manager of the running proxy, for cache inspection from the API.
+static ACTIVE_CERT_MANAGER: Lazy<Mutex<Option<Arc<CertManager>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Leaf cache stats of the running proxy (None when stopped or HTTPS is off)
+pub fn cert_cache_stats() -> Option<LeafCacheStats> {
+    ACTIVE_CERT_MANAGER
+        .lock()
+        .ok()?
+        .as_ref()
+        .map(|manager| manager.cache_stats())
+}
+
+/// Clear the running proxy's leaf cache, returning how many entries were dropped
+pub fn clear_cert_cache() -> usize {
+    ACTIVE_CERT_MANAGER
+        .lock()
+        .ok()
+        .and_then(|guard| guard.as_ref().map(|manager| manager.clear_cache()))
+        .unwrap_or(0)
+}
+
+/// Close every idle upstream connection, so the next request to each origin
+/// handshakes again (e.g. with changed TLS settings)
+pub(crate) fn clear_upstream_pool() {
+    UPSTREAM_POOL.clear();
+}
+
+/// Connection-scoped headers never forwarded upstream (RFC 7230 section 6.1).
+/// `Transfer-Encoding` is not listed because request bodies are relayed with their
+/// original framing, and `Connection` itself is always rewritten.
+const DEFAULT_HOP_BY_HOP_HEADERS: &[&str] = &[
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Upgrade",
+];
+
+/// Headers the body is relayed by, never dropped as hop-by-hop: the body is
+/// forwarded with the client's framing, so removing them (e.g. via
+/// `Connection: Content-Length`) would desync the upstream connection.
+const FRAMING_HEADERS: &[&str] = &["Host", "Content-Length", "Transfer-Encoding"];
 
 #[derive(Debug, Error)]
 #[error("request body exceeds configured limit of {limit} bytes")]
@@ -49,12 +130,54 @@ impl RequestBodyTooLarge {
     }
 }
 
-fn build_tls_client_config() -> anyhow::Result<ClientConfig> {
+/// Upstream accepted the request but sent no response head in time
+#[derive(Debug, Error)]
+#[error("no response headers from upstream within {0:?}")]
+struct UpstreamResponseTimeout(Duration);
+
+/// Default wait for an upstream's response head once the request is sent
+const DEFAULT_RESPONSE_HEADER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ports CONNECT may target when tunnels are restricted to TLS services
+/// (HTTPS, alternate HTTPS, IMAPS, POP3S, SMTPS, DNS over TLS).
+const TLS_CONNECT_PORTS: &[u16] = &[443, 8443, 993, 995, 465, 853];
+
+/// A message head that ended (EOF or size cap) before its terminating blank line.
+#[derive(Debug, Error)]
+#[error("{reason}")]
+struct IncompleteMessageHead {
+    reason: &'static str,
+    partial: Vec<u8>,
+}
+
+/// The upstream sent bytes that are not an HTTP response (e.g. a TLS alert
+/// on a plain connection, or a raw error banner).
+#[derive(Debug, Error)]
+#[error("upstream returned non-HTTP data")]
+struct InvalidUpstreamResponse {
+    raw: Vec<u8>,
+}
+
+/// Upstream TLS client config. With `enable_h2`, `h2` is offered ahead of
+/// `http/1.1` and h2-negotiated upstreams are handled by [`upstream_h2`].
+/// Without `session_resumption`, no session tickets or IDs are stored, so every
+/// handshake is a full one; otherwise the rustls default in-memory cache is used.
+pub(crate) fn build_tls_client_config(
+    enable_h2: bool,
+    session_resumption: bool,
+) -> anyhow::Result<ClientConfig> {
     let root_store = RootCertStore::from_iter(TLS_SERVER_ROOTS.iter().cloned());
     let mut config = ClientConfig::builder()
         .with_root_certificates(root_store)
         .with_no_client_auth();
-    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    config.alpn_protocols = if enable_h2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+    if !session_resumption {
+        config.resumption = Resumption::disabled();
+    }
     Ok(config)
 }
 
@@ -68,6 +191,244 @@ pub struct ProxyConfig {
     pub enable_https: bool,
     /// Certificate storage root
     pub storage_path: String,
+    /// Capture responses only: request bodies stream straight through without
+    /// being buffered, and breakpoints are never applied to requests
+    pub response_only_mode: bool,
+    /// Responses larger than this (or without a Content-Length) are streamed to the
+    /// client instead of buffered. `None` uses the body capture cap, and larger
+    /// values are clamped to it so a buffered body never exceeds what is kept.
+    pub stream_threshold_bytes: Option<usize>,
+    /// Bytes of each request and response body to keep. `None` uses
+    /// [`MAX_BODY_CAPTURE_BYTES`].
+    pub body_capture_limit_bytes: Option<usize>,
+    /// Headers stripped before forwarding upstream, in addition to any named in the
+    /// request's `Connection` header. `None` uses the RFC 7230 hop-by-hop set.
+    pub hop_by_hop_headers: Option<Vec<String>>,
+    /// Record a raw byte transcript of every intercepted connection (debugging only)
+    pub record_transcripts: bool,
+    /// Close pooled upstream connections idle longer than this. `None` uses the pool default.
+    pub upstream_idle_timeout_secs: Option<u64>,
+    /// SNI patterns to intercept after CONNECT; others are tunneled untouched.
+    /// Empty intercepts every SNI not excluded.
+    pub sni_intercept_include: Vec<String>,
+    /// SNI patterns always tunneled without interception (wins over include)
+    pub sni_intercept_exclude: Vec<String>,
+    /// Keep a bounded prefix of non-HTTP upstream replies as the response body
+    pub capture_invalid_responses: bool,
+    /// Host patterns allowed to target the proxy's own listen address
+    pub self_loop_allowlist: Vec<String>,
+    /// Answer TRACE with 405 instead of forwarding it. TRACE echoes the request,
+    /// including cookies and auth headers, back to the caller, which is the basis
+    /// of cross-site tracing (XST) attacks.
+    pub block_trace: bool,
+    /// Refuse CONNECT to ports other than well-known TLS ports, so the proxy
+    /// cannot be used to tunnel arbitrary TCP (SSH, SMTP, ...)
+    pub connect_tls_ports_only: bool,
+    /// Refuse requests whose Host header is duplicated, malformed, or contradicts
+    /// an absolute-form target. Front-end and back-end servers resolving such a
+    /// request to different hosts is a request smuggling / cache poisoning vector.
+    pub strict_host_validation: bool,
+    /// Refuse requests with ambiguous framing (bare LF line endings, both
+    /// Content-Length and Transfer-Encoding, conflicting lengths, ...) instead
+    /// of only recording a security warning on the transaction
+    pub reject_ambiguous_framing: bool,
+    /// Mark transactions a previous run left Pending/Breakpointed (e.g. after a
+    /// crash mid-request) as Failed once older than this. Checked at startup
+    /// and periodically; transactions of the current run are never reaped.
+    pub abandoned_transaction_secs: Option<u64>,
+    /// Score each captured body's Shannon entropy (first 4 KiB) to help tell
+    /// compressed/encrypted payloads from text
+    pub body_entropy: bool,
+    /// Report the proxy's own measurements back to the client as `X-Cheddar-*`
+    /// response headers (total and waiting time so far, upstream IP)
+    pub debug_timing_headers: bool,
+    /// When no leaf certificate can be issued for a CONNECT host (e.g. a name
+    /// that is not valid in a certificate), tunnel the connection untouched
+    /// and record why, rather than failing it
+    pub tunnel_on_cert_failure: bool,
+    /// Record JA3/JA4 fingerprints of the ClientHello on intercepted HTTPS
+    /// connections, identifying the client's TLS stack
+    pub capture_client_fingerprint: bool,
+    /// Redact query strings in stored paths; the upstream still gets the full path
+    pub query_redaction: QueryRedaction,
+    /// Ping the upstream of a WebSocket idle this long. `None` disables keepalive
+    /// unless enabled for a single connection.
+    pub websocket_keepalive_secs: Option<u64>,
+    /// Fail the request with 504 if the upstream sends no response head this
+    /// long after the request was sent. `None` uses the default; 0 waits forever.
+    pub response_header_timeout_secs: Option<u64>,
+    /// Offer HTTP/2 to HTTPS upstreams. Clients still speak HTTP/1.1 to the
+    /// proxy; h2 responses are translated back (see [`upstream_h2`]).
+    pub enable_h2: bool,
+    /// Record non-standard message syntax on transactions as conformance notes
+    pub record_conformance_notes: bool,
+    /// Allow resumed upstream TLS handshakes (see [`build_tls_client_config`])
+    pub upstream_tls_resumption: bool,
+    /// Store request bodies; when off only their hash is kept
+    pub capture_request_bodies: bool,
+    /// Store response bodies; when off only their hash and size are kept
+    pub capture_response_bodies: bool,
+    /// Handling of `Upgrade: h2c` offers
+    pub h2c_upgrade: H2cUpgradeMode,
+}
+
+/// Per-request behaviour derived from [`ProxyConfig`], shared by every connection.
+#[derive(Debug, Clone)]
+struct InterceptOptions {
+    response_only_mode: bool,
+    stream_threshold_bytes: usize,
+    body_capture_limit: usize,
+    hop_by_hop_headers: Vec<String>,
+    record_transcripts: bool,
+    sni_policy: SniPolicy,
+    capture_invalid_responses: bool,
+    /// Address the proxy listens on, used to refuse requests that loop back into it
+    listen_addr: Option<SocketAddr>,
+    self_loop_allowlist: Vec<String>,
+    block_trace: bool,
+    connect_tls_ports_only: bool,
+    strict_host_validation: bool,
+    reject_ambiguous_framing: bool,
+    body_entropy: bool,
+    debug_timing_headers: bool,
+    tunnel_on_cert_failure: bool,
+    capture_client_fingerprint: bool,
+    query_redaction: QueryRedaction,
+    websocket_keepalive: Option<Duration>,
+    response_header_timeout: Option<Duration>,
+    record_conformance_notes: bool,
+    capture_request_bodies: bool,
+    capture_response_bodies: bool,
+    h2c_upgrade: H2cUpgradeMode,
+}
+
+impl Default for InterceptOptions {
+    fn default() -> Self {
+        Self {
+            response_only_mode: false,
+            stream_threshold_bytes: MAX_BODY_CAPTURE_BYTES,
+            body_capture_limit: MAX_BODY_CAPTURE_BYTES,
+            hop_by_hop_headers: default_hop_by_hop_headers(),
+            record_transcripts: false,
+            sni_policy: SniPolicy::default(),
+            capture_invalid_responses: false,
+            listen_addr: None,
+            self_loop_allowlist: Vec::new(),
+            block_trace: true,
+            connect_tls_ports_only: false,
+            strict_host_validation: false,
+            reject_ambiguous_framing: false,
+            body_entropy: false,
+            debug_timing_headers: false,
+            tunnel_on_cert_failure: true,
+            capture_client_fingerprint: false,
+            query_redaction: QueryRedaction::Off,
+            websocket_keepalive: None,
+            response_header_timeout: Some(DEFAULT_RESPONSE_HEADER_TIMEOUT),
+            record_conformance_notes: false,
+            capture_request_bodies: true,
+            capture_response_bodies: true,
+            h2c_upgrade: H2cUpgradeMode::Downgrade,
+        }
+    }
+}
+
+/// Methods a request can be repeated with without changing the outcome
+/// (RFC 9110 section 9.2.2)
+fn is_idempotent(method: HttpMethod) -> bool {
+    matches!(
+        method,
+        HttpMethod::Get
+            | HttpMethod::Head
+            | HttpMethod::Options
+            | HttpMethod::Put
+            | HttpMethod::Delete
+            | HttpMethod::Trace
+    )
+}
+
+fn default_hop_by_hop_headers() -> Vec<String> {
+    DEFAULT_HOP_BY_HOP_HEADERS
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+}
+
+impl InterceptOptions {
+    /// Largest response read into memory before forwarding; anything bigger is
+    /// streamed through [`BodyCapture`]
+    fn buffer_limit(&self) -> usize {
+        self.stream_threshold_bytes.min(self.body_capture_limit)
+    }
+
+    /// Bytes of each request body to keep: none in response-only mode or with
+    /// request capture off
+    fn request_capture_limit(&self) -> usize {
+        if self.response_only_mode || !self.capture_request_bodies {
+            0
+        } else {
+            self.body_capture_limit
+        }
+    }
+
+    /// Bytes of each response body to keep
+    fn response_capture_limit(&self) -> usize {
+        if self.capture_response_bodies {
+            self.body_capture_limit
+        } else {
+            0
+        }
+    }
+
+    fn from_config(config: &ProxyConfig) -> Self {
+        let body_capture_limit = config
+            .body_capture_limit_bytes
+            .unwrap_or(MAX_BODY_CAPTURE_BYTES);
+        Self {
+            response_only_mode: config.response_only_mode,
+            stream_threshold_bytes: config.stream_threshold_bytes.unwrap_or(body_capture_limit),
+            body_capture_limit,
+            hop_by_hop_headers: config
+                .hop_by_hop_headers
+                .clone()
+                .unwrap_or_else(default_hop_by_hop_headers),
+            record_transcripts: config.record_transcripts,
+            sni_policy: SniPolicy::new(
+                config.sni_intercept_include.clone(),
+                config.sni_intercept_exclude.clone(),
+            ),
+            capture_invalid_responses: config.capture_invalid_responses,
+            listen_addr: None,
+            self_loop_allowlist: config
+                .self_loop_allowlist
+                .iter()
+                .map(|p| p.trim().to_ascii_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            block_trace: config.block_trace,
+            connect_tls_ports_only: config.connect_tls_ports_only,
+            strict_host_validation: config.strict_host_validation,
+            reject_ambiguous_framing: config.reject_ambiguous_framing,
+            body_entropy: config.body_entropy,
+            debug_timing_headers: config.debug_timing_headers,
+            tunnel_on_cert_failure: config.tunnel_on_cert_failure,
+            capture_client_fingerprint: config.capture_client_fingerprint,
+            query_redaction: config.query_redaction,
+            websocket_keepalive: config
+                .websocket_keepalive_secs
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            response_header_timeout: match config.response_header_timeout_secs {
+                None => Some(DEFAULT_RESPONSE_HEADER_TIMEOUT),
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+            },
+            record_conformance_notes: config.record_conformance_notes,
+            capture_request_bodies: config.capture_request_bodies,
+            capture_response_bodies: config.capture_response_bodies,
+            h2c_upgrade: config.h2c_upgrade,
+        }
+    }
 }
 
 /// Run the proxy server
@@ -82,13 +443,37 @@ pub async fn run_server(config: ProxyConfig) -> anyhow::Result<()> {
     } else {
         None
     };
+    if let Ok(mut active) = ACTIVE_CERT_MANAGER.lock() {
+        *active = cert_manager.clone();
+    }
 
+    upstream_tls::set_client_defaults(config.enable_h2, config.upstream_tls_resumption)?;
     let tls_client_config = if config.enable_https {
-        Some(Arc::new(build_tls_client_config()?))
+        Some(Arc::new(build_tls_client_config(
+            config.enable_h2,
+            config.upstream_tls_resumption,
+        )?))
     } else {
         None
     };
 
+    let options = Arc::new(InterceptOptions {
+        listen_addr: listener.local_addr().ok(),
+        ..InterceptOptions::from_config(&config)
+    });
+
+    UPSTREAM_POOL.set_idle_timeout(
+        config
+            .upstream_idle_timeout_secs
+            .map(std::time::Duration::from_secs)
+            .unwrap_or(upstream_pool::DEFAULT_IDLE_TIMEOUT),
+    );
+    let reaper = tokio::spawn(reap_idle_upstreams());
+    let abandoned_reaper = config
+        .abandoned_transaction_secs
+        .filter(|secs| *secs > 0)
+        .map(|secs| tokio::spawn(reap_abandoned_transactions(Duration::from_secs(secs))));
+
     loop {
         if !is_running_internal() {
             break;
@@ -103,8 +488,10 @@ pub async fn run_server(config: ProxyConfig) -> anyhow::Result<()> {
                 tracing::debug!("Connection from {}", peer_addr);
                 let cert_manager = cert_manager.clone();
                 let tls_client_config = tls_client_config.clone();
+                let options = options.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = handle_connection(socket, cert_manager, tls_client_config).await
+                    if let Err(e) =
+                        handle_connection(socket, cert_manager, tls_client_config, options).await
                     {
                         // Downgrade expected errors to debug level:
                         // - "tls handshake eof" = client rejected intercepted cert
@@ -131,10 +518,53 @@ pub async fn run_server(config: ProxyConfig) -> anyhow::Result<()> {
         }
     }
 
+    reaper.abort();
+    if let Some(task) = abandoned_reaper {
+        task.abort();
+    }
+    UPSTREAM_POOL.clear();
+    UPSTREAM_DNS.clear();
+    if let Ok(mut active) = ACTIVE_CERT_MANAGER.lock() {
+        *active = None;
+    }
     tracing::info!("Proxy server stopped");
     Ok(())
 }
 
+/// Periodically close pooled upstream connections that outlived the idle TTL,
+/// so servers' half-closed sockets are not held open until the next checkout.
+async fn reap_idle_upstreams() {
+    loop {
+        let interval = (UPSTREAM_POOL.idle_timeout() / 2).max(std::time::Duration::from_secs(1));
+        tokio::time::sleep(interval).await;
+        let reaped = UPSTREAM_POOL.reap_expired();
+        if reaped > 0 {
+            tracing::debug!("Closed {reaped} idle upstream connection(s)");
+        }
+    }
+}
+
+/// Fail transactions a previous run left in a non-terminal state for longer
+/// than `max_age`, once right away and then periodically for leftovers that
+/// were still younger than that at startup.
+async fn reap_abandoned_transactions(max_age: Duration) {
+    let interval = max_age.clamp(Duration::from_secs(1), Duration::from_secs(60));
+    loop {
+        match storage::fail_abandoned_transactions(max_age.as_secs()).await {
+            Ok(reaped) => {
+                if !reaped.is_empty() {
+                    tracing::info!("Marked {} abandoned transaction(s) as failed", reaped.len());
+                }
+                for tx in reaped {
+                    send_transaction_to_sink(tx);
+                }
+            }
+            Err(err) => tracing::warn!("Failed to reap abandoned transactions: {}", err),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
 /// Keep-alive idle timeout in seconds
 const KEEP_ALIVE_TIMEOUT_SECS: u64 = 30;
 
@@ -154,18 +584,24 @@ async fn handle_connection(
     mut socket: TcpStream,
     cert_manager: Option<Arc<CertManager>>,
     tls_client_config: Option<Arc<ClientConfig>>,
+    options: Arc<InterceptOptions>,
 ) -> anyhow::Result<()> {
     let mut request_number: u32 = 0;
+    let transcript_id = options
+        .record_transcripts
+        .then(|| uuid::Uuid::new_v4().to_string());
+    let mut throttle = ConnectionThrottle::default();
 
     loop {
         request_number += 1;
         let connection_reused = request_number > 1;
         let req_start = Instant::now();
+        let mut client = Recorded::client(&mut socket, transcript_id.as_deref());
 
         // Read request with keep-alive timeout
-        let parsed_request = match tokio::time::timeout(
+        let mut parsed_request = match tokio::time::timeout(
             tokio::time::Duration::from_secs(KEEP_ALIVE_TIMEOUT_SECS),
-            read_http_request(&mut socket, RequestScheme::Http),
+            read_http_request(&mut client, RequestScheme::Http),
         )
         .await
         {
@@ -175,7 +611,7 @@ async fn handle_connection(
                 if request_number == 1 {
                     tracing::warn!("Failed to parse request: {err}");
                     let _ = respond_with_status(
-                        &mut socket,
+                        &mut client,
                         400,
                         "Bad Request",
                         "Unable to parse HTTP request",
@@ -195,19 +631,50 @@ async fn handle_connection(
         let keep_alive =
             should_keep_alive(&parsed_request.version, &parsed_request.request_headers);
 
+        // The CA download page is answered here, never forwarded
+        if parsed_request.method != HttpMethod::Connect
+            && cert_portal::is_portal_host(&parsed_request.host)
+        {
+            let ca_cert_pem = cert_manager
+                .as_ref()
+                .map(|manager| manager.ca_cert_pem.as_str());
+            let reusable =
+                answer_portal_request(&mut client, &mut parsed_request, ca_cert_pem).await?;
+            if !keep_alive || !reusable {
+                break;
+            }
+            continue;
+        }
+
         // CONNECT method takes over the connection completely
         if parsed_request.method == HttpMethod::Connect {
-            handle_connect_tunnel(socket, parsed_request, cert_manager, tls_client_config).await?;
+            if options.connect_tls_ports_only && !TLS_CONNECT_PORTS.contains(&parsed_request.port) {
+                refuse_connect(&mut client, &parsed_request).await?;
+                return Ok(());
+            }
+            handle_connect_tunnel(
+                socket,
+                parsed_request,
+                cert_manager,
+                tls_client_config,
+                options,
+                transcript_id,
+                throttle,
+            )
+            .await?;
             return Ok(()); // Connection is now a tunnel, exit
         }
 
         // Process the request
         if let Err(e) = process_request(
-            &mut socket,
+            &mut client,
             parsed_request,
             req_start,
             tls_client_config.clone(),
             connection_reused,
+            &options,
+            transcript_id.as_deref(),
+            &mut throttle,
         )
         .await
         {
@@ -223,6 +690,36 @@ async fn handle_connection(
     Ok(())
 }
 
+/// Answer a request for the CA download page. Any request body is read and
+/// discarded first so the next request on the connection starts at a request
+/// boundary; returns false when that failed and the connection must close.
+async fn answer_portal_request<S>(
+    client: &mut S,
+    parsed_request: &mut ParsedRequest,
+    ca_cert_pem: Option<&str>,
+) -> anyhow::Result<bool>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let drained = forward_request_body(
+        client,
+        &mut tokio::io::sink(),
+        parsed_request,
+        &mut BodyCapture::new(0),
+    )
+    .await;
+    if let Err(err) = &drained {
+        tracing::debug!("Could not discard portal request body: {err}");
+    }
+    let response = cert_portal::portal_response(
+        &parsed_request.path,
+        header_value(&parsed_request.request_headers, "user-agent").as_deref(),
+        ca_cert_pem,
+    );
+    client.write_all(&response).await?;
+    Ok(drained.is_ok())
+}
+
 #[cfg(test)]
 async fn handle_connection_with_stream<S>(
     client: &mut S,
@@ -233,15 +730,29 @@ where
 {
     let req_start = Instant::now();
     let parsed_request = read_http_request(client, RequestScheme::Http).await?;
-    process_request(client, parsed_request, req_start, tls_client_config, false).await
+    process_request(
+        client,
+        parsed_request,
+        req_start,
+        tls_client_config,
+        false,
+        &InterceptOptions::default(),
+        None,
+        &mut ConnectionThrottle::default(),
+    )
+    .await
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_request<S>(
     client: &mut S,
     mut parsed_request: ParsedRequest,
     req_start: Instant,
     tls_client_config: Option<Arc<ClientConfig>>,
     connection_reused: bool,
+    options: &InterceptOptions,
+    transcript_id: Option<&str>,
+    throttle: &mut ConnectionThrottle,
 ) -> anyhow::Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send,
@@ -251,12 +762,31 @@ where
         &parsed_request.scheme,
         &parsed_request.host,
         parsed_request.port,
-        &parsed_request.path,
+        &options.query_redaction.apply(&parsed_request.path),
         parsed_request.request_headers.clone(),
     );
+    tx.query_redacted = tx.path != parsed_request.path;
+    tx.request_header_list = parsed_request.header_list.clone();
     tx.http_version = parsed_request.version.clone();
+    tx.client_http_version = Some(parsed_request.version.clone());
     tx.connection_reused = connection_reused;
+    tx.transcript_id = transcript_id.map(str::to_string);
+    tx.url_credentials = parsed_request.url_credentials.clone();
+    tx.request_target_form = Some(parsed_request.target_form);
     tx.request_content_type = header_value(&parsed_request.request_headers, "content-type");
+    tx.was_intercepted = parsed_request.intercepted;
+    if let Some(fingerprint) = &parsed_request.client_fingerprint {
+        tx.client_ja3 = Some(fingerprint.ja3_hash.clone());
+        tx.client_ja4 = Some(fingerprint.ja4.clone());
+    }
+    tx.security_warnings = parsed_request
+        .framing_anomalies
+        .iter()
+        .map(|problem| format!("Potential request smuggling: {problem}"))
+        .collect();
+    if options.record_conformance_notes {
+        tx.conformance_notes = parsed_request.conformance_notes.clone();
+    }
 
     // Detect WebSocket upgrade request
     let is_websocket_upgrade = parsed_request.method == HttpMethod::Get
@@ -278,9 +808,131 @@ where
 
     tx.is_websocket = is_websocket_upgrade;
 
+    // h2c only exists on cleartext connections; over TLS the offer is bogus.
+    let is_h2c_tunnel = if parsed_request.offers_h2c_upgrade() {
+        let mode = if parsed_request.scheme.eq_ignore_ascii_case("http") {
+            options.h2c_upgrade
+        } else {
+            H2cUpgradeMode::Downgrade
+        };
+        if mode == H2cUpgradeMode::Downgrade {
+            parsed_request.strip_h2c_upgrade();
+        }
+        tx.h2c_upgrade = Some(mode);
+        tx.applied_actions.push(match mode {
+            H2cUpgradeMode::Downgrade => "h2c:downgrade".to_string(),
+            H2cUpgradeMode::Tunnel => "h2c:tunnel".to_string(),
+        });
+        mode == H2cUpgradeMode::Tunnel
+    } else {
+        false
+    };
+
     send_transaction_to_sink(tx.clone());
 
-    if let Err(err) = handle_breakpoints(&mut tx, &mut parsed_request).await {
+    if options.block_trace && parsed_request.method == HttpMethod::Trace {
+        tracing::info!(
+            "Blocked TRACE request to {}:{}",
+            parsed_request.host,
+            parsed_request.port
+        );
+        // A 405 must say which methods are allowed: everything but TRACE.
+        respond_with_headers(
+            client,
+            405,
+            "Method Not Allowed",
+            &[(
+                "Allow",
+                "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS, CONNECT",
+            )],
+            "TRACE is disabled",
+        )
+        .await?;
+        tx.state = TransactionState::Failed;
+        tx.status_code = Some(405);
+        tx.status_message = Some("Method Not Allowed".to_string());
+        tx.notes = Some("TRACE blocked by proxy policy".to_string());
+        tx.applied_actions.push("block:trace".to_string());
+        send_transaction_to_sink(tx);
+        return Ok(());
+    }
+
+    if options.strict_host_validation {
+        if let Some(problem) = host_ambiguity(&parsed_request) {
+            tracing::warn!(
+                "Rejected request to {}:{}: {problem}",
+                parsed_request.host,
+                parsed_request.port
+            );
+            respond_with_status(client, 400, "Bad Request", &problem).await?;
+            tx.state = TransactionState::Failed;
+            tx.status_code = Some(400);
+            tx.status_message = Some("Bad Request".to_string());
+            tx.notes = Some(format!("Rejected by strict Host validation: {problem}"));
+            tx.applied_actions.push("reject:ambiguous-host".to_string());
+            send_transaction_to_sink(tx);
+            return Ok(());
+        }
+    }
+
+    if options.reject_ambiguous_framing && !parsed_request.framing_anomalies.is_empty() {
+        let problems = parsed_request.framing_anomalies.join("; ");
+        tracing::warn!(
+            "Rejected request to {}:{}: {problems}",
+            parsed_request.host,
+            parsed_request.port
+        );
+        respond_with_status(client, 400, "Bad Request", "Ambiguous request framing").await?;
+        tx.state = TransactionState::Failed;
+        tx.status_code = Some(400);
+        tx.status_message = Some("Bad Request".to_string());
+        tx.notes = Some(format!("Rejected for ambiguous framing: {problems}"));
+        tx.applied_actions
+            .push("reject:ambiguous-framing".to_string());
+        send_transaction_to_sink(tx);
+        return Ok(());
+    }
+
+    if let Some(preflight) = rewrite::cors_preflight_headers(
+        parsed_request.method,
+        &parsed_request.host,
+        &parsed_request.path,
+        &parsed_request.request_headers,
+    ) {
+        record_rewrite_rules(&mut tx, preflight.rule_ids);
+        let cors_headers = preflight.headers;
+        let mut response = String::from("HTTP/1.1 204 No Content\r\n");
+        for (name, value) in &cors_headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        response.push_str("Content-Length: 0\r\n\r\n");
+        client.write_all(response.as_bytes()).await?;
+        tx.state = TransactionState::Completed;
+        tx.status_code = Some(204);
+        tx.status_message = Some("No Content".to_string());
+        tx.response_headers = Some(cors_headers.into_iter().collect());
+        tx.response_body = Some(Vec::new());
+        tx.response_size = Some(0);
+        tx.notes = Some("CORS preflight answered by rewrite rule".to_string());
+        tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
+        persist_and_stream(tx).await;
+        return Ok(());
+    }
+
+    let breakpoint_result = if options.response_only_mode {
+        Ok(())
+    } else {
+        handle_breakpoints(
+            &mut tx,
+            &mut parsed_request,
+            options.request_capture_limit(),
+        )
+        .await
+    };
+    // A breakpoint edit may have replaced the stored path with the full one.
+    tx.path = options.query_redaction.apply(&tx.path);
+    tx.query_redacted = tx.path != parsed_request.path;
+    if let Err(err) = breakpoint_result {
         tracing::info!("Request aborted by breakpoint: {}", err);
         respond_with_status(client, 409, "Conflict", "Request aborted at breakpoint").await?;
         tx.state = TransactionState::Failed;
@@ -290,9 +942,55 @@ where
         return Ok(());
     }
 
-    let (mut upstream, conn_timing) =
-        match connect_upstream(&parsed_request, tls_client_config).await {
-            Ok(result) => result,
+    if targets_own_listener(&parsed_request, options).await {
+        tracing::warn!(
+            "Refusing request to the proxy's own address {}:{}",
+            parsed_request.host,
+            parsed_request.port
+        );
+        respond_with_status(
+            client,
+            421,
+            "Misdirected Request",
+            "Request targets the proxy itself",
+        )
+        .await?;
+        tx.state = TransactionState::Failed;
+        tx.status_code = Some(421);
+        tx.status_message = Some("Misdirected Request".to_string());
+        tx.notes = Some("Request loops back into the proxy's own listen address".to_string());
+        tx.applied_actions.push("reject:proxy-loop".to_string());
+        send_transaction_to_sink(tx);
+        return Ok(());
+    }
+
+    // Without request capture the body is forwarded but never retained.
+    let request_capture_limit = options.request_capture_limit();
+    if let Some(config) = throttle.refresh() {
+        tx.applied_actions.push(config.label());
+    }
+
+    // A pooled connection may have been closed by the server while idle. If nothing
+    // was streamed from the client yet, retry once on a fresh connection.
+    let mut allow_pooled = true;
+    tx.timing.blocked_ms = Some(req_start.elapsed().as_millis() as u32);
+    let (mut upstream, response_head_result, waiting_start) = loop {
+        let connected =
+            connect_upstream(&parsed_request, tls_client_config.clone(), allow_pooled).await;
+        let (mut upstream, conn_timing) = match connected {
+            Ok((mut stream, timing)) => {
+                if let UpstreamStream::Recorded(recorded) = &mut stream {
+                    let accept = header_value(&parsed_request.request_headers, "accept-encoding");
+                    recorded.encode_for_client(accept.as_deref());
+                    tx.notes = Some(format!(
+                        "Served from recording {}",
+                        recorded.transaction_id()
+                    ));
+                    tx.applied_actions
+                        .push(format!("mock:{}", recorded.transaction_id()));
+                }
+                (Recorded::upstream(stream, transcript_id), timing)
+            }
             Err(err) => {
                 tracing::error!(
                     "Failed to connect to upstream {}:{} - {}",
@@ -315,55 +1013,118 @@ where
                 return Ok(());
             }
         };
+        let can_retry = conn_timing.reused && parsed_request.body_kind.is_replayable();
+
+        // Store connection timing and metadata
+        tx.timing.dns_lookup_ms = Some(conn_timing.dns_ms);
+        tx.timing.tcp_connect_ms = Some(conn_timing.tcp_ms);
+        tx.timing.tls_handshake_ms = conn_timing.tls_ms;
+        tx.server_ip = conn_timing.server_ip;
+        tx.tls_version = conn_timing.tls_version;
+        tx.tls_cipher = conn_timing.tls_cipher;
+        tx.tls_resumed = conn_timing.tls_resumed;
+        tx.upstream_connection_reused = conn_timing.reused;
+
+        if upstream_h2::is_h2(upstream.get_ref().negotiated_alpn()) {
+            return exchange_over_h2(
+                client,
+                upstream,
+                parsed_request,
+                tx,
+                options,
+                req_start,
+                throttle,
+            )
+            .await;
+        }
 
-    // Store connection timing and metadata
-    tx.timing.dns_lookup_ms = Some(conn_timing.dns_ms);
-    tx.timing.tcp_connect_ms = Some(conn_timing.tcp_ms);
-    tx.timing.tls_handshake_ms = conn_timing.tls_ms;
-    tx.server_ip = conn_timing.server_ip;
-    tx.tls_version = conn_timing.tls_version;
-    tx.tls_cipher = conn_timing.tls_cipher;
-
-    // Measure request send time
-    let send_start = Instant::now();
-    let mut request_capture = BodyCapture::new(MAX_BODY_CAPTURE_BYTES);
-    let forward_result = forward_request_to_upstream(
-        client,
-        &mut upstream,
-        &mut parsed_request,
-        &mut request_capture,
-    )
-    .await;
-    tx.request_body = request_capture.into_option();
+        // Measure request send time
+        let send_start = Instant::now();
+        let mut request_capture = BodyCapture::new(request_capture_limit);
+        let forward_result = forward_request_to_upstream(
+            client,
+            &mut Throttled::new(&mut upstream, throttle.upload()),
+            &mut parsed_request,
+            &mut request_capture,
+            &options.hop_by_hop_headers,
+        )
+        .await;
 
-    if let Err(err) = forward_result {
-        let is_too_large = err.downcast_ref::<RequestBodyTooLarge>().is_some();
-        let (code, label, body) = if is_too_large {
-            (
-                413,
-                "Payload Too Large",
-                "Request body exceeds allowed size",
-            )
-        } else {
-            (400, "Bad Request", "Failed to read request body")
-        };
-        tracing::warn!("Failed to forward request upstream: {err}");
-        respond_with_status(client, code, label, body).await?;
-        tx.state = TransactionState::Failed;
-        tx.status_code = Some(code);
-        tx.status_message = Some(label.to_string());
-        send_transaction_to_sink(tx);
-        return Ok(());
-    }
+        if let Err(err) = forward_result {
+            if can_retry {
+                tracing::debug!("Pooled upstream connection failed ({err}), reconnecting");
+                allow_pooled = false;
+                continue;
+            }
+            (tx.request_body, tx.request_body_sha256) = request_capture.finish();
+            tx.refresh_form_fields();
+            tx.refresh_jsonrpc();
+            let is_too_large = err.downcast_ref::<RequestBodyTooLarge>().is_some();
+            let (code, label, body) = if is_too_large {
+                (
+                    413,
+                    "Payload Too Large",
+                    "Request body exceeds allowed size",
+                )
+            } else {
+                (400, "Bad Request", "Failed to read request body")
+            };
+            tracing::warn!("Failed to forward request upstream: {err}");
+            respond_with_status(client, code, label, body).await?;
+            tx.state = TransactionState::Failed;
+            tx.status_code = Some(code);
+            tx.status_message = Some(label.to_string());
+            send_transaction_to_sink(tx);
+            return Ok(());
+        }
+        (tx.request_body, tx.request_body_sha256) = request_capture.finish();
+        tx.refresh_form_fields();
+        tx.refresh_jsonrpc();
 
-    let _ = upstream.flush().await;
-    tx.timing.request_send_ms = Some(send_start.elapsed().as_millis() as u32);
+        let _ = upstream.flush().await;
+        tx.timing.request_send_ms = Some(send_start.elapsed().as_millis() as u32);
 
-    // Measure waiting time (TTFB - time to first byte)
-    let waiting_start = Instant::now();
+        // Measure waiting time (TTFB - time to first byte)
+        let waiting_start = Instant::now();
+        let response_head_result = match options.response_header_timeout {
+            Some(limit) => tokio::time::timeout(limit, read_response_head(&mut upstream))
+                .await
+                .unwrap_or_else(|_| Err(UpstreamResponseTimeout(limit).into())),
+            None => read_response_head(&mut upstream).await,
+        };
+        if let Err(err) = &response_head_result {
+            // A stale pooled connection fails fast; a slow upstream is not retried.
+            // The request may have reached the server by now, so only methods
+            // that are safe to repeat are sent again.
+            if can_retry
+                && is_idempotent(parsed_request.method)
+                && !err.is::<UpstreamResponseTimeout>()
+            {
+                tracing::debug!("Pooled upstream connection failed ({err}), reconnecting");
+                allow_pooled = false;
+                continue;
+            }
+        }
+        break (upstream, response_head_result, waiting_start);
+    };
+    let pool_key = PoolKey::new(
+        &parsed_request.scheme,
+        &parsed_request.host,
+        parsed_request.port,
+    );
 
-    match read_response_head(&mut upstream).await {
+    match response_head_result {
         Ok(mut response_head) => {
+            if let Some(latency) = throttle.latency() {
+                tokio::time::sleep(latency).await;
+            }
+            let (upload, download) = throttle.buckets();
+            let client = &mut Throttled::new(client, download);
+            tx.upstream_http_version = Some(response_head.version.clone());
+            if options.record_conformance_notes {
+                tx.conformance_notes
+                    .extend(response_head.conformance_notes.iter().cloned());
+            }
             // Handle WebSocket upgrade (101 Switching Protocols)
             if is_websocket_upgrade && response_head.status_code == 101 {
                 tx.timing.waiting_ms = Some(waiting_start.elapsed().as_millis() as u32);
@@ -378,6 +1139,7 @@ where
 
                 tx.status_code = Some(101);
                 tx.status_message = Some(response_head.reason.clone());
+                tx.response_header_list = response_head.header_list.clone();
                 tx.response_headers = Some(response_head.headers.clone());
                 tx.state = TransactionState::Completed;
                 tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
@@ -394,16 +1156,73 @@ where
 
                 // Now tunnel the WebSocket connection with frame parsing
                 let connection_id = tx.id.clone();
-                websocket_tunnel(client, &mut upstream, connection_id).await?;
+                let deflate = header_value(&response_head.headers, "sec-websocket-extensions")
+                    .and_then(|value| PerMessageDeflate::from_response_header(&value));
+                websocket_tunnel(
+                    client,
+                    &mut Throttled::new(&mut upstream, upload),
+                    connection_id,
+                    options.websocket_keepalive,
+                    deflate,
+                )
+                .await?;
+
+                return Ok(());
+            }
+
+            let has_body = response_has_body(parsed_request.method, response_head.status_code);
+            let content_length = if has_body {
+                header_value(&response_head.headers, "content-length")
+                    .and_then(|v| v.parse::<usize>().ok())
+            } else {
+                Some(0)
+            };
+            let is_chunked = has_body
+                && header_value(&response_head.headers, "transfer-encoding")
+                    .map(|v| v.to_ascii_lowercase().contains("chunked"))
+                    .unwrap_or(false);
+            // Accepted h2c upgrade: the connection now speaks HTTP/2, relayed as is
+            if is_h2c_tunnel && response_head.status_code == 101 {
+                tx.timing.waiting_ms = Some(waiting_start.elapsed().as_millis() as u32);
+                client.write_all(&response_head.raw_head).await?;
+                if !response_head.body_prefix.is_empty() {
+                    client.write_all(&response_head.body_prefix).await?;
+                }
+                client.flush().await?;
+
+                tx.status_code = Some(101);
+                tx.status_message = Some(response_head.reason.clone());
+                tx.response_header_list = response_head.header_list.clone();
+                tx.response_headers = Some(response_head.headers.clone());
+                tx.upstream_http_version = Some("HTTP/2".to_string());
+                tx.state = TransactionState::Completed;
+                tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
+                tx.notes = Some("Upgraded to h2c; HTTP/2 traffic tunneled uncaptured".to_string());
+                persist_and_stream(tx).await;
 
+                let upstream = &mut Throttled::new(&mut upstream, upload);
+                if let Err(e) = tokio::io::copy_bidirectional(client, upstream).await {
+                    tracing::debug!("h2c tunnel closed: {}", e);
+                }
                 return Ok(());
             }
 
-            let content_length = header_value(&response_head.headers, "content-length")
-                .and_then(|v| v.parse::<usize>().ok());
-            let is_chunked = header_value(&response_head.headers, "transfer-encoding")
-                .map(|v| v.to_ascii_lowercase().contains("chunked"))
-                .unwrap_or(false);
+            let keep_upstream = !is_websocket_upgrade && response_allows_reuse(&response_head);
+            let rewrite = rewrite::response_headers(
+                parsed_request.method,
+                &parsed_request.host,
+                &parsed_request.path,
+                &parsed_request.request_headers,
+                &response_head.headers,
+            );
+            record_rewrite_rules(&mut tx, rewrite.rule_ids);
+            let mut injected = rewrite.headers;
+            if options.debug_timing_headers {
+                injected.extend(debug_timing_headers(&tx, req_start, waiting_start));
+            }
+            if !injected.is_empty() {
+                set_response_headers(&mut response_head, &injected);
+            }
 
             if is_chunked {
                 // TTFB is time until we got response headers
@@ -411,44 +1230,85 @@ where
 
                 let download_start = Instant::now();
                 client.write_all(&response_head.raw_head).await?;
-                let (captured_body, total_len) =
-                    forward_chunked_body(response_head.body_prefix, &mut upstream, client).await?;
+                let mut response_capture = BodyCapture::for_response(&tx, &response_head, options);
+                let total_len = forward_chunked_body(
+                    response_head.body_prefix,
+                    &mut upstream,
+                    client,
+                    &mut response_capture,
+                )
+                .await?;
                 tx.timing.content_download_ms = Some(download_start.elapsed().as_millis() as u32);
+                if keep_upstream {
+                    release_upstream(pool_key, upstream.into_inner(), &tx);
+                }
 
                 tx.status_code = Some(response_head.status_code);
                 tx.status_message = Some(response_head.reason.clone());
+                tx.response_header_list = response_head.header_list.clone();
                 tx.response_headers = Some(response_head.headers.clone());
-                tx.response_body = Some(captured_body);
+                (tx.response_body, tx.response_body_sha256) = response_capture.finish();
                 tx.response_content_type = header_value(&response_head.headers, "content-type");
                 tx.response_size = Some(total_len);
+                decode_response_body(&mut tx, options.response_capture_limit());
+                tx.refresh_grpc_frames();
+                tx.refresh_server_timing();
+                tx.refresh_pagination();
+                tx.refresh_jsonrpc();
+                if options.body_entropy {
+                    tx.refresh_body_entropy();
+                }
                 tx.state = TransactionState::Completed;
                 tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
                 persist_and_stream(tx).await;
                 return Ok(());
             }
 
-            let should_stream = content_length.is_none()
-                || content_length
-                    .map(|len| len > MAX_BODY_CAPTURE_BYTES)
-                    .unwrap_or(true);
+            let should_stream = content_length
+                .map(|len| len > options.buffer_limit())
+                .unwrap_or(true);
 
             if should_stream {
                 // TTFB is time until we got response headers
                 tx.timing.waiting_ms = Some(waiting_start.elapsed().as_millis() as u32);
 
                 let download_start = Instant::now();
+                let mut response_capture = BodyCapture::for_response(&tx, &response_head, options);
                 client.write_all(&response_head.raw_head).await?;
-                let mut streamed_bytes = response_head.body_prefix.len() as u64;
-                if !response_head.body_prefix.is_empty() {
-                    client.write_all(&response_head.body_prefix).await?;
+                let mut body_prefix = mem::take(&mut response_head.body_prefix);
+                if let Some(len) = content_length {
+                    body_prefix.truncate(len);
                 }
-                streamed_bytes += stream_response_body(&mut upstream, client).await?;
+                let mut streamed_bytes = body_prefix.len() as u64;
+                if !body_prefix.is_empty() {
+                    client.write_all(&body_prefix).await?;
+                    response_capture.push(&body_prefix);
+                }
+                // With a Content-Length, stop at the end of the body rather than EOF
+                // so the upstream connection can be kept alive.
+                let remaining = content_length.map(|len| (len - body_prefix.len()) as u64);
+                streamed_bytes +=
+                    stream_response_body(&mut upstream, client, &mut response_capture, remaining)
+                        .await?;
                 tx.timing.content_download_ms = Some(download_start.elapsed().as_millis() as u32);
+                if keep_upstream && content_length.is_some() {
+                    release_upstream(pool_key, upstream.into_inner(), &tx);
+                }
 
                 tx.status_code = Some(response_head.status_code);
                 tx.status_message = Some(response_head.reason.clone());
+                tx.response_header_list = response_head.header_list.clone();
                 tx.response_headers = Some(response_head.headers.clone());
+                (tx.response_body, tx.response_body_sha256) = response_capture.finish();
                 tx.response_content_type = header_value(&response_head.headers, "content-type");
+                decode_response_body(&mut tx, options.response_capture_limit());
+                tx.refresh_grpc_frames();
+                tx.refresh_server_timing();
+                tx.refresh_pagination();
+                tx.refresh_jsonrpc();
+                if options.body_entropy {
+                    tx.refresh_body_entropy();
+                }
                 tx.state = TransactionState::Completed;
                 tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
                 if let Some(len) = content_length {
@@ -480,42 +1340,343 @@ where
             full_response.extend_from_slice(&body_bytes);
             client.write_all(&full_response).await?;
             tx.timing.content_download_ms = Some(download_start.elapsed().as_millis() as u32);
-
-            let mut captured_body = body_bytes.clone();
-            if captured_body.len() > MAX_BODY_CAPTURE_BYTES {
-                captured_body.truncate(MAX_BODY_CAPTURE_BYTES);
+            if keep_upstream {
+                release_upstream(pool_key, upstream.into_inner(), &tx);
             }
 
+            let mut response_capture = BodyCapture::for_response(&tx, &response_head, options);
+            response_capture.push(&body_bytes);
+
             tx.status_code = Some(response_head.status_code);
             tx.status_message = Some(response_head.reason.clone());
+            tx.response_header_list = response_head.header_list.clone();
             tx.response_headers = Some(response_head.headers.clone());
-            tx.response_body = Some(captured_body);
+            (tx.response_body, tx.response_body_sha256) = response_capture.finish();
             tx.response_content_type = header_value(&response_head.headers, "content-type");
             tx.response_size = Some(full_response.len() as u64);
+            decode_response_body(&mut tx, options.response_capture_limit());
+            tx.refresh_grpc_frames();
+            tx.refresh_server_timing();
+            tx.refresh_pagination();
+            tx.refresh_jsonrpc();
+            if options.body_entropy {
+                tx.refresh_body_entropy();
+            }
             tx.state = TransactionState::Completed;
             tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
             persist_and_stream(tx).await;
         }
-        Err(err) => {
-            tracing::error!("Failed to read response head: {err}");
-            respond_with_status(client, 502, "Bad Gateway", "Failed to read response").await?;
+        Err(err) if err.is::<InvalidUpstreamResponse>() => {
+            let raw = &err
+                .downcast_ref::<InvalidUpstreamResponse>()
+                .expect("checked above")
+                .raw;
+            tracing::warn!(
+                "Upstream {}:{} returned {} bytes of non-HTTP data",
+                parsed_request.host,
+                parsed_request.port,
+                raw.len()
+            );
+            respond_with_status(
+                client,
+                502,
+                "Bad Gateway",
+                "Upstream returned non-HTTP data",
+            )
+            .await?;
             tx.state = TransactionState::Failed;
             tx.status_code = Some(502);
-            tx.status_message = Some("Failed to read response".to_string());
-            tx.notes = Some("No response from upstream".to_string());
+            tx.status_message = Some("Invalid upstream response".to_string());
+            tx.notes = Some("Upstream returned non-HTTP data".to_string());
+            tx.response_size = Some(raw.len() as u64);
+            if options.capture_invalid_responses {
+                tx.response_body =
+                    capture_body(&raw[..raw.len().min(MAX_INVALID_RESPONSE_CAPTURE_BYTES)]);
+                tx.response_content_type = Some("application/octet-stream".to_string());
+            }
             send_transaction_to_sink(tx);
         }
+        Err(err) => fail_response_head(client, tx, err, req_start).await?,
     }
 
     Ok(())
 }
 
-/// Timing data from connection establishment
-struct ConnectionTiming {
-    /// DNS resolution time in milliseconds (currently combined with TCP due to tokio)
-    dns_ms: u32,
-    /// TCP handshake time in milliseconds  
-    tcp_ms: u32,
+/// Store the captured response body decoded per its `Content-Encoding`, so it
+/// reads as text in the UI and MCP. Only the stored copy changes; the client
+/// already got the encoded bytes. Output is capped at the capture limit.
+fn decode_response_body(tx: &mut HttpTransaction, limit: usize) {
+    let Some(encoding) = tx
+        .response_headers
+        .as_ref()
+        .and_then(|headers| header_value(headers, "content-encoding"))
+    else {
+        return;
+    };
+    let Some(body) = tx.response_body.as_deref().filter(|b| !b.is_empty()) else {
+        return;
+    };
+    if let Some(decoded) = content_coding::decode_body(&encoding, body, limit) {
+        tx.response_body = Some(decoded);
+        tx.response_body_encoding = Some(encoding.trim().to_ascii_lowercase());
+    }
+}
+
+/// Answer the client when no response head came back from the upstream:
+/// 504 if it timed out, 502 otherwise.
+async fn fail_response_head<S>(
+    client: &mut S,
+    mut tx: HttpTransaction,
+    err: anyhow::Error,
+    req_start: Instant,
+) -> anyhow::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    if err.is::<UpstreamResponseTimeout>() {
+        tracing::warn!("{err}");
+        respond_with_status(client, 504, "Gateway Timeout", "Upstream response timeout").await?;
+        tx.status_code = Some(504);
+        tx.status_message = Some("Gateway Timeout".to_string());
+        tx.notes = Some("Upstream response timeout".to_string());
+    } else {
+        tracing::error!("Failed to read response head: {err}");
+        respond_with_status(client, 502, "Bad Gateway", "Failed to read response").await?;
+        tx.status_code = Some(502);
+        tx.status_message = Some("Failed to read response".to_string());
+        tx.notes = Some("No response from upstream".to_string());
+    }
+    tx.state = TransactionState::Failed;
+    tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
+    send_transaction_to_sink(tx);
+    Ok(())
+}
+
+/// Relay one request to an upstream that negotiated HTTP/2 and translate the
+/// response back to HTTP/1.1 for the client. The request body is read in full
+/// first; the response body is streamed (chunked when it has no length).
+async fn exchange_over_h2<S, U>(
+    client: &mut S,
+    upstream: U,
+    mut parsed_request: ParsedRequest,
+    mut tx: HttpTransaction,
+    options: &InterceptOptions,
+    req_start: Instant,
+    throttle: &mut ConnectionThrottle,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+    U: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tx.upstream_http_version = Some("HTTP/2".to_string());
+    if header_value(&parsed_request.request_headers, "upgrade").is_some() {
+        respond_with_status(
+            client,
+            502,
+            "Bad Gateway",
+            "Upgrade not supported over HTTP/2",
+        )
+        .await?;
+        tx.state = TransactionState::Failed;
+        tx.status_code = Some(502);
+        tx.status_message = Some("Bad Gateway".to_string());
+        tx.notes = Some("Upstream negotiated HTTP/2, which cannot carry an Upgrade".to_string());
+        send_transaction_to_sink(tx);
+        return Ok(());
+    }
+
+    let send_start = Instant::now();
+    let mut body_reader = BodyCapture::new(MAX_REQUEST_BODY_BYTES);
+    // The body is buffered for h2; pacing its read paces the upload.
+    let read_result = forward_request_body(
+        client,
+        &mut Throttled::new(&mut tokio::io::sink(), throttle.upload()),
+        &mut parsed_request,
+        &mut body_reader,
+    )
+    .await;
+    let request_body = body_reader.buf;
+    let mut request_capture = BodyCapture::new(options.request_capture_limit());
+    request_capture.push(&request_body);
+    (tx.request_body, tx.request_body_sha256) = request_capture.finish();
+    tx.refresh_form_fields();
+    tx.refresh_jsonrpc();
+    if let Err(err) = read_result {
+        let (code, label, body) = if err.downcast_ref::<RequestBodyTooLarge>().is_some() {
+            (
+                413,
+                "Payload Too Large",
+                "Request body exceeds allowed size",
+            )
+        } else {
+            (400, "Bad Request", "Failed to read request body")
+        };
+        tracing::warn!("Failed to read request body for HTTP/2 upstream: {err}");
+        respond_with_status(client, code, label, body).await?;
+        tx.state = TransactionState::Failed;
+        tx.status_code = Some(code);
+        tx.status_message = Some(label.to_string());
+        send_transaction_to_sink(tx);
+        return Ok(());
+    }
+    tx.timing.request_send_ms = Some(send_start.elapsed().as_millis() as u32);
+
+    let default_port = if parsed_request.scheme == "https" {
+        443
+    } else {
+        80
+    };
+    let authority = if parsed_request.port == default_port {
+        parsed_request.host.clone()
+    } else {
+        format!("{}:{}", parsed_request.host, parsed_request.port)
+    };
+    let uri = format!(
+        "{}://{}{}",
+        parsed_request.scheme, authority, parsed_request.path
+    );
+
+    let method = parsed_request.method.to_string();
+    let waiting_start = Instant::now();
+    let send = upstream_h2::send_request(
+        upstream,
+        &method,
+        &uri,
+        &parsed_request.header_list,
+        &options.hop_by_hop_headers,
+        request_body,
+    );
+    let result = match options.response_header_timeout {
+        Some(limit) => tokio::time::timeout(limit, send)
+            .await
+            .unwrap_or_else(|_| Err(UpstreamResponseTimeout(limit).into())),
+        None => send.await,
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(err) => return fail_response_head(client, tx, err, req_start).await,
+    };
+    if let Some(latency) = throttle.latency() {
+        tokio::time::sleep(latency).await;
+    }
+    let (_, download) = throttle.buckets();
+    let client = &mut Throttled::new(client, download);
+    tx.timing.waiting_ms = Some(waiting_start.elapsed().as_millis() as u32);
+
+    let (parts, mut body) = response.into_parts();
+    let status_code = parts.status.as_u16();
+    let reason = parts.status.canonical_reason().unwrap_or("").to_string();
+    let header_list: Vec<(String, String)> = parts
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let headers: HashMap<String, String> = header_list.iter().cloned().collect();
+    let has_body = response_has_body(parsed_request.method, status_code);
+    let content_length =
+        header_value(&headers, "content-length").and_then(|v| v.parse::<u64>().ok());
+    let chunked = has_body && content_length.is_none();
+
+    let mut raw = format!("HTTP/1.1 {status_code} {reason}\r\n");
+    for (name, value) in &header_list {
+        raw.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if chunked {
+        raw.push_str("Transfer-Encoding: chunked\r\n");
+    }
+    raw.push_str("\r\n");
+    let mut response_head = ResponseHead {
+        status_code,
+        version: "HTTP/2".to_string(),
+        reason,
+        headers,
+        header_list,
+        raw_head: raw.into_bytes(),
+        body_prefix: Vec::new(),
+        conformance_notes: Vec::new(),
+    };
+    let rewrite = rewrite::response_headers(
+        parsed_request.method,
+        &parsed_request.host,
+        &parsed_request.path,
+        &parsed_request.request_headers,
+        &response_head.headers,
+    );
+    record_rewrite_rules(&mut tx, rewrite.rule_ids);
+    let mut injected = rewrite.headers;
+    if options.debug_timing_headers {
+        injected.extend(debug_timing_headers(&tx, req_start, waiting_start));
+    }
+    if !injected.is_empty() {
+        set_response_headers(&mut response_head, &injected);
+    }
+    client.write_all(&response_head.raw_head).await?;
+
+    let download_start = Instant::now();
+    let mut response_capture = BodyCapture::for_response(&tx, &response_head, options);
+    let mut total_len = 0u64;
+    if has_body {
+        use http_body_util::BodyExt;
+        while let Some(frame) = body.frame().await {
+            let Ok(data) = frame?.into_data() else {
+                continue; // trailers are not relayed
+            };
+            if data.is_empty() {
+                continue;
+            }
+            if chunked {
+                client
+                    .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+                    .await?;
+                client.write_all(&data).await?;
+                client.write_all(b"\r\n").await?;
+            } else {
+                client.write_all(&data).await?;
+            }
+            client.flush().await?;
+            response_capture.push(&data);
+            total_len += data.len() as u64;
+        }
+        if chunked {
+            client.write_all(b"0\r\n\r\n").await?;
+        }
+    }
+    client.flush().await?;
+    tx.timing.content_download_ms = Some(download_start.elapsed().as_millis() as u32);
+
+    tx.status_code = Some(status_code);
+    tx.status_message = Some(response_head.reason.clone());
+    tx.response_content_type = header_value(&response_head.headers, "content-type");
+    tx.response_header_list = response_head.header_list.clone();
+    tx.response_headers = Some(response_head.headers);
+    (tx.response_body, tx.response_body_sha256) = response_capture.finish();
+    tx.response_size = Some(total_len);
+    decode_response_body(&mut tx, options.response_capture_limit());
+    tx.refresh_grpc_frames();
+    tx.refresh_server_timing();
+    tx.refresh_pagination();
+    tx.refresh_jsonrpc();
+    if options.body_entropy {
+        tx.refresh_body_entropy();
+    }
+    tx.state = TransactionState::Completed;
+    tx.timing.total_ms = Some(req_start.elapsed().as_millis() as u32);
+    persist_and_stream(tx).await;
+    Ok(())
+}
+
+/// Timing data from connection establishment
+#[derive(Default)]
+struct ConnectionTiming {
+    /// DNS resolution time in milliseconds (currently combined with TCP due to tokio)
+    dns_ms: u32,
+    /// TCP handshake time in milliseconds  
+    tcp_ms: u32,
     /// TLS handshake time in milliseconds (None for HTTP)
     tls_ms: Option<u32>,
     /// Resolved server IP address
@@ -524,12 +1685,111 @@ struct ConnectionTiming {
     tls_version: Option<String>,
     /// TLS cipher suite
     tls_cipher: Option<String>,
+    /// Whether the TLS handshake resumed an earlier session (None for HTTP or
+    /// a pooled connection, where no handshake happened)
+    tls_resumed: Option<bool>,
+    /// Whether the connection came from the idle upstream pool
+    reused: bool,
+}
+
+/// Idle upstream connection plus the metadata captured when it was opened.
+struct PooledUpstream {
+    stream: UpstreamStream,
+    server_ip: Option<String>,
+    tls_version: Option<String>,
+    tls_cipher: Option<String>,
+}
+
+/// Park an upstream connection whose response was fully read for reuse.
+fn release_upstream(key: PoolKey, stream: UpstreamStream, tx: &HttpTransaction) {
+    if matches!(stream, UpstreamStream::Recorded(_)) {
+        return;
+    }
+    UPSTREAM_POOL.checkin(
+        key,
+        PooledUpstream {
+            stream,
+            server_ip: tx.server_ip.clone(),
+            tls_version: tx.tls_version.clone(),
+            tls_cipher: tx.tls_cipher.clone(),
+        },
+    );
+}
+
+/// Pop an idle connection for the origin, skipping any the server has since closed.
+fn checkout_pooled_upstream(key: &PoolKey) -> Option<PooledUpstream> {
+    while let Some(mut pooled) = UPSTREAM_POOL.checkout(key) {
+        if is_idle_connection_open(&mut pooled.stream) {
+            return Some(pooled);
+        }
+    }
+    None
+}
+
+/// An idle connection is open if a read would block; EOF or unsolicited bytes mean
+/// it can no longer carry a new request.
+fn is_idle_connection_open<S>(stream: &mut S) -> bool
+where
+    S: AsyncRead + Unpin,
+{
+    let mut probe = [0u8; 1];
+    let mut buf = ReadBuf::new(&mut probe);
+    let mut cx = TaskContext::from_waker(futures::task::noop_waker_ref());
+    matches!(Pin::new(stream).poll_read(&mut cx, &mut buf), Poll::Pending)
+}
+
+/// Whether the upstream may keep the connection open after this response.
+fn response_allows_reuse(head: &ResponseHead) -> bool {
+    head.raw_head.starts_with(b"HTTP/1.1")
+        && !header_value(&head.headers, "connection")
+            .map(|v| v.to_ascii_lowercase().contains("close"))
+            .unwrap_or(false)
+}
+
+/// Responses to HEAD, and 1xx/204/304 responses, never carry a body.
+fn response_has_body(method: HttpMethod, status: u16) -> bool {
+    method != HttpMethod::Head && !(100..200).contains(&status) && status != 204 && status != 304
 }
 
 async fn connect_upstream(
     parsed_request: &ParsedRequest,
     tls_client_config: Option<Arc<ClientConfig>>,
+    allow_pooled: bool,
 ) -> anyhow::Result<(UpstreamStream, ConnectionTiming)> {
+    if let Some(recorded) = playback::recorded_response_for(
+        &parsed_request.method.to_string(),
+        &parsed_request.scheme,
+        &parsed_request.host,
+        parsed_request.port,
+        &parsed_request.path,
+    ) {
+        return Ok((
+            UpstreamStream::Recorded(recorded),
+            ConnectionTiming::default(),
+        ));
+    }
+
+    if allow_pooled {
+        let key = PoolKey::new(
+            &parsed_request.scheme,
+            &parsed_request.host,
+            parsed_request.port,
+        );
+        if let Some(pooled) = checkout_pooled_upstream(&key) {
+            let timing = ConnectionTiming {
+                dns_ms: 0,
+                tcp_ms: 0,
+                tls_ms: None,
+                server_ip: pooled.server_ip,
+                tls_version: pooled.tls_version,
+                tls_cipher: pooled.tls_cipher,
+                tls_resumed: None,
+                reused: true,
+            };
+            return Ok((pooled.stream, timing));
+        }
+    }
+
     #[cfg(test)]
     let connector_opt = {
         let guard = TEST_CONNECTOR.lock().unwrap();
@@ -539,19 +1799,26 @@ async fn connect_upstream(
     if let Some(connector) = connector_opt {
         return connector(parsed_request).await;
     }
-    // Note: tokio::net::TcpStream::connect does DNS resolution internally,
-    // so we can't measure DNS and TCP separately without using lookup_host.
-    // For now, we measure them together and split the time roughly.
-    let connect_start = Instant::now();
-    let stream = TcpStream::connect(format!("{}:{}", parsed_request.host, parsed_request.port))
+    let dns_start = Instant::now();
+    let (addrs, cached) = UPSTREAM_DNS
+        .resolve(&parsed_request.host, parsed_request.port)
         .await
-        .with_context(|| {
-            format!(
-                "connecting to upstream {}:{}",
-                parsed_request.host, parsed_request.port
-            )
-        })?;
-    let connect_elapsed = connect_start.elapsed().as_millis() as u32;
+        .with_context(|| format!("resolving upstream host {}", parsed_request.host))?;
+    // A cache hit did no lookup, so it reports none.
+    let dns_ms = if cached {
+        0
+    } else {
+        dns_start.elapsed().as_millis() as u32
+    };
+
+    let connect_start = Instant::now();
+    let stream = connect_first(&addrs).await.with_context(|| {
+        format!(
+            "connecting to upstream {}:{}",
+            parsed_request.host, parsed_request.port
+        )
+    })?;
+    let tcp_ms = connect_start.elapsed().as_millis() as u32;
 
     // Capture server IP address, preferring IPv4 representation
     let server_ip = stream.peer_addr().ok().map(|addr| {
@@ -568,12 +1835,9 @@ async fn connect_upstream(
         }
     });
 
-    // Estimate DNS vs TCP split (rough heuristic: DNS ~40% of connect time)
-    let dns_ms = connect_elapsed * 4 / 10;
-    let tcp_ms = connect_elapsed - dns_ms;
-
     if parsed_request.scheme == "https" {
-        let config = tls_client_config
+        let config = upstream_tls::client_config_for(&parsed_request.host)
+            .or(tls_client_config)
             .ok_or_else(|| anyhow!("TLS client config unavailable for HTTPS request"))?;
         let host_name = parsed_request.host.clone();
         let server_name = ServerName::try_from(host_name.clone())
@@ -590,6 +1854,9 @@ async fn connect_upstream(
         let tls_cipher = conn_data
             .negotiated_cipher_suite()
             .map(|cs| format!("{:?}", cs.suite()));
+        let tls_resumed = conn_data
+            .handshake_kind()
+            .map(|kind| kind == HandshakeKind::Resumed);
 
         let timing = ConnectionTiming {
             dns_ms,
@@ -598,6 +1865,8 @@ async fn connect_upstream(
             server_ip,
             tls_version,
             tls_cipher,
+            tls_resumed,
+            reused: false,
         };
         Ok((UpstreamStream::Tls(TlsStream::from(tls)), timing))
     } else {
@@ -608,15 +1877,40 @@ async fn connect_upstream(
             server_ip,
             tls_version: None,
             tls_cipher: None,
+            tls_resumed: None,
+            reused: false,
         };
         Ok((UpstreamStream::Plain(stream), timing))
     }
 }
 
+/// Connect to the first resolved address that accepts, in resolver order,
+/// like `TcpStream::connect` does for a host name.
+async fn connect_first(addrs: &[SocketAddr]) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses to connect to")
+    }))
+}
+
 /// Tunnel data between client and upstream server
-async fn tunnel(mut client: TcpStream, mut upstream: TcpStream) -> anyhow::Result<()> {
+async fn tunnel(
+    mut client: TcpStream,
+    mut upstream: TcpStream,
+    mut throttle: ConnectionThrottle,
+) -> anyhow::Result<()> {
     let (mut client_reader, mut client_writer) = client.split();
     let (mut upstream_reader, mut upstream_writer) = upstream.split();
+    throttle.refresh();
+    let (upload, download) = throttle.buckets();
+    let mut upstream_writer = Throttled::new(&mut upstream_writer, upload);
+    let mut client_writer = Throttled::new(&mut client_writer, download);
 
     let client_to_upstream = tokio::io::copy(&mut client_reader, &mut upstream_writer);
     let upstream_to_client = tokio::io::copy(&mut upstream_reader, &mut client_writer);
@@ -637,31 +1931,72 @@ async fn tunnel(mut client: TcpStream, mut upstream: TcpStream) -> anyhow::Resul
     Ok(())
 }
 /// Tunnel WebSocket data bidirectionally between client and upstream
-/// Parses frames and captures messages as they pass through
+/// Parses frames and captures messages as they pass through. With
+/// `permessage-deflate` the captured copies are inflated; the bytes forwarded
+/// stay compressed.
 async fn websocket_tunnel<C, U>(
     client: &mut C,
     upstream: &mut U,
     connection_id: String,
+    keepalive: Option<Duration>,
+    deflate: Option<PerMessageDeflate>,
 ) -> anyhow::Result<()>
 where
     C: AsyncRead + AsyncWrite + Unpin + Send,
     U: AsyncRead + AsyncWrite + Unpin + Send,
 {
-    use crate::models::MessageDirection;
-    use crate::proxy::websocket::extract_message;
+    use crate::models::{MessageDirection, WebSocketMessage, WebSocketOpcode};
+    use crate::proxy::websocket::{
+        build_ping_frame, extract_message, fragment_open_after, keepalive_interval,
+        register_connection, MessageInflater, KEEPALIVE_PING_PAYLOAD,
+    };
     use crate::storage::add_websocket_message;
 
+    /// How often to re-check for a keepalive setting while none is active
+    const KEEPALIVE_RECHECK: Duration = Duration::from_secs(1);
+
     let mut client_buf = vec![0u8; 65536];
     let mut upstream_buf = vec![0u8; 65536];
 
     // Accumulation buffers for partial frames
     let mut client_pending = Vec::new();
     let mut upstream_pending = Vec::new();
+    // Set between the first and final frame of a fragmented data message
+    let mut client_fragment_open = false;
+    let mut upstream_fragment_open = false;
+    // Each direction has its own compression context
+    let mut client_inflater = deflate.map(|d| MessageInflater::new(d.client_no_context_takeover));
+    let mut upstream_inflater = deflate.map(|d| MessageInflater::new(d.server_no_context_takeover));
+    let mut last_activity = tokio::time::Instant::now();
+    // Set while a due ping waits for a client frame to complete
+    let mut ping_retry_at = None;
+    let mut injections = register_connection(&connection_id);
+    let mut queued_injections = VecDeque::new();
 
     loop {
+        if let Err(e) = write_injections(
+            &mut queued_injections,
+            client,
+            upstream,
+            (&client_pending, client_fragment_open),
+            (&upstream_pending, upstream_fragment_open),
+        )
+        .await
+        {
+            tracing::debug!("WebSocket: Error writing injected message: {}", e);
+            break;
+        }
+        let interval = keepalive_interval(&connection_id, keepalive);
+        let wake_at = match interval {
+            Some(interval) => {
+                (last_activity + interval).max(ping_retry_at.unwrap_or(last_activity))
+            }
+            None => tokio::time::Instant::now() + KEEPALIVE_RECHECK,
+        };
         tokio::select! {
             // Client -> Upstream (messages from client)
             result = client.read(&mut client_buf) => {
+                last_activity = tokio::time::Instant::now();
                 match result {
                     Ok(0) => {
                         tracing::debug!("WebSocket: Client closed connection");
@@ -683,7 +2018,9 @@ where
                             &client_pending,
                             &connection_id,
                             MessageDirection::ClientToServer,
+                            client_inflater.as_mut(),
                         ) {
+                            client_fragment_open = fragment_open_after(client_fragment_open, &msg);
                             add_websocket_message(msg);
                             client_pending.drain(..consumed);
                         }
@@ -696,6 +2033,7 @@ where
             }
             // Upstream -> Client (messages from server)
             result = upstream.read(&mut upstream_buf) => {
+                last_activity = tokio::time::Instant::now();
                 match result {
                     Ok(0) => {
                         tracing::debug!("WebSocket: Upstream closed connection");
@@ -717,7 +2055,10 @@ where
                             &upstream_pending,
                             &connection_id,
                             MessageDirection::ServerToClient,
+                            upstream_inflater.as_mut(),
                         ) {
+                            upstream_fragment_open =
+                                fragment_open_after(upstream_fragment_open, &msg);
                             add_websocket_message(msg);
                             upstream_pending.drain(..consumed);
                         }
@@ -728,8 +2069,44 @@ where
                     }
                 }
             }
+            Some(message) = injections.recv() => {
+                queued_injections.push_back(message);
+            }
+            // Idle: ping the upstream so it keeps the connection open. Only at a
+            // frame boundary, so the ping cannot split a client frame.
+            _ = tokio::time::sleep_until(wake_at) => {
+                let Some(interval) = interval else {
+                    continue;
+                };
+                if !client_pending.is_empty() {
+                    // Still overdue next time round; look again later instead of spinning.
+                    ping_retry_at =
+                        Some(tokio::time::Instant::now() + interval.min(KEEPALIVE_RECHECK));
+                    continue;
+                }
+                let random = uuid::Uuid::new_v4().into_bytes();
+                let mask_key = [random[0], random[1], random[2], random[3]];
+                let frame = build_ping_frame(KEEPALIVE_PING_PAYLOAD, mask_key);
+                if let Err(e) = upstream.write_all(&frame).await {
+                    tracing::debug!("WebSocket: Error writing keepalive ping: {}", e);
+                    break;
+                }
+                let _ = upstream.flush().await;
+                let mut ping = WebSocketMessage::new(
+                    connection_id.clone(),
+                    MessageDirection::ClientToServer,
+                    WebSocketOpcode::Ping,
+                    KEEPALIVE_PING_PAYLOAD.to_vec(),
+                    true,
+                );
+                ping.injected = true;
+                add_websocket_message(ping);
+                last_activity = tokio::time::Instant::now();
+            }
         }
     }
+    crate::proxy::websocket::clear_connection_keepalive(&connection_id);
+    crate::proxy::websocket::unregister_connection(&connection_id);
 
     tracing::info!(
         "WebSocket connection closed: {} ({} client pending, {} server pending)",
@@ -741,10 +2118,66 @@ where
     Ok(())
 }
 
+/// Write queued injected messages whose direction is at a frame boundary (no
+/// partial frame pending), recording each. Data messages also wait for a
+/// fragmented message in their direction to finish, since a new data frame
+/// may not interleave with its fragments; control frames may. Each direction
+/// is given as its pending bytes and whether a fragmented message is open.
+/// The rest stay queued, in order.
+async fn write_injections<C, U>(
+    queued: &mut VecDeque<crate::models::WebSocketMessage>,
+    client: &mut C,
+    upstream: &mut U,
+    (client_pending, client_fragment_open): (&[u8], bool),
+    (upstream_pending, upstream_fragment_open): (&[u8], bool),
+) -> std::io::Result<()>
+where
+    C: AsyncWrite + Unpin,
+    U: AsyncWrite + Unpin,
+{
+    use crate::models::{MessageDirection, WebSocketOpcode};
+    use crate::proxy::websocket::{build_frame, is_control};
+
+    let ready = |pending: &[u8], fragment_open: bool, opcode: WebSocketOpcode| {
+        pending.is_empty() && (is_control(opcode) || !fragment_open)
+    };
+    let mut waiting = VecDeque::new();
+    while let Some(message) = queued.pop_front() {
+        match message.direction {
+            MessageDirection::ClientToServer
+                if ready(client_pending, client_fragment_open, message.opcode) =>
+            {
+                let random = uuid::Uuid::new_v4().into_bytes();
+                let mask_key = [random[0], random[1], random[2], random[3]];
+                let frame = build_frame(message.opcode, &message.payload, Some(mask_key));
+                upstream.write_all(&frame).await?;
+                upstream.flush().await?;
+            }
+            MessageDirection::ServerToClient
+                if ready(upstream_pending, upstream_fragment_open, message.opcode) =>
+            {
+                client
+                    .write_all(&build_frame(message.opcode, &message.payload, None))
+                    .await?;
+                client.flush().await?;
+            }
+            _ => {
+                waiting.push_back(message);
+                continue;
+            }
+        }
+        crate::storage::add_websocket_message(message);
+    }
+    *queued = waiting;
+    Ok(())
+}
+
 #[allow(clippy::large_enum_variant)]
 enum UpstreamStream {
     Plain(TcpStream),
     Tls(TlsStream<TcpStream>),
+    /// A stored response served in place of the network (record/replay mode)
+    Recorded(RecordedResponse),
     #[cfg(test)]
     Mock(DuplexStream),
 }
@@ -769,12 +2202,24 @@ where
     let mut guard = TEST_CONNECTOR.lock().unwrap();
     let arc_connector: Arc<TestConnectorFn> = Arc::new(move |req| Box::pin(connector(req)));
     *guard = Some(arc_connector);
+    UPSTREAM_POOL.clear();
 }
 
 #[cfg(test)]
 fn reset_test_upstream_connector() {
     let mut guard = TEST_CONNECTOR.lock().unwrap();
     guard.take();
+    UPSTREAM_POOL.clear();
+}
+
+impl UpstreamStream {
+    /// Protocol the upstream selected via ALPN, if any
+    fn negotiated_alpn(&self) -> Option<&[u8]> {
+        match self {
+            UpstreamStream::Tls(stream) => stream.get_ref().1.alpn_protocol(),
+            _ => None,
+        }
+    }
 }
 
 impl AsyncRead for UpstreamStream {
@@ -786,6 +2231,7 @@ impl AsyncRead for UpstreamStream {
         match self.get_mut() {
             UpstreamStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
             UpstreamStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            UpstreamStream::Recorded(stream) => Pin::new(stream).poll_read(cx, buf),
             #[cfg(test)]
             UpstreamStream::Mock(stream) => Pin::new(stream).poll_read(cx, buf),
         }
@@ -801,6 +2247,7 @@ impl AsyncWrite for UpstreamStream {
         match self.get_mut() {
             UpstreamStream::Plain(stream) => Pin::new(stream).poll_write(cx, data),
             UpstreamStream::Tls(stream) => Pin::new(stream).poll_write(cx, data),
+            UpstreamStream::Recorded(stream) => Pin::new(stream).poll_write(cx, data),
             #[cfg(test)]
             UpstreamStream::Mock(stream) => Pin::new(stream).poll_write(cx, data),
         }
@@ -810,6 +2257,7 @@ impl AsyncWrite for UpstreamStream {
         match self.get_mut() {
             UpstreamStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
             UpstreamStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            UpstreamStream::Recorded(stream) => Pin::new(stream).poll_flush(cx),
             #[cfg(test)]
             UpstreamStream::Mock(stream) => Pin::new(stream).poll_flush(cx),
         }
@@ -819,6 +2267,7 @@ impl AsyncWrite for UpstreamStream {
         match self.get_mut() {
             UpstreamStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
             UpstreamStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            UpstreamStream::Recorded(stream) => Pin::new(stream).poll_shutdown(cx),
             #[cfg(test)]
             UpstreamStream::Mock(stream) => Pin::new(stream).poll_shutdown(cx),
         }
@@ -837,9 +2286,60 @@ struct ParsedRequest {
     header_list: Vec<(String, String)>,
     body_kind: RequestBodyKind,
     buffered_body: Vec<u8>,
+    /// Redacted `user:***` when the request URL carried credentials
+    url_credentials: Option<String>,
+    target_form: RequestTargetForm,
+    /// Framing that the client and a downstream server could read differently
+    framing_anomalies: Vec<String>,
+    /// Fingerprint of the TLS ClientHello that opened the connection
+    client_fingerprint: Option<ClientFingerprint>,
+    /// Read from a TLS connection the proxy terminated (MITM)
+    intercepted: bool,
+    /// Tolerated but non-standard syntax in the request head
+    conformance_notes: Vec<String>,
 }
 
 impl ParsedRequest {
+    /// Whether the client offers to switch to cleartext HTTP/2 (`Upgrade: h2c`)
+    fn offers_h2c_upgrade(&self) -> bool {
+        header_value(&self.request_headers, "upgrade").is_some_and(|value| {
+            value
+                .split(',')
+                .any(|protocol| protocol.trim().eq_ignore_ascii_case("h2c"))
+        })
+    }
+
+    /// Drop the h2c offer so the request goes upstream as plain HTTP/1.1:
+    /// `Upgrade`, `HTTP2-Settings` and their `Connection` tokens are removed.
+    fn strip_h2c_upgrade(&mut self) {
+        let is_offer = |name: &str| {
+            name.eq_ignore_ascii_case("upgrade") || name.eq_ignore_ascii_case("http2-settings")
+        };
+        let without_offer = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|token| !token.is_empty() && !is_offer(token))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        self.header_list.retain(|(name, _)| !is_offer(name));
+        self.request_headers.retain(|name, _| !is_offer(name));
+        let connection_values = self
+            .header_list
+            .iter_mut()
+            .map(|(name, value)| (&*name, value))
+            .chain(self.request_headers.iter_mut())
+            .filter(|(name, _)| name.eq_ignore_ascii_case("connection"));
+        for (_, value) in connection_values {
+            *value = without_offer(value);
+        }
+        self.header_list
+            .retain(|(name, value)| !(name.eq_ignore_ascii_case("connection") && value.is_empty()));
+        self.request_headers
+            .retain(|name, value| !(name.eq_ignore_ascii_case("connection") && value.is_empty()));
+    }
+
     fn to_breakpoint_context(&self) -> BreakpointContext {
         BreakpointContext {
             method: self.method,
@@ -878,6 +2378,12 @@ impl ParsedRequest {
             self.remove_header("transfer-encoding");
             self.set_header("Content-Length", self.body_kind.len().to_string());
         }
+        if matches!(self.body_kind, RequestBodyKind::Edited { .. }) {
+            // The edited body is sent right after the head, so there is nothing
+            // left to wait for; an interim 100 from upstream would only be
+            // mistaken for the final response.
+            self.remove_header("expect");
+        }
     }
 
     fn set_header(&mut self, name: &str, value: String) {
@@ -923,11 +2429,26 @@ impl RequestBodyKind {
             RequestBodyKind::Edited { data } => data.len(),
         }
     }
+
+    /// Whether the body can be sent again without reading more from the client.
+    fn is_replayable(&self) -> bool {
+        match self {
+            RequestBodyKind::None | RequestBodyKind::Edited { .. } => true,
+            RequestBodyKind::ContentLength { length } => *length == 0,
+            RequestBodyKind::Chunked => false,
+        }
+    }
 }
 
+/// Captures a body prefix up to `limit` while hashing every byte seen.
 struct BodyCapture {
     buf: Vec<u8>,
     limit: usize,
+    hasher: Sha256,
+    seen: u64,
+    /// Set for a `text/event-stream` response: the event data is captured
+    /// instead of the raw bytes
+    events: Option<LiveEvents>,
 }
 
 impl BodyCapture {
@@ -935,10 +2456,39 @@ impl BodyCapture {
         Self {
             buf: Vec::new(),
             limit,
+            hasher: Sha256::new(),
+            seen: 0,
+            events: None,
+        }
+    }
+
+    /// Capture for a response body. Event streams are captured as their event
+    /// data, with `tx` re-sent to the UI as events arrive.
+    fn for_response(
+        tx: &HttpTransaction,
+        response_head: &ResponseHead,
+        options: &InterceptOptions,
+    ) -> Self {
+        let mut capture = Self::new(options.response_capture_limit());
+        if capture.limit > 0 && is_event_stream(&response_head.headers) {
+            let mut snapshot = tx.clone();
+            snapshot.status_code = Some(response_head.status_code);
+            snapshot.status_message = Some(response_head.reason.clone());
+            snapshot.response_header_list = response_head.header_list.clone();
+            snapshot.response_headers = Some(response_head.headers.clone());
+            snapshot.response_content_type = header_value(&response_head.headers, "content-type");
+            capture.events = Some(LiveEvents::start(snapshot, capture.limit));
         }
+        capture
     }
 
     fn push(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.seen += data.len() as u64;
+        if let Some(events) = &self.events {
+            events.push(data);
+            return;
+        }
         if self.buf.len() >= self.limit {
             return;
         }
@@ -949,21 +2499,110 @@ impl BodyCapture {
         }
     }
 
-    fn into_option(self) -> Option<Vec<u8>> {
-        if self.buf.is_empty() {
-            None
-        } else {
-            Some(self.buf)
+    /// Returns the captured prefix and the hex SHA-256 of the full body. The
+    /// prefix is `None` when nothing was kept (empty body or capture off); the
+    /// hash is `None` only for an empty body.
+    fn finish(self) -> (Option<Vec<u8>>, Option<String>) {
+        if self.seen == 0 {
+            return (None, None);
+        }
+        let hash = format!("{:x}", self.hasher.finalize());
+        let buf = match &self.events {
+            Some(events) => events.finish(),
+            None => self.buf,
+        };
+        let body = if buf.is_empty() { None } else { Some(buf) };
+        (body, Some(hash))
+    }
+}
+
+/// Whether a response is an uncompressed `text/event-stream`
+fn is_event_stream(headers: &HashMap<String, String>) -> bool {
+    let is_sse = header_value(headers, "content-type").is_some_and(|ct| {
+        ct.split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+    });
+    let encoded = header_value(headers, "content-encoding")
+        .is_some_and(|encoding| !encoding.trim().eq_ignore_ascii_case("identity"));
+    is_sse && !encoded
+}
+
+struct EventState {
+    parser: EventStreamParser,
+    /// Events arrived since the UI was last updated
+    unsent: bool,
+}
+
+/// Event data of a streaming SSE response, periodically sent to the UI as the
+/// response body of the still-pending transaction.
+struct LiveEvents {
+    state: Arc<Mutex<EventState>>,
+    ticker: tokio::task::JoinHandle<()>,
+}
+
+impl LiveEvents {
+    fn start(mut snapshot: HttpTransaction, limit: usize) -> Self {
+        let state = Arc::new(Mutex::new(EventState {
+            parser: EventStreamParser::new(limit),
+            unsent: false,
+        }));
+        let shared = state.clone();
+        let ticker = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SSE_EMIT_INTERVAL);
+            loop {
+                interval.tick().await;
+                let events = {
+                    let mut state = shared.lock().unwrap();
+                    if !mem::take(&mut state.unsent) {
+                        continue;
+                    }
+                    state.parser.events().to_vec()
+                };
+                snapshot.response_body = Some(events);
+                send_transaction_to_sink(snapshot.clone());
+            }
+        });
+        Self { state, ticker }
+    }
+
+    fn push(&self, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        if state.parser.push(data) {
+            state.unsent = true;
         }
     }
+
+    fn finish(&self) -> Vec<u8> {
+        self.ticker.abort();
+        self.state.lock().unwrap().parser.take_events()
+    }
+}
+
+impl Drop for LiveEvents {
+    fn drop(&mut self) {
+        self.ticker.abort();
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> Option<String> {
+    if data.is_empty() {
+        return None;
+    }
+    Some(format!("{:x}", Sha256::digest(data)))
 }
 
-struct ResponseHead {
-    status_code: u16,
-    reason: String,
-    headers: HashMap<String, String>,
+pub(crate) struct ResponseHead {
+    pub(crate) status_code: u16,
+    version: String,
+    pub(crate) reason: String,
+    pub(crate) headers: HashMap<String, String>,
+    /// Headers in the order received, repeats included
+    header_list: Vec<(String, String)>,
     raw_head: Vec<u8>,
-    body_prefix: Vec<u8>,
+    pub(crate) body_prefix: Vec<u8>,
+    /// Tolerated but non-standard syntax in the response head
+    conformance_notes: Vec<String>,
 }
 
 #[derive(Clone, Copy)]
@@ -999,7 +2638,15 @@ where
 
     let mut header_storage = [httparse::EMPTY_HEADER; MAX_HEADER_COUNT];
     let mut req = httparse::Request::new(&mut header_storage);
-    let status = req.parse(&raw_head)?;
+    let status = match req.parse(&raw_head) {
+        Ok(status) => status,
+        Err(err) if scan_raw_head(&raw_head).has_obs_fold() => {
+            return Err(anyhow!(
+                "{err}: obsolete line folding in request headers (potential request smuggling)"
+            ));
+        }
+        Err(err) => return Err(err.into()),
+    };
     if status.is_partial() {
         return Err(anyhow!("partial HTTP request"));
     }
@@ -1013,6 +2660,8 @@ where
         .iter()
         .cloned()
         .collect::<HashMap<String, String>>();
+    let framing_anomalies = framing_anomalies(&raw_head, &headers_vec);
+    let conformance_notes = conformance_notes("request", &raw_head);
 
     let content_length =
         header_value(&header_map, "content-length").and_then(|v| v.parse::<usize>().ok());
@@ -1041,10 +2690,16 @@ where
             header_list: headers_vec,
             body_kind: RequestBodyKind::None,
             buffered_body,
+            url_credentials: None,
+            target_form: RequestTargetForm::Authority,
+            framing_anomalies,
+            client_fingerprint: None,
+            intercepted: false,
+            conformance_notes,
         });
     }
 
-    let (scheme, host, port, path) = resolve_target(path_raw, &header_map, default_scheme)?;
+    let target = resolve_target(path_raw, &header_map, default_scheme)?;
     let body_kind = if is_chunked {
         RequestBodyKind::Chunked
     } else if let Some(len) = content_length {
@@ -1053,18 +2708,45 @@ where
         RequestBodyKind::None
     };
 
-    Ok(ParsedRequest {
+    let mut parsed = ParsedRequest {
         method,
-        scheme,
-        host,
-        port,
-        path,
+        scheme: target.scheme,
+        host: target.host,
+        port: target.port,
+        path: target.path,
         version,
         request_headers: header_map,
         header_list: headers_vec,
         body_kind,
         buffered_body,
-    })
+        url_credentials: None,
+        target_form: target.form,
+        framing_anomalies,
+        client_fingerprint: None,
+        intercepted: false,
+        conformance_notes,
+    };
+    if let Some(userinfo) = target.userinfo {
+        apply_url_credentials(&mut parsed, &userinfo);
+    }
+    Ok(parsed)
+}
+
+/// Turn `user:pass@` from an absolute-form URL into Basic auth (unless the client
+/// already sent an Authorization header) and remember a redacted form.
+fn apply_url_credentials(parsed: &mut ParsedRequest, userinfo: &str) {
+    // Userinfo keeps `+` literal, unlike form encoding
+    let decoded = percent_decode(userinfo.as_bytes(), false);
+    if header_value(&parsed.request_headers, "authorization").is_none() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(decoded.as_bytes());
+        parsed.set_header("Authorization", format!("Basic {encoded}"));
+    }
+    let user = decoded.split(':').next().unwrap_or_default();
+    parsed.url_credentials = Some(if decoded.contains(':') {
+        format!("{user}:***")
+    } else {
+        user.to_string()
+    });
 }
 
 async fn handle_connect_tunnel(
@@ -1072,29 +2754,153 @@ async fn handle_connect_tunnel(
     parsed: ParsedRequest,
     cert_manager: Option<Arc<CertManager>>,
     tls_client_config: Option<Arc<ClientConfig>>,
+    options: Arc<InterceptOptions>,
+    transcript_id: Option<String>,
+    throttle: ConnectionThrottle,
 ) -> anyhow::Result<()> {
     if let (Some(manager), Some(tls_config)) = (cert_manager, tls_client_config) {
-        intercept_tls_connection(client, parsed.host.clone(), manager, tls_config).await?;
+        intercept_tls_connection(
+            client,
+            parsed,
+            manager,
+            tls_config,
+            options,
+            transcript_id,
+            throttle,
+        )
+        .await?;
         return Ok(());
     }
 
-    handle_plain_connect(client, parsed).await
+    handle_plain_connect(client, parsed, throttle).await
 }
 
 async fn intercept_tls_connection(
-    client: TcpStream,
-    host: String,
+    mut client: TcpStream,
+    parsed: ParsedRequest,
     cert_manager: Arc<CertManager>,
     tls_client_config: Arc<ClientConfig>,
+    options: Arc<InterceptOptions>,
+    transcript_id: Option<String>,
+    throttle: ConnectionThrottle,
 ) -> anyhow::Result<()> {
-    intercept_tls_stream(client, host, cert_manager, tls_client_config).await
-}
-
-async fn intercept_tls_stream<S>(
-    mut client: S,
+    if !options.sni_policy.is_active() {
+        if let Some(note) = cert_failure_note(&cert_manager, &parsed.host, &options) {
+            client
+                .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                .await?;
+            return tunnel_acknowledged_connect(
+                client,
+                parsed,
+                note,
+                "bypass:cert-failure",
+                throttle,
+            )
+            .await;
+        }
+        return intercept_tls_stream(
+            client,
+            parsed.host,
+            cert_manager,
+            tls_client_config,
+            options,
+            transcript_id,
+            throttle,
+        )
+        .await;
+    }
+
+    // The ClientHello only arrives after the CONNECT is acknowledged.
+    client
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    let sni = sni::peek_sni(&client).await;
+    if !options.sni_policy.should_intercept(sni.as_deref()) {
+        tracing::debug!(
+            "Tunneling {}:{} without interception (SNI {:?})",
+            parsed.host,
+            parsed.port,
+            sni
+        );
+        let note = match &sni {
+            Some(name) => format!("HTTPS Tunnel (SNI {name} not intercepted)"),
+            None => "HTTPS Tunnel (no SNI)".to_string(),
+        };
+        return tunnel_acknowledged_connect(client, parsed, note, "bypass:sni-policy", throttle)
+            .await;
+    }
+
+    // Issue the leaf for the name the client will verify, not the CONNECT authority.
+    let leaf_host = sni.unwrap_or_else(|| parsed.host.clone());
+    if let Some(note) = cert_failure_note(&cert_manager, &leaf_host, &options) {
+        return tunnel_acknowledged_connect(client, parsed, note, "bypass:cert-failure", throttle)
+            .await;
+    }
+    serve_intercepted_tls(
+        client,
+        leaf_host,
+        cert_manager,
+        tls_client_config,
+        options,
+        transcript_id,
+        throttle,
+    )
+    .await
+}
+
+/// With `tunnel_on_cert_failure`, the note to record when no leaf certificate
+/// can be issued for `host` (the connection is then tunneled instead).
+/// A successfully issued leaf stays cached for the handshake that follows.
+fn cert_failure_note(
+    cert_manager: &CertManager,
+    host: &str,
+    options: &InterceptOptions,
+) -> Option<String> {
+    if !options.tunnel_on_cert_failure {
+        return None;
+    }
+    let err = cert_manager.server_config_for_host(host).err()?;
+    tracing::warn!("Cannot intercept {host}, tunneling instead: {err:#}");
+    Some(format!("Interception failed, tunneled: {err:#}"))
+}
+
+async fn intercept_tls_stream<S>(
+    mut client: S,
+    host: String,
+    cert_manager: Arc<CertManager>,
+    tls_client_config: Arc<ClientConfig>,
+    options: Arc<InterceptOptions>,
+    transcript_id: Option<String>,
+    throttle: ConnectionThrottle,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    client
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+
+    serve_intercepted_tls(
+        client,
+        host,
+        cert_manager,
+        tls_client_config,
+        options,
+        transcript_id,
+        throttle,
+    )
+    .await
+}
+
+/// Terminate TLS on an already-acknowledged CONNECT and proxy the HTTP requests inside.
+async fn serve_intercepted_tls<S>(
+    client: S,
     host: String,
     cert_manager: Arc<CertManager>,
     tls_client_config: Arc<ClientConfig>,
+    options: Arc<InterceptOptions>,
+    transcript_id: Option<String>,
+    mut throttle: ConnectionThrottle,
 ) -> anyhow::Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -1103,45 +2909,54 @@ where
         .server_config_for_host(&host)
         .context("Failed to build server config")?;
 
-    client
-        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
-        .await?;
+    let mut client = client;
+    let (client, fingerprint) = if options.capture_client_fingerprint {
+        let hello = fingerprint::read_client_hello(&mut client).await?;
+        let fingerprint = fingerprint::fingerprint_client_hello(&hello);
+        (PrefixedStream::new(hello, client), fingerprint)
+    } else {
+        (PrefixedStream::new(Vec::new(), client), None)
+    };
 
     let acceptor = TlsAcceptor::from(server_config);
     let tls_stream = acceptor.accept(client).await?;
-    let mut tls_stream = TlsStream::from(tls_stream);
+    // Record after TLS termination so the transcript shows plaintext HTTP.
+    let mut tls_stream = Recorded::client(TlsStream::from(tls_stream), transcript_id.as_deref());
 
     let mut request_count: u32 = 0;
 
     loop {
         request_count += 1;
         let req_start = Instant::now();
-        let parsed_request = match read_http_request(&mut tls_stream, RequestScheme::Https).await {
-            Ok(req) => req,
-            Err(err) => {
-                if request_count == 1 {
-                    let is_too_large = err.downcast_ref::<RequestBodyTooLarge>().is_some();
-                    let (code, label, body) = if is_too_large {
-                        (
-                            413,
-                            "Payload Too Large",
-                            "Request body exceeds allowed size",
-                        )
+        let mut parsed_request =
+            match read_http_request(&mut tls_stream, RequestScheme::Https).await {
+                Ok(req) => req,
+                Err(err) => {
+                    if request_count == 1 {
+                        let is_too_large = err.downcast_ref::<RequestBodyTooLarge>().is_some();
+                        let (code, label, body) = if is_too_large {
+                            (
+                                413,
+                                "Payload Too Large",
+                                "Request body exceeds allowed size",
+                            )
+                        } else {
+                            (400, "Bad Request", "Unable to parse HTTPS request")
+                        };
+                        tracing::warn!("Failed to parse HTTPS request: {err}");
+                        let _ = respond_with_status(&mut tls_stream, code, label, body).await;
                     } else {
-                        (400, "Bad Request", "Unable to parse HTTPS request")
-                    };
-                    tracing::warn!("Failed to parse HTTPS request: {err}");
-                    let _ = respond_with_status(&mut tls_stream, code, label, body).await;
-                } else {
-                    tracing::debug!(
-                        "HTTPS keep-alive connection closed after {} requests: {err}",
-                        request_count - 1
-                    );
+                        tracing::debug!(
+                            "HTTPS keep-alive connection closed after {} requests: {err}",
+                            request_count - 1
+                        );
+                    }
+                    break;
                 }
-                break;
-            }
-        };
+            };
 
+        parsed_request.client_fingerprint = fingerprint.clone();
+        parsed_request.intercepted = true;
         let keep_alive =
             should_keep_alive(&parsed_request.version, &parsed_request.request_headers);
 
@@ -1151,6 +2966,9 @@ where
             req_start,
             Some(tls_client_config.clone()),
             request_count > 1,
+            &options,
+            transcript_id.as_deref(),
+            &mut throttle,
         )
         .await
         {
@@ -1166,7 +2984,139 @@ where
     Ok(())
 }
 
-async fn handle_plain_connect(mut client: TcpStream, parsed: ParsedRequest) -> anyhow::Result<()> {
+/// Relay an already-acknowledged CONNECT that is not intercepted; `action`
+/// records why (SNI excluded, no certificate could be issued).
+async fn tunnel_acknowledged_connect(
+    client: TcpStream,
+    parsed: ParsedRequest,
+    note: String,
+    action: &str,
+    throttle: ConnectionThrottle,
+) -> anyhow::Result<()> {
+    let mut tx = HttpTransaction::new(
+        parsed.method,
+        &parsed.scheme,
+        &parsed.host,
+        parsed.port,
+        "/",
+        parsed.request_headers.clone(),
+    );
+    tx.notes = Some(note);
+    tx.applied_actions.push(action.to_string());
+
+    match TcpStream::connect(format!("{}:{}", parsed.host, parsed.port)).await {
+        Ok(upstream) => {
+            tx.state = TransactionState::Completed;
+            tx.status_code = Some(200);
+            tx.status_message = Some("Connection Established".to_string());
+            send_transaction_to_sink(tx);
+            tunnel(client, upstream, throttle).await
+        }
+        Err(e) => {
+            // The 200 was already sent; closing is the only signal left.
+            tracing::error!(
+                "Failed to establish CONNECT tunnel to {}:{} - {}",
+                parsed.host,
+                parsed.port,
+                e
+            );
+            tx.state = TransactionState::Failed;
+            tx.status_code = Some(502);
+            send_transaction_to_sink(tx);
+            Ok(())
+        }
+    }
+}
+
+/// `X-Cheddar-*` headers describing what the proxy measured up to the response
+/// head. The body is forwarded unchanged, so Content-Length stays valid.
+fn debug_timing_headers(
+    tx: &HttpTransaction,
+    req_start: Instant,
+    waiting_start: Instant,
+) -> Vec<(String, String)> {
+    let mut headers = vec![
+        (
+            "X-Cheddar-Total-Ms".to_string(),
+            req_start.elapsed().as_millis().to_string(),
+        ),
+        (
+            "X-Cheddar-Waiting-Ms".to_string(),
+            waiting_start.elapsed().as_millis().to_string(),
+        ),
+    ];
+    if let Some(ip) = &tx.server_ip {
+        headers.push(("X-Cheddar-Upstream-Ip".to_string(), ip.clone()));
+    }
+    headers
+}
+
+/// Replace (or add) headers in a response head before it is forwarded, keeping
+/// the raw head and the parsed map in sync. Framing is unaffected.
+fn set_response_headers(head: &mut ResponseHead, headers: &[(String, String)]) {
+    let replaced = |name: &str| headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name));
+    let text = String::from_utf8_lossy(&head.raw_head).into_owned();
+    let mut raw = String::with_capacity(text.len() + 256);
+    for line in text.trim_end_matches("\r\n").split("\r\n") {
+        let name = line.split_once(':').map(|(n, _)| n.trim()).unwrap_or("");
+        if !name.is_empty() && replaced(name) {
+            continue;
+        }
+        raw.push_str(line);
+        raw.push_str("\r\n");
+    }
+    head.headers.retain(|name, _| !replaced(name));
+    head.header_list.retain(|(name, _)| !replaced(name));
+    for (name, value) in headers {
+        raw.push_str(&format!("{name}: {value}\r\n"));
+        head.headers.insert(name.clone(), value.clone());
+        head.header_list.push((name.clone(), value.clone()));
+    }
+    raw.push_str("\r\n");
+    head.raw_head = raw.into_bytes();
+}
+
+/// Reject a CONNECT to a non-TLS port and record the refused tunnel.
+async fn refuse_connect<S>(client: &mut S, parsed: &ParsedRequest) -> anyhow::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    tracing::warn!(
+        "Refusing CONNECT to non-TLS port {}:{}",
+        parsed.host,
+        parsed.port
+    );
+    let mut tx = HttpTransaction::new(
+        parsed.method,
+        &parsed.scheme,
+        &parsed.host,
+        parsed.port,
+        "/",
+        parsed.request_headers.clone(),
+    );
+    respond_with_status(
+        client,
+        403,
+        "Forbidden",
+        "CONNECT is restricted to TLS ports",
+    )
+    .await?;
+    tx.state = TransactionState::Failed;
+    tx.status_code = Some(403);
+    tx.status_message = Some("Forbidden".to_string());
+    tx.notes = Some(format!(
+        "CONNECT to port {} blocked by proxy policy",
+        parsed.port
+    ));
+    send_transaction_to_sink(tx);
+    Ok(())
+}
+
+async fn handle_plain_connect(
+    mut client: TcpStream,
+    parsed: ParsedRequest,
+    throttle: ConnectionThrottle,
+) -> anyhow::Result<()> {
     let mut tx = HttpTransaction::new(
         parsed.method,
         &parsed.scheme,
@@ -1189,7 +3139,7 @@ async fn handle_plain_connect(mut client: TcpStream, parsed: ParsedRequest) -> a
             tx.status_message = Some("Connection Established".to_string());
             send_transaction_to_sink(tx);
 
-            tunnel(client, upstream).await?;
+            tunnel(client, upstream, throttle).await?;
         }
         Err(e) => {
             tracing::error!(
@@ -1211,31 +3161,70 @@ async fn handle_plain_connect(mut client: TcpStream, parsed: ParsedRequest) -> a
     Ok(())
 }
 
-async fn read_response_head<S>(stream: &mut S) -> anyhow::Result<ResponseHead>
+pub(crate) async fn read_response_head<S>(stream: &mut S) -> anyhow::Result<ResponseHead>
 where
     S: AsyncRead + Unpin,
 {
-    let (raw_head, buffered_body) = read_message_head(stream).await?;
+    let (raw_head, buffered_body) = match read_message_head(stream).await {
+        Ok(head) => head,
+        Err(err) => {
+            return Err(match err.downcast::<IncompleteMessageHead>() {
+                // A truncated status line is still HTTP; anything else is not.
+                Ok(head) if !head.partial.is_empty() && !head.partial.starts_with(b"HTTP/") => {
+                    InvalidUpstreamResponse { raw: head.partial }.into()
+                }
+                Ok(head) => head.into(),
+                Err(err) => err,
+            });
+        }
+    };
 
     let mut header_storage = [httparse::EMPTY_HEADER; MAX_HEADER_COUNT];
     let mut res = httparse::Response::new(&mut header_storage);
-    let status = res.parse(&raw_head)?;
+    // Servers still emit obs-fold; accept it and report it as a conformance note.
+    let status = match httparse::ParserConfig::default()
+        .allow_obsolete_multiline_headers_in_responses(true)
+        .parse_response(&mut res, &raw_head)
+    {
+        Ok(status) => status,
+        Err(_) => {
+            let mut raw = raw_head;
+            raw.extend_from_slice(&buffered_body);
+            return Err(InvalidUpstreamResponse { raw }.into());
+        }
+    };
     if status.is_partial() {
         return Err(anyhow!("partial HTTP response"));
     }
 
     let status_code = res.code.unwrap_or(500);
     let reason = res.reason.unwrap_or("").to_string();
-    let headers = headers_from_httparse(res.headers)
+    // A folded value keeps its line breaks; unfold it with single spaces.
+    let header_list: Vec<(String, String)> = headers_from_httparse(res.headers)
         .into_iter()
+        .map(|(name, value)| {
+            if value.contains('\n') {
+                (name, value.split_whitespace().collect::<Vec<_>>().join(" "))
+            } else {
+                (name, value)
+            }
+        })
+        .collect();
+    let headers = header_list
+        .iter()
+        .cloned()
         .collect::<HashMap<String, String>>();
 
+    let conformance_notes = conformance_notes("response", &raw_head);
     Ok(ResponseHead {
         status_code,
+        version: format!("HTTP/1.{}", res.version.unwrap_or(1)),
         reason,
         headers,
+        header_list,
         raw_head,
         body_prefix: buffered_body,
+        conformance_notes,
     })
 }
 
@@ -1259,18 +3248,27 @@ where
         }
 
         if buffer.len() > MAX_HEADER_BYTES {
-            return Err(anyhow!("HTTP headers exceed allowed size"));
+            return Err(IncompleteMessageHead {
+                reason: "HTTP headers exceed allowed size",
+                partial: buffer,
+            }
+            .into());
         }
     }
 
-    Err(anyhow!("connection closed before headers completed"))
+    Err(IncompleteMessageHead {
+        reason: "connection closed before headers completed",
+        partial: buffer,
+    }
+    .into())
 }
 
 async fn read_exact_body<S>(stream: &mut S, expected_len: usize) -> anyhow::Result<Vec<u8>>
 where
     S: AsyncRead + Unpin,
 {
-    let mut body = Vec::with_capacity(expected_len);
+    // Grown as data arrives; the length comes from the peer and is not trusted
+    let mut body = Vec::new();
     let mut remaining = expected_len;
 
     while remaining > 0 {
@@ -1286,26 +3284,53 @@ where
     Ok(body)
 }
 
-async fn stream_response_body<R, W>(upstream: &mut R, client: &mut W) -> anyhow::Result<u64>
+/// Copy the response body to the client, keeping up to the capture limit.
+///
+/// Reads `remaining` bytes when the length is known, otherwise until EOF.
+async fn stream_response_body<R, W>(
+    upstream: &mut R,
+    client: &mut W,
+    capture: &mut BodyCapture,
+    remaining: Option<u64>,
+) -> anyhow::Result<u64>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
 {
-    let bytes = tokio::io::copy(upstream, client).await?;
-    Ok(bytes)
+    let mut buf = vec![0u8; 8192];
+    let mut total = 0u64;
+    loop {
+        let want = match remaining {
+            Some(limit) if total >= limit => break,
+            Some(limit) => buf.len().min((limit - total) as usize),
+            None => buf.len(),
+        };
+        let n = upstream.read(&mut buf[..want]).await?;
+        if n == 0 {
+            if remaining.is_some() {
+                return Err(anyhow!("upstream closed before end of response body"));
+            }
+            break;
+        }
+        client.write_all(&buf[..n]).await?;
+        client.flush().await?;
+        capture.push(&buf[..n]);
+        total += n as u64;
+    }
+    Ok(total)
 }
 
 async fn forward_chunked_body<R, W>(
     initial_buffer: Vec<u8>,
     upstream: &mut R,
     client: &mut W,
-) -> anyhow::Result<(Vec<u8>, u64)>
+    capture: &mut BodyCapture,
+) -> anyhow::Result<u64>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
 {
     let mut buffer: VecDeque<u8> = initial_buffer.into();
-    let mut captured = Vec::new();
     let mut total_body_bytes = 0u64;
 
     loop {
@@ -1337,24 +3362,28 @@ where
             break;
         }
 
-        let chunk_data = read_exact_from_buffer(&mut buffer, upstream, chunk_size).await?;
-        client.write_all(&chunk_data).await?;
-        total_body_bytes += chunk_size as u64;
-
-        if captured.len() < MAX_BODY_CAPTURE_BYTES {
-            let remaining = MAX_BODY_CAPTURE_BYTES - captured.len();
-            let capture_len = remaining.min(chunk_data.len());
-            captured.extend_from_slice(&chunk_data[..capture_len]);
+        // Relayed in slices, so a huge declared size is never allocated up front
+        let mut chunk_left = chunk_size;
+        while chunk_left > 0 {
+            let slice_len = chunk_left.min(8192);
+            let chunk_data = read_exact_from_buffer(&mut buffer, upstream, slice_len).await?;
+            client.write_all(&chunk_data).await?;
+            capture.push(&chunk_data);
+            chunk_left -= slice_len;
         }
+        total_body_bytes = total_body_bytes
+            .checked_add(chunk_size as u64)
+            .ok_or_else(|| anyhow!("chunked body length overflows"))?;
 
         let crlf = read_exact_from_buffer(&mut buffer, upstream, 2).await?;
         if crlf != b"\r\n" {
             return Err(anyhow!("invalid chunk terminator"));
         }
         client.write_all(&crlf).await?;
+        client.flush().await?;
     }
 
-    Ok((captured, total_body_bytes))
+    Ok(total_body_bytes)
 }
 
 struct NullWriter;
@@ -1377,8 +3406,9 @@ impl AsyncWrite for NullWriter {
     }
 }
 
-#[allow(dead_code)]
-async fn decode_chunked_body<R>(
+/// Read a chunked body, keeping at most [`MAX_BODY_CAPTURE_BYTES`] of it.
+/// Returns the kept bytes and the full decoded length.
+pub(crate) async fn decode_chunked_body<R>(
     initial_buffer: Vec<u8>,
     upstream: &mut R,
 ) -> anyhow::Result<(Vec<u8>, u64)>
@@ -1386,7 +3416,9 @@ where
     R: AsyncRead + Unpin,
 {
     let mut sink = NullWriter;
-    forward_chunked_body(initial_buffer, upstream, &mut sink).await
+    let mut capture = BodyCapture::new(MAX_BODY_CAPTURE_BYTES);
+    let total = forward_chunked_body(initial_buffer, upstream, &mut sink, &mut capture).await?;
+    Ok((capture.buf, total))
 }
 
 async fn read_crlf_line<R>(buffer: &mut VecDeque<u8>, stream: &mut R) -> anyhow::Result<Vec<u8>>
@@ -1403,6 +3435,9 @@ where
             }
             return Ok(line);
         }
+        if buffer.len() > MAX_HEADER_BYTES {
+            return Err(anyhow!("chunk line exceeds {MAX_HEADER_BYTES} bytes"));
+        }
         fill_buffer(buffer, stream).await?;
     }
 }
@@ -1451,10 +3486,168 @@ fn find_crlf_in_deque(buffer: &VecDeque<u8>) -> Option<usize> {
     (0..buffer.len() - 1).find(|&i| buffer[i] == b'\r' && buffer[i + 1] == b'\n')
 }
 
+/// End of the message head: a blank line, also accepting bare LF line endings
+/// (RFC 9112 section 2.2) so such requests can be parsed and flagged.
 fn find_header_end(buf: &[u8]) -> Option<usize> {
-    buf.windows(4)
-        .position(|window| window == b"\r\n\r\n")
-        .map(|pos| pos + 4)
+    buf.iter().enumerate().find_map(|(i, b)| {
+        if *b != b'\n' {
+            return None;
+        }
+        match &buf[i + 1..] {
+            [b'\n', ..] => Some(i + 2),
+            [b'\r', b'\n', ..] => Some(i + 3),
+            _ => None,
+        }
+    })
+}
+
+/// Line structure of a raw message head, as sent and before httparse
+/// normalizes it
+struct RawHeadScan<'a> {
+    /// Some line ends in a bare LF rather than CRLF
+    bare_lf: bool,
+    /// Header lines after the start line, without their line endings
+    header_lines: Vec<&'a [u8]>,
+}
+
+impl RawHeadScan<'_> {
+    fn has_obs_fold(&self) -> bool {
+        self.header_lines.iter().any(|line| is_folded(line))
+    }
+}
+
+/// A header line that continues the previous one (obs-fold)
+fn is_folded(line: &[u8]) -> bool {
+    matches!(line.first(), Some(b' ' | b'\t'))
+}
+
+/// Read line endings and header lines from a raw head, shared by the framing
+/// and conformance checks
+fn scan_raw_head(raw_head: &[u8]) -> RawHeadScan<'_> {
+    let bare_lf = raw_head
+        .iter()
+        .enumerate()
+        .any(|(i, b)| *b == b'\n' && (i == 0 || raw_head[i - 1] != b'\r'));
+    let header_lines = raw_head
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .collect();
+    RawHeadScan {
+        bare_lf,
+        header_lines,
+    }
+}
+
+/// Framing in a request head that servers may interpret differently. The proxy
+/// forwards a normalized head, so a server behind another intermediary could
+/// see a different request boundary than the client intended.
+fn framing_anomalies(raw_head: &[u8], headers: &[(String, String)]) -> Vec<String> {
+    let mut problems = Vec::new();
+    if scan_raw_head(raw_head).bare_lf {
+        problems.push("bare LF line endings in request head".to_string());
+    }
+
+    let values = |name: &str| -> Vec<&str> {
+        headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+            .collect()
+    };
+    let lengths = values("content-length");
+    let encodings = values("transfer-encoding");
+
+    if !lengths.is_empty() && !encodings.is_empty() {
+        problems.push("both Content-Length and Transfer-Encoding present".to_string());
+    }
+    let length_values: Vec<&str> = lengths
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    if length_values
+        .iter()
+        .any(|v| v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()))
+    {
+        problems.push(format!("invalid Content-Length {:?}", lengths.join(", ")));
+    } else if length_values.windows(2).any(|w| w[0] != w[1]) {
+        problems.push(format!(
+            "conflicting Content-Length values {:?}",
+            lengths.join(", ")
+        ));
+    }
+    if encodings.len() > 1 {
+        problems.push(format!("{} Transfer-Encoding headers", encodings.len()));
+    }
+    if let Some(last) = encodings.last() {
+        let final_coding = last.rsplit(',').next().unwrap_or_default().trim();
+        if !final_coding.eq_ignore_ascii_case("chunked") {
+            problems.push(format!(
+                "Transfer-Encoding {last:?} does not end in chunked"
+            ));
+        }
+    }
+    problems
+}
+
+/// Headers that must not repeat; a second copy leaves the message ambiguous
+const SINGLETON_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "content-type",
+    "content-encoding",
+    "authorization",
+    "location",
+];
+
+/// Tolerated but non-standard syntax in a message head. httparse normalizes
+/// some of it away (folded values, padding), so this reads `raw_head` itself.
+fn conformance_notes(message: &str, raw_head: &[u8]) -> Vec<String> {
+    let mut notes = Vec::new();
+    let scan = scan_raw_head(raw_head);
+    if scan.bare_lf {
+        notes.push(format!("bare LF line endings in {message} head"));
+    }
+
+    let mut seen: Vec<(String, usize)> = Vec::new();
+    let mut previous: Option<String> = None;
+    for line in scan.header_lines {
+        if is_folded(line) {
+            let name = previous.as_deref().unwrap_or("?");
+            notes.push(format!("obsolete line folding in {message} header {name}"));
+            continue;
+        }
+        let line = String::from_utf8_lossy(line);
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let padded_start = value.starts_with('\t') || value.starts_with("  ");
+        let padded_end = value.ends_with([' ', '\t']);
+        if padded_start || padded_end {
+            notes.push(format!(
+                "{message} header {name} has {} whitespace around its value",
+                match (padded_start, padded_end) {
+                    (true, true) => "leading and trailing",
+                    (true, false) => "leading",
+                    _ => "trailing",
+                }
+            ));
+        }
+        let lower = name.to_ascii_lowercase();
+        match seen.iter_mut().find(|(seen_name, _)| *seen_name == lower) {
+            Some((_, count)) => *count += 1,
+            None => seen.push((lower, 1)),
+        }
+        previous = Some(name.to_string());
+    }
+    for (name, count) in seen {
+        if count > 1 && SINGLETON_HEADERS.contains(&name.as_str()) {
+            notes.push(format!("{count} {name} headers in {message}"));
+        }
+    }
+    notes
 }
 
 fn headers_from_httparse(headers: &[httparse::Header]) -> Vec<(String, String)> {
@@ -1483,11 +3676,22 @@ fn capture_body(body: &[u8]) -> Option<Vec<u8>> {
     Some(body[..cap].to_vec())
 }
 
+/// Where a request is going, as resolved from its request target and Host header
+struct RequestTarget {
+    scheme: String,
+    host: String,
+    port: u16,
+    path: String,
+    /// Raw `user[:password]` from an absolute-form authority
+    userinfo: Option<String>,
+    form: RequestTargetForm,
+}
+
 fn resolve_target(
     raw_path: &str,
     headers: &HashMap<String, String>,
     default_scheme: RequestScheme,
-) -> anyhow::Result<(String, String, u16, String)> {
+) -> anyhow::Result<RequestTarget> {
     if raw_path.starts_with("http://") {
         return parse_absolute_target(raw_path, RequestScheme::Http);
     }
@@ -1500,18 +3704,17 @@ fn resolve_target(
         .ok_or_else(|| anyhow!("Missing Host header in HTTP/1.1 request"))?;
     let (host, port) = split_host_and_port(&host_header, default_scheme.default_port());
 
-    Ok((
-        default_scheme.as_str().to_string(),
+    Ok(RequestTarget {
+        scheme: default_scheme.as_str().to_string(),
         host,
         port,
-        raw_path.to_string(),
-    ))
+        path: raw_path.to_string(),
+        userinfo: None,
+        form: RequestTargetForm::Origin,
+    })
 }
 
-fn parse_absolute_target(
-    target: &str,
-    scheme: RequestScheme,
-) -> anyhow::Result<(String, String, u16, String)> {
+fn parse_absolute_target(target: &str, scheme: RequestScheme) -> anyhow::Result<RequestTarget> {
     let without_scheme = target
         .split_once("://")
         .map(|(_, rest)| rest)
@@ -1522,8 +3725,54 @@ fn parse_absolute_target(
         (without_scheme, "/".to_string())
     };
 
+    let (userinfo, host_port) = match host_port.rsplit_once('@') {
+        Some((userinfo, host_port)) => (Some(userinfo.to_string()), host_port),
+        None => (None, host_port),
+    };
+
     let (host, port) = split_host_and_port(host_port, scheme.default_port());
-    Ok((scheme.as_str().to_string(), host, port, path_part))
+    Ok(RequestTarget {
+        scheme: scheme.as_str().to_string(),
+        host,
+        port,
+        path: path_part,
+        userinfo,
+        form: RequestTargetForm::Absolute,
+    })
+}
+
+/// Why the request's Host could be read differently by another server, if it
+/// could: several Host headers, a value that is not a plain `host[:port]`, or
+/// an absolute-form target naming a different authority than Host.
+fn host_ambiguity(parsed: &ParsedRequest) -> Option<String> {
+    let hosts: Vec<&str> = parsed
+        .header_list
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.as_str())
+        .collect();
+    if hosts.len() > 1 {
+        return Some(format!("{} Host headers", hosts.len()));
+    }
+    let host = hosts.first()?.trim();
+    let malformed = host.is_empty()
+        || host
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ',' | '/' | '@' | '\\' | '?' | '#'));
+    if malformed {
+        return Some(format!("malformed Host header {host:?}"));
+    }
+    if parsed.target_form == RequestTargetForm::Absolute {
+        let default_port = if parsed.scheme == "https" { 443 } else { 80 };
+        let (name, port) = split_host_and_port(host, default_port);
+        if !name.eq_ignore_ascii_case(&parsed.host) || port != parsed.port {
+            return Some(format!(
+                "Host header {host:?} does not match request target {}:{}",
+                parsed.host, parsed.port
+            ));
+        }
+    }
+    None
 }
 
 fn split_host_and_port(input: &str, default_port: u16) -> (String, u16) {
@@ -1541,14 +3790,33 @@ async fn respond_with_status<W>(
     message: &str,
     body: &str,
 ) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    respond_with_headers(stream, code, message, &[], body).await
+}
+
+/// [`respond_with_status`] with extra headers, such as the `Allow` a 405 needs
+async fn respond_with_headers<W>(
+    stream: &mut W,
+    code: u16,
+    message: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> anyhow::Result<()>
 where
     W: AsyncWrite + Unpin,
 {
     let body_bytes = body.as_bytes();
+    let extra: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
     let response = format!(
-        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\nContent-Type: text/plain\r\n\r\n{}",
+        "HTTP/1.1 {} {}\r\n{}Content-Length: {}\r\nConnection: close\r\nContent-Type: text/plain\r\n\r\n{}",
         code,
         message,
+        extra,
         body_bytes.len(),
         body
     );
@@ -1556,11 +3824,80 @@ where
     Ok(())
 }
 
+/// Whether the request's upstream resolves to the proxy's own listener, which
+/// would recurse back into this proxy. Allowlisted hosts are let through.
+async fn targets_own_listener(parsed_request: &ParsedRequest, options: &InterceptOptions) -> bool {
+    let Some(listen_addr) = options.listen_addr else {
+        return false;
+    };
+    if parsed_request.port != listen_addr.port() {
+        return false;
+    }
+    let host = parsed_request.host.to_ascii_lowercase();
+    if options
+        .self_loop_allowlist
+        .iter()
+        .any(|p| sni::pattern_matches(p, &host))
+    {
+        return false;
+    }
+    let Ok(addrs) =
+        tokio::net::lookup_host((parsed_request.host.as_str(), parsed_request.port)).await
+    else {
+        return false;
+    };
+    let listen_ip = listen_addr.ip();
+    let ips: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+    if !listen_ip.is_unspecified() {
+        return ips
+            .iter()
+            .any(|&ip| ip == listen_ip || (ip.is_unspecified() && listen_ip.is_loopback()));
+    }
+    if ips.iter().any(|ip| ip.is_loopback() || ip.is_unspecified()) {
+        return true;
+    }
+    // Probing binds sockets, which blocks; keep it off the runtime threads.
+    tokio::task::spawn_blocking(move || ips.into_iter().any(local_interface_ip))
+        .await
+        .unwrap_or(false)
+}
+
+/// Whether `ip` is assigned to this machine (probed by binding to it).
+fn local_interface_ip(ip: IpAddr) -> bool {
+    std::net::UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
 async fn persist_and_stream(tx: HttpTransaction) {
+    if quiet_hosts::is_quiet(&tx.host) {
+        return;
+    }
     if let Err(err) = storage::persist_transaction(tx.clone()).await {
         tracing::error!("Failed to persist transaction: {}", err);
     }
-    send_transaction_to_sink(tx);
+    let enrich = geoip::wants_enrichment(&tx);
+    send_transaction_to_sink(tx.clone());
+    if enrich {
+        tokio::spawn(enrich_with_geo(tx));
+    }
+}
+
+/// Annotate a persisted transaction with its server's location and publish the
+/// update. Only `server_geo` is written, so edits made to the stored
+/// transaction meanwhile (tags, pins, ...) are kept.
+async fn enrich_with_geo(tx: HttpTransaction) {
+    let id = tx.id.clone();
+    let Ok(Some(geo)) = tokio::task::spawn_blocking(move || geoip::lookup_server(&tx)).await else {
+        return;
+    };
+    if let Err(err) = storage::set_transaction_server_geo(&id, geo).await {
+        tracing::error!("Failed to persist GeoIP enrichment: {}", err);
+        return;
+    }
+    match storage::get_transaction_by_id(&id).await {
+        Ok(Some(tx)) => send_transaction_to_sink(tx),
+        Ok(None) => {}
+        Err(err) => tracing::error!("Failed to reload GeoIP-enriched transaction: {}", err),
+    }
 }
 
 async fn forward_request_to_upstream<S, U>(
@@ -1568,6 +3905,7 @@ async fn forward_request_to_upstream<S, U>(
     upstream: &mut U,
     parsed_request: &mut ParsedRequest,
     capture: &mut BodyCapture,
+    hop_by_hop_headers: &[String],
 ) -> anyhow::Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send,
@@ -1579,13 +3917,28 @@ where
         &parsed_request.path,
         &parsed_request.version,
         &parsed_request.header_list,
+        hop_by_hop_headers,
     )
     .await?;
+    forward_request_body(client, upstream, parsed_request, capture).await
+}
 
-    match &mut parsed_request.body_kind {
-        RequestBodyKind::None => {}
-        RequestBodyKind::ContentLength { length } => {
-            if *length > MAX_REQUEST_BODY_BYTES {
+/// Relay the request body from the client per its framing, capturing the
+/// decoded bytes. Chunked bodies keep their chunked framing on `upstream`.
+async fn forward_request_body<S, U>(
+    client: &mut S,
+    upstream: &mut U,
+    parsed_request: &mut ParsedRequest,
+    capture: &mut BodyCapture,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + Unpin,
+    U: AsyncWrite + Unpin,
+{
+    match &mut parsed_request.body_kind {
+        RequestBodyKind::None => {}
+        RequestBodyKind::ContentLength { length } => {
+            if *length > MAX_REQUEST_BODY_BYTES {
                 return Err(RequestBodyTooLarge::new(MAX_REQUEST_BODY_BYTES).into());
             }
             forward_fixed_length_body(
@@ -1618,26 +3971,40 @@ where
     Ok(())
 }
 
+/// Note on `tx` each rewrite rule that acted on it
+fn record_rewrite_rules(tx: &mut HttpTransaction, rule_ids: Vec<String>) {
+    tx.applied_actions
+        .extend(rule_ids.into_iter().map(|id| format!("rewrite:{id}")));
+}
+
 async fn handle_breakpoints(
     tx: &mut HttpTransaction,
     parsed_request: &mut ParsedRequest,
+    request_capture_limit: usize,
 ) -> anyhow::Result<()> {
     if let Some(edit) =
         breakpoints::maybe_pause_request(tx, parsed_request.to_breakpoint_context()).await?
     {
         parsed_request.apply_edit(&edit);
-        update_transaction_from_parsed(tx, parsed_request);
+        update_transaction_from_parsed(tx, parsed_request, request_capture_limit);
         send_transaction_to_sink(tx.clone());
     }
     Ok(())
 }
 
+/// Write the upstream request head, dropping hop-by-hop headers.
+///
+/// Besides `hop_by_hop_headers`, every header named in the client's `Connection`
+/// header is stripped. `Connection` is rewritten to `keep-alive` so the upstream
+/// connection can be pooled, except for protocol upgrades where `Upgrade` is kept
+/// and `Connection: Upgrade` is sent instead.
 async fn write_request_head<W>(
     writer: &mut W,
     method: &str,
     path: &str,
     version: &str,
     headers: &[(String, String)],
+    hop_by_hop_headers: &[String],
 ) -> anyhow::Result<()>
 where
     W: AsyncWrite + Unpin,
@@ -1646,23 +4013,38 @@ where
         .write_all(format!("{method} {path} {version}\r\n").as_bytes())
         .await?;
 
-    let mut has_connection = false;
+    let connection_tokens: Vec<String> = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Connection"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect();
+    let is_upgrade = connection_tokens.iter().any(|token| token == "upgrade");
+
     for (name, value) in headers {
-        if name.eq_ignore_ascii_case("Proxy-Connection") || name.eq_ignore_ascii_case("Keep-Alive")
-        {
+        if name.eq_ignore_ascii_case("Connection") {
             continue;
         }
-        if name.eq_ignore_ascii_case("Connection") {
-            has_connection = true;
-            writer.write_all(b"Connection: close\r\n").await?;
+        let keep = (is_upgrade && name.eq_ignore_ascii_case("Upgrade"))
+            || FRAMING_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name));
+        let is_hop_by_hop = hop_by_hop_headers
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(name))
+            || connection_tokens
+                .iter()
+                .any(|token| token.eq_ignore_ascii_case(name));
+        if is_hop_by_hop && !keep {
             continue;
         }
         writer
             .write_all(format!("{name}: {value}\r\n").as_bytes())
             .await?;
     }
-    if !has_connection {
-        writer.write_all(b"Connection: close\r\n").await?;
+    if is_upgrade {
+        writer.write_all(b"Connection: Upgrade\r\n").await?;
+    } else {
+        writer.write_all(b"Connection: keep-alive\r\n").await?;
     }
     writer.write_all(b"\r\n").await?;
     Ok(())
@@ -1770,24 +4152,37 @@ where
     Ok(())
 }
 
-fn update_transaction_from_parsed(tx: &mut HttpTransaction, parsed: &ParsedRequest) {
+/// Refresh `tx` after a breakpoint edit. An edited body is stored like a
+/// forwarded one: at most `request_capture_limit` bytes, plus its hash.
+fn update_transaction_from_parsed(
+    tx: &mut HttpTransaction,
+    parsed: &ParsedRequest,
+    request_capture_limit: usize,
+) {
     tx.method = parsed.method;
     tx.path = parsed.path.clone();
     tx.scheme = parsed.scheme.clone();
     tx.host = parsed.host.clone();
     tx.request_headers = parsed.request_headers.clone();
+    tx.request_header_list = parsed.header_list.clone();
     tx.request_content_type = header_value(&parsed.request_headers, "content-type");
     if let RequestBodyKind::Edited { data } = &parsed.body_kind {
-        tx.request_body = capture_body(data);
+        tx.request_body = capture_body(&data[..data.len().min(request_capture_limit)]);
+        tx.request_body_sha256 = sha256_hex(data);
+        tx.refresh_form_fields();
+        tx.refresh_jsonrpc();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api::proxy_api::{reset_test_transaction_observer, set_test_transaction_observer};
+    use crate::api::proxy_api::{
+        reset_test_transaction_observer, set_stream_final_state_only, set_test_transaction_observer,
+    };
     use crate::models::breakpoint::BreakpointRuleInput;
-    use crate::models::TransactionFilter;
+    use crate::models::{TransactionFilter, TranscriptDirection};
+    use crate::proxy::alerts;
     use serial_test::serial;
     use std::net::TcpListener as StdTcpListener;
     use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
@@ -1802,6 +4197,58 @@ mod tests {
             .port()
     }
 
+    /// Serve every upstream connection the proxy opens with `serve`, which
+    /// gets the upstream end of an in-memory stream.
+    fn mock_upstream<F, Fut>(serve: F)
+    where
+        F: Fn(tokio::io::DuplexStream) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        set_test_upstream_connector(move |_req| {
+            let (mock_stream, upstream_peer) = duplex(4096);
+            tokio::spawn(serve(upstream_peer));
+            async move {
+                Ok((
+                    UpstreamStream::Mock(mock_stream),
+                    ConnectionTiming::default(),
+                ))
+            }
+        });
+    }
+
+    /// Hand `stream` to the first upstream connection the proxy opens.
+    fn mock_upstream_stream(stream: tokio::io::DuplexStream) {
+        let holder = Mutex::new(Some(stream));
+        set_test_upstream_connector(move |_req| {
+            let stream = holder
+                .lock()
+                .unwrap()
+                .take()
+                .expect("connector already used");
+            async move { Ok((UpstreamStream::Mock(stream), ConnectionTiming::default())) }
+        });
+    }
+
+    /// Every transaction state sent to the sink, in order
+    fn observe_transactions() -> mpsc::UnboundedReceiver<HttpTransaction> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        set_test_transaction_observer(move |tx| {
+            let _ = sender.send(tx.clone());
+        });
+        receiver
+    }
+
+    /// The last transaction state observed so far
+    fn last_observed(
+        observed: &mut mpsc::UnboundedReceiver<HttpTransaction>,
+    ) -> Option<HttpTransaction> {
+        let mut last = None;
+        while let Ok(tx) = observed.try_recv() {
+            last = Some(tx);
+        }
+        last
+    }
+
     fn build_test_request(
         host: &str,
         method: HttpMethod,
@@ -1825,143 +4272,3000 @@ mod tests {
             header_list: headers,
             body_kind,
             buffered_body: Vec::new(),
+            url_credentials: None,
+            target_form: RequestTargetForm::Origin,
+            framing_anomalies: Vec::new(),
+            client_fingerprint: None,
+            intercepted: false,
+            conformance_notes: Vec::new(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore = "Requires opening local TCP ports"]
+    #[serial]
+    async fn http_request_persisted_via_handle_connection() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let storage_path = storage_dir.path().to_string_lossy().to_string();
+        let _ = storage::reset_store_for_tests(&storage_path);
+
+        let upstream_listener = TcpListener::bind(("127.0.0.1", available_port()))
+            .await
+            .unwrap();
+        let upstream_addr = upstream_listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            if let Ok((mut socket, _)) = upstream_listener.accept().await {
+                let mut buf = vec![0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK";
+                let _ = socket.write_all(response).await;
+            }
+        });
+
+        let proxy_listener = TcpListener::bind(("127.0.0.1", available_port()))
+            .await
+            .unwrap();
+        let proxy_addr = proxy_listener.local_addr().unwrap();
+        let proxy_task = tokio::spawn(async move {
+            if let Ok((socket, _)) = proxy_listener.accept().await {
+                handle_connection(socket, None, None, Arc::default())
+                    .await
+                    .expect("handle connection");
+            }
+        });
+
+        let mut client = tokio::net::TcpStream::connect(proxy_addr).await.unwrap();
+        let request = format!(
+            "GET http://127.0.0.1:{}/test HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 0\r\n\r\n",
+            upstream_addr.port()
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut resp_buf = vec![0u8; 128];
+        let _ = client.read(&mut resp_buf).await.unwrap();
+        proxy_task.await.unwrap();
+
+        let filter = TransactionFilter::default();
+        let result = storage::query_transactions(&filter, 0, 10)
+            .await
+            .expect("query");
+        assert_eq!(result.items.len(), 1);
+        let tx = &result.items[0];
+        assert_eq!(tx.host, "127.0.0.1");
+        assert_eq!(tx.path, "/test");
+        assert_eq!(tx.status_code, Some(200));
+        assert_eq!(
+            tx.request_headers.get("Host").map(|s| s.as_str()),
+            Some("127.0.0.1")
+        );
+    }
+
+    #[tokio::test]
+    async fn portal_request_body_is_discarded_before_the_next_request() {
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        client_peer
+            .write_all(
+                b"POST http://cheddar.proxy/cert.pem HTTP/1.1\r\nHost: cheddar.proxy\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut parsed = read_http_request(&mut proxy_client, RequestScheme::Http)
+            .await
+            .unwrap();
+        let reusable = answer_portal_request(&mut proxy_client, &mut parsed, Some("PEM"))
+            .await
+            .unwrap();
+        assert!(reusable);
+        let mut response = vec![0u8; 1024];
+        let n = client_peer.read(&mut response).await.unwrap();
+        assert!(response[..n].starts_with(b"HTTP/1.1 200 OK"));
+
+        // The body was consumed, so the next read starts at the next request.
+        client_peer
+            .write_all(b"GET http://cheddar.proxy/ HTTP/1.1\r\nHost: cheddar.proxy\r\n\r\n")
+            .await
+            .unwrap();
+        let next = read_http_request(&mut proxy_client, RequestScheme::Http)
+            .await
+            .unwrap();
+        assert_eq!(next.method, HttpMethod::Get);
+        assert_eq!(next.path, "/");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_request_persists_transaction_with_mock_upstream() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+
+        let holder = Arc::new(Mutex::new(Some(mock_stream)));
+        set_test_upstream_connector({
+            let holder = holder.clone();
+            move |_req| {
+                let mut guard = holder.lock().unwrap();
+                let stream = guard.take().expect("connector already used");
+                async move {
+                    let timing = ConnectionTiming {
+                        dns_ms: 0,
+                        tcp_ms: 0,
+                        tls_ms: None,
+                        server_ip: None,
+                        tls_version: None,
+                        tls_cipher: None,
+                        tls_resumed: None,
+                        reused: false,
+                    };
+                    Ok((UpstreamStream::Mock(stream), timing))
+                }
+            }
+        });
+
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let n = upstream_peer.read(&mut buf).await.unwrap();
+            let forwarded = String::from_utf8_lossy(&buf[..n]);
+            assert!(forwarded.contains("GET /mock"));
+            upstream_peer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nPONG")
+                .await
+                .unwrap();
+        });
+
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/mock",
+            vec![
+                ("Host".to_string(), "example.com".to_string()),
+                ("Content-Length".to_string(), "0".to_string()),
+            ],
+        );
+
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+
+        let mut response_buf = vec![0u8; 512];
+        let n = client_peer.read(&mut response_buf).await.unwrap();
+        let response = String::from_utf8_lossy(&response_buf[..n]);
+        assert!(response.contains("200 OK"));
+        assert!(response.contains("PONG"));
+
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.items.len(), 1);
+        let tx = &result.items[0];
+        assert_eq!(tx.path, "/mock");
+        assert_eq!(tx.status_code, Some(200));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn final_state_only_stream_skips_pending_update() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+        });
+
+        let mut tx_rx = observe_transactions();
+        set_stream_final_state_only(true);
+
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/final",
+            vec![("Host".to_string(), "example.com".to_string())],
+        );
+        let result = process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await;
+        set_stream_final_state_only(false);
+        reset_test_transaction_observer();
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+        result.expect("process request should succeed");
+
+        let mut seen = Vec::new();
+        while let Ok(tx) = tx_rx.try_recv() {
+            seen.push(tx.state);
         }
+        assert_eq!(seen, vec![TransactionState::Completed]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn response_only_mode_skips_request_body_capture() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        const UPLOAD_LEN: usize = 2 * MAX_BODY_CAPTURE_BYTES;
+
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        let (mock_stream, mut upstream_peer) = duplex(64 * 1024);
+
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 16 * 1024];
+            loop {
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "upstream closed before full upload arrived");
+                received.extend_from_slice(&buf[..n]);
+                if let Some(head_end) = find_header_end(&received) {
+                    if received.len() >= head_end + UPLOAD_LEN {
+                        break;
+                    }
+                }
+            }
+            upstream_peer
+                .write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 7\r\n\r\nSTORED!")
+                .await
+                .unwrap();
+            received.len()
+        });
+
+        let client_task = tokio::spawn(async move {
+            client_peer
+                .write_all(&vec![b'x'; UPLOAD_LEN])
+                .await
+                .unwrap();
+            let mut response = Vec::new();
+            let mut buf = vec![0u8; 512];
+            while !response.ends_with(b"STORED!") {
+                let n = client_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "proxy closed before response arrived");
+                response.extend_from_slice(&buf[..n]);
+            }
+            response
+        });
+
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Post,
+            "/upload",
+            vec![
+                ("Host".to_string(), "example.com".to_string()),
+                ("Content-Length".to_string(), UPLOAD_LEN.to_string()),
+            ],
+        );
+
+        let options = InterceptOptions {
+            response_only_mode: true,
+            ..Default::default()
+        };
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+
+        let response = client_task.await.unwrap();
+        assert!(String::from_utf8_lossy(&response).contains("201 Created"));
+        let forwarded_len = upstream_task.await.unwrap();
+        assert!(forwarded_len >= UPLOAD_LEN);
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.items.len(), 1);
+        let tx = &result.items[0];
+        assert_eq!(tx.status_code, Some(201));
+        assert!(tx.request_body.is_none());
+        assert_eq!(tx.response_body.as_deref(), Some(&b"STORED!"[..]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn request_only_capture_keeps_request_body_and_hashes_response() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        let (mock_stream, mut upstream_peer) = duplex(64 * 1024);
+
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.ends_with(b"{\"q\":1}") {
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "upstream closed before the request body arrived");
+                received.extend_from_slice(&buf[..n]);
+            }
+            upstream_peer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nSECRET")
+                .await
+                .unwrap();
+        });
+
+        let client_task = tokio::spawn(async move {
+            client_peer.write_all(b"{\"q\":1}").await.unwrap();
+            let mut response = Vec::new();
+            let mut buf = vec![0u8; 512];
+            while !response.ends_with(b"SECRET") {
+                let n = client_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "proxy closed before response arrived");
+                response.extend_from_slice(&buf[..n]);
+            }
+        });
+
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Post,
+            "/search",
+            vec![
+                ("Host".to_string(), "example.com".to_string()),
+                ("Content-Length".to_string(), "7".to_string()),
+            ],
+        );
+
+        let options = InterceptOptions {
+            capture_response_bodies: false,
+            ..Default::default()
+        };
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+
+        client_task.await.unwrap();
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.items.len(), 1);
+        let tx = &result.items[0];
+        assert_eq!(tx.request_body.as_deref(), Some(&b"{\"q\":1}"[..]));
+        assert!(tx.response_body.is_none());
+        assert_eq!(tx.response_body_sha256, sha256_hex(b"SECRET"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn empty_response_body_is_stored_the_same_way_on_every_path() {
+        let responses: [&[u8]; 3] = [
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+            // No length: streamed until the upstream closes
+            b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n",
+        ];
+        for raw in responses {
+            let (mut proxy_client, mut client_peer) = duplex(4096);
+            let (mock_stream, mut upstream_peer) = duplex(4096);
+            mock_upstream_stream(mock_stream);
+
+            let upstream_task = tokio::spawn(async move {
+                let mut buf = vec![0u8; 1024];
+                let _ = upstream_peer.read(&mut buf).await.unwrap();
+                upstream_peer.write_all(raw).await.unwrap();
+            });
+
+            let mut tx_rx = observe_transactions();
+            let parsed_request = build_test_request(
+                "example.com",
+                HttpMethod::Get,
+                "/empty",
+                vec![("Host".to_string(), "example.com".to_string())],
+            );
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+            upstream_task.await.unwrap();
+            drop(proxy_client);
+            let mut response = Vec::new();
+            client_peer.read_to_end(&mut response).await.unwrap();
+            reset_test_transaction_observer();
+            reset_test_upstream_connector();
+
+            let tx = last_observed(&mut tx_rx).expect("transaction observed");
+            let head = String::from_utf8_lossy(raw);
+            assert_eq!(tx.status_code, Some(200), "{head}");
+            assert_eq!(tx.response_body, None, "{head}");
+            assert_eq!(tx.response_body_sha256, None, "{head}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn h2c_upgrade_offer_is_downgraded_by_default() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        let (mock_stream, mut upstream_peer) = duplex(64 * 1024);
+
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while find_header_end(&received).is_none() {
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "upstream closed before the request head arrived");
+                received.extend_from_slice(&buf[..n]);
+            }
+            upstream_peer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        let client_task = tokio::spawn(async move {
+            let mut response = Vec::new();
+            let mut buf = vec![0u8; 512];
+            while !response.ends_with(b"ok") {
+                let n = client_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "proxy closed before response arrived");
+                response.extend_from_slice(&buf[..n]);
+            }
+            String::from_utf8(response).unwrap()
+        });
+
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/",
+            vec![
+                ("Host".to_string(), "example.com".to_string()),
+                (
+                    "Connection".to_string(),
+                    "Upgrade, HTTP2-Settings".to_string(),
+                ),
+                ("Upgrade".to_string(), "h2c".to_string()),
+                (
+                    "HTTP2-Settings".to_string(),
+                    "AAMAAABkAAQAoAAAAAIAAAAA".to_string(),
+                ),
+            ],
+        );
+
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+
+        let response = client_task.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let forwarded = upstream_task.await.unwrap().to_ascii_lowercase();
+        assert!(!forwarded.contains("upgrade"), "{forwarded}");
+        assert!(!forwarded.contains("http2-settings"), "{forwarded}");
+        assert!(forwarded.contains("connection: keep-alive"));
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        let tx = &result.items[0];
+        assert_eq!(tx.status_code, Some(200));
+        assert_eq!(tx.h2c_upgrade, Some(H2cUpgradeMode::Downgrade));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn streamed_response_is_partially_captured_and_fully_forwarded() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        const BODY_LEN: usize = MAX_BODY_CAPTURE_BYTES + 4096;
+
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        let (mock_stream, mut upstream_peer) = duplex(64 * 1024);
+
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {BODY_LEN}\r\n\r\n").as_bytes(),
+                )
+                .await
+                .unwrap();
+            upstream_peer
+                .write_all(&vec![b'z'; BODY_LEN])
+                .await
+                .unwrap();
+            // Dropping the peer closes the stream, ending the streamed copy.
+        });
+
+        let client_task = tokio::spawn(async move {
+            let mut response = Vec::new();
+            client_peer.read_to_end(&mut response).await.unwrap();
+            response
+        });
+
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/download",
+            vec![("Host".to_string(), "example.com".to_string())],
+        );
+
+        let options = InterceptOptions {
+            stream_threshold_bytes: 1024,
+            ..Default::default()
+        };
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        upstream_task.await.unwrap();
+        drop(proxy_client);
+
+        let response = client_task.await.unwrap();
+        let head_end = find_header_end(&response).expect("response head");
+        assert_eq!(response.len() - head_end, BODY_LEN);
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.items.len(), 1);
+        let tx = &result.items[0];
+        assert_eq!(tx.response_size, Some(BODY_LEN as u64));
+        assert_eq!(
+            tx.response_body.as_ref().map(|b| b.len()),
+            Some(MAX_BODY_CAPTURE_BYTES)
+        );
+    }
+
+    #[test]
+    fn responses_above_the_capture_cap_are_never_buffered() {
+        let options = InterceptOptions {
+            stream_threshold_bytes: usize::MAX,
+            body_capture_limit: 4096,
+            ..Default::default()
+        };
+        assert_eq!(options.buffer_limit(), 4096);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn streamed_response_is_fully_captured_under_a_higher_capture_cap() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        const BODY_LEN: usize = MAX_BODY_CAPTURE_BYTES + 4096;
+
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        let (mock_stream, mut upstream_peer) = duplex(64 * 1024);
+
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {BODY_LEN}\r\n\r\n").as_bytes(),
+                )
+                .await
+                .unwrap();
+            upstream_peer
+                .write_all(&vec![b'z'; BODY_LEN])
+                .await
+                .unwrap();
+        });
+
+        let client_task = tokio::spawn(async move {
+            let mut response = Vec::new();
+            client_peer.read_to_end(&mut response).await.unwrap();
+            response
+        });
+
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/download",
+            vec![("Host".to_string(), "example.com".to_string())],
+        );
+
+        let options = InterceptOptions {
+            stream_threshold_bytes: 1024,
+            body_capture_limit: 2 * MAX_BODY_CAPTURE_BYTES,
+            ..Default::default()
+        };
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        upstream_task.await.unwrap();
+        drop(proxy_client);
+
+        let response = client_task.await.unwrap();
+        let head_end = find_header_end(&response).expect("response head");
+        assert_eq!(response.len() - head_end, BODY_LEN);
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        let tx = &result.items[0];
+        assert_eq!(tx.response_size, Some(BODY_LEN as u64));
+        assert_eq!(tx.response_body.as_ref().map(|b| b.len()), Some(BODY_LEN));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn second_request_reuses_pooled_upstream_connection() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+
+        // A single upstream connection answers both requests.
+        let upstream_task = tokio::spawn(async move {
+            for body in ["ONE", "TWO"] {
+                let mut buf = vec![0u8; 512];
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                assert!(String::from_utf8_lossy(&buf[..n]).contains("Connection: keep-alive"));
+                upstream_peer
+                    .write_all(
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n{body}").as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            upstream_peer
+        });
+
+        for path in ["/one", "/two"] {
+            let (mut proxy_client, mut client_peer) = duplex(4096);
+            let parsed_request = build_test_request(
+                "example.com",
+                HttpMethod::Get,
+                path,
+                vec![("Host".to_string(), "example.com".to_string())],
+            );
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+            let mut response_buf = vec![0u8; 256];
+            let n = client_peer.read(&mut response_buf).await.unwrap();
+            assert!(String::from_utf8_lossy(&response_buf[..n]).contains("200 OK"));
+        }
+
+        let _upstream_peer = upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        let first = result.items.iter().find(|tx| tx.path == "/one").unwrap();
+        let second = result.items.iter().find(|tx| tx.path == "/two").unwrap();
+        assert!(!first.upstream_connection_reused);
+        assert!(second.upstream_connection_reused);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn only_idempotent_requests_are_resent_after_reused_connection_fails() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        // The first connection looks pooled and dies after reading the request;
+        // any later one answers.
+        let connects = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        set_test_upstream_connector({
+            let connects = connects.clone();
+            move |_req| {
+                let attempt = connects.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let (mock_stream, mut upstream_peer) = duplex(4096);
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 512];
+                    let _ = upstream_peer.read(&mut buf).await;
+                    if attempt > 0 {
+                        let _ = upstream_peer
+                            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                            .await;
+                    }
+                });
+                let timing = ConnectionTiming {
+                    reused: attempt == 0,
+                    ..Default::default()
+                };
+                async move { Ok((UpstreamStream::Mock(mock_stream), timing)) }
+            }
+        });
+
+        for (method, expected) in [(HttpMethod::Post, "502"), (HttpMethod::Get, "200")] {
+            connects.store(0, std::sync::atomic::Ordering::SeqCst);
+            let (mut proxy_client, mut client_peer) = duplex(4096);
+            let parsed_request = build_test_request(
+                "example.com",
+                method,
+                "/",
+                vec![("Content-Length".to_string(), "0".to_string())],
+            );
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+            let mut response = vec![0u8; 256];
+            let n = client_peer.read(&mut response).await.unwrap();
+            let response = String::from_utf8_lossy(&response[..n]);
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {expected}")),
+                "{method:?}: {response}"
+            );
+            let expected_connects = if method == HttpMethod::Post { 1 } else { 2 };
+            assert_eq!(
+                connects.load(std::sync::atomic::Ordering::SeqCst),
+                expected_connects
+            );
+        }
+        reset_test_upstream_connector();
+    }
+
+    async fn parse_raw_request(raw: &[u8]) -> ParsedRequest {
+        let (mut proxy_side, mut client_peer) = duplex(4096);
+        client_peer.write_all(raw).await.unwrap();
+        read_http_request(&mut proxy_side, RequestScheme::Http)
+            .await
+            .expect("request parses")
+    }
+
+    #[tokio::test]
+    async fn origin_form_target_resolves_host_from_header() {
+        let parsed =
+            parse_raw_request(b"GET /items?id=1 HTTP/1.1\r\nHost: api.example.com:8080\r\n\r\n")
+                .await;
+        assert_eq!(parsed.target_form, RequestTargetForm::Origin);
+        assert_eq!(parsed.host, "api.example.com");
+        assert_eq!(parsed.port, 8080);
+        assert_eq!(parsed.path, "/items?id=1");
+        assert_eq!(host_ambiguity(&parsed), None);
+
+        let duplicated = parse_raw_request(
+            b"GET / HTTP/1.1\r\nHost: api.example.com\r\nHost: internal.example.com\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            host_ambiguity(&duplicated).as_deref(),
+            Some("2 Host headers")
+        );
+    }
+
+    #[tokio::test]
+    async fn absolute_form_target_wins_over_host_header() {
+        let raw = b"GET http://api.example.com/items HTTP/1.1\r\nHost: api.example.com\r\n\r\n";
+        let parsed = parse_raw_request(raw).await;
+        assert_eq!(parsed.target_form, RequestTargetForm::Absolute);
+        assert_eq!(parsed.host, "api.example.com");
+        assert_eq!(parsed.port, 80);
+        assert_eq!(parsed.path, "/items");
+        assert_eq!(host_ambiguity(&parsed), None);
+
+        let mismatched =
+            parse_raw_request(b"GET http://api.example.com/ HTTP/1.1\r\nHost: evil.test\r\n\r\n")
+                .await;
+        assert_eq!(mismatched.host, "api.example.com");
+        assert!(host_ambiguity(&mismatched).is_some());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn strict_host_validation_rejects_mismatched_host() {
+        let mut tx_rx = observe_transactions();
+        let options = InterceptOptions {
+            strict_host_validation: true,
+            ..Default::default()
+        };
+
+        let parsed =
+            parse_raw_request(b"GET http://api.example.com/ HTTP/1.1\r\nHost: evil.test\r\n\r\n")
+                .await;
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        process_request(
+            &mut proxy_client,
+            parsed,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        reset_test_transaction_observer();
+
+        let mut response = vec![0u8; 256];
+        let n = client_peer.read(&mut response).await.unwrap();
+        assert!(String::from_utf8_lossy(&response[..n]).starts_with("HTTP/1.1 400"));
+
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.state, TransactionState::Failed);
+        assert_eq!(tx.status_code, Some(400));
+        assert_eq!(tx.request_target_form, Some(RequestTargetForm::Absolute));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn bare_lf_request_is_forwarded_with_smuggling_warning() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let (upstream_sender, mut upstream_rx) = mpsc::unbounded_channel::<String>();
+        mock_upstream(move |mut upstream_peer| {
+            let upstream_sender = upstream_sender.clone();
+            async move {
+                let mut buf = vec![0u8; 1024];
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                let _ = upstream_sender.send(String::from_utf8_lossy(&buf[..n]).into_owned());
+                upstream_peer
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .await
+                    .unwrap();
+            }
+        });
+        let mut tx_rx = observe_transactions();
+
+        let parsed =
+            parse_raw_request(b"GET /lf HTTP/1.1\nHost: example.com\nX-Probe: 1\r\n\r\n").await;
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let result = process_request(
+            &mut proxy_client,
+            parsed,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await;
+        reset_test_transaction_observer();
+        reset_test_upstream_connector();
+        result.expect("process request should succeed");
+
+        // Recorded, not blocked: the upstream gets a normalized CRLF head.
+        let forwarded = upstream_rx.recv().await.expect("upstream saw request");
+        assert!(forwarded.starts_with("GET /lf HTTP/1.1\r\n"), "{forwarded}");
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.state, TransactionState::Completed);
+        assert_eq!(
+            tx.security_warnings,
+            vec!["Potential request smuggling: bare LF line endings in request head".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ambiguous_framing_is_rejected_when_configured() {
+        let options = InterceptOptions {
+            reject_ambiguous_framing: true,
+            ..Default::default()
+        };
+        let parsed = parse_raw_request(
+            b"POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            parsed.framing_anomalies,
+            vec!["both Content-Length and Transfer-Encoding present".to_string()]
+        );
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        process_request(
+            &mut proxy_client,
+            parsed,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+
+        let mut response = vec![0u8; 256];
+        let n = client_peer.read(&mut response).await.unwrap();
+        assert!(String::from_utf8_lossy(&response[..n]).starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn framing_anomalies_flag_conflicting_lengths_and_encodings() {
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert!(framing_anomalies(b"GET / HTTP/1.1\r\n\r\n", &[]).is_empty());
+        assert_eq!(
+            framing_anomalies(
+                b"",
+                &[header("Content-Length", "5"), header("content-length", "6")]
+            ),
+            vec![r#"conflicting Content-Length values "5, 6""#.to_string()]
+        );
+        assert!(framing_anomalies(b"", &[header("Content-Length", "5, 5")]).is_empty());
+        assert_eq!(
+            framing_anomalies(b"", &[header("Content-Length", "+5")]),
+            vec![r#"invalid Content-Length "+5""#.to_string()]
+        );
+        assert_eq!(
+            framing_anomalies(b"", &[header("Transfer-Encoding", "chunked, identity")]),
+            vec![r#"Transfer-Encoding "chunked, identity" does not end in chunked"#.to_string()]
+        );
+        assert!(scan_raw_head(b"GET / HTTP/1.1\r\nX-A: 1\r\n  continued\r\n\r\n").has_obs_fold());
+        assert!(!scan_raw_head(b"GET / HTTP/1.1\r\nX-A: 1\r\n\r\n").has_obs_fold());
+    }
+
+    #[tokio::test]
+    async fn url_credentials_become_basic_auth_header() {
+        let (mut proxy_side, mut client_peer) = duplex(4096);
+        client_peer
+            .write_all(b"GET http://u:p@example.com/ HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+
+        let parsed = read_http_request(&mut proxy_side, RequestScheme::Http)
+            .await
+            .expect("request parses");
+        assert_eq!(parsed.host, "example.com");
+        assert_eq!(parsed.port, 80);
+        assert_eq!(parsed.path, "/");
+        assert_eq!(
+            header_value(&parsed.request_headers, "authorization").as_deref(),
+            Some("Basic dTpw")
+        );
+        assert_eq!(parsed.url_credentials.as_deref(), Some("u:***"));
+
+        let mut forwarded = Vec::new();
+        write_request_head(
+            &mut forwarded,
+            &parsed.method.to_string(),
+            &parsed.path,
+            &parsed.version,
+            &parsed.header_list,
+            &[],
+        )
+        .await
+        .unwrap();
+        let forwarded = String::from_utf8(forwarded).unwrap();
+        assert!(forwarded.starts_with("GET / HTTP/1.1\r\n"));
+        assert!(forwarded.contains("Authorization: Basic dTpw\r\n"));
+        assert!(!forwarded.contains("u:p@"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn transcript_records_request_line_on_both_legs() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nPONG")
+                .await
+                .unwrap();
+        });
+
+        client_peer
+            .write_all(b"GET http://example.com/traced HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+
+        let transcript_id = "transcript-test-connection";
+        let mut client = Recorded::client(&mut proxy_client, Some(transcript_id));
+        let parsed_request = read_http_request(&mut client, RequestScheme::Http)
+            .await
+            .expect("request parses");
+        process_request(
+            &mut client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            Some(transcript_id),
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+
+        let transcript =
+            storage::get_connection_transcript(transcript_id).expect("transcript recorded");
+        let leg = |direction: TranscriptDirection| {
+            let bytes: Vec<u8> = transcript
+                .chunks
+                .iter()
+                .filter(|c| c.direction == direction)
+                .flat_map(|c| {
+                    (0..c.data_hex.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&c.data_hex[i..i + 2], 16).unwrap())
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            String::from_utf8(bytes).unwrap()
+        };
+        assert!(leg(TranscriptDirection::ClientToProxy)
+            .starts_with("GET http://example.com/traced HTTP/1.1\r\n"));
+        assert!(leg(TranscriptDirection::ProxyToUpstream).starts_with("GET /traced HTTP/1.1\r\n"));
+        assert!(leg(TranscriptDirection::UpstreamToProxy).ends_with("PONG"));
+        assert!(leg(TranscriptDirection::ProxyToClient).ends_with("PONG"));
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(
+            result.items[0].transcript_id.as_deref(),
+            Some(transcript_id)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn non_http_upstream_reply_is_captured_with_warning() {
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+
+        // A TLS alert record followed by junk, then the upstream hangs up.
+        let garbage: Vec<u8> = [0x15, 0x03, 0x01, 0x00, 0x02, 0x02, 0x46]
+            .into_iter()
+            .chain(std::iter::repeat_n(b'?', 8 * 1024))
+            .collect();
+        let sent = garbage.clone();
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer.write_all(&sent).await.unwrap();
+        });
+
+        let mut tx_rx = observe_transactions();
+
+        let (mut proxy_client, mut client_peer) = duplex(16 * 1024);
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/broken",
+            vec![("Host".to_string(), "example.com".to_string())],
+        );
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions {
+                capture_invalid_responses: true,
+                ..Default::default()
+            },
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+        reset_test_transaction_observer();
+
+        let mut response = vec![0u8; 256];
+        let n = client_peer.read(&mut response).await.unwrap();
+        assert!(String::from_utf8_lossy(&response[..n]).starts_with("HTTP/1.1 502"));
+
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.state, TransactionState::Failed);
+        assert_eq!(tx.notes.as_deref(), Some("Upstream returned non-HTTP data"));
+        let body = tx.response_body.expect("raw prefix captured");
+        assert_eq!(body.len(), MAX_INVALID_RESPONSE_CAPTURE_BYTES);
+        assert_eq!(body[..], garbage[..MAX_INVALID_RESPONSE_CAPTURE_BYTES]);
+    }
+
+    /// Minimal TLS ClientHello record carrying `server_name` as its SNI
+    fn client_hello_with_sni(server_name: &[u8]) -> Vec<u8> {
+        let len16 = |n: usize| (n as u16).to_be_bytes();
+        let mut entry = vec![0u8];
+        entry.extend(len16(server_name.len()));
+        entry.extend(server_name);
+        let mut sni_ext = vec![0u8, 0];
+        sni_ext.extend(len16(entry.len() + 2));
+        sni_ext.extend(len16(entry.len()));
+        sni_ext.extend(entry);
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend([0u8; 32]);
+        hello.extend([0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        hello.extend(len16(sni_ext.len()));
+        hello.extend(sni_ext);
+
+        let mut handshake = vec![0x01];
+        handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(hello);
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend(len16(handshake.len()));
+        record.extend(handshake);
+        record
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn cert_generation_failure_falls_back_to_tunnel() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let cert_dir = tempfile::tempdir().unwrap();
+        let cert_manager = Arc::new(CertManager::new(cert_dir.path().to_str().unwrap()).unwrap());
+        let tls_client_config = Arc::new(build_tls_client_config(false, true).unwrap());
+
+        // A non-ASCII SNI cannot go into a certificate's DNS name.
+        let hello = client_hello_with_sni("bücher.example".as_bytes());
+        let expected = hello.clone();
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        let upstream_task = tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            let mut received = vec![0u8; expected.len()];
+            socket.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, expected, "ClientHello forwarded untouched");
+            socket.write_all(b"tunneled").await.unwrap();
+        });
+
+        let mut tx_rx = observe_transactions();
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let client_task = tokio::spawn(async move {
+            let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+            let established = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+            let mut head = vec![0u8; established.len()];
+            client.read_exact(&mut head).await.unwrap();
+            assert_eq!(head, established);
+            client.write_all(&hello).await.unwrap();
+            let mut reply = vec![0u8; 8];
+            client.read_exact(&mut reply).await.unwrap();
+            reply
+        });
+
+        let (socket, _) = proxy.accept().await.unwrap();
+        let mut parsed = build_test_request(
+            "127.0.0.1",
+            HttpMethod::Connect,
+            &format!("127.0.0.1:{upstream_port}"),
+            Vec::new(),
+        );
+        parsed.port = upstream_port;
+        let options = Arc::new(InterceptOptions {
+            sni_policy: SniPolicy::new(Vec::new(), vec!["excluded.test".to_string()]),
+            ..Default::default()
+        });
+        intercept_tls_connection(
+            socket,
+            parsed,
+            cert_manager,
+            tls_client_config,
+            options,
+            None,
+            ConnectionThrottle::default(),
+        )
+        .await
+        .expect("connection tunneled");
+        assert_eq!(client_task.await.unwrap(), b"tunneled");
+        upstream_task.await.unwrap();
+        reset_test_transaction_observer();
+
+        let tx = tx_rx.try_recv().expect("tunnel recorded");
+        assert_eq!(tx.state, TransactionState::Completed);
+        assert!(
+            tx.notes
+                .as_deref()
+                .is_some_and(|n| n.starts_with("Interception failed, tunneled")),
+            "{:?}",
+            tx.notes
+        );
+        assert!(!tx.was_intercepted, "tunneled CONNECT is not decrypted");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn connect_tunnel_is_paced_by_the_connection_throttle() {
+        use crate::models::throttle::ThrottleConfig;
+        use crate::proxy::throttle::set_throttle;
+
+        const PAYLOAD: usize = 50_000;
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        // 800 kbps = 100,000 bytes per second
+        set_throttle(Some(ThrottleConfig {
+            down_kbps: 800,
+            up_kbps: 0,
+            added_latency_ms: 0,
+        }));
+
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        let upstream_task = tokio::spawn(async move {
+            let (mut socket, _) = upstream.accept().await.unwrap();
+            socket.write_all(&[7u8; PAYLOAD]).await.unwrap();
+        });
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let proxy_task = tokio::spawn(async move {
+            let (socket, _) = proxy.accept().await.unwrap();
+            handle_connection(socket, None, None, Arc::default()).await
+        });
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        let connect = format!(
+            "CONNECT 127.0.0.1:{upstream_port} HTTP/1.1\r\nHost: 127.0.0.1:{upstream_port}\r\n\r\n"
+        );
+        client.write_all(connect.as_bytes()).await.unwrap();
+        let established = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+        let mut head = vec![0u8; established.len()];
+        client.read_exact(&mut head).await.unwrap();
+        assert_eq!(head, established);
+
+        let started = Instant::now();
+        let mut received = vec![0u8; PAYLOAD];
+        client.read_exact(&mut received).await.unwrap();
+        let elapsed = started.elapsed();
+        set_throttle(None);
+        upstream_task.await.unwrap();
+        drop(client);
+        proxy_task.await.unwrap().expect("tunnel closes");
+
+        // The tunnel draws from the connection's download bucket: after the
+        // first burst, bytes only arrive at the refill rate.
+        assert!(received.iter().all(|&b| b == 7));
+        assert!(elapsed >= Duration::from_millis(400), "{elapsed:?}");
+    }
+
+    /// Start a TLS handshake to a local listener and return the SNI the
+    /// upstream saw in the ClientHello.
+    async fn sni_sent_to_upstream(host: &str) -> Option<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut parsed = build_test_request(host, HttpMethod::Get, "/", Vec::new());
+        parsed.scheme = "https".into();
+        parsed.port = listener.local_addr().unwrap().port();
+        let config = Arc::new(build_tls_client_config(false, true).unwrap());
+        let connect =
+            tokio::spawn(async move { connect_upstream(&parsed, Some(config), false).await });
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut hello = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            assert!(n > 0, "client closed before sending its ClientHello");
+            hello.extend_from_slice(&buf[..n]);
+            if hello.len() >= 5 {
+                let record_len = u16::from_be_bytes([hello[3], hello[4]]) as usize;
+                if hello.len() >= 5 + record_len {
+                    break;
+                }
+            }
+        }
+        drop(socket);
+        connect.abort();
+        sni::parse_client_hello_sni(&hello)
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn upstream_tls_override_can_disable_sni() {
+        reset_test_upstream_connector();
+        upstream_tls::set_upstream_tls_overrides(Vec::new()).unwrap();
+        assert_eq!(
+            sni_sent_to_upstream("localhost").await.as_deref(),
+            Some("localhost")
+        );
+
+        upstream_tls::set_upstream_tls_overrides(vec![
+            crate::models::upstream_tls::UpstreamTlsOverride {
+                host_pattern: "LOCALHOST".to_string(),
+                alpn_protocols: Some(vec!["http/1.1".to_string()]),
+                send_sni: false,
+                accept_invalid_certs: true,
+            },
+        ])
+        .unwrap();
+        let sni = sni_sent_to_upstream("localhost").await;
+        upstream_tls::set_upstream_tls_overrides(Vec::new()).unwrap();
+        assert_eq!(sni, None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn changing_upstream_tls_overrides_closes_idle_connections() {
+        reset_test_upstream_connector();
+        let (stream, _peer) = duplex(64);
+        let tx = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "localhost",
+            443,
+            "/",
+            HashMap::new(),
+        );
+        release_upstream(
+            PoolKey::new("https", "localhost", 443),
+            UpstreamStream::Mock(stream),
+            &tx,
+        );
+        assert_eq!(UPSTREAM_POOL.idle_count(), 1);
+
+        upstream_tls::set_upstream_tls_overrides(Vec::new()).unwrap();
+        assert_eq!(UPSTREAM_POOL.idle_count(), 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn trace_request_is_blocked_with_405() {
+        let mut tx_rx = observe_transactions();
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request = build_test_request("example.com", HttpMethod::Trace, "/", vec![]);
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        reset_test_transaction_observer();
+
+        let mut response = vec![0u8; 256];
+        let n = client_peer.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..n]);
+        assert!(response.starts_with("HTTP/1.1 405"));
+        assert!(response
+            .contains("\r\nAllow: GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS, CONNECT\r\n"));
+
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.method, HttpMethod::Trace);
+        assert_eq!(tx.state, TransactionState::Failed);
+        assert_eq!(tx.status_code, Some(405));
+    }
+
+    #[tokio::test]
+    async fn edited_body_drops_chunked_framing_and_expect() {
+        let mut parsed = parse_raw_request(
+            b"POST /upload HTTP/1.1\r\nHost: example.com\r\nTransfer-Encoding: chunked\r\nExpect: 100-continue\r\n\r\n",
+        )
+        .await;
+        assert!(matches!(parsed.body_kind, RequestBodyKind::Chunked));
+        parsed.apply_edit(&RequestEdit {
+            body: Some(b"hello".to_vec()),
+            ..Default::default()
+        });
+
+        let (mut client, _client_peer) = duplex(1024);
+        let (mut upstream, mut upstream_peer) = duplex(1024);
+        let mut capture = BodyCapture::new(1024);
+        forward_request_to_upstream(
+            &mut client,
+            &mut upstream,
+            &mut parsed,
+            &mut capture,
+            &default_hop_by_hop_headers(),
+        )
+        .await
+        .expect("edited request forwards");
+        drop(upstream);
+
+        let mut forwarded = String::new();
+        upstream_peer.read_to_string(&mut forwarded).await.unwrap();
+        let (head, body) = forwarded.split_once("\r\n\r\n").expect("complete head");
+        let head = head.to_ascii_lowercase();
+        assert!(head.contains("content-length: 5"), "{head}");
+        assert!(!head.contains("transfer-encoding"), "{head}");
+        assert!(!head.contains("expect"), "{head}");
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn request_to_own_listener_is_refused() {
+        let listen_addr: SocketAddr = "127.0.0.1:9191".parse().unwrap();
+        let options = InterceptOptions {
+            listen_addr: Some(listen_addr),
+            ..Default::default()
+        };
+
+        let mut tx_rx = observe_transactions();
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let mut parsed_request = build_test_request(
+            "localhost",
+            HttpMethod::Get,
+            "/",
+            vec![("Host".to_string(), "localhost:9191".to_string())],
+        );
+        parsed_request.port = listen_addr.port();
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        reset_test_transaction_observer();
+
+        let mut response = vec![0u8; 256];
+        let n = client_peer.read(&mut response).await.unwrap();
+        assert!(String::from_utf8_lossy(&response[..n]).starts_with("HTTP/1.1 421"));
+
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.state, TransactionState::Failed);
+        assert_eq!(tx.status_code, Some(421));
+
+        let mut request = build_test_request("localhost", HttpMethod::Get, "/", vec![]);
+        request.port = listen_addr.port();
+        assert!(targets_own_listener(&request, &options).await);
+        request.port = 9192;
+        assert!(!targets_own_listener(&request, &options).await);
+        request.port = listen_addr.port();
+        let allowed = InterceptOptions {
+            self_loop_allowlist: vec!["localhost".to_string()],
+            ..options
+        };
+        assert!(!targets_own_listener(&request, &allowed).await);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn records_client_and_upstream_http_versions_separately() {
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+
+        // An HTTP/1.0 origin behind a client speaking HTTP/1.1.
+        let upstream_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+        });
+
+        let mut tx_rx = observe_transactions();
+
+        let (mut proxy_client, _client_peer) = duplex(16 * 1024);
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/legacy",
+            vec![("Host".to_string(), "example.com".to_string())],
+        );
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+        reset_test_transaction_observer();
+
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.client_http_version.as_deref(), Some("HTTP/1.1"));
+        assert_eq!(tx.upstream_http_version.as_deref(), Some("HTTP/1.0"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn armed_recording_is_served_without_upstream() {
+        let mut recorded = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "recorded.invalid",
+            80,
+            "/users/1",
+            HashMap::new(),
+        );
+        recorded.status_code = Some(201);
+        recorded.status_message = Some("Created".to_string());
+        recorded.response_headers = Some(HashMap::from([
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Transfer-Encoding".to_string(), "chunked".to_string()),
+        ]));
+        recorded.response_body = Some(br#"{"id":1}"#.to_vec());
+        playback::arm_recording(&recorded, false).unwrap();
+
+        let mut tx_rx = observe_transactions();
+
+        // No test connector: a request reaching the network would fail to resolve.
+        let (mut proxy_client, mut client_peer) = duplex(16 * 1024);
+        let parsed_request = build_test_request(
+            "recorded.invalid",
+            HttpMethod::Get,
+            "/users/1",
+            vec![("Host".to_string(), "recorded.invalid".to_string())],
+        );
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        drop(proxy_client);
+        reset_test_transaction_observer();
+        playback::clear_recordings();
+
+        let mut response = Vec::new();
+        client_peer.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert!(response.ends_with(r#"{"id":1}"#));
+        assert!(!response.to_ascii_lowercase().contains("transfer-encoding"));
+
+        let tx = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(tx.status_code, Some(201));
+        assert_eq!(
+            tx.notes,
+            Some(format!("Served from recording {}", recorded.id))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn recording_keeps_every_set_cookie_header() {
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+        let upstream_task = tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                assert!(n > 0, "proxy closed before the request head arrived");
+                request.extend_from_slice(&buf[..n]);
+            }
+            upstream_peer
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\
+                      Content-Length: 2\r\n\r\nok",
+                )
+                .await
+                .unwrap();
+        });
+        let mut tx_rx = observe_transactions();
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let request = || {
+            build_test_request(
+                "cookies.invalid",
+                HttpMethod::Get,
+                "/login",
+                vec![("Host".to_string(), "cookies.invalid".to_string())],
+            )
+        };
+        process_request(
+            &mut proxy_client,
+            request(),
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+        reset_test_transaction_observer();
+        let captured = last_observed(&mut tx_rx).expect("transaction observed");
+        playback::arm_recording(&captured, false).unwrap();
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        process_request(
+            &mut proxy_client,
+            request(),
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        drop(proxy_client);
+        playback::clear_recordings();
+
+        let mut response = Vec::new();
+        client_peer.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.contains("Set-Cookie: a=1\r\nSet-Cookie: b=2\r\n"));
+        assert!(response.ends_with("ok"));
+    }
+
+    #[test]
+    #[serial]
+    fn recording_with_a_partly_stored_body_is_refused() {
+        let mut recorded = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "recorded.invalid",
+            80,
+            "/large",
+            HashMap::new(),
+        );
+        let full = vec![b'x'; MAX_BODY_CAPTURE_BYTES + 10];
+        recorded.status_code = Some(200);
+        recorded.response_body = Some(full[..MAX_BODY_CAPTURE_BYTES].to_vec());
+        recorded.response_body_sha256 = sha256_hex(&full);
+        recorded.response_size = Some(full.len() as u64);
+
+        let err = playback::arm_recording(&recorded, false).unwrap_err();
+        assert!(err.to_string().contains("only part of its response body"));
+        assert!(playback::armed_recordings().is_empty());
+
+        recorded.response_body = Some(full);
+        playback::arm_recording(&recorded, false).unwrap();
+        playback::clear_recordings();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn recorded_response_is_gzipped_for_clients_that_accept_it() {
+        let mut recorded = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "recorded.invalid",
+            80,
+            "/users/2",
+            HashMap::new(),
+        );
+        recorded.status_code = Some(200);
+        recorded.status_message = Some("OK".to_string());
+        recorded.response_headers = Some(HashMap::from([(
+            "Content-Type".to_string(),
+            "application/json".to_string(),
+        )]));
+        recorded.response_body = Some(br#"{"id":2}"#.to_vec());
+
+        for compress in [true, false] {
+            playback::arm_recording(&recorded, compress).unwrap();
+
+            let mut tx_rx = observe_transactions();
+            let (mut proxy_client, mut client_peer) = duplex(16 * 1024);
+            let parsed_request = build_test_request(
+                "recorded.invalid",
+                HttpMethod::Get,
+                "/users/2",
+                vec![
+                    ("Host".to_string(), "recorded.invalid".to_string()),
+                    ("Accept-Encoding".to_string(), "gzip".to_string()),
+                ],
+            );
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+            drop(proxy_client);
+            reset_test_transaction_observer();
+            playback::clear_recordings();
+
+            let mut response = Vec::new();
+            client_peer.read_to_end(&mut response).await.unwrap();
+            let split = response
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .expect("response head");
+            let head = String::from_utf8_lossy(&response[..split]).to_ascii_lowercase();
+            let body = &response[split + 4..];
+            assert!(head.contains(&format!("content-length: {}", body.len())));
+            let tx = last_observed(&mut tx_rx).expect("transaction observed");
+            assert_eq!(tx.response_body.as_deref(), Some(&br#"{"id":2}"#[..]));
+
+            if compress {
+                assert!(head.contains("content-encoding: gzip"), "{head}");
+                assert_eq!(
+                    content_coding::decode_body("gzip", body, usize::MAX).unwrap(),
+                    br#"{"id":2}"#
+                );
+                assert_eq!(tx.response_body_encoding.as_deref(), Some("gzip"));
+            } else {
+                assert!(!head.contains("content-encoding"), "{head}");
+                assert_eq!(body, br#"{"id":2}"#);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn identical_request_bodies_share_sha256() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+
+        let upstream_task = tokio::spawn(async move {
+            for body in ["ONE", "TWO"] {
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 512];
+                while !received.ends_with(b"payload") {
+                    let n = upstream_peer.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "upstream closed before request body arrived");
+                    received.extend_from_slice(&buf[..n]);
+                }
+                upstream_peer
+                    .write_all(
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n{body}").as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            upstream_peer
+        });
+
+        for path in ["/a", "/b"] {
+            let (mut proxy_client, mut client_peer) = duplex(4096);
+            client_peer.write_all(b"payload").await.unwrap();
+            let parsed_request = build_test_request(
+                "example.com",
+                HttpMethod::Post,
+                path,
+                vec![
+                    ("Host".to_string(), "example.com".to_string()),
+                    ("Content-Length".to_string(), "7".to_string()),
+                ],
+            );
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+        }
+
+        let _upstream_peer = upstream_task.await.unwrap();
+        reset_test_upstream_connector();
+
+        let expected = format!("{:x}", Sha256::digest(b"payload"));
+        let filter = TransactionFilter {
+            body_sha256: Some(expected.clone()),
+            ..Default::default()
+        };
+        let result = storage::query_transactions(&filter, 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.total, 2);
+        for tx in &result.items {
+            assert_eq!(tx.request_body_sha256.as_deref(), Some(expected.as_str()));
+        }
+        assert_ne!(
+            result.items[0].response_body_sha256,
+            result.items[1].response_body_sha256
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn cors_rule_answers_preflight_and_injects_headers() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        rewrite::add_rewrite_rule(crate::models::rewrite::RewriteRuleInput {
+            enabled: true,
+            method: None,
+            host_contains: Some("api.example.com".to_string()),
+            path_contains: None,
+            action: crate::models::rewrite::RewriteAction::Cors { allow_origin: None },
+            priority: 0,
+        });
+
+        mock_upstream(move |mut upstream_peer| async move {
+            let mut buf = vec![0u8; 512];
+            let n = upstream_peer.read(&mut buf).await.unwrap();
+            assert!(buf[..n].starts_with(b"GET "), "preflight reached upstream");
+            upstream_peer
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nVary: Accept-Encoding\r\nX-Request-Id: 7\r\n\
+                      Content-Length: 2\r\n\r\n{}",
+                )
+                .await
+                .unwrap();
+        });
+
+        let origin = ("Origin".to_string(), "http://localhost:3000".to_string());
+        let requests = [
+            build_test_request(
+                "api.example.com",
+                HttpMethod::Options,
+                "/items",
+                vec![
+                    origin.clone(),
+                    (
+                        "Access-Control-Request-Method".to_string(),
+                        "POST".to_string(),
+                    ),
+                ],
+            ),
+            build_test_request(
+                "api.example.com",
+                HttpMethod::Get,
+                "/items",
+                vec![origin, ("X-Token".to_string(), "t".to_string())],
+            ),
+        ];
+        let mut responses = Vec::new();
+        for parsed_request in requests {
+            let (mut proxy_client, mut client_peer) = duplex(4096);
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+            let mut response = vec![0u8; 1024];
+            let n = client_peer.read(&mut response).await.unwrap();
+            responses.push(String::from_utf8_lossy(&response[..n]).into_owned());
+        }
+        reset_test_upstream_connector();
+        rewrite::clear_rewrite_rules();
+
+        let preflight = &responses[0];
+        assert!(preflight.starts_with("HTTP/1.1 204"), "{preflight}");
+        assert!(preflight.contains("Access-Control-Allow-Origin: http://localhost:3000\r\n"));
+        assert!(preflight.contains("Access-Control-Allow-Methods: POST\r\n"));
+        assert!(preflight.contains("Vary: Origin\r\n"));
+
+        let get = &responses[1];
+        assert!(get.starts_with("HTTP/1.1 200"), "{get}");
+        assert!(get.contains("Access-Control-Allow-Origin: http://localhost:3000\r\n"));
+        assert!(get.contains("Vary: Accept-Encoding, Origin\r\n"), "{get}");
+        // With credentials a `*` would be literal, so the names are listed.
+        assert!(get.contains("Access-Control-Allow-Credentials: true\r\n"));
+        assert!(
+            get.contains("Access-Control-Allow-Headers: origin, x-token\r\n"),
+            "{get}"
+        );
+        assert!(
+            get.contains("Access-Control-Expose-Headers: content-length, vary, x-request-id\r\n"),
+            "{get}"
+        );
+        assert!(get.ends_with("\r\n\r\n{}"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn rewritten_request_records_applied_rule() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let rule = rewrite::add_rewrite_rule(crate::models::rewrite::RewriteRuleInput {
+            enabled: true,
+            method: None,
+            host_contains: Some("api.example.com".to_string()),
+            path_contains: None,
+            action: crate::models::rewrite::RewriteAction::SetResponseHeader {
+                name: "X-Env".to_string(),
+                value: "staging".to_string(),
+            },
+            priority: 0,
+        });
+
+        mock_upstream(move |mut upstream_peer| async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+        });
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request =
+            build_test_request("api.example.com", HttpMethod::Get, "/items", Vec::new());
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        let mut response = vec![0u8; 1024];
+        let n = client_peer.read(&mut response).await.unwrap();
+        reset_test_upstream_connector();
+        rewrite::clear_rewrite_rules();
+        assert!(String::from_utf8_lossy(&response[..n]).contains("X-Env: staging\r\n"));
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(
+            result.items[0].applied_actions,
+            vec![format!("rewrite:{}", rule.id)]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn event_stream_is_captured_as_event_data() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        mock_upstream(move |mut upstream_peer| {
+            async move {
+                let mut buf = vec![0u8; 512];
+                let _ = upstream_peer.read(&mut buf).await.unwrap();
+                upstream_peer
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                          Transfer-Encoding: chunked\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+                // The second event is split across chunks (and reads)
+                for chunk in [&b"data: one\n\ndata: t"[..], b"wo\n", b"\n"] {
+                    upstream_peer
+                        .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                        .await
+                        .unwrap();
+                    upstream_peer.write_all(chunk).await.unwrap();
+                    upstream_peer.write_all(b"\r\n").await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                upstream_peer.write_all(b"0\r\n\r\n").await.unwrap();
+            }
+        });
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request =
+            build_test_request("api.example.com", HttpMethod::Get, "/events", Vec::new());
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        drop(proxy_client);
+        let mut response = Vec::new();
+        client_peer.read_to_end(&mut response).await.unwrap();
+        reset_test_upstream_connector();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.contains("data: t\r\n3\r\nwo\n"), "{response}");
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        assert_eq!(result.items.len(), 1);
+        let tx = &result.items[0];
+        assert_eq!(tx.response_body.as_deref(), Some(&b"one\ntwo\n"[..]));
+        assert!(tx.response_body_sha256.is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn debug_timing_headers_are_added_to_response() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        set_test_upstream_connector(|_req| {
+            let (mock_stream, mut upstream_peer) = duplex(4096);
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 512];
+                let _ = upstream_peer.read(&mut buf).await.unwrap();
+                upstream_peer
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .await
+                    .unwrap();
+            });
+            async move {
+                let timing = ConnectionTiming {
+                    server_ip: Some("192.0.2.10".to_string()),
+                    ..Default::default()
+                };
+                Ok((UpstreamStream::Mock(mock_stream), timing))
+            }
+        });
+
+        let options = InterceptOptions {
+            debug_timing_headers: true,
+            ..InterceptOptions::default()
+        };
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request =
+            build_test_request("example.com", HttpMethod::Get, "/timed", Vec::new());
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request should succeed");
+        reset_test_upstream_connector();
+
+        let mut response = vec![0u8; 1024];
+        let n = client_peer.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..n]);
+        let total = response
+            .lines()
+            .find_map(|line| line.strip_prefix("X-Cheddar-Total-Ms: "))
+            .expect("timing header present");
+        assert!(total.parse::<u64>().is_ok(), "{response}");
+        assert!(response.contains("X-Cheddar-Upstream-Ip: 192.0.2.10\r\n"));
+        assert!(response.contains("Content-Length: 2\r\n"));
+        assert!(response.ends_with("\r\n\r\nok"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn query_redaction_masks_stored_values_but_forwards_full_query() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let (upstream_sender, mut upstream_rx) = mpsc::unbounded_channel::<String>();
+        mock_upstream(move |mut upstream_peer| {
+            let upstream_sender = upstream_sender.clone();
+            async move {
+                let mut buf = vec![0u8; 1024];
+                let n = upstream_peer.read(&mut buf).await.unwrap();
+                let _ = upstream_sender.send(String::from_utf8_lossy(&buf[..n]).into_owned());
+                upstream_peer
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .await
+                    .unwrap();
+            }
+        });
+        let mut tx_rx = observe_transactions();
+
+        let options = InterceptOptions {
+            query_redaction: QueryRedaction::MaskValues,
+            ..InterceptOptions::default()
+        };
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/search?token=secret123&q=x",
+            Vec::new(),
+        );
+        let result = process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await;
+        reset_test_transaction_observer();
+        reset_test_upstream_connector();
+        result.expect("process request should succeed");
+
+        let forwarded = upstream_rx.recv().await.expect("upstream saw request");
+        assert!(
+            forwarded.starts_with("GET /search?token=secret123&q=x HTTP/1.1\r\n"),
+            "{forwarded}"
+        );
+        let mut stored = Vec::new();
+        while let Ok(tx) = tx_rx.try_recv() {
+            assert!(!tx.path.contains("secret123"), "leaked in {}", tx.path);
+            stored.push(tx);
+        }
+        let last = stored.last().expect("transaction observed");
+        assert_eq!(last.state, TransactionState::Completed);
+        assert_eq!(last.path, "/search?token=***&q=***");
+        assert!(last.query_redacted);
+        assert!(last
+            .query_params()
+            .contains(&("token".to_string(), "***".to_string())));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn h2_upstream_response_is_relayed_as_http1() {
+        use hyper::body::{Bytes, Incoming};
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let mut tx_rx = observe_transactions();
+
+        let (upstream_io, server_io) = duplex(64 * 1024);
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(|req: hyper::Request<Incoming>| async move {
+                use http_body_util::BodyExt;
+                let line = format!("{} {}", req.method(), req.uri());
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                let reply = format!("{line} {}", String::from_utf8_lossy(&body));
+                Ok::<_, hyper::Error>(
+                    hyper::Response::builder()
+                        .status(200)
+                        .header("content-type", "text/plain")
+                        .body(http_body_util::Full::new(Bytes::from(reply)))
+                        .unwrap(),
+                )
+            });
+            hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(server_io), service)
+                .await
+                .unwrap();
+        });
+
+        let (mut proxy_client, mut client_peer) = duplex(64 * 1024);
+        client_peer.write_all(b"hello").await.unwrap();
+        let mut parsed_request = build_test_request(
+            "h2.test",
+            HttpMethod::Post,
+            "/echo?x=1",
+            vec![
+                ("Host".to_string(), "h2.test".to_string()),
+                ("Content-Length".to_string(), "5".to_string()),
+            ],
+        );
+        parsed_request.scheme = "https".into();
+        parsed_request.port = 443;
+        let tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "https",
+            "h2.test",
+            443,
+            "/echo?x=1",
+            parsed_request.request_headers.clone(),
+        );
+        let result = exchange_over_h2(
+            &mut proxy_client,
+            upstream_io,
+            parsed_request,
+            tx,
+            &InterceptOptions::default(),
+            Instant::now(),
+            &mut ConnectionThrottle::default(),
+        )
+        .await;
+        reset_test_transaction_observer();
+        result.expect("h2 exchange should succeed");
+
+        drop(proxy_client);
+        let mut response = Vec::new();
+        client_peer.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(
+            response.contains("POST https://h2.test/echo?x=1 hello"),
+            "{response}"
+        );
+
+        let last = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(last.state, TransactionState::Completed);
+        assert_eq!(last.upstream_http_version.as_deref(), Some("HTTP/2"));
+        assert_eq!(last.status_code, Some(200));
+        assert_eq!(last.request_body.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(
+            last.response_body.as_deref(),
+            Some(&b"POST https://h2.test/echo?x=1 hello"[..])
+        );
+        assert_eq!(last.response_content_type.as_deref(), Some("text/plain"));
+
+        let offered = build_tls_client_config(true, true).unwrap().alpn_protocols;
+        assert_eq!(offered, vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+        let offered = build_tls_client_config(false, true).unwrap().alpn_protocols;
+        assert_eq!(offered, vec![b"http/1.1".to_vec()]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn gzip_response_is_stored_decoded_but_forwarded_untouched() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let plain = br#"{"items":[1,2,3],"note":"compressed upstream"}"#.to_vec();
+        let gzipped = content_coding::ContentCoding::Gzip.encode(&plain).unwrap();
+        let mut wire = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+             Content-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzipped.len()
+        )
+        .into_bytes();
+        wire.extend_from_slice(&gzipped);
+        let (mock_stream, mut upstream_peer) = duplex(4096);
+        mock_upstream_stream(mock_stream);
+        let upstream_task = tokio::spawn({
+            let response = wire.clone();
+            async move {
+                let mut request = Vec::new();
+                let mut buf = vec![0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = upstream_peer.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "proxy closed before the request head arrived");
+                    request.extend_from_slice(&buf[..n]);
+                }
+                upstream_peer.write_all(&response).await.unwrap();
+            }
+        });
+        let mut tx_rx = observe_transactions();
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request = build_test_request("gzip.test", HttpMethod::Get, "/", Vec::new());
+        let result = process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await;
+        reset_test_transaction_observer();
+        reset_test_upstream_connector();
+        result.expect("process request should succeed");
+        upstream_task.await.unwrap();
+
+        // Read the forwarded response until its whole Content-Length has arrived
+        let mut received = Vec::new();
+        let mut buf = vec![0u8; 1024];
+        let complete = |received: &[u8]| {
+            received
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .is_some_and(|end| received.len() >= end + 4 + gzipped.len())
+        };
+        while !complete(&received) {
+            let n = tokio::time::timeout(Duration::from_secs(2), client_peer.read(&mut buf))
+                .await
+                .expect("response before timeout")
+                .unwrap();
+            assert!(n > 0, "proxy closed before the response body arrived");
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert!(
+            received.ends_with(&gzipped),
+            "client gets the compressed bytes"
+        );
+
+        let last = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(last.response_body.as_deref(), Some(plain.as_slice()));
+        assert_eq!(last.response_body_encoding.as_deref(), Some("gzip"));
+        assert_eq!(last.response_size, Some(wire.len() as u64));
+    }
+
+    #[test]
+    fn conformance_notes_read_the_raw_head() {
+        assert!(conformance_notes("request", b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").is_empty());
+        assert_eq!(
+            conformance_notes(
+                "request",
+                b"GET / HTTP/1.1\nHost: a\nHost: b\nAccept:  */*\nX-Pad: v \n\n"
+            ),
+            vec![
+                "bare LF line endings in request head",
+                "request header Accept has leading whitespace around its value",
+                "request header X-Pad has trailing whitespace around its value",
+                "2 host headers in request",
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn folded_response_header_is_noted() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        let wire = b"HTTP/1.1 200 OK\r\nX-Long: first part\r\n  second part\r\n\
+                     Content-Length: 2\r\n\r\nok"
+            .to_vec();
+        let upstream_response = wire.clone();
+        mock_upstream(move |mut upstream_peer| {
+            let response = upstream_response.clone();
+            async move {
+                let mut buf = vec![0u8; 1024];
+                let _ = upstream_peer.read(&mut buf).await.unwrap();
+                upstream_peer.write_all(&response).await.unwrap();
+            }
+        });
+        let mut tx_rx = observe_transactions();
+
+        let options = InterceptOptions {
+            record_conformance_notes: true,
+            ..InterceptOptions::default()
+        };
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request = build_test_request("fold.test", HttpMethod::Get, "/", Vec::new());
+        let result = process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &options,
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await;
+        reset_test_transaction_observer();
+        reset_test_upstream_connector();
+        result.expect("process request should succeed");
+
+        drop(proxy_client);
+        let mut received = Vec::new();
+        client_peer.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, wire, "the folded head is relayed as sent");
+
+        let last = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(last.status_code, Some(200));
+        assert_eq!(
+            last.conformance_notes,
+            vec!["obsolete line folding in response header X-Long"]
+        );
+        let folded = last
+            .response_headers
+            .as_ref()
+            .and_then(|headers| headers.get("X-Long"))
+            .cloned();
+        assert_eq!(folded.as_deref(), Some("first part second part"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn silent_upstream_fails_with_gateway_timeout() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        mock_upstream(move |mut upstream_peer| {
+            async move {
+                // Accept the request, then never answer (but keep the connection open).
+                let mut buf = vec![0u8; 1024];
+                let _ = upstream_peer.read(&mut buf).await;
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                drop(upstream_peer);
+            }
+        });
+        let mut tx_rx = observe_transactions();
+
+        let options = InterceptOptions {
+            response_header_timeout: Some(Duration::from_millis(200)),
+            ..InterceptOptions::default()
+        };
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let parsed_request = build_test_request("slow.test", HttpMethod::Get, "/", Vec::new());
+        let started = Instant::now();
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &options,
+                None,
+                &mut ConnectionThrottle::default(),
+            ),
+        )
+        .await;
+        reset_test_transaction_observer();
+        reset_test_upstream_connector();
+        result
+            .expect("request should not hang")
+            .expect("process request should succeed");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        drop(proxy_client);
+        let mut response = Vec::new();
+        client_peer.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(
+            response.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"),
+            "{response}"
+        );
+
+        let last = last_observed(&mut tx_rx).expect("transaction observed");
+        assert_eq!(last.state, TransactionState::Failed);
+        assert_eq!(last.status_code, Some(504));
+        assert_eq!(last.notes.as_deref(), Some("Upstream response timeout"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn quiet_host_is_forwarded_but_not_persisted() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        quiet_hosts::set_quiet_hosts(vec!["*.telemetry.test".to_string()]);
+
+        mock_upstream(move |mut upstream_peer| async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        for host in ["collect.telemetry.test", "api.example.com"] {
+            let (mut proxy_client, mut client_peer) = duplex(4096);
+            let parsed_request = build_test_request(
+                host,
+                HttpMethod::Get,
+                "/event",
+                vec![("Host".to_string(), host.to_string())],
+            );
+            process_request(
+                &mut proxy_client,
+                parsed_request,
+                Instant::now(),
+                None,
+                false,
+                &InterceptOptions::default(),
+                None,
+                &mut ConnectionThrottle::default(),
+            )
+            .await
+            .expect("process request should succeed");
+
+            let mut response = vec![0u8; 128];
+            let n = client_peer.read(&mut response).await.unwrap();
+            assert!(String::from_utf8_lossy(&response[..n]).starts_with("HTTP/1.1 204"));
+        }
+        reset_test_upstream_connector();
+        quiet_hosts::set_quiet_hosts(Vec::new());
+
+        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query transactions");
+        let hosts: Vec<_> = result.items.iter().map(|tx| tx.host.as_str()).collect();
+        assert_eq!(hosts, vec!["api.example.com"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn idle_websocket_gets_keepalive_pings() {
+        use crate::proxy::websocket::{parse_frame_header, KEEPALIVE_PING_PAYLOAD};
+
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).expect("init store");
+
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let (mut proxy_upstream, mut upstream_peer) = duplex(4096);
+        let connection_id = "ws-keepalive-test".to_string();
+        let tunnel = tokio::spawn({
+            let connection_id = connection_id.clone();
+            async move {
+                websocket_tunnel(
+                    &mut proxy_client,
+                    &mut proxy_upstream,
+                    connection_id,
+                    Some(Duration::from_millis(50)),
+                    None,
+                )
+                .await
+            }
+        });
+
+        let started = Instant::now();
+        let mut frames = Vec::new();
+        for _ in 0..2 {
+            // Two header bytes plus the mask key precede the ping payload.
+            let mut frame = vec![0u8; 6 + KEEPALIVE_PING_PAYLOAD.len()];
+            tokio::time::timeout(Duration::from_secs(2), upstream_peer.read_exact(&mut frame))
+                .await
+                .expect("ping before timeout")
+                .unwrap();
+            frames.push(frame);
+        }
+        let elapsed = started.elapsed();
+        tunnel.abort();
+
+        assert!(
+            elapsed >= Duration::from_millis(100),
+            "pinged after {elapsed:?}"
+        );
+        for frame in &frames {
+            let header = parse_frame_header(frame).expect("complete frame");
+            assert_eq!(header.opcode, crate::models::WebSocketOpcode::Ping);
+            assert!(header.masked, "client-side frames must be masked");
+        }
+        let recorded = storage::get_websocket_messages(&connection_id, 0, None);
+        assert_eq!(recorded.len(), 2);
+        assert!(recorded
+            .iter()
+            .all(|m| m.injected && m.payload == KEEPALIVE_PING_PAYLOAD));
+        storage::clear_websocket_messages(&connection_id);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn captured_websocket_message_is_edited_and_reinjected() {
+        use crate::models::{MessageDirection, WebSocketOpcode};
+        use crate::proxy::websocket::{build_frame, parse_frame_header, unmask_payload};
+
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).expect("init store");
+
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let (mut proxy_upstream, mut upstream_peer) = duplex(4096);
+        let connection_id = "ws-replay-test".to_string();
+        let tunnel = tokio::spawn({
+            let connection_id = connection_id.clone();
+            async move {
+                websocket_tunnel(
+                    &mut proxy_client,
+                    &mut proxy_upstream,
+                    connection_id,
+                    None,
+                    None,
+                )
+                .await
+            }
+        });
+
+        let original = build_frame(WebSocketOpcode::Text, b"hello", Some([1, 2, 3, 4]));
+        client_peer.write_all(&original).await.unwrap();
+        let mut forwarded = vec![0u8; original.len()];
+        upstream_peer.read_exact(&mut forwarded).await.unwrap();
+        assert_eq!(forwarded, original);
+
+        let captured = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                if let Some(msg) = storage::get_websocket_messages(&connection_id, 0, None).pop() {
+                    break msg;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("message captured");
+
+        let injected = crate::api::proxy_api::replay_websocket_message(
+            captured.id.clone(),
+            Some(b"hello, edited".to_vec()),
+        )
+        .expect("connection is open");
+        assert_eq!(injected.direction, MessageDirection::ClientToServer);
+
+        // Header, mask key, then the edited payload
+        let mut frame = vec![0u8; 6 + b"hello, edited".len()];
+        tokio::time::timeout(Duration::from_secs(2), upstream_peer.read_exact(&mut frame))
+            .await
+            .expect("injected frame before timeout")
+            .unwrap();
+        let header = parse_frame_header(&frame).expect("complete frame");
+        assert_eq!(header.opcode, WebSocketOpcode::Text);
+        let mut payload = frame[header.header_len..].to_vec();
+        unmask_payload(
+            &mut payload,
+            header.mask_key.expect("client frames are masked"),
+        );
+        assert_eq!(payload, b"hello, edited");
+
+        let recorded = storage::get_websocket_messages(&connection_id, 0, None);
+        assert_eq!(recorded.len(), 2);
+        assert!(recorded[1].injected);
+        assert_eq!(recorded[1].payload, b"hello, edited");
+
+        drop(client_peer);
+        tunnel.await.unwrap().unwrap();
+        let closed = crate::api::proxy_api::replay_websocket_message(captured.id, None);
+        assert!(closed.unwrap_err().contains("not open"));
+        storage::clear_websocket_messages(&connection_id);
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
-    #[ignore = "Requires opening local TCP ports"]
+    #[tokio::test]
     #[serial]
-    async fn http_request_persisted_via_handle_connection() {
+    async fn injected_message_waits_for_a_fragmented_message_to_finish() {
+        use crate::models::{MessageDirection, WebSocketMessage, WebSocketOpcode};
+        use crate::proxy::websocket::{build_frame, inject_message, parse_frame_header};
+
         let storage_dir = tempfile::tempdir().unwrap();
-        let storage_path = storage_dir.path().to_string_lossy().to_string();
-        let _ = storage::reset_store_for_tests(&storage_path);
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).expect("init store");
 
-        let upstream_listener = TcpListener::bind(("127.0.0.1", available_port()))
-            .await
-            .unwrap();
-        let upstream_addr = upstream_listener.local_addr().unwrap();
-        tokio::spawn(async move {
-            if let Ok((mut socket, _)) = upstream_listener.accept().await {
-                let mut buf = vec![0u8; 1024];
-                let _ = socket.read(&mut buf).await;
-                let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK";
-                let _ = socket.write_all(response).await;
+        let (mut proxy_client, mut client_peer) = duplex(4096);
+        let (mut proxy_upstream, mut upstream_peer) = duplex(4096);
+        let connection_id = "ws-fragment-test".to_string();
+        let tunnel = tokio::spawn({
+            let connection_id = connection_id.clone();
+            async move {
+                websocket_tunnel(
+                    &mut proxy_client,
+                    &mut proxy_upstream,
+                    connection_id,
+                    None,
+                    None,
+                )
+                .await
             }
         });
 
-        let proxy_listener = TcpListener::bind(("127.0.0.1", available_port()))
+        // First fragment of a text message: FIN cleared
+        let mut first = build_frame(WebSocketOpcode::Text, b"hel", Some([1, 2, 3, 4]));
+        first[0] &= 0x7F;
+        client_peer.write_all(&first).await.unwrap();
+        let mut forwarded = vec![0u8; first.len()];
+        upstream_peer.read_exact(&mut forwarded).await.unwrap();
+
+        // Give the tunnel a moment to parse the fragment before injecting
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        inject_message(WebSocketMessage::new(
+            connection_id.clone(),
+            MessageDirection::ClientToServer,
+            WebSocketOpcode::Text,
+            b"injected".to_vec(),
+            true,
+        ))
+        .expect("connection is open");
+        let mut early = [0u8; 1];
+        let read = tokio::time::timeout(
+            Duration::from_millis(200),
+            upstream_peer.read_exact(&mut early),
+        )
+        .await;
+        assert!(read.is_err(), "injected frame split a fragmented message");
+
+        let last = build_frame(WebSocketOpcode::Continuation, b"lo", Some([5, 6, 7, 8]));
+        client_peer.write_all(&last).await.unwrap();
+        let mut forwarded = vec![0u8; last.len()];
+        upstream_peer.read_exact(&mut forwarded).await.unwrap();
+        assert_eq!(forwarded, last);
+
+        let mut frame = vec![0u8; 6 + b"injected".len()];
+        tokio::time::timeout(Duration::from_secs(2), upstream_peer.read_exact(&mut frame))
             .await
+            .expect("injected frame after the final fragment")
             .unwrap();
-        let proxy_addr = proxy_listener.local_addr().unwrap();
-        let proxy_task = tokio::spawn(async move {
-            if let Ok((socket, _)) = proxy_listener.accept().await {
-                handle_connection(socket, None, None)
-                    .await
-                    .expect("handle connection");
-            }
-        });
+        let header = parse_frame_header(&frame).expect("complete frame");
+        assert_eq!(header.opcode, WebSocketOpcode::Text);
+        assert!(header.fin);
 
-        let mut client = tokio::net::TcpStream::connect(proxy_addr).await.unwrap();
-        let request = format!(
-            "GET http://127.0.0.1:{}/test HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 0\r\n\r\n",
-            upstream_addr.port()
-        );
-        client.write_all(request.as_bytes()).await.unwrap();
-        let mut resp_buf = vec![0u8; 128];
-        let _ = client.read(&mut resp_buf).await.unwrap();
-        proxy_task.await.unwrap();
+        drop(client_peer);
+        tunnel.await.unwrap().unwrap();
+        storage::clear_websocket_messages(&connection_id);
+    }
 
-        let filter = TransactionFilter::default();
-        let result = storage::query_transactions(&filter, 0, 10)
-            .await
-            .expect("query");
-        assert_eq!(result.items.len(), 1);
-        let tx = &result.items[0];
-        assert_eq!(tx.host, "127.0.0.1");
-        assert_eq!(tx.path, "/test");
-        assert_eq!(tx.status_code, Some(200));
-        assert_eq!(
-            tx.request_headers.get("Host").map(|s| s.as_str()),
-            Some("127.0.0.1")
-        );
+    struct FixedGeo;
+
+    impl geoip::GeoLookup for FixedGeo {
+        fn lookup(&self, ip: std::net::IpAddr) -> Option<crate::models::GeoInfo> {
+            (ip.to_string() == "203.0.113.7").then(|| crate::models::GeoInfo {
+                country: Some("NL".to_string()),
+                asn: Some(64500),
+                org: Some("Example Transit".to_string()),
+            })
+        }
     }
 
     #[tokio::test]
     #[serial]
-    async fn process_request_persists_transaction_with_mock_upstream() {
+    async fn completed_transaction_is_enriched_with_server_geo() {
         let storage_dir = tempfile::tempdir().unwrap();
         storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        geoip::set_lookup(Some(Arc::new(FixedGeo)));
 
-        let (mut proxy_client, mut client_peer) = duplex(4096);
-        let (mock_stream, mut upstream_peer) = duplex(4096);
+        let mut tx_rx = observe_transactions();
 
-        let holder = Arc::new(Mutex::new(Some(mock_stream)));
-        set_test_upstream_connector({
-            let holder = holder.clone();
-            move |_req| {
-                let mut guard = holder.lock().unwrap();
-                let stream = guard.take().expect("connector already used");
-                async move {
-                    let timing = ConnectionTiming {
-                        dns_ms: 0,
-                        tcp_ms: 0,
-                        tls_ms: None,
-                        server_ip: None,
-                        tls_version: None,
-                        tls_cipher: None,
-                    };
-                    Ok((UpstreamStream::Mock(stream), timing))
-                }
-            }
-        });
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "api.example.com",
+            443,
+            "/",
+            HashMap::new(),
+        );
+        tx.server_ip = Some("203.0.113.7".to_string());
+        tx.state = TransactionState::Completed;
+        persist_and_stream(tx).await;
+
+        // The first update is published without waiting for the lookup.
+        let first = tx_rx.recv().await.expect("completed transaction");
+        assert!(first.server_geo.is_none());
+        let enriched = tokio::time::timeout(std::time::Duration::from_secs(2), tx_rx.recv())
+            .await
+            .expect("enrichment published")
+            .expect("enriched transaction");
+        reset_test_transaction_observer();
+        geoip::set_lookup(None);
 
-        let upstream_task = tokio::spawn(async move {
-            let mut buf = vec![0u8; 512];
-            let n = upstream_peer.read(&mut buf).await.unwrap();
-            let forwarded = String::from_utf8_lossy(&buf[..n]);
-            assert!(forwarded.contains("GET /mock"));
-            upstream_peer
-                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nPONG")
+        let geo = enriched.server_geo.expect("geo populated");
+        assert_eq!(geo.country.as_deref(), Some("NL"));
+        assert_eq!(geo.asn, Some(64500));
+        assert_eq!(geo.org.as_deref(), Some("Example Transit"));
+    }
+
+    /// Answers like [`FixedGeo`], but only after a pause
+    struct SlowGeo;
+
+    impl geoip::GeoLookup for SlowGeo {
+        fn lookup(&self, ip: std::net::IpAddr) -> Option<crate::models::GeoInfo> {
+            std::thread::sleep(Duration::from_millis(200));
+            FixedGeo.lookup(ip)
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn geo_enrichment_keeps_edits_made_during_the_lookup() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        geoip::set_lookup(Some(Arc::new(SlowGeo)));
+
+        let mut tx_rx = observe_transactions();
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "api.example.com",
+            443,
+            "/",
+            HashMap::new(),
+        );
+        tx.server_ip = Some("203.0.113.7".to_string());
+        tx.state = TransactionState::Completed;
+        let id = tx.id.clone();
+        persist_and_stream(tx).await;
+        let _first = tx_rx.recv().await.expect("completed transaction");
+        assert!(
+            storage::set_transaction_tags(&id, vec!["ticket-1".to_string()])
                 .await
-                .unwrap();
+                .unwrap()
+        );
+
+        let enriched = tokio::time::timeout(Duration::from_secs(2), tx_rx.recv())
+            .await
+            .expect("enrichment published")
+            .expect("enriched transaction");
+        reset_test_transaction_observer();
+        geoip::set_lookup(None);
+
+        assert!(enriched.server_geo.is_some());
+        assert_eq!(enriched.tags, vec!["ticket-1"]);
+        let stored = storage::get_transaction_by_id(&id).await.unwrap().unwrap();
+        assert!(stored.server_geo.is_some());
+        assert_eq!(stored.tags, vec!["ticket-1"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn server_error_from_watched_host_fires_alert() {
+        use crate::models::alert::{AlertCondition, AlertRuleInput};
+
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        alerts::clear_alerts();
+        let rule = alerts::add_alert_rule(AlertRuleInput {
+            name: "payments 5xx".to_string(),
+            enabled: true,
+            host_contains: Some("payments.example.com".to_string()),
+            path_contains: None,
+            condition: AlertCondition::StatusBetween { min: 500, max: 599 },
         });
 
-        let parsed_request = build_test_request(
-            "example.com",
-            HttpMethod::Get,
-            "/mock",
-            vec![
-                ("Host".to_string(), "example.com".to_string()),
-                ("Content-Length".to_string(), "0".to_string()),
-            ],
+        let finished = |host: &str, status: u16| {
+            let mut tx = HttpTransaction::new(
+                HttpMethod::Post,
+                "https",
+                host,
+                443,
+                "/charge",
+                HashMap::new(),
+            );
+            tx.status_code = Some(status);
+            tx.state = TransactionState::Completed;
+            tx
+        };
+        persist_and_stream(finished("payments.example.com", 200)).await;
+        persist_and_stream(finished("other.example.com", 503)).await;
+        let failing = finished("payments.example.com", 503);
+        let failing_id = failing.id.clone();
+        persist_and_stream(failing).await;
+
+        let fired = alerts::alerts();
+        alerts::clear_alert_rules();
+        alerts::clear_alerts();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].rule_id, rule.id);
+        assert_eq!(fired[0].transaction_id, failing_id);
+        assert_eq!(
+            fired[0].message,
+            "POST https://payments.example.com/charge returned 503"
         );
+    }
 
-        process_request(
+    #[tokio::test]
+    #[serial]
+    async fn failed_upstream_connect_fires_alert_once() {
+        use crate::models::alert::{AlertCondition, AlertRuleInput};
+
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+        alerts::clear_alerts();
+        let rule = alerts::add_alert_rule(AlertRuleInput {
+            name: "api down".to_string(),
+            enabled: true,
+            host_contains: Some("api.example.com".to_string()),
+            path_contains: None,
+            condition: AlertCondition::Failed,
+        });
+        set_test_upstream_connector(|_req| async {
+            Err::<(UpstreamStream, ConnectionTiming), _>(anyhow!("connection refused"))
+        });
+
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let parsed_request =
+            build_test_request("api.example.com", HttpMethod::Get, "/health", Vec::new());
+        let _ = process_request(
             &mut proxy_client,
             parsed_request,
             Instant::now(),
             None,
             false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
         )
-        .await
-        .expect("process request should succeed");
-
-        let mut response_buf = vec![0u8; 512];
-        let n = client_peer.read(&mut response_buf).await.unwrap();
-        let response = String::from_utf8_lossy(&response_buf[..n]);
-        assert!(response.contains("200 OK"));
-        assert!(response.contains("PONG"));
-
-        upstream_task.await.unwrap();
+        .await;
         reset_test_upstream_connector();
 
-        let result = storage::query_transactions(&TransactionFilter::default(), 0, 10)
-            .await
-            .expect("query transactions");
-        assert_eq!(result.items.len(), 1);
-        let tx = &result.items[0];
-        assert_eq!(tx.path, "/mock");
-        assert_eq!(tx.status_code, Some(200));
+        let fired = alerts::alerts();
+        alerts::clear_alert_rules();
+        alerts::clear_alerts();
+        assert_eq!(fired.len(), 1, "{fired:?}");
+        assert_eq!(fired[0].rule_id, rule.id);
+        assert!(
+            fired[0]
+                .message
+                .starts_with("GET http://api.example.com/health failed"),
+            "{}",
+            fired[0].message
+        );
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -1985,6 +7289,8 @@ mod tests {
                         server_ip: None,
                         tls_version: None,
                         tls_cipher: None,
+                        tls_resumed: None,
+                        reused: false,
                     };
                     Ok((UpstreamStream::Mock(stream), timing))
                 }
@@ -2036,6 +7342,81 @@ mod tests {
         assert_eq!(tx.status_code, Some(200));
     }
 
+    #[tokio::test]
+    async fn write_request_head_strips_connection_listed_headers() {
+        let headers = vec![
+            ("Host".to_string(), "example.com".to_string()),
+            ("Connection".to_string(), "keep-alive, X-Custom".to_string()),
+            ("X-Custom".to_string(), "secret".to_string()),
+            ("Keep-Alive".to_string(), "timeout=5".to_string()),
+            ("TE".to_string(), "trailers".to_string()),
+            ("Accept".to_string(), "*/*".to_string()),
+        ];
+        let mut out = Vec::new();
+        write_request_head(
+            &mut out,
+            "GET",
+            "/",
+            "HTTP/1.1",
+            &headers,
+            &default_hop_by_hop_headers(),
+        )
+        .await
+        .unwrap();
+        let head = String::from_utf8(out).unwrap();
+        assert!(!head.contains("X-Custom"));
+        assert!(!head.contains("Keep-Alive"));
+        assert!(!head.contains("TE:"));
+        assert!(head.contains("Accept: */*"));
+        assert!(head.contains("Connection: keep-alive\r\n"));
+
+        let upgrade_headers = vec![
+            ("Host".to_string(), "example.com".to_string()),
+            ("Connection".to_string(), "Upgrade".to_string()),
+            ("Upgrade".to_string(), "websocket".to_string()),
+        ];
+        let mut out = Vec::new();
+        write_request_head(
+            &mut out,
+            "GET",
+            "/ws",
+            "HTTP/1.1",
+            &upgrade_headers,
+            &default_hop_by_hop_headers(),
+        )
+        .await
+        .unwrap();
+        let head = String::from_utf8(out).unwrap();
+        assert!(head.contains("Upgrade: websocket\r\n"));
+        assert!(head.contains("Connection: Upgrade\r\n"));
+
+        // Framing headers stay even when the client lists them.
+        let framing_headers = vec![
+            ("Host".to_string(), "example.com".to_string()),
+            (
+                "Connection".to_string(),
+                "content-length, transfer-encoding, host".to_string(),
+            ),
+            ("Content-Length".to_string(), "5".to_string()),
+            ("Transfer-Encoding".to_string(), "chunked".to_string()),
+        ];
+        let mut out = Vec::new();
+        write_request_head(
+            &mut out,
+            "POST",
+            "/",
+            "HTTP/1.1",
+            &framing_headers,
+            &default_hop_by_hop_headers(),
+        )
+        .await
+        .unwrap();
+        let head = String::from_utf8(out).unwrap();
+        assert!(head.contains("Host: example.com\r\n"));
+        assert!(head.contains("Content-Length: 5\r\n"));
+        assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+    }
+
     #[tokio::test]
     async fn decode_chunked_body_decodes_payload_bytes() {
         let (mut reader, mut writer) = duplex(256);
@@ -2070,7 +7451,9 @@ mod tests {
             method: Some(HttpMethod::Get),
             host_contains: Some("example.com".into()),
             path_contains: Some("break".into()),
-        });
+            ..BreakpointRuleInput::default()
+        })
+        .unwrap();
 
         let (mock_stream, mut upstream_peer) = duplex(4096);
         let holder = Arc::new(Mutex::new(Some(mock_stream)));
@@ -2087,6 +7470,8 @@ mod tests {
                         server_ip: None,
                         tls_version: None,
                         tls_cipher: None,
+                        tls_resumed: None,
+                        reused: false,
                     };
                     Ok((UpstreamStream::Mock(stream), timing))
                 }
@@ -2150,6 +7535,9 @@ mod tests {
             Instant::now(),
             None,
             false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
         )
         .await
         .expect("process request succeeds");
@@ -2174,6 +7562,105 @@ mod tests {
         breakpoints::reset_for_tests();
     }
 
+    #[test]
+    fn edited_request_body_is_not_stored_with_request_capture_off() {
+        let mut parsed = build_test_request("example.com", HttpMethod::Post, "/", Vec::new());
+        parsed.body_kind = RequestBodyKind::Edited {
+            data: b"token=secret".to_vec(),
+        };
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "http",
+            "example.com",
+            80,
+            "/",
+            HashMap::new(),
+        );
+
+        update_transaction_from_parsed(&mut tx, &parsed, 0);
+        assert_eq!(tx.request_body, None);
+        assert_eq!(tx.request_body_sha256, sha256_hex(b"token=secret"));
+
+        update_transaction_from_parsed(&mut tx, &parsed, MAX_BODY_CAPTURE_BYTES);
+        assert_eq!(tx.request_body.as_deref(), Some(&b"token=secret"[..]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[serial]
+    async fn breakpoint_pause_is_recorded_as_blocked_time() {
+        reset_test_transaction_observer();
+        breakpoints::reset_for_tests();
+        let storage_dir = tempfile::tempdir().unwrap();
+        storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        breakpoints::add_breakpoint_rule(BreakpointRuleInput {
+            enabled: true,
+            method: Some(HttpMethod::Get),
+            host_contains: Some("example.com".into()),
+            path_contains: Some("held".into()),
+            ..BreakpointRuleInput::default()
+        })
+        .unwrap();
+        mock_upstream(move |mut upstream_peer| async move {
+            let mut buf = vec![0u8; 512];
+            let _ = upstream_peer.read(&mut buf).await.unwrap();
+            upstream_peer
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let mut tx_rx = observe_transactions();
+        let resume_task = tokio::spawn(async move {
+            let mut last = None;
+            while let Some(tx) = tx_rx.recv().await {
+                if tx.state == TransactionState::Breakpointed {
+                    tokio::time::sleep(Duration::from_millis(150)).await;
+                    for _ in 0..10 {
+                        if breakpoints::resume_breakpoint(&tx.id, RequestEdit::default()).is_ok() {
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                }
+                if tx.state == TransactionState::Completed {
+                    last = Some(tx);
+                    break;
+                }
+            }
+            last
+        });
+
+        let (mut proxy_client, _client_peer) = duplex(4096);
+        let parsed_request = build_test_request(
+            "example.com",
+            HttpMethod::Get,
+            "/held",
+            vec![("Host".to_string(), "example.com".to_string())],
+        );
+        process_request(
+            &mut proxy_client,
+            parsed_request,
+            Instant::now(),
+            None,
+            false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
+        )
+        .await
+        .expect("process request succeeds");
+
+        let completed = resume_task.await.unwrap().expect("request completed");
+        reset_test_transaction_observer();
+        reset_test_upstream_connector();
+        breakpoints::reset_for_tests();
+
+        let blocked = completed.timing.blocked_ms.expect("blocked time recorded");
+        assert!(blocked >= 150, "blocked for {blocked} ms");
+        assert!(completed.timing.total_ms.unwrap() >= blocked);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     #[serial]
     async fn breakpoint_abort_returns_409_and_failed_state() {
@@ -2190,7 +7677,9 @@ mod tests {
             method: Some(HttpMethod::Get),
             host_contains: Some("example.com".into()),
             path_contains: Some("abort".into()),
-        });
+            ..BreakpointRuleInput::default()
+        })
+        .unwrap();
 
         let (state_sender, mut state_rx) = mpsc::unbounded_channel::<(String, TransactionState)>();
         set_test_transaction_observer(move |tx| {
@@ -2238,6 +7727,9 @@ mod tests {
             Instant::now(),
             None,
             false,
+            &InterceptOptions::default(),
+            None,
+            &mut ConnectionThrottle::default(),
         )
         .await
         .expect("process request succeeds");
@@ -2262,6 +7754,76 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn upstream_tls_resumption_is_reported_and_can_be_disabled() {
+        let cert_dir = tempfile::tempdir().unwrap();
+        let cert_manager = CertManager::new(cert_dir.path().to_str().unwrap()).unwrap();
+        let acceptor = TlsAcceptor::from(cert_manager.server_config_for_host("localhost").unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut buf = [0u8; 256];
+                    let _ = tls.read(&mut buf).await;
+                    let _ = tls.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await;
+                    let _ = tls.shutdown().await;
+                });
+            }
+        });
+
+        let mut request = build_test_request("localhost", HttpMethod::Get, "/", Vec::new());
+        request.scheme = "https".into();
+        request.port = port;
+        // Reading the response also takes in the session tickets the server
+        // sends after the handshake.
+        let handshake = |config: Arc<ClientConfig>| {
+            let request = &request;
+            async move {
+                let (mut upstream, timing) = connect_upstream(request, Some(config), false)
+                    .await
+                    .unwrap();
+                upstream
+                    .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .await
+                    .unwrap();
+                let mut buf = [0u8; 64];
+                assert!(upstream.read(&mut buf).await.unwrap() > 0);
+                timing.tls_resumed
+            }
+        };
+
+        let resuming = Arc::new(upstream_tls::accept_any_certificate_config().unwrap());
+        assert_eq!(handshake(resuming.clone()).await, Some(false));
+        assert_eq!(handshake(resuming).await, Some(true));
+
+        // As run_server applies `upstream_tls_resumption: false`; per-host
+        // overrides are rebuilt with it too.
+        upstream_tls::set_upstream_tls_overrides(vec![
+            crate::models::upstream_tls::UpstreamTlsOverride {
+                host_pattern: "localhost".to_string(),
+                alpn_protocols: None,
+                send_sni: true,
+                accept_invalid_certs: true,
+            },
+        ])
+        .unwrap();
+        upstream_tls::set_client_defaults(false, false).unwrap();
+        let full_only = Arc::new(upstream_tls::accept_any_certificate_config().unwrap());
+        let override_config = upstream_tls::client_config_for("localhost").unwrap();
+        upstream_tls::set_upstream_tls_overrides(Vec::new()).unwrap();
+        upstream_tls::set_client_defaults(false, true).unwrap();
+        assert_eq!(handshake(full_only.clone()).await, Some(false));
+        assert_eq!(handshake(full_only).await, Some(false));
+        assert_eq!(handshake(override_config.clone()).await, Some(false));
+        assert_eq!(handshake(override_config).await, Some(false));
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
     #[serial]
     async fn https_connect_interception_captures_transaction() {
@@ -2270,7 +7832,7 @@ mod tests {
 
         let cert_dir = tempfile::tempdir().unwrap();
         let cert_manager = Arc::new(CertManager::new(cert_dir.path().to_str().unwrap()).unwrap());
-        let tls_client_config = Arc::new(build_tls_client_config().unwrap());
+        let tls_client_config = Arc::new(build_tls_client_config(false, true).unwrap());
 
         let (mock_stream, mut upstream_peer) = duplex(4096);
         set_test_upstream_connector({
@@ -2290,6 +7852,8 @@ mod tests {
                         server_ip: None,
                         tls_version: None,
                         tls_cipher: None,
+                        tls_resumed: None,
+                        reused: false,
                     };
                     Ok((UpstreamStream::Mock(stream), timing))
                 }
@@ -2316,6 +7880,9 @@ mod tests {
                 "example.com".into(),
                 cert_manager_clone,
                 tls_config_clone,
+                Arc::default(),
+                None,
+                ConnectionThrottle::default(),
             )
             .await
             .expect("intercept tls");
@@ -2361,12 +7928,14 @@ mod tests {
         let result = storage::query_transactions(&TransactionFilter::default(), 0, 50)
             .await
             .expect("query transactions");
+        let secure = result
+            .items
+            .iter()
+            .find(|tx| tx.host == "example.com" && tx.path == "/secure" && tx.scheme == "https")
+            .expect("stored https transaction present");
         assert!(
-            result
-                .items
-                .iter()
-                .any(|tx| tx.host == "example.com" && tx.path == "/secure" && tx.scheme == "https"),
-            "stored https transaction present"
+            secure.was_intercepted,
+            "MITM request is flagged as intercepted"
         );
     }
 
@@ -2378,7 +7947,7 @@ mod tests {
 
         let cert_dir = tempfile::tempdir().unwrap();
         let cert_manager = Arc::new(CertManager::new(cert_dir.path().to_str().unwrap()).unwrap());
-        let tls_client_config = Arc::new(build_tls_client_config().unwrap());
+        let tls_client_config = Arc::new(build_tls_client_config(false, true).unwrap());
 
         set_test_upstream_connector({
             move |req| {
@@ -2413,6 +7982,8 @@ mod tests {
                         server_ip: None,
                         tls_version: None,
                         tls_cipher: None,
+                        tls_resumed: None,
+                        reused: false,
                     };
                     Ok((UpstreamStream::Mock(server_side), timing))
                 }
@@ -2428,6 +7999,9 @@ mod tests {
                 "example.com".into(),
                 cert_manager_clone,
                 tls_config_clone,
+                Arc::default(),
+                None,
+                ConnectionThrottle::default(),
             )
             .await
             .expect("intercept tls");
@@ -0,0 +1,224 @@
+//! SNI-based intercept-vs-tunnel decisions for CONNECT tunnels.
+//!
+//! The CONNECT authority can differ from the name the client actually asks for
+//! (e.g. CONNECT to an IP while sending a hostname in SNI), so the decision is
+//! made against the server name peeked from the TLS ClientHello.
+
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// TLS record header plus the largest plaintext record (RFC 8446 section 5.1).
+const MAX_CLIENT_HELLO_BYTES: usize = 5 + 16 * 1024;
+/// How long to wait for the client to send its ClientHello.
+const PEEK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Include/exclude SNI patterns. Patterns are exact hostnames, `*.suffix`
+/// (subdomains only) or `*`. Matching is case-insensitive.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SniPolicy {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl SniPolicy {
+    pub(crate) fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        let normalize = |patterns: Vec<String>| {
+            patterns
+                .into_iter()
+                .map(|p| p.trim().to_ascii_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect()
+        };
+        Self {
+            include: normalize(include),
+            exclude: normalize(exclude),
+        }
+    }
+
+    /// Whether any pattern is configured (otherwise no peeking is needed).
+    pub(crate) fn is_active(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Exclusions win; with an include list, only matching names are intercepted.
+    /// A ClientHello without SNI only matches when no include list is set.
+    pub(crate) fn should_intercept(&self, sni: Option<&str>) -> bool {
+        let Some(sni) = sni.map(|s| s.to_ascii_lowercase()) else {
+            return self.include.is_empty();
+        };
+        if self.exclude.iter().any(|p| pattern_matches(p, &sni)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|p| pattern_matches(p, &sni))
+    }
+}
+
+pub(crate) fn pattern_matches(pattern: &str, name: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => name
+            .strip_suffix(suffix)
+            .is_some_and(|rest| rest.ends_with('.') && rest.len() > 1),
+        None => pattern == name,
+    }
+}
+
+/// Peek (without consuming) the client's first TLS record and return its SNI.
+/// Returns `None` if the client sends something else, no SNI, or times out.
+pub(crate) async fn peek_sni(stream: &TcpStream) -> Option<String> {
+    let mut buf = vec![0u8; MAX_CLIENT_HELLO_BYTES];
+    let peek = async {
+        loop {
+            let n = stream.peek(&mut buf).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            if n >= 5 {
+                if buf[0] != 0x16 {
+                    return None;
+                }
+                let record_len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+                if n >= 5 + record_len || n == buf.len() {
+                    return parse_client_hello_sni(&buf[..n]);
+                }
+            }
+            // Partial record: wait for the rest without spinning on peek.
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    };
+    tokio::time::timeout(PEEK_TIMEOUT, peek)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Extract the host_name from a TLS record containing a ClientHello.
+pub(crate) fn parse_client_hello_sni(record: &[u8]) -> Option<String> {
+    let mut r = Reader(record);
+    // Record header: handshake content type, version, length.
+    if r.u8()? != 0x16 {
+        return None;
+    }
+    r.skip(2)?;
+    let record_len = r.u16()? as usize;
+    let mut r = Reader(r.take(record_len.min(r.0.len()))?);
+
+    // Handshake header: ClientHello, 24-bit length.
+    if r.u8()? != 0x01 {
+        return None;
+    }
+    r.skip(3)?;
+    r.skip(2 + 32)?; // legacy_version + random
+    let session_id_len = r.u8()? as usize;
+    r.skip(session_id_len)?;
+    let cipher_suites_len = r.u16()? as usize;
+    r.skip(cipher_suites_len)?;
+    let compression_len = r.u8()? as usize;
+    r.skip(compression_len)?;
+
+    let extensions_len = r.u16()? as usize;
+    let mut extensions = Reader(r.take(extensions_len)?);
+    while !extensions.0.is_empty() {
+        let ext_type = extensions.u16()?;
+        let ext_len = extensions.u16()? as usize;
+        let data = extensions.take(ext_len)?;
+        if ext_type != 0x0000 {
+            continue;
+        }
+        let mut names = Reader(data);
+        let list_len = names.u16()? as usize;
+        let mut names = Reader(names.take(list_len)?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name_len = names.u16()? as usize;
+            let name = names.take(name_len)?;
+            if name_type == 0 {
+                return std::str::from_utf8(name).ok().map(str::to_string);
+            }
+        }
+    }
+    None
+}
+
+/// Cursor over big-endian TLS wire data; every read fails on underrun
+pub(super) struct Reader<'a>(pub(super) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(super) fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    pub(super) fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    pub(super) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub(super) fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn client_hello(server_name: &str) -> Vec<u8> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+        let name = ServerName::try_from(server_name.to_string()).unwrap();
+        let mut conn = ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut hello = Vec::new();
+        conn.write_tls(&mut hello).unwrap();
+        hello
+    }
+
+    #[tokio::test]
+    async fn peeked_sni_routes_per_pattern() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hello = client_hello("api.example.com");
+        let sent = hello.clone();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(&sent).await.unwrap();
+            stream
+        });
+
+        let (mut server_side, _) = listener.accept().await.unwrap();
+        let sni = peek_sni(&server_side).await;
+        assert_eq!(sni.as_deref(), Some("api.example.com"));
+
+        // Peeking must leave the ClientHello for the TLS acceptor or tunnel.
+        let mut replayed = vec![0u8; hello.len()];
+        server_side.read_exact(&mut replayed).await.unwrap();
+        assert_eq!(replayed, hello);
+        drop(client.await.unwrap());
+
+        let policy = SniPolicy::new(vec!["*.example.com".into()], vec![]);
+        assert!(policy.should_intercept(sni.as_deref()));
+        assert!(!policy.should_intercept(Some("example.com")));
+        assert!(!policy.should_intercept(Some("api.other.com")));
+        assert!(!policy.should_intercept(None));
+
+        let policy = SniPolicy::new(vec![], vec!["API.example.com".into()]);
+        assert!(!policy.should_intercept(sni.as_deref()));
+        assert!(policy.should_intercept(Some("www.example.com")));
+        assert!(policy.should_intercept(None));
+    }
+}
@@ -0,0 +1,118 @@
+//! Server-Sent Events capture
+//!
+//! A `text/event-stream` response stays open and delivers events over time,
+//! so its raw bytes make a poor body to show. The parser collects the `data:`
+//! payload of each event as it completes, one event per line, reassembling
+//! events that arrive split across reads.
+
+/// Collects event data from a `text/event-stream` body.
+pub(crate) struct EventStreamParser {
+    /// Bytes of the event still being received
+    pending: Vec<u8>,
+    events: Vec<u8>,
+    limit: usize,
+}
+
+impl EventStreamParser {
+    /// A parser keeping at most `limit` bytes of event data.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            events: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Feed body bytes; returns whether any event carrying data completed.
+    pub fn push(&mut self, data: &[u8]) -> bool {
+        if self.events.len() >= self.limit {
+            return false;
+        }
+        self.pending.extend_from_slice(data);
+        let mut completed = false;
+        while let Some((end, separator_len)) = event_end(&self.pending) {
+            let block: Vec<u8> = self.pending.drain(..end + separator_len).collect();
+            if let Some(data) = event_data(&block[..end]) {
+                let room = self.limit.saturating_sub(self.events.len());
+                self.events.extend(data.iter().chain(b"\n").take(room));
+                completed = true;
+            }
+        }
+        // An event larger than the whole capture would never fit.
+        if self.pending.len() > self.limit {
+            self.pending.clear();
+        }
+        completed
+    }
+
+    /// Event data received so far
+    pub fn events(&self) -> &[u8] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Where the first complete event ends, and the length of the blank line
+/// that ends it.
+fn event_end(buf: &[u8]) -> Option<(usize, usize)> {
+    let lf = buf.windows(2).position(|w| w == b"\n\n").map(|at| (at, 2));
+    let crlf = buf
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|at| (at, 4));
+    match (lf, crlf) {
+        (Some(lf), Some(crlf)) => Some(if crlf.0 < lf.0 { crlf } else { lf }),
+        (lf, crlf) => lf.or(crlf),
+    }
+}
+
+/// The `data:` lines of one event joined by newlines; None if it has none
+/// (comments, or only `event:`/`id:`/`retry:` fields).
+fn event_data(block: &[u8]) -> Option<Vec<u8>> {
+    let mut data: Option<Vec<u8>> = None;
+    for line in block.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let (field, value) = match line.iter().position(|&b| b == b':') {
+            Some(at) => (&line[..at], &line[at + 1..]),
+            None => (line, &[][..]),
+        };
+        if field != b"data" {
+            continue;
+        }
+        let value = value.strip_prefix(b" ").unwrap_or(value);
+        match &mut data {
+            Some(data) => {
+                data.push(b'\n');
+                data.extend_from_slice(value);
+            }
+            None => data = Some(value.to_vec()),
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_split_across_reads_are_reassembled() {
+        let mut parser = EventStreamParser::new(1024);
+        assert!(!parser.push(b": keep-alive\n\nevent: delta\nda"));
+        assert!(!parser.push(b"ta: {\"token\":\"Hel\"}\n"));
+        assert!(parser.push(b"\ndata: {\"token\":\"lo\"}\r\n\r\nid: 7\n"));
+        assert!(parser.push(b"data: line one\ndata:line two\n\nretry: 500\n\n"));
+        assert_eq!(
+            parser.events(),
+            b"{\"token\":\"Hel\"}\n{\"token\":\"lo\"}\nline one\nline two\n"
+        );
+
+        let mut small = EventStreamParser::new(8);
+        small.push(b"data: 0123456789\n\n");
+        assert_eq!(small.events(), b"01234567");
+        assert!(!small.push(b"data: more\n\n"));
+    }
+}
@@ -0,0 +1,228 @@
+//! Network throttling: simulate slow links such as 3G.
+//!
+//! Each client connection gets one token bucket per direction, refilled at the
+//! configured rate and shared by every request, tunnel and WebSocket on it;
+//! writes only go through as fast as tokens accumulate, in chunks no larger
+//! than what the bucket holds. Added latency is spent after the response head
+//! arrives, so it shows up in the transaction's TTFB. The limits can be
+//! changed while the proxy runs and apply from a connection's next request.
+
+use crate::models::throttle::ThrottleConfig;
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// The bucket holds this much of a second's worth of bytes, which bounds
+/// both bursts and the size of each write.
+const BURST_FRACTION: f64 = 0.05;
+
+static THROTTLE: Lazy<RwLock<Option<ThrottleConfig>>> = Lazy::new(|| RwLock::new(None));
+
+/// Replace the throttle settings (None = full speed)
+pub fn set_throttle(config: Option<ThrottleConfig>) {
+    if let Ok(mut guard) = THROTTLE.write() {
+        *guard = config;
+    }
+}
+
+/// Current throttle settings
+pub fn throttle() -> Option<ThrottleConfig> {
+    THROTTLE.read().ok().and_then(|guard| *guard)
+}
+
+impl ThrottleConfig {
+    /// Delay before a response is passed on, if any
+    pub(crate) fn latency(&self) -> Option<Duration> {
+        (self.added_latency_ms > 0).then(|| Duration::from_millis(self.added_latency_ms.into()))
+    }
+
+    /// How the limits read in `applied_actions`
+    pub(crate) fn label(&self) -> String {
+        format!(
+            "throttle:down={}kbps,up={}kbps,latency={}ms",
+            self.down_kbps, self.up_kbps, self.added_latency_ms
+        )
+    }
+}
+
+/// Bytes allowed through at a fixed rate, with a small burst allowance.
+pub(crate) struct TokenBucket {
+    bytes_per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// A bucket for `kbps` kilobits per second; None for 0 (unlimited).
+    pub fn from_kbps(kbps: u32) -> Option<Self> {
+        if kbps == 0 {
+            return None;
+        }
+        let bytes_per_sec = f64::from(kbps) * 1000.0 / 8.0;
+        let capacity = (bytes_per_sec * BURST_FRACTION).max(1.0);
+        Some(Self {
+            bytes_per_sec,
+            capacity,
+            tokens: capacity,
+            refilled_at: Instant::now(),
+        })
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// How many of `want` bytes may be sent now, or how long to wait until a
+    /// full chunk may
+    fn available(&mut self, want: usize) -> Result<usize, Duration> {
+        self.refill();
+        let chunk = (want as f64).min(self.capacity);
+        if self.tokens >= chunk {
+            return Ok(chunk as usize);
+        }
+        Err(Duration::from_secs_f64(
+            (chunk - self.tokens) / self.bytes_per_sec,
+        ))
+    }
+
+    fn consume(&mut self, sent: usize) {
+        self.tokens -= sent as f64;
+    }
+}
+
+/// The buckets of one client connection. Requests on a keep-alive connection,
+/// and a CONNECT tunnel or WebSocket it turns into, draw from the same buckets
+/// instead of each starting with a full one.
+#[derive(Default)]
+pub(crate) struct ConnectionThrottle {
+    config: Option<ThrottleConfig>,
+    upload: Option<TokenBucket>,
+    download: Option<TokenBucket>,
+}
+
+impl ConnectionThrottle {
+    /// Adopt the current settings, starting fresh buckets only if they
+    /// changed, and return them
+    pub fn refresh(&mut self) -> Option<ThrottleConfig> {
+        let current = throttle();
+        if current != self.config {
+            self.upload = current.and_then(|config| TokenBucket::from_kbps(config.up_kbps));
+            self.download = current.and_then(|config| TokenBucket::from_kbps(config.down_kbps));
+            self.config = current;
+        }
+        current
+    }
+
+    /// Delay before a response is passed on, if any
+    pub fn latency(&self) -> Option<Duration> {
+        self.config.and_then(|config| config.latency())
+    }
+
+    /// Bucket pacing writes to the upstream
+    pub fn upload(&mut self) -> Option<&mut TokenBucket> {
+        self.upload.as_mut()
+    }
+
+    /// Upload and download buckets, for when both directions are in use
+    pub fn buckets(&mut self) -> (Option<&mut TokenBucket>, Option<&mut TokenBucket>) {
+        (self.upload.as_mut(), self.download.as_mut())
+    }
+}
+
+/// A stream whose writes are paced by a token bucket; reads pass through.
+pub(crate) struct Throttled<'a, S> {
+    inner: &'a mut S,
+    bucket: Option<&'a mut TokenBucket>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<'a, S> Throttled<'a, S> {
+    pub fn new(inner: &'a mut S, bucket: Option<&'a mut TokenBucket>) -> Self {
+        Self {
+            inner,
+            bucket,
+            sleep: None,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Throttled<'_, S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(bucket) = this.bucket.as_deref_mut() else {
+            return Pin::new(&mut *this.inner).poll_write(cx, buf);
+        };
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        loop {
+            if let Some(sleep) = &mut this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+            match bucket.available(buf.len()) {
+                Ok(allowed) => {
+                    let written =
+                        ready!(Pin::new(&mut *this.inner).poll_write(cx, &buf[..allowed]))?;
+                    bucket.consume(written);
+                    return Poll::Ready(Ok(written));
+                }
+                Err(wait) => this.sleep = Some(Box::pin(tokio::time::sleep(wait))),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Throttled<'_, S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_are_paced_to_the_configured_rate() {
+        // 80 kbps = 10,000 bytes per second
+        let mut sink = Vec::new();
+        let mut bucket = TokenBucket::from_kbps(80);
+        let mut throttled = Throttled::new(&mut sink, bucket.as_mut());
+        let started = Instant::now();
+        throttled.write_all(&[0u8; 20_000]).await.unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(sink.len(), 20_000);
+        // The first burst goes out at once, the rest at the refill rate.
+        assert!(elapsed >= Duration::from_millis(1_900), "{elapsed:?}");
+        assert!(elapsed <= Duration::from_millis(2_100), "{elapsed:?}");
+
+        assert!(TokenBucket::from_kbps(0).is_none());
+    }
+}
@@ -0,0 +1,89 @@
+//! Connection transcript recording
+//!
+//! [`Recorded`] wraps a stream and copies every byte read or written into the
+//! transcript store. Client streams are wrapped after TLS termination, so the
+//! transcript shows the decrypted protocol bytes.
+
+use crate::models::TranscriptDirection;
+use crate::storage;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A stream that records its traffic when a transcript id is set.
+pub(crate) struct Recorded<S> {
+    inner: S,
+    transcript_id: Option<String>,
+    read_direction: TranscriptDirection,
+    write_direction: TranscriptDirection,
+}
+
+impl<S> Recorded<S> {
+    /// Wrap the client side of a connection.
+    pub(crate) fn client(inner: S, transcript_id: Option<&str>) -> Self {
+        Self {
+            inner,
+            transcript_id: transcript_id.map(str::to_string),
+            read_direction: TranscriptDirection::ClientToProxy,
+            write_direction: TranscriptDirection::ProxyToClient,
+        }
+    }
+
+    /// Wrap the upstream side of a connection.
+    pub(crate) fn upstream(inner: S, transcript_id: Option<&str>) -> Self {
+        Self {
+            inner,
+            transcript_id: transcript_id.map(str::to_string),
+            read_direction: TranscriptDirection::UpstreamToProxy,
+            write_direction: TranscriptDirection::ProxyToUpstream,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Recorded<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(id)) = (&result, &this.transcript_id) {
+            storage::append_transcript_chunk(id, this.read_direction, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Recorded<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(id)) = (&result, &this.transcript_id) {
+            storage::append_transcript_chunk(id, this.write_direction, &buf[..*n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
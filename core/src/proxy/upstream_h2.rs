@@ -0,0 +1,170 @@
+//! HTTP/2 to upstream servers.
+//!
+//! With `enable_h2` set, `h2` is offered in the upstream ALPN list. Clients keep
+//! talking HTTP/1.1 to the proxy; when an upstream selects h2, the request is
+//! sent as a single stream on that connection and the response is translated
+//! back to HTTP/1.1 for the client.
+//!
+//! Capture fidelity matches HTTP/1.1 for method, path, headers, status, body
+//! and timing. h2-specific details (frame layout, HPACK, stream priorities,
+//! server push, trailers) are not recorded, and h2 connections are not pooled.
+
+use anyhow::Context;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Request, Response, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Headers that are specific to an HTTP/1.1 connection and forbidden in h2
+/// (RFC 9113 section 8.2.2). `Host` becomes the `:authority` pseudo-header and
+/// `Content-Length` is recomputed from the (possibly de-chunked) body.
+const CONNECTION_SPECIFIC_HEADERS: &[&str] = &[
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+];
+
+/// Whether a negotiated ALPN protocol is HTTP/2.
+pub(crate) fn is_h2(alpn: Option<&[u8]>) -> bool {
+    alpn == Some(b"h2".as_slice())
+}
+
+/// Send one request over an h2-negotiated connection and return the response
+/// head with its still-streaming body. `hop_by_hop` headers, and any named in
+/// the request's `Connection` header, are dropped.
+pub(crate) async fn send_request<IO>(
+    io: IO,
+    method: &str,
+    uri: &str,
+    headers: &[(String, String)],
+    hop_by_hop: &[String],
+    body: Vec<u8>,
+) -> anyhow::Result<Response<Incoming>>
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let connection_tokens: Vec<String> = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("connection"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .collect();
+    let is_dropped = |name: &str| {
+        CONNECTION_SPECIFIC_HEADERS
+            .iter()
+            .any(|h| name.eq_ignore_ascii_case(h))
+            || hop_by_hop.iter().any(|h| name.eq_ignore_ascii_case(h))
+            || connection_tokens
+                .iter()
+                .any(|t| name.eq_ignore_ascii_case(t))
+    };
+
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .version(Version::HTTP_2)
+        .body(http_body_util::Full::new(Bytes::from(body)))
+        .context("building HTTP/2 request")?;
+    let request_headers = request.headers_mut();
+    for (name, value) in headers {
+        // `TE: trailers` is the only TE value h2 allows.
+        if is_dropped(name)
+            || (name.eq_ignore_ascii_case("te") && !value.eq_ignore_ascii_case("trailers"))
+        {
+            continue;
+        }
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("invalid header name {name:?}"))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("invalid value for header {name}"))?;
+        request_headers.append(name, value);
+    }
+
+    let (mut sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(io))
+            .await
+            .context("HTTP/2 handshake with upstream")?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            tracing::debug!("HTTP/2 upstream connection closed: {err}");
+        }
+    });
+    sender
+        .send_request(request)
+        .await
+        .context("sending HTTP/2 request upstream")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use hyper::service::service_fn;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn request_round_trips_over_h2() {
+        let (client_io, server_io) = duplex(64 * 1024);
+        tokio::spawn(async move {
+            let service = service_fn(|req: Request<Incoming>| async move {
+                assert_eq!(req.version(), Version::HTTP_2);
+                assert!(req.headers().get("connection").is_none());
+                assert!(req.headers().get("x-drop-me").is_none());
+                let summary = format!(
+                    "{} {} {} {}",
+                    req.method(),
+                    req.uri().path(),
+                    req.uri().authority().map(|a| a.as_str()).unwrap_or(""),
+                    req.headers()["x-custom"].to_str().unwrap()
+                );
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                let reply = format!("{summary} {}", String::from_utf8_lossy(&body));
+                Ok::<_, hyper::Error>(
+                    Response::builder()
+                        .status(201)
+                        .header("x-served-by", "h2")
+                        .body(http_body_util::Full::new(Bytes::from(reply)))
+                        .unwrap(),
+                )
+            });
+            hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(server_io), service)
+                .await
+                .unwrap();
+        });
+
+        let headers = vec![
+            ("Host".to_string(), "api.test".to_string()),
+            (
+                "Connection".to_string(),
+                "keep-alive, X-Drop-Me".to_string(),
+            ),
+            ("X-Drop-Me".to_string(), "1".to_string()),
+            ("X-Custom".to_string(), "yes".to_string()),
+            ("Content-Length".to_string(), "5".to_string()),
+        ];
+        let response = send_request(
+            client_io,
+            "POST",
+            "https://api.test/items",
+            &headers,
+            &[],
+            b"hello".to_vec(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers()["x-served-by"], "h2");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"POST /items api.test yes hello");
+
+        assert!(is_h2(Some(b"h2")));
+        assert!(!is_h2(Some(b"http/1.1")));
+        assert!(!is_h2(None));
+    }
+}
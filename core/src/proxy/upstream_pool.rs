@@ -0,0 +1,183 @@
+//! Idle upstream connection pool
+//!
+//! After a complete keep-alive response the upstream connection is parked here so
+//! the next request to the same origin can skip the TCP/TLS handshake.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Maximum idle connections kept per origin
+pub const MAX_IDLE_PER_ORIGIN: usize = 8;
+/// Default time an idle connection may sit in the pool before it is closed
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Origin a pooled connection is bound to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl PoolKey {
+    pub fn new(scheme: &str, host: &str, port: u16) -> Self {
+        Self {
+            scheme: scheme.to_ascii_lowercase(),
+            host: host.to_ascii_lowercase(),
+            port,
+        }
+    }
+}
+
+struct IdleEntry<T> {
+    conn: T,
+    idle_since: Instant,
+}
+
+/// Per-origin stacks of idle connections (most recently used first out).
+pub struct ConnectionPool<T> {
+    idle: Mutex<HashMap<PoolKey, Vec<IdleEntry<T>>>>,
+    max_idle_per_origin: usize,
+    idle_timeout_ms: AtomicU64,
+}
+
+impl<T> ConnectionPool<T> {
+    pub fn new(max_idle_per_origin: usize, idle_timeout: Duration) -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            max_idle_per_origin,
+            idle_timeout_ms: AtomicU64::new(idle_timeout.as_millis() as u64),
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_ms.load(Ordering::Relaxed))
+    }
+
+    /// Change the idle TTL; applies to connections already parked.
+    pub fn set_idle_timeout(&self, idle_timeout: Duration) {
+        self.idle_timeout_ms
+            .store(idle_timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Take the most recently parked connection for `key`, discarding expired ones.
+    pub fn checkout(&self, key: &PoolKey) -> Option<T> {
+        let idle_timeout = self.idle_timeout();
+        let mut idle = self.idle.lock().expect("pool mutex poisoned");
+        let entries = idle.get_mut(key)?;
+        let mut found = None;
+        while let Some(entry) = entries.pop() {
+            if entry.idle_since.elapsed() < idle_timeout {
+                found = Some(entry.conn);
+                break;
+            }
+        }
+        if entries.is_empty() {
+            idle.remove(key);
+        }
+        found
+    }
+
+    /// Park a connection for later reuse, evicting the oldest when the origin is full.
+    pub fn checkin(&self, key: PoolKey, conn: T) {
+        if self.max_idle_per_origin == 0 {
+            return;
+        }
+        let mut idle = self.idle.lock().expect("pool mutex poisoned");
+        let entries = idle.entry(key).or_default();
+        if entries.len() >= self.max_idle_per_origin {
+            entries.remove(0);
+        }
+        entries.push(IdleEntry {
+            conn,
+            idle_since: Instant::now(),
+        });
+    }
+
+    /// Close connections idle beyond the TTL, returning how many were dropped.
+    pub fn reap_expired(&self) -> usize {
+        let idle_timeout = self.idle_timeout();
+        let mut idle = self.idle.lock().expect("pool mutex poisoned");
+        let mut reaped = 0;
+        idle.retain(|_, entries| {
+            let before = entries.len();
+            entries.retain(|entry| entry.idle_since.elapsed() < idle_timeout);
+            reaped += before - entries.len();
+            !entries.is_empty()
+        });
+        reaped
+    }
+
+    /// Drop every idle connection.
+    pub fn clear(&self) {
+        self.idle.lock().expect("pool mutex poisoned").clear();
+    }
+
+    /// Number of idle connections across all origins.
+    pub fn idle_count(&self) -> usize {
+        self.idle
+            .lock()
+            .expect("pool mutex poisoned")
+            .values()
+            .map(Vec::len)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkout_returns_most_recent_connection_for_origin() {
+        let pool = ConnectionPool::new(2, DEFAULT_IDLE_TIMEOUT);
+        let key = PoolKey::new("http", "Example.com", 80);
+        pool.checkin(key.clone(), 1);
+        pool.checkin(key.clone(), 2);
+        pool.checkin(key.clone(), 3);
+
+        assert_eq!(pool.idle_count(), 2, "oldest evicted at capacity");
+        assert_eq!(
+            pool.checkout(&PoolKey::new("http", "example.com", 80)),
+            Some(3)
+        );
+        assert_eq!(pool.checkout(&key), Some(2));
+        assert_eq!(pool.checkout(&key), None);
+        assert_eq!(
+            pool.checkout(&PoolKey::new("https", "example.com", 443)),
+            None
+        );
+    }
+
+    #[test]
+    fn expired_connections_are_not_reused() {
+        let pool = ConnectionPool::new(4, Duration::ZERO);
+        let key = PoolKey::new("http", "example.com", 80);
+        pool.checkin(key.clone(), 1);
+        assert_eq!(pool.checkout(&key), None);
+        assert_eq!(pool.idle_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn reaper_closes_connections_idle_past_ttl() {
+        let pool = ConnectionPool::new(4, Duration::from_millis(50));
+        let stale = PoolKey::new("http", "stale.example.com", 80);
+        let fresh = PoolKey::new("http", "fresh.example.com", 80);
+        pool.checkin(stale.clone(), 1);
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        pool.checkin(fresh.clone(), 2);
+
+        assert_eq!(pool.reap_expired(), 1);
+        assert_eq!(pool.idle_count(), 1);
+        assert_eq!(pool.checkout(&stale), None);
+        assert_eq!(pool.checkout(&fresh), Some(2));
+
+        // Lowering the TTL applies to connections already parked.
+        pool.checkin(fresh.clone(), 3);
+        pool.set_idle_timeout(Duration::ZERO);
+        assert_eq!(pool.checkout(&fresh), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Per-host TLS client configuration for upstream connections.
+//!
+//! Most upstreams work with the global client config, but some need a specific
+//! ALPN list, no SNI (IP-based endpoints) or a self-signed certificate accepted.
+//! Overrides are matched in the order they were configured; the first match
+//! supplies a prebuilt `ClientConfig`, otherwise the global one is used.
+//! Tailored configs start from the running proxy's HTTP/2 and session
+//! resumption settings, like the global one.
+
+use crate::models::upstream_tls::UpstreamTlsOverride;
+use crate::proxy::server::build_tls_client_config;
+use crate::proxy::sni::pattern_matches;
+use once_cell::sync::Lazy;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use std::sync::{Arc, RwLock};
+
+struct Entry {
+    settings: UpstreamTlsOverride,
+    config: Arc<ClientConfig>,
+}
+
+static OVERRIDES: Lazy<RwLock<Vec<Entry>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Arguments to [`build_tls_client_config`] every tailored config starts from
+#[derive(Debug, Clone, Copy)]
+struct ClientDefaults {
+    enable_h2: bool,
+    session_resumption: bool,
+}
+
+static DEFAULTS: RwLock<ClientDefaults> = RwLock::new(ClientDefaults {
+    enable_h2: false,
+    session_resumption: true,
+});
+
+/// Use the running proxy's `enable_h2` and `upstream_tls_resumption` for
+/// tailored configs, rebuilding the configured overrides.
+pub(crate) fn set_client_defaults(enable_h2: bool, session_resumption: bool) -> anyhow::Result<()> {
+    *DEFAULTS
+        .write()
+        .map_err(|_| anyhow::anyhow!("upstream TLS defaults poisoned"))? = ClientDefaults {
+        enable_h2,
+        session_resumption,
+    };
+    set_upstream_tls_overrides(upstream_tls_overrides())
+}
+
+/// The global client config as the running proxy builds it
+pub(crate) fn default_client_config() -> anyhow::Result<ClientConfig> {
+    let defaults = *DEFAULTS
+        .read()
+        .map_err(|_| anyhow::anyhow!("upstream TLS defaults poisoned"))?;
+    build_tls_client_config(defaults.enable_h2, defaults.session_resumption)
+}
+
+/// Replace the per-host overrides. Fails without changing anything if a config
+/// cannot be built. Idle pooled upstream connections are closed, since they
+/// were set up with the previous settings.
+pub fn set_upstream_tls_overrides(overrides: Vec<UpstreamTlsOverride>) -> anyhow::Result<()> {
+    let entries = overrides
+        .into_iter()
+        .filter(|o| !o.host_pattern.trim().is_empty())
+        .map(|mut settings| {
+            settings.host_pattern = settings.host_pattern.trim().to_ascii_lowercase();
+            let config = Arc::new(build_override_config(&settings)?);
+            Ok(Entry { settings, config })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut guard = OVERRIDES
+        .write()
+        .map_err(|_| anyhow::anyhow!("upstream TLS overrides poisoned"))?;
+    *guard = entries;
+    drop(guard);
+    crate::proxy::server::clear_upstream_pool();
+    Ok(())
+}
+
+/// Currently configured overrides, in match order
+pub fn upstream_tls_overrides() -> Vec<UpstreamTlsOverride> {
+    OVERRIDES
+        .read()
+        .map(|entries| entries.iter().map(|e| e.settings.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// The tailored client config for `host`, if an override matches it.
+pub(crate) fn client_config_for(host: &str) -> Option<Arc<ClientConfig>> {
+    let entries = OVERRIDES.read().ok()?;
+    let host = host.to_ascii_lowercase();
+    entries
+        .iter()
+        .find(|e| pattern_matches(&e.settings.host_pattern, &host))
+        .map(|e| e.config.clone())
+}
+
+fn build_override_config(settings: &UpstreamTlsOverride) -> anyhow::Result<ClientConfig> {
+    let mut config = default_client_config()?;
+    if let Some(alpn) = &settings.alpn_protocols {
+        config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    }
+    config.enable_sni = settings.send_sni;
+    if settings.accept_invalid_certs {
+        let provider = config.crypto_provider().clone();
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)));
+    }
+    Ok(config)
+}
+
+/// Client config that accepts any upstream certificate.
+pub(crate) fn accept_any_certificate_config() -> anyhow::Result<ClientConfig> {
+    let mut config = default_client_config()?;
+    let provider = config.crypto_provider().clone();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)));
+    Ok(config)
+}
+
+/// Accepts any certificate chain but still checks the handshake signatures, so
+/// the connection is at least bound to the key in the presented certificate.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
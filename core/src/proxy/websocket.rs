@@ -1,16 +1,230 @@
 //! WebSocket frame parsing
 //!
-//! Parses WebSocket frames according to RFC 6455
+//! Parses WebSocket frames according to RFC 6455, inflating messages
+//! compressed with the `permessage-deflate` extension (RFC 7692)
 
+use crate::models::protobuf::{decode_without_schema, DescriptorPool};
 use crate::models::{MessageDirection, WebSocketMessage, WebSocketOpcode};
+use flate2::{Decompress, FlushDecompress, Status};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
 
 /// Maximum payload we'll capture per frame (to avoid memory issues with large binary frames)
 const MAX_CAPTURE_SIZE: usize = 256 * 1024; // 256KB
 
+/// Binary payloads larger than this are not decoded as protobuf
+const MAX_DECODE_SIZE: usize = 64 * 1024;
+
+/// Payload of pings the proxy injects to keep idle connections open
+pub const KEEPALIVE_PING_PAYLOAD: &[u8] = b"cheddar-keepalive";
+
+/// Per-connection keepalive overrides; `None` disables keepalive for that connection
+static KEEPALIVE_OVERRIDES: Lazy<RwLock<HashMap<String, Option<u32>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Open connections accepting injected messages, by connection id
+static LIVE_CONNECTIONS: Lazy<RwLock<HashMap<String, mpsc::UnboundedSender<WebSocketMessage>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Schema used to decode binary frames: descriptor pool and root message type
+static PROTOBUF_SCHEMA: Lazy<RwLock<Option<(DescriptorPool, String)>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// Decode binary frames as `message_type` from a serialized `FileDescriptorSet`.
+/// `None` goes back to schema-less decoding (field numbers and wire types only).
+pub fn set_protobuf_schema(
+    descriptor_set: Option<&[u8]>,
+    message_type: Option<&str>,
+) -> anyhow::Result<()> {
+    let schema = match descriptor_set {
+        Some(bytes) => {
+            let pool = DescriptorPool::from_descriptor_set(bytes)?;
+            let message_type = message_type
+                .map(|t| t.trim().trim_start_matches('.'))
+                .filter(|t| !t.is_empty())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("a message type is required with a descriptor set")
+                })?;
+            if !pool.contains(message_type) {
+                anyhow::bail!("message type {message_type} not found in descriptor set");
+            }
+            Some((pool, message_type.to_string()))
+        }
+        None => None,
+    };
+    let mut guard = PROTOBUF_SCHEMA
+        .write()
+        .map_err(|_| anyhow::anyhow!("protobuf schema poisoned"))?;
+    *guard = schema;
+    Ok(())
+}
+
+/// Protobuf view of a binary payload (see [`WebSocketMessage::decoded_view`])
+fn binary_view(payload: &[u8]) -> Option<String> {
+    if payload.is_empty() || payload.len() > MAX_DECODE_SIZE {
+        return None;
+    }
+    let with_schema = PROTOBUF_SCHEMA.read().ok().and_then(|schema| {
+        let (pool, message_type) = schema.as_ref()?;
+        pool.decode(message_type, payload)
+    });
+    let view = with_schema.or_else(|| decode_without_schema(payload))?;
+    serde_json::to_string(&view).ok()
+}
+
+/// Override the keepalive interval of one connection, taking effect immediately.
+/// `None` disables keepalive for it regardless of the global setting.
+pub fn set_connection_keepalive(connection_id: &str, interval_secs: Option<u32>) {
+    if let Ok(mut overrides) = KEEPALIVE_OVERRIDES.write() {
+        overrides.insert(connection_id.to_string(), interval_secs);
+    }
+}
+
+/// Forget a connection's keepalive override (called when the connection closes)
+pub(crate) fn clear_connection_keepalive(connection_id: &str) {
+    if let Ok(mut overrides) = KEEPALIVE_OVERRIDES.write() {
+        overrides.remove(connection_id);
+    }
+}
+
+/// Keepalive interval for a connection: its override if set, else the global default
+pub(crate) fn keepalive_interval(
+    connection_id: &str,
+    default: Option<Duration>,
+) -> Option<Duration> {
+    match KEEPALIVE_OVERRIDES
+        .read()
+        .ok()
+        .and_then(|o| o.get(connection_id).copied())
+    {
+        Some(secs) => secs
+            .filter(|s| *s > 0)
+            .map(|s| Duration::from_secs(s.into())),
+        None => default,
+    }
+}
+
+/// Accept injected messages for a connection until [`unregister_connection`]
+pub(crate) fn register_connection(
+    connection_id: &str,
+) -> mpsc::UnboundedReceiver<WebSocketMessage> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if let Ok(mut live) = LIVE_CONNECTIONS.write() {
+        live.insert(connection_id.to_string(), sender);
+    }
+    receiver
+}
+
+/// Stop accepting injected messages (called when the connection closes)
+pub(crate) fn unregister_connection(connection_id: &str) {
+    if let Ok(mut live) = LIVE_CONNECTIONS.write() {
+        live.remove(connection_id);
+    }
+}
+
+/// Queue `message` to be written into its connection, in its direction, as one
+/// unfragmented and uncompressed frame. Fails if the connection is closed.
+pub fn inject_message(message: WebSocketMessage) -> anyhow::Result<()> {
+    let sender = LIVE_CONNECTIONS
+        .read()
+        .ok()
+        .and_then(|live| live.get(&message.connection_id).cloned());
+    let closed = || anyhow::anyhow!("WebSocket connection {} is not open", message.connection_id);
+    let sender = sender.ok_or_else(closed)?;
+    sender.send(message.clone()).map_err(|_| closed())
+}
+
+/// Send a captured message again, with `edited_payload` in place of the
+/// original if given. Returns the injected message as it will be recorded.
+pub fn replay_message(
+    message_id: &str,
+    edited_payload: Option<Vec<u8>>,
+) -> anyhow::Result<WebSocketMessage> {
+    let original = crate::storage::get_websocket_message(message_id)
+        .ok_or_else(|| anyhow::anyhow!("WebSocket message {message_id} not found"))?;
+    if original.opcode == WebSocketOpcode::Continuation {
+        anyhow::bail!("a continuation fragment cannot be sent on its own");
+    }
+    let payload = edited_payload.unwrap_or(original.payload);
+    if is_control(original.opcode) && payload.len() > 125 {
+        anyhow::bail!("control frame payloads are at most 125 bytes");
+    }
+    let mut message = WebSocketMessage::new(
+        original.connection_id,
+        original.direction,
+        original.opcode,
+        payload,
+        true,
+    );
+    message.injected = true;
+    if message.opcode == WebSocketOpcode::Binary {
+        message.decoded_view = binary_view(&message.payload);
+    }
+    inject_message(message.clone())?;
+    Ok(message)
+}
+
+pub(crate) fn is_control(opcode: WebSocketOpcode) -> bool {
+    matches!(
+        opcode,
+        WebSocketOpcode::Close | WebSocketOpcode::Ping | WebSocketOpcode::Pong
+    )
+}
+
+/// Whether a fragmented data message is still open in a direction after
+/// `message` passed through. Control frames may arrive between fragments and
+/// leave it unchanged.
+pub(crate) fn fragment_open_after(open: bool, message: &WebSocketMessage) -> bool {
+    if is_control(message.opcode) {
+        open
+    } else {
+        !message.is_final
+    }
+}
+
+/// Build a single unfragmented frame. Frames from the client side of a
+/// connection must be masked with `mask_key`; server frames carry none.
+pub fn build_frame(opcode: WebSocketOpcode, payload: &[u8], mask_key: Option<[u8; 4]>) -> Vec<u8> {
+    let mask_bit = if mask_key.is_some() { 0x80 } else { 0 };
+    let mut frame = vec![0x80 | opcode.to_u8()];
+    match payload.len() {
+        len @ 0..=125 => frame.push(mask_bit | len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    let mut payload = payload.to_vec();
+    if let Some(mask_key) = mask_key {
+        frame.extend_from_slice(&mask_key);
+        unmask_payload(&mut payload, mask_key);
+    }
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Build a masked ping frame, as sent from the client side of a connection.
+pub fn build_ping_frame(payload: &[u8], mask_key: [u8; 4]) -> Vec<u8> {
+    debug_assert!(
+        payload.len() <= 125,
+        "control frame payloads are at most 125 bytes"
+    );
+    build_frame(WebSocketOpcode::Ping, payload, Some(mask_key))
+}
+
 /// Parsed WebSocket frame header
 #[derive(Debug, Clone)]
 pub struct FrameHeader {
     pub fin: bool,
+    /// Set on the first frame of a compressed message
+    pub rsv1: bool,
     pub opcode: WebSocketOpcode,
     pub masked: bool,
     pub payload_len: u64,
@@ -26,6 +240,7 @@ pub fn parse_frame_header(data: &[u8]) -> Option<FrameHeader> {
     }
 
     let fin = (data[0] & 0x80) != 0;
+    let rsv1 = (data[0] & 0x40) != 0;
     let opcode = WebSocketOpcode::from_u8(data[0] & 0x0F)?;
     let masked = (data[1] & 0x80) != 0;
     let mut payload_len = (data[1] & 0x7F) as u64;
@@ -75,6 +290,7 @@ pub fn parse_frame_header(data: &[u8]) -> Option<FrameHeader> {
 
     Some(FrameHeader {
         fin,
+        rsv1,
         opcode,
         masked,
         payload_len,
@@ -90,12 +306,128 @@ pub fn unmask_payload(data: &mut [u8], mask_key: [u8; 4]) {
     }
 }
 
-/// Try to extract a complete WebSocket frame and create a message
+/// `permessage-deflate` parameters accepted by the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerMessageDeflate {
+    /// The server starts each message with an empty compression window
+    pub server_no_context_takeover: bool,
+    /// The client starts each message with an empty compression window
+    pub client_no_context_takeover: bool,
+}
+
+impl PerMessageDeflate {
+    /// The extension as negotiated by a 101 response's
+    /// `Sec-WebSocket-Extensions` header, if it was accepted.
+    ///
+    /// Window sizes are not tracked: inflating with the full 32 KiB window
+    /// handles any smaller window the peers agreed on.
+    pub fn from_response_header(value: &str) -> Option<Self> {
+        value.split(',').find_map(|extension| {
+            let mut params = extension.split(';').map(str::trim);
+            if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+                return None;
+            }
+            let mut deflate = Self::default();
+            for param in params {
+                let name = param.split('=').next().unwrap_or_default().trim();
+                if name.eq_ignore_ascii_case("server_no_context_takeover") {
+                    deflate.server_no_context_takeover = true;
+                } else if name.eq_ignore_ascii_case("client_no_context_takeover") {
+                    deflate.client_no_context_takeover = true;
+                }
+            }
+            Some(deflate)
+        })
+    }
+}
+
+/// Inflates the compressed messages of one direction of a connection. The
+/// compression window carries over between messages unless the sender resets
+/// it, so one inflater must see every frame of its direction in order.
+pub struct MessageInflater {
+    decompress: Decompress,
+    no_context_takeover: bool,
+    /// Inside a fragmented message whose first frame had RSV1 set
+    in_compressed_message: bool,
+}
+
+impl MessageInflater {
+    pub fn new(no_context_takeover: bool) -> Self {
+        Self {
+            decompress: Decompress::new(false),
+            no_context_takeover,
+            in_compressed_message: false,
+        }
+    }
+
+    /// Inflate a frame's payload, capped at [`MAX_CAPTURE_SIZE`]. `None` if the
+    /// frame is not compressed; a payload that fails to inflate is returned as is.
+    fn inflate_frame(&mut self, header: &FrameHeader, payload: &[u8]) -> Option<Vec<u8>> {
+        let compressed = match header.opcode {
+            WebSocketOpcode::Text | WebSocketOpcode::Binary => header.rsv1,
+            WebSocketOpcode::Continuation => self.in_compressed_message,
+            // Control frames are never compressed
+            _ => false,
+        };
+        if !compressed {
+            return None;
+        }
+        self.in_compressed_message = !header.fin;
+
+        let mut inflated = Vec::new();
+        let mut result = self.feed(payload, &mut inflated);
+        if result.is_ok() && header.fin {
+            // The sender strips this empty stored block from the end of each message
+            result = self.feed(&[0x00, 0x00, 0xff, 0xff], &mut inflated);
+        }
+        if let Err(err) = result {
+            tracing::debug!("WebSocket: could not inflate compressed frame: {}", err);
+            self.decompress.reset(false);
+            self.in_compressed_message = false;
+            return Some(payload[..payload.len().min(MAX_CAPTURE_SIZE)].to_vec());
+        }
+        if header.fin && self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Some(inflated)
+    }
+
+    /// Run `input` through the decompressor, keeping output up to the capture
+    /// limit. All input is consumed regardless so the window stays in sync.
+    fn feed(&mut self, mut input: &[u8], out: &mut Vec<u8>) -> Result<(), flate2::DecompressError> {
+        let mut chunk = vec![0u8; 32 * 1024];
+        loop {
+            let (in_before, out_before) = (self.decompress.total_in(), self.decompress.total_out());
+            let status = self
+                .decompress
+                .decompress(input, &mut chunk, FlushDecompress::Sync)?;
+            let consumed = (self.decompress.total_in() - in_before) as usize;
+            let produced = (self.decompress.total_out() - out_before) as usize;
+            input = &input[consumed..];
+            let keep = produced.min(MAX_CAPTURE_SIZE.saturating_sub(out.len()));
+            out.extend_from_slice(&chunk[..keep]);
+
+            if status == Status::StreamEnd {
+                // A final deflate block ends the stream; the next message starts afresh.
+                self.decompress.reset(false);
+                return Ok(());
+            }
+            let stalled = consumed == 0 && produced == 0;
+            if stalled || (input.is_empty() && produced < chunk.len()) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Try to extract a complete WebSocket frame and create a message, inflating
+/// the payload with `inflater` when the connection negotiated compression.
 /// Returns (message, bytes_consumed) or None if not enough data
 pub fn extract_message(
     data: &[u8],
     connection_id: &str,
     direction: MessageDirection,
+    inflater: Option<&mut MessageInflater>,
 ) -> Option<(WebSocketMessage, usize)> {
     let header = parse_frame_header(data)?;
 
@@ -114,20 +446,124 @@ pub fn extract_message(
         unmask_payload(&mut payload, mask_key);
     }
 
+    let inflated = inflater.and_then(|inflater| inflater.inflate_frame(&header, &payload));
+    let compressed = inflated.is_some();
+
     // Truncate large payloads for storage
-    let captured_payload = if payload.len() > MAX_CAPTURE_SIZE {
-        payload[..MAX_CAPTURE_SIZE].to_vec()
-    } else {
-        payload
+    let captured_payload = match inflated {
+        Some(inflated) => inflated,
+        None if payload.len() > MAX_CAPTURE_SIZE => payload[..MAX_CAPTURE_SIZE].to_vec(),
+        None => payload,
     };
 
-    let msg = WebSocketMessage::new(
+    let mut msg = WebSocketMessage::new(
         connection_id.to_string(),
         direction,
         header.opcode,
         captured_payload,
         header.fin,
     );
+    msg.compressed = compressed;
+    if header.opcode == WebSocketOpcode::Binary {
+        msg.decoded_view = binary_view(&msg.payload);
+    }
 
     Some((msg, total_len))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compress, Compression, FlushCompress};
+
+    /// Compress a message as a permessage-deflate sender does: sync flush,
+    /// then drop the trailing empty block.
+    fn deflate_message(compress: &mut Compress, message: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(message.len() + 64);
+        compress
+            .compress_vec(message, &mut out, FlushCompress::Sync)
+            .unwrap();
+        assert!(out.ends_with(&[0x00, 0x00, 0xff, 0xff]));
+        out.truncate(out.len() - 4);
+        out
+    }
+
+    fn frame(first_byte: u8, payload: &[u8], mask_key: Option<[u8; 4]>) -> Vec<u8> {
+        assert!(payload.len() < 126);
+        let mut frame = vec![first_byte, payload.len() as u8];
+        let mut payload = payload.to_vec();
+        if let Some(key) = mask_key {
+            frame[1] |= 0x80;
+            frame.extend_from_slice(&key);
+            unmask_payload(&mut payload, key);
+        }
+        frame.extend_from_slice(&payload);
+        frame
+    }
+
+    #[test]
+    fn inflates_compressed_messages_across_context_and_fragments() {
+        let deflate = PerMessageDeflate::from_response_header(
+            "x-other, permessage-deflate; client_no_context_takeover; server_max_window_bits=10",
+        )
+        .unwrap();
+        assert!(deflate.client_no_context_takeover);
+        assert!(!deflate.server_no_context_takeover);
+        assert_eq!(
+            PerMessageDeflate::from_response_header("x-webkit-deflate"),
+            None
+        );
+
+        // Server messages share one window: the second refers back to the first.
+        let mut server = Compress::new(Compression::default(), false);
+        let text = br#"{"event":"price","symbol":"CHDR","value":42}"#;
+        let mut inflater = MessageInflater::new(deflate.server_no_context_takeover);
+        for _ in 0..2 {
+            let wire = frame(0xC1, &deflate_message(&mut server, text), None);
+            let (msg, consumed) = extract_message(
+                &wire,
+                "conn",
+                MessageDirection::ServerToClient,
+                Some(&mut inflater),
+            )
+            .unwrap();
+            assert_eq!(consumed, wire.len());
+            assert!(msg.compressed);
+            assert_eq!(msg.payload, text);
+        }
+
+        // A fragmented, masked client message; only its first frame has RSV1.
+        let mut client = Compress::new(Compression::default(), false);
+        let compressed = deflate_message(&mut client, b"hello hello hello");
+        let (head, tail) = compressed.split_at(compressed.len() / 2);
+        let mut inflater = MessageInflater::new(deflate.client_no_context_takeover);
+        let mut inflated = Vec::new();
+        for wire in [
+            frame(0x42, head, Some([1, 2, 3, 4])),
+            frame(0x80, tail, Some([5, 6, 7, 8])),
+        ] {
+            let (msg, _) = extract_message(
+                &wire,
+                "conn",
+                MessageDirection::ClientToServer,
+                Some(&mut inflater),
+            )
+            .unwrap();
+            assert!(msg.compressed);
+            inflated.extend_from_slice(&msg.payload);
+        }
+        assert_eq!(inflated, b"hello hello hello");
+
+        // Control frames pass through as is.
+        let ping = frame(0x89, b"hi", None);
+        let (msg, _) = extract_message(
+            &ping,
+            "conn",
+            MessageDirection::ServerToClient,
+            Some(&mut inflater),
+        )
+        .unwrap();
+        assert!(!msg.compressed);
+        assert_eq!(msg.payload, b"hi");
+    }
+}
@@ -0,0 +1,494 @@
+//! Parse a pasted curl command into a direct request
+//!
+//! Covers what API docs and browser "Copy as cURL" produce: POSIX shell quoting
+//! (including `$'...'`), backslash-newline continuations, and the common
+//! request-shaping options. Options that only affect how curl itself behaves
+//! (`-s`, `-L`, `--compressed`, ...) are accepted and ignored; anything else is
+//! rejected rather than guessed at. Our own export's
+//! `printf '%s' '<base64>' | base64 --decode | curl ... --data-binary @-`
+//! form is understood too.
+
+use super::DirectRequestParams;
+use base64::Engine;
+use std::collections::HashMap;
+
+/// Options without an argument that don't change the request
+const IGNORED_FLAGS: &[&str] = &[
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-L",
+    "--location",
+    "-k",
+    "--insecure",
+    "-v",
+    "--verbose",
+    "-i",
+    "--include",
+    "-g",
+    "--globoff",
+    "-f",
+    "--fail",
+    "--compressed",
+    "--http1.1",
+    "--http2",
+    "--no-buffer",
+    "-N",
+];
+
+/// Options whose argument doesn't change the request
+const IGNORED_WITH_ARG: &[&str] = &[
+    "-o",
+    "--output",
+    "--connect-timeout",
+    "--retry",
+    "-w",
+    "--write-out",
+];
+
+/// One shell word; bytes rather than text, since `$'\xNN'` can spell any byte
+type Word = Vec<u8>;
+
+/// Build a [`DirectRequestParams`] from a curl command line.
+pub fn parse_curl_command(command: &str) -> Result<DirectRequestParams, String> {
+    let words = split_words(command)?;
+    let (stdin, words) = split_stdin_pipeline(&words)?;
+    let mut args = words.iter();
+    match args.next().map(Vec::as_slice) {
+        Some(b"curl") => {}
+        _ => return Err("Not a curl command".to_string()),
+    }
+
+    let mut url = None;
+    let mut method: Option<String> = None;
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut data: Vec<Vec<u8>> = Vec::new();
+    let mut form_data = false;
+    let mut get = false;
+    let mut head = false;
+    let mut timeout_ms = None;
+
+    while let Some(raw_arg) = args.next() {
+        let arg = String::from_utf8_lossy(raw_arg);
+        if !arg.starts_with('-') {
+            if url.replace(arg.into_owned()).is_some() {
+                return Err("curl command has more than one URL".to_string());
+            }
+            continue;
+        }
+        if IGNORED_FLAGS.contains(&arg.as_ref()) || is_ignored_short_cluster(&arg) {
+            continue;
+        }
+        // Short options may carry their value attached: -XPOST, -H'Accept: */*'
+        let (option, attached) = match arg.strip_prefix("--") {
+            Some(_) => (arg.as_ref(), None),
+            None if raw_arg.len() > 2 && raw_arg[1].is_ascii() => {
+                (&arg[..2], Some(raw_arg[2..].to_vec()))
+            }
+            None => (arg.as_ref(), None),
+        };
+        let mut value = || {
+            attached
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("curl option {} needs a value", option))
+        };
+        match option {
+            "-X" | "--request" => method = Some(text(value()?).to_uppercase()),
+            "-H" | "--header" => {
+                let header = text(value()?);
+                let (name, value) = header
+                    .split_once(':')
+                    .ok_or_else(|| format!("Invalid header '{}'", header))?;
+                // `-H 'Name:'` tells curl to drop the header; there is nothing to drop.
+                if !value.trim().is_empty() {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            "-d" | "--data" | "--data-ascii" => {
+                form_data = true;
+                // curl strips newlines from plain --data
+                let mut value = value()?;
+                value.retain(|b| !matches!(b, b'\r' | b'\n'));
+                data.push(data_argument(&value, stdin.as_deref())?);
+            }
+            "--data-raw" => {
+                form_data = true;
+                data.push(value()?);
+            }
+            "--data-binary" => data.push(data_argument(&value()?, stdin.as_deref())?),
+            "--data-urlencode" => {
+                form_data = true;
+                data.push(urlencode_argument(&value()?).into_bytes());
+            }
+            "-u" | "--user" => {
+                let credentials = base64::engine::general_purpose::STANDARD.encode(value()?);
+                headers.push((
+                    "Authorization".to_string(),
+                    format!("Basic {}", credentials),
+                ));
+            }
+            "-A" | "--user-agent" => headers.push(("User-Agent".to_string(), text(value()?))),
+            "-e" | "--referer" => headers.push(("Referer".to_string(), text(value()?))),
+            "-b" | "--cookie" => {
+                let cookie = text(value()?);
+                if !cookie.contains('=') {
+                    return Err("Reading cookies from a file (-b <file>) is not supported".into());
+                }
+                headers.push(("Cookie".to_string(), cookie));
+            }
+            "-m" | "--max-time" => {
+                let seconds = text(value()?);
+                let seconds: f64 = seconds
+                    .parse()
+                    .map_err(|_| format!("Invalid --max-time '{}'", seconds))?;
+                timeout_ms = Some((seconds * 1000.0).round() as u64);
+            }
+            "-G" | "--get" => get = true,
+            "-I" | "--head" => head = true,
+            "--url" => {
+                if url.replace(text(value()?)).is_some() {
+                    return Err("curl command has more than one URL".to_string());
+                }
+            }
+            _ if IGNORED_WITH_ARG.contains(&option) => {
+                value()?;
+            }
+            _ => return Err(format!("Unsupported curl option {}", arg)),
+        }
+    }
+
+    let mut url = url.ok_or("curl command has no URL")?;
+    if !url.contains("://") {
+        // curl's default scheme
+        url = format!("http://{}", url);
+    }
+    let body = (!data.is_empty()).then(|| data.join(&b'&'));
+    let (body, method) = if get || head {
+        if let Some(query) = &body {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&String::from_utf8_lossy(query));
+        }
+        (
+            None,
+            method.unwrap_or_else(|| if head { "HEAD" } else { "GET" }.into()),
+        )
+    } else {
+        let method = method.unwrap_or_else(|| if body.is_some() { "POST" } else { "GET" }.into());
+        (body, method)
+    };
+    if form_data
+        && body.is_some()
+        && !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+    {
+        headers.push((
+            "Content-Type".to_string(),
+            "application/x-www-form-urlencoded".to_string(),
+        ));
+    }
+
+    Ok(DirectRequestParams {
+        url,
+        method,
+        headers: merge_headers(headers),
+        body,
+        capture_wire: false,
+        timeout_ms,
+    })
+}
+
+/// A word used as text; bytes that aren't UTF-8 (from `$'\xNN'`) are replaced.
+fn text(word: Word) -> String {
+    String::from_utf8(word).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// `-sSL` and the like: every letter is an ignored short flag.
+fn is_ignored_short_cluster(arg: &str) -> bool {
+    arg.len() > 2
+        && !arg.starts_with("--")
+        && arg[1..]
+            .chars()
+            .all(|c| IGNORED_FLAGS.contains(&format!("-{}", c).as_str()))
+}
+
+/// Repeated headers folded into one value, as a proxy would see them combined
+fn merge_headers(headers: Vec<(String, String)>) -> HashMap<String, String> {
+    let mut merged: HashMap<String, String> = HashMap::new();
+    for (name, value) in headers {
+        let existing = merged
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&name));
+        match existing {
+            Some((key, combined)) => {
+                let separator = if key.eq_ignore_ascii_case("cookie") {
+                    "; "
+                } else {
+                    ", "
+                };
+                combined.push_str(separator);
+                combined.push_str(&value);
+            }
+            None => {
+                merged.insert(name, value);
+            }
+        }
+    }
+    merged
+}
+
+/// A `--data` value; `@-` reads what was piped in, other `@file` forms are refused.
+fn data_argument(value: &[u8], stdin: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    match value.strip_prefix(b"@") {
+        Some(b"-") => stdin
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| "curl reads the body from stdin, which is not available".to_string()),
+        Some(file) => Err(format!(
+            "Reading the body from a file ({}) is not supported",
+            String::from_utf8_lossy(file)
+        )),
+        None => Ok(value.to_vec()),
+    }
+}
+
+/// `--data-urlencode` forms: `content`, `=content`, `name=content`
+fn urlencode_argument(value: &[u8]) -> String {
+    let encode = |s: &[u8]| {
+        s.iter()
+            .map(|&b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                    char::from(b).to_string()
+                }
+                b' ' => "+".to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect::<String>()
+    };
+    match value.iter().position(|&b| b == b'=') {
+        Some(0) => encode(&value[1..]),
+        Some(eq) => format!(
+            "{}={}",
+            String::from_utf8_lossy(&value[..eq]),
+            encode(&value[eq + 1..])
+        ),
+        None => encode(value),
+    }
+}
+
+/// Pull the body out of a leading `printf '%s' <b64> | base64 --decode |`,
+/// returning it with the words of the curl command itself.
+fn split_stdin_pipeline(words: &[Word]) -> Result<(Option<Vec<u8>>, &[Word]), String> {
+    let stages: Vec<&[Word]> = words.split(|w| w == b"|").collect();
+    match stages.as_slice() {
+        [curl] => Ok((None, curl)),
+        [[printf, format, encoded], [base64, decode], curl]
+            if printf == b"printf"
+                && format == b"%s"
+                && base64 == b"base64"
+                && (decode == b"--decode" || decode == b"-d") =>
+        {
+            let body = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|e| format!("Invalid base64 body: {}", e))?;
+            Ok((Some(body), curl))
+        }
+        _ => Err("Only a single curl command can be imported".to_string()),
+    }
+}
+
+/// Split a command line into words the way a POSIX shell would (no expansion).
+/// An unquoted `|` is its own word and an unquoted `#` starting a word comments
+/// out the rest of the line.
+fn split_words(command: &str) -> Result<Vec<Word>, String> {
+    let mut words = Vec::new();
+    let mut word = Vec::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                // Line continuation
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(escaped) => {
+                    push_char(&mut word, escaped);
+                    in_word = true;
+                }
+                None => return Err("Command ends with a backslash".to_string()),
+            },
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => push_char(&mut word, c),
+                        None => return Err("Unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c @ ('"' | '\\' | '$' | '`')) => push_char(&mut word, c),
+                            Some(c) => {
+                                word.push(b'\\');
+                                push_char(&mut word, c);
+                            }
+                            None => return Err("Unterminated double quote".to_string()),
+                        },
+                        Some(c) => push_char(&mut word, c),
+                        None => return Err("Unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                in_word = true;
+                ansi_c_quoted(&mut chars, &mut word)?;
+            }
+            '#' if !in_word => while chars.next_if(|&c| c != '\n').is_some() {},
+            '|' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                words.push(b"|".to_vec());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                push_char(&mut word, c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn push_char(word: &mut Vec<u8>, c: char) {
+    word.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+/// The body of a `$'...'` string, as written by browsers' "Copy as cURL (bash)"
+fn ansi_c_quoted(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    word: &mut Vec<u8>,
+) -> Result<(), String> {
+    loop {
+        match chars.next() {
+            Some('\'') => return Ok(()),
+            Some('\\') => match chars.next() {
+                Some('n') => word.push(b'\n'),
+                Some('r') => word.push(b'\r'),
+                Some('t') => word.push(b'\t'),
+                Some('x') => {
+                    let hex: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_hexdigit))
+                        .take(2)
+                        .collect();
+                    let byte = u8::from_str_radix(&hex, 16)
+                        .map_err(|_| "Invalid \\x escape".to_string())?;
+                    // A raw byte, as bash emits it, not the code point of that value
+                    word.push(byte);
+                }
+                Some('u') => {
+                    let hex: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_hexdigit))
+                        .take(4)
+                        .collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| "Invalid \\u escape".to_string())?;
+                    push_char(word, c);
+                }
+                Some(c) => push_char(word, c),
+                None => break,
+            },
+            Some(c) => push_char(word, c),
+            None => break,
+        }
+    }
+    Err("Unterminated $'...' quote".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoting_continuations_and_repeated_headers() {
+        let command = r#"curl 'https://api.example.com/v1/items?limit=2' \
+  -X PUT \
+  -H 'Accept: application/json' \
+  -H "X-Trace: \"abc\"" \
+  -H $'Cookie: a=1' -H 'cookie: b=2' \
+  -u admin:s3cret \
+  --data-binary '{"name":"it'\''s"}' \
+  --compressed -sS"#;
+        let params = parse_curl_command(command).unwrap();
+        assert_eq!(params.url, "https://api.example.com/v1/items?limit=2");
+        assert_eq!(params.method, "PUT");
+        assert_eq!(params.headers["Accept"], "application/json");
+        assert_eq!(params.headers["X-Trace"], "\"abc\"");
+        assert_eq!(params.headers["Cookie"], "a=1; b=2");
+        assert_eq!(params.headers["Authorization"], "Basic YWRtaW46czNjcmV0");
+        assert!(!params.headers.contains_key("Content-Type"));
+        assert_eq!(
+            params.body.as_deref(),
+            Some(br#"{"name":"it's"}"#.as_slice())
+        );
+    }
+
+    #[test]
+    fn data_implies_post_or_query_and_pipes_are_decoded() {
+        let params = parse_curl_command("curl example.com/search -d q=rust -d page=2").unwrap();
+        assert_eq!(params.url, "http://example.com/search");
+        assert_eq!(params.method, "POST");
+        assert_eq!(params.body.as_deref(), Some(b"q=rust&page=2".as_slice()));
+        assert_eq!(
+            params.headers["Content-Type"],
+            "application/x-www-form-urlencoded"
+        );
+
+        let params =
+            parse_curl_command("curl -G https://a.test/s?x=1 --data-urlencode 'q=a b'").unwrap();
+        assert_eq!(params.url, "https://a.test/s?x=1&q=a+b");
+        assert_eq!((params.method.as_str(), params.body), ("GET", None));
+
+        let params = parse_curl_command(
+            "printf '%s' 'AAEC' | base64 --decode | curl -X POST 'https://a.test/up' --data-binary @-",
+        )
+        .unwrap();
+        assert_eq!(params.body.as_deref(), Some([0u8, 1, 2].as_slice()));
+
+        assert!(parse_curl_command("curl https://a.test -d @body.json").is_err());
+        assert!(parse_curl_command("curl --proxy-negotiate https://a.test").is_err());
+        assert!(parse_curl_command("wget https://a.test").is_err());
+    }
+
+    #[test]
+    fn comments_are_skipped_and_hex_escapes_are_raw_bytes() {
+        let command = "# copied from the docs\ncurl https://a.test/up#frag \\\n  --data-binary $'\\xff\\x00\\u00e9' # trailing note";
+        let params = parse_curl_command(command).unwrap();
+        assert_eq!(params.url, "https://a.test/up#frag");
+        assert_eq!(
+            params.body.as_deref(),
+            Some([0xff, 0x00, 0xc3, 0xa9].as_slice())
+        );
+    }
+}
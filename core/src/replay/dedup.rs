@@ -0,0 +1,78 @@
+//! Replay deduplication
+//!
+//! Scheduled replays and load tests can produce thousands of identical
+//! exchanges. With a dedup window, a replay whose request and response match a
+//! recent replay is counted on that transaction (`occurrence_count`) instead of
+//! being stored as a new one. Response headers are left out of the comparison,
+//! since `Date` and similar headers differ on every response.
+
+use crate::models::HttpTransaction;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Most distinct replays remembered for matching
+const MAX_TRACKED: usize = 256;
+
+struct Recent {
+    fingerprint: String,
+    transaction_id: String,
+    seen_at: Instant,
+}
+
+static RECENT: Lazy<Mutex<VecDeque<Recent>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// Hash of everything that makes two replays identical: method, URL, request
+/// headers and body, response status and body.
+pub(super) fn fingerprint(tx: &HttpTransaction) -> String {
+    let mut hasher = Sha256::new();
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    field(tx.method.to_string().as_bytes());
+    field(tx.full_url().as_bytes());
+    let mut headers: Vec<_> = tx
+        .request_headers
+        .iter()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v))
+        .collect();
+    headers.sort();
+    for (name, value) in headers {
+        field(name.as_bytes());
+        field(value.as_bytes());
+    }
+    field(tx.request_body.as_deref().unwrap_or_default());
+    field(&tx.status_code.unwrap_or_default().to_le_bytes());
+    field(tx.response_body.as_deref().unwrap_or_default());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Id of a replay with the same fingerprint seen within `window`. A match
+/// restarts its window, so a steady stream of repeats keeps collapsing.
+pub(super) fn recent_match(fingerprint: &str, window: Duration) -> Option<String> {
+    let mut recent = RECENT.lock().ok()?;
+    let entry = recent
+        .iter_mut()
+        .find(|r| r.fingerprint == fingerprint && r.seen_at.elapsed() <= window)?;
+    entry.seen_at = Instant::now();
+    Some(entry.transaction_id.clone())
+}
+
+/// Remember a stored replay so later identical ones can be collapsed into it
+pub(super) fn remember(fingerprint: String, transaction_id: String) {
+    let Ok(mut recent) = RECENT.lock() else {
+        return;
+    };
+    recent.retain(|r| r.fingerprint != fingerprint);
+    recent.push_back(Recent {
+        fingerprint,
+        transaction_id,
+        seen_at: Instant::now(),
+    });
+    while recent.len() > MAX_TRACKED {
+        recent.pop_front();
+    }
+}
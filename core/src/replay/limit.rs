@@ -0,0 +1,54 @@
+//! Cap on concurrent outbound replays
+//!
+//! Every replay opens its own client and connection, so an unbounded burst
+//! (a load test, a script calling the API in a loop) can exhaust file
+//! descriptors. Replays beyond the cap wait in a queue for a free slot and
+//! fail if none frees up within the queue timeout.
+
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Replays allowed in flight at once unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_REPLAYS: usize = 100;
+/// How long a queued replay waits for a slot unless configured otherwise
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Limiter {
+    slots: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+static LIMITER: Lazy<RwLock<Limiter>> = Lazy::new(|| {
+    RwLock::new(Limiter {
+        slots: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_REPLAYS)),
+        queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+    })
+});
+
+/// Replace the cap and queue timeout. Replays already running or queued keep
+/// the slots of the previous limit.
+pub fn set_limits(max_concurrent: usize, queue_timeout: Duration) {
+    let mut limiter = LIMITER.write().unwrap_or_else(|e| e.into_inner());
+    *limiter = Limiter {
+        slots: Arc::new(Semaphore::new(max_concurrent.max(1))),
+        queue_timeout,
+    };
+}
+
+/// Wait for a replay slot; it is released when the permit is dropped.
+pub(super) async fn acquire() -> Result<OwnedSemaphorePermit, String> {
+    let (slots, queue_timeout) = {
+        let limiter = LIMITER.read().unwrap_or_else(|e| e.into_inner());
+        (limiter.slots.clone(), limiter.queue_timeout)
+    };
+    match tokio::time::timeout(queue_timeout, slots.acquire_owned()).await {
+        Ok(Ok(permit)) => Ok(permit),
+        Ok(Err(_)) => Err("Replay limiter closed".to_string()),
+        Err(_) => Err(format!(
+            "Too many concurrent replays: no slot freed up within {}s",
+            queue_timeout.as_secs_f32()
+        )),
+    }
+}
@@ -4,18 +4,60 @@
 //! previously captured by the proxy.
 
 use crate::api::proxy_api::{get_proxy_status, send_transaction_to_sink};
-use crate::models::{HttpMethod, HttpTransaction, TransactionState, TransactionTiming};
+use crate::models::{
+    HttpMethod, HttpTransaction, PaginationInfo, PaginationStyle, ReplayEnvironment,
+    TransactionState, TransactionTiming,
+};
+use crate::proxy::content_coding::ContentCoding;
 use crate::storage::{get_transaction_by_id, persist_transaction};
+use schemars::JsonSchema;
+use serde::Deserialize;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
+use wire::WireCapture;
+
+mod curl;
+mod dedup;
+mod limit;
+mod ordered;
+mod wire;
+
+pub use curl::parse_curl_command;
+pub use limit::{set_limits, DEFAULT_MAX_CONCURRENT_REPLAYS, DEFAULT_QUEUE_TIMEOUT};
+
+/// Longest pause inserted between two requests of a replayed sequence
+pub const MAX_SEQUENCE_GAP: Duration = Duration::from_secs(30);
+
+/// Most pages [`replay_all_pages`] fetches when no limit is given
+pub const DEFAULT_MAX_PAGES: usize = 20;
+
+/// Upper bound on the pages a single [`replay_all_pages`] call may fetch
+pub const MAX_PAGES: usize = 500;
+
+/// Redirects followed before a same-host-only replay gives up, as reqwest does
+const MAX_REDIRECTS: usize = 10;
+
+/// How a replay reconciles a `Content-Encoding` request header with a body
+/// that is not actually encoded (e.g. a decoded capture or a plain override).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayContentEncoding {
+    /// Compress the body to match the declared encoding
+    #[default]
+    Recompress,
+    /// Drop the `Content-Encoding` header and send the body plain
+    Strip,
+}
 
 /// Parameters for replaying a request
 #[derive(Debug, Clone, Default)]
 pub struct ReplayParams {
     /// Override the HTTP method
     pub method: Option<HttpMethod>,
-    /// Override the request path
+    /// Override the request path. Without it a capture stored with a redacted
+    /// query string is replayed with that redacted query.
     pub path: Option<String>,
     /// Override specific headers (merged with original)
     pub headers: Option<HashMap<String, String>>,
@@ -23,6 +65,35 @@ pub struct ReplayParams {
     pub body: Option<Vec<u8>>,
     /// Allow invalid TLS certificates (defaults to false)
     pub accept_invalid_certs: bool,
+    /// How to handle a plain body sent under a `Content-Encoding` header
+    pub content_encoding: ReplayContentEncoding,
+    /// Record the raw bytes exchanged with the upstream to a file. The request
+    /// then goes directly to the upstream instead of through the running proxy.
+    pub capture_wire: bool,
+    /// Drop conditional/caching request headers and send `Cache-Control:
+    /// no-cache`, so the server returns a full response instead of a 304
+    pub bypass_cache: bool,
+    /// Collapse a replay whose request and response match a replay made within
+    /// this window into that transaction's `occurrence_count`
+    pub dedup_window: Option<Duration>,
+    /// Send to this origin instead of the captured one. A path in the URL is
+    /// prefixed to the request path (see [`ReplayEnvironment::base_url`]).
+    /// The captured headers, credentials included, go along unless
+    /// `drop_credentials` is set or `headers` replaces them.
+    pub base_url: Option<String>,
+    /// Don't send the captured `Cookie`, `Authorization` and
+    /// `Proxy-Authorization` headers; ones given in `headers` are still sent
+    pub drop_credentials: bool,
+    /// Send the captured headers in their original order and casing, with
+    /// overrides applied in place. Needs a capture that recorded the order.
+    pub preserve_header_order: bool,
+    /// Give up on the exchange, body included, after this many milliseconds
+    /// and record the replay as failed (None = wait indefinitely)
+    pub timeout_ms: Option<u64>,
+    /// Only follow redirects that stay on the original host. A redirect to
+    /// another host is not followed, so credentials cannot leak to it; the
+    /// redirect response is recorded and its target reported instead.
+    pub redirect_same_host_only: bool,
 }
 
 /// Result of a replay operation
@@ -36,6 +107,10 @@ pub struct ReplayResult {
     pub success: bool,
     /// Error message if replay failed
     pub error: Option<String>,
+    /// File holding the raw upstream bytes, when wire capture was requested
+    pub wire_capture_path: Option<String>,
+    /// Target of a redirect (or next page) on another origin that was not followed
+    pub blocked_redirect: Option<String>,
 }
 
 /// Replay a previously captured HTTP request
@@ -56,6 +131,15 @@ pub async fn replay_request(
         headers: header_overrides,
         body,
         accept_invalid_certs,
+        content_encoding,
+        capture_wire,
+        bypass_cache,
+        dedup_window,
+        base_url,
+        preserve_header_order,
+        timeout_ms,
+        redirect_same_host_only,
+        drop_credentials,
     } = params;
 
     // Get the original transaction
@@ -63,26 +147,49 @@ pub async fn replay_request(
         .await
         .map_err(|e| format!("Failed to get transaction: {}", e))?
         .ok_or_else(|| format!("Transaction not found: {}", transaction_id))?;
+    if preserve_header_order && original.request_header_list.is_empty() {
+        return Err(format!(
+            "Transaction {} has no recorded header order to preserve",
+            transaction_id
+        ));
+    }
 
-    // Build the request URL
-    let scheme = &original.scheme;
-    let host = &original.host;
-    let port = original.port;
+    // Build the request URL. A redacted capture only has the masked query.
+    let sends_redacted_query = original.query_redacted && path.is_none();
+    if sends_redacted_query {
+        tracing::warn!(
+            "Replaying {} with the redacted query string it was stored with",
+            transaction_id
+        );
+    }
     let path = path.as_deref().unwrap_or(&original.path);
-
-    let url = if (scheme == "https" && port == 443) || (scheme == "http" && port == 80) {
-        format!("{}://{}{}", scheme, host, path)
-    } else {
-        format!("{}://{}:{}{}", scheme, host, port, path)
+    let (scheme, host, port, path) = match base_url.as_deref() {
+        Some(base_url) => rebase(base_url, path)?,
+        None => (
+            original.scheme.clone(),
+            original.host.clone(),
+            original.port,
+            path.to_string(),
+        ),
     };
 
+    let url = format!("{}://{}{}", scheme, host_header(&scheme, &host, port), path);
+
     // Determine method
     let method = method.unwrap_or(original.method);
 
     // Build headers - start with original, then apply overrides
     let mut headers = original.request_headers.clone();
+    if drop_credentials {
+        headers.retain(|name, _| {
+            !["cookie", "authorization", "proxy-authorization"]
+                .iter()
+                .any(|credential| name.eq_ignore_ascii_case(credential))
+        });
+    }
     if let Some(override_headers) = header_overrides {
         for (k, v) in override_headers {
+            headers.retain(|name, _| !name.eq_ignore_ascii_case(&k));
             headers.insert(k, v);
         }
     }
@@ -94,9 +201,13 @@ pub async fn replay_request(
     headers.remove("Content-Length");
     headers.remove("transfer-encoding");
     headers.remove("Transfer-Encoding");
+    if bypass_cache {
+        strip_cache_validators(&mut headers);
+    }
 
     // Determine body
     let body = body.or_else(|| original.request_body.clone());
+    let body = reconcile_content_encoding(&mut headers, body, content_encoding)?;
 
     // Create a new transaction for tracking
     let new_id = Uuid::new_v4().to_string();
@@ -105,95 +216,88 @@ pub async fn replay_request(
         .unwrap()
         .as_millis() as i64;
 
-    let mut new_tx = HttpTransaction::new(method, scheme, host, port, path, headers.clone());
+    let ordered_headers = preserve_header_order.then(|| {
+        ordered_header_list(
+            &original,
+            &headers,
+            &host_header(&scheme, &host, port),
+            body.as_deref(),
+        )
+    });
+
+    let mut new_tx = HttpTransaction::new(method, &scheme, &host, port, &path, headers.clone());
+    if let Some(list) = &ordered_headers {
+        new_tx.request_header_list = list.clone();
+    }
     new_tx.id = new_id.clone();
     new_tx.timing.start_time = start_time;
     new_tx.request_body = body.clone();
-    new_tx.notes = Some(format!("Replayed from {}", transaction_id));
+    new_tx.notes = Some(if sends_redacted_query {
+        format!(
+            "Replayed from {} with its redacted query string; pass a path to send the original",
+            transaction_id
+        )
+    } else {
+        format!("Replayed from {}", transaction_id)
+    });
 
-    // Send initial state to UI
-    send_transaction_to_sink(new_tx.clone());
+    // Wait for a slot before the replay shows up, so a queue timeout does not
+    // leave a pending transaction behind. Released on return.
+    let _slot = limit::acquire().await?;
+
+    // Send initial state to UI, unless the replay may turn out to be a duplicate
+    if dedup_window.is_none() {
+        send_transaction_to_sink(new_tx.clone());
+    }
 
     // Make the HTTP request
     let request_start = Instant::now();
-    let mut client_builder = reqwest::Client::builder();
-    // Route through our proxy if running so timing is captured consistently.
-    if let Ok(status) = std::panic::catch_unwind(get_proxy_status) {
-        if status.is_running {
-            let proxy_url = format!("http://127.0.0.1:{}", status.port);
-            if let Ok(proxy) = reqwest::Proxy::all(&proxy_url) {
-                // Route through our proxy; disable env/system proxies to avoid loops.
-                client_builder = client_builder.no_proxy().proxy(proxy);
-                // Trust our MITM cert for replay traffic (reqwest doesn't use OS trust on macOS/Windows).
-                client_builder = client_builder.danger_accept_invalid_certs(true);
-            }
+    let wire_capture = start_wire_capture(capture_wire, &mut new_tx).await?;
+    let exchange = match &ordered_headers {
+        Some(list) => {
+            let request = ordered::OrderedRequest {
+                method: &method.to_string(),
+                scheme: &scheme,
+                host: &host,
+                port,
+                path: &path,
+                headers: list,
+                body: body.as_deref().unwrap_or_default(),
+            };
+            within_timeout(
+                timeout_ms,
+                send_ordered(&request, wire_capture.as_ref(), accept_invalid_certs),
+            )
+            .await
         }
-    }
-    if accept_invalid_certs {
-        client_builder = client_builder.danger_accept_invalid_certs(true);
-    }
-    let client = client_builder
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-    let reqwest_method = match method {
-        HttpMethod::Get => reqwest::Method::GET,
-        HttpMethod::Post => reqwest::Method::POST,
-        HttpMethod::Put => reqwest::Method::PUT,
-        HttpMethod::Patch => reqwest::Method::PATCH,
-        HttpMethod::Delete => reqwest::Method::DELETE,
-        HttpMethod::Head => reqwest::Method::HEAD,
-        HttpMethod::Options => reqwest::Method::OPTIONS,
-        HttpMethod::Connect => reqwest::Method::CONNECT,
-        HttpMethod::Trace => reqwest::Method::TRACE,
-    };
-
-    let mut request_builder = client.request(reqwest_method, &url);
-
-    // Add headers
-    for (key, value) in &headers {
-        if let Ok(header_name) = reqwest::header::HeaderName::try_from(key.as_str()) {
-            if let Ok(header_value) = reqwest::header::HeaderValue::from_str(value) {
-                request_builder = request_builder.header(header_name, header_value);
-            }
+        None => {
+            send_with_reqwest(
+                method,
+                &url,
+                &headers,
+                body,
+                wire_capture.as_ref(),
+                accept_invalid_certs,
+                timeout_ms,
+                redirect_same_host_only,
+            )
+            .await?
         }
-    }
-
-    // Add body if present
-    if let Some(body_bytes) = body {
-        request_builder = request_builder.body(body_bytes);
-    }
-
-    // Execute request
-    // Measure time until headers are received (TTFB)
-    let response_result = request_builder.send().await;
-    let ttfb = request_start.elapsed();
-    let ttfb_ms = ttfb.as_millis() as u32;
-
-    match response_result {
-        Ok(response) => {
-            let status = response.status().as_u16();
-            let status_text = response.status().canonical_reason().map(String::from);
-
-            // Collect response headers
-            let response_headers: HashMap<String, String> = response
-                .headers()
-                .iter()
-                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-                .collect();
+    };
 
+    match exchange {
+        Ok(Exchange {
+            status,
+            status_text,
+            response_headers,
+            response_body,
+            ttfb_ms,
+            download_ms,
+            blocked_redirect,
+        }) => {
             let content_type = response_headers.get("content-type").cloned();
-
-            // Get response body
-            // Measure download time
-            let download_start = Instant::now();
-            let response_body = response
-                .bytes()
-                .await
-                .map(|b| b.to_vec())
-                .unwrap_or_default();
-            let download_ms = download_start.elapsed().as_millis() as u32;
             let response_size = response_body.len() as u64;
+            let wire_capture_path = finish_wire_capture(wire_capture);
 
             // Total time is start to finish
             let total_ms = request_start.elapsed().as_millis() as u32;
@@ -206,6 +310,13 @@ pub async fn replay_request(
             new_tx.response_content_type = content_type;
             new_tx.response_size = Some(response_size);
             new_tx.state = TransactionState::Completed;
+            new_tx.refresh_pagination();
+            if let Some(location) = &blocked_redirect {
+                new_tx.notes = Some(format!(
+                    "Replayed from {}; redirect to another host not followed: {}",
+                    transaction_id, location
+                ));
+            }
 
             // Populate timing breakdown
             // waiting_ms = TTFB (includes DNS/TCP/TLS since we can't separate them with high-level reqwest)
@@ -217,6 +328,24 @@ pub async fn replay_request(
                 ..Default::default()
             };
 
+            if let Some(window) = dedup_window {
+                let fingerprint = dedup::fingerprint(&new_tx);
+                if let Some(mut existing) = recent_duplicate(&fingerprint, window).await {
+                    existing.occurrence_count += 1;
+                    send_transaction_to_sink(existing.clone());
+                    let _ = persist_transaction(existing.clone()).await;
+                    return Ok(ReplayResult {
+                        transaction_id: existing.id,
+                        status_code: Some(status),
+                        success: true,
+                        error: None,
+                        wire_capture_path,
+                        blocked_redirect,
+                    });
+                }
+                dedup::remember(fingerprint, new_id.clone());
+            }
+
             // Send updated state to UI
             send_transaction_to_sink(new_tx.clone());
 
@@ -228,16 +357,18 @@ pub async fn replay_request(
                 status_code: Some(status),
                 success: true,
                 error: None,
+                wire_capture_path,
+                blocked_redirect,
             })
         }
         Err(e) => {
-            // Update transaction with error
-            // If request failed, use ttfb as total duration (time until failure)
+            let wire_capture_path = finish_wire_capture(wire_capture);
+            // Update transaction with error; total is the time until failure
             new_tx.state = TransactionState::Failed;
-            new_tx.notes = Some(format!("Replay failed: {}", e));
+            new_tx.notes = Some(failure_note("Replay failed", &e, timeout_ms));
             new_tx.timing = TransactionTiming {
                 start_time,
-                total_ms: Some(ttfb_ms),
+                total_ms: Some(request_start.elapsed().as_millis() as u32),
                 ..Default::default()
             };
 
@@ -252,11 +383,593 @@ pub async fn replay_request(
                 status_code: None,
                 success: false,
                 error: Some(e.to_string()),
+                wire_capture_path,
+                blocked_redirect: None,
             })
         }
     }
 }
 
+/// A response received for a replay
+struct Exchange {
+    status: u16,
+    status_text: Option<String>,
+    response_headers: HashMap<String, String>,
+    response_body: Vec<u8>,
+    ttfb_ms: u32,
+    download_ms: u32,
+    /// Redirect target on another host that was not followed
+    blocked_redirect: Option<String>,
+}
+
+/// Failure message of a request that ran past its `timeout_ms`
+fn timed_out(timeout_ms: u64) -> String {
+    format!("timed out after {timeout_ms}ms")
+}
+
+/// Transaction note for a failed request: the timeout message as is, anything
+/// else as `prefix: error`
+fn failure_note(prefix: &str, error: &str, timeout_ms: Option<u64>) -> String {
+    if timeout_ms.is_some_and(|ms| error == timed_out(ms)) {
+        error.to_string()
+    } else {
+        format!("{prefix}: {error}")
+    }
+}
+
+/// Run `send`, failing it with [`timed_out`] once `timeout_ms` passes
+async fn within_timeout<T>(
+    timeout_ms: Option<u64>,
+    send: impl std::future::Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    match timeout_ms {
+        Some(ms) => tokio::time::timeout(Duration::from_millis(ms), send)
+            .await
+            .unwrap_or_else(|_| Err(timed_out(ms))),
+        None => send.await,
+    }
+}
+
+/// Send through reqwest, via the running proxy (or the wire capture relay).
+/// The outer error is a client setup failure, the inner one a failed request.
+#[allow(clippy::too_many_arguments)]
+async fn send_with_reqwest(
+    method: HttpMethod,
+    url: &str,
+    headers: &HashMap<String, String>,
+    body: Option<Vec<u8>>,
+    wire_capture: Option<&WireCapture>,
+    accept_invalid_certs: bool,
+    timeout_ms: Option<u64>,
+    redirect_same_host_only: bool,
+) -> Result<Result<Exchange, String>, String> {
+    let request_start = Instant::now();
+    let mut client_builder = reqwest::Client::builder();
+    if let Some(ms) = timeout_ms {
+        client_builder = client_builder.timeout(Duration::from_millis(ms));
+    }
+    let blocked_redirect = Arc::new(Mutex::new(None));
+    if redirect_same_host_only {
+        client_builder = client_builder.redirect(same_host_redirects(blocked_redirect.clone()));
+    }
+    if let Some(capture) = wire_capture {
+        // Connect through the recording relay rather than the proxy.
+        let proxy = reqwest::Proxy::all(capture.proxy_url())
+            .map_err(|e| format!("Failed to configure wire capture: {}", e))?;
+        client_builder = client_builder.no_proxy().proxy(proxy);
+    } else if let Some(port) = running_proxy_port() {
+        // Route through our proxy if running so timing is captured consistently.
+        let proxy_url = format!("http://127.0.0.1:{}", port);
+        if let Ok(proxy) = reqwest::Proxy::all(&proxy_url) {
+            // Route through our proxy; disable env/system proxies to avoid loops.
+            client_builder = client_builder.no_proxy().proxy(proxy);
+            // Trust our MITM cert for replay traffic (reqwest doesn't use OS trust on macOS/Windows).
+            client_builder = client_builder.danger_accept_invalid_certs(true);
+        }
+    }
+    if accept_invalid_certs {
+        client_builder = client_builder.danger_accept_invalid_certs(true);
+    }
+    let client = client_builder
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let reqwest_method = match method {
+        HttpMethod::Get => reqwest::Method::GET,
+        HttpMethod::Post => reqwest::Method::POST,
+        HttpMethod::Put => reqwest::Method::PUT,
+        HttpMethod::Patch => reqwest::Method::PATCH,
+        HttpMethod::Delete => reqwest::Method::DELETE,
+        HttpMethod::Head => reqwest::Method::HEAD,
+        HttpMethod::Options => reqwest::Method::OPTIONS,
+        HttpMethod::Connect => reqwest::Method::CONNECT,
+        HttpMethod::Trace => reqwest::Method::TRACE,
+    };
+
+    let mut request_builder = client.request(reqwest_method, url);
+
+    // Add headers
+    for (key, value) in headers {
+        if let Ok(header_name) = reqwest::header::HeaderName::try_from(key.as_str()) {
+            if let Ok(header_value) = reqwest::header::HeaderValue::from_str(value) {
+                request_builder = request_builder.header(header_name, header_value);
+            }
+        }
+    }
+
+    // Add body if present
+    if let Some(body_bytes) = body {
+        request_builder = request_builder.body(body_bytes);
+    }
+
+    // reqwest reports its own timeout; the guard also covers the body read.
+    let request_error = |e: reqwest::Error| match timeout_ms {
+        Some(ms) if e.is_timeout() => timed_out(ms),
+        _ => e.to_string(),
+    };
+    let exchange = async {
+        // Measure time until headers are received (TTFB)
+        let response = request_builder.send().await.map_err(request_error)?;
+        let ttfb_ms = request_start.elapsed().as_millis() as u32;
+        read_exchange(response, ttfb_ms, request_error).await
+    };
+    Ok(within_timeout(timeout_ms, exchange)
+        .await
+        .map(|exchange| Exchange {
+            blocked_redirect: blocked_redirect.lock().ok().and_then(|mut url| url.take()),
+            ..exchange
+        }))
+}
+
+/// reqwest's default redirect handling, limited to the host of the original
+/// request. A redirect elsewhere is not followed and its target is stored in
+/// `blocked`, leaving the redirect itself as the response.
+fn same_host_redirects(blocked: Arc<Mutex<Option<String>>>) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        let same_host = attempt
+            .previous()
+            .first()
+            .is_some_and(|origin| origin.host_str() == attempt.url().host_str());
+        if !same_host {
+            if let Ok(mut url) = blocked.lock() {
+                *url = Some(attempt.url().to_string());
+            }
+            attempt.stop()
+        } else if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Read the status, headers and body of a reqwest response
+async fn read_exchange(
+    response: reqwest::Response,
+    ttfb_ms: u32,
+    request_error: impl Fn(reqwest::Error) -> String,
+) -> Result<Exchange, String> {
+    let status = response.status().as_u16();
+    let status_text = response.status().canonical_reason().map(String::from);
+
+    // Collect response headers
+    let response_headers: HashMap<String, String> = response
+        .headers()
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+        .collect();
+
+    // Get response body
+    // Measure download time
+    let download_start = Instant::now();
+    let response_body = match response.bytes().await {
+        Ok(bytes) => bytes.to_vec(),
+        Err(e) if e.is_timeout() => return Err(request_error(e)),
+        Err(_) => Vec::new(),
+    };
+    let download_ms = download_start.elapsed().as_millis() as u32;
+
+    Ok(Exchange {
+        status,
+        status_text,
+        response_headers,
+        response_body,
+        ttfb_ms,
+        download_ms,
+        blocked_redirect: None,
+    })
+}
+
+/// Send with the exact header list, via the wire capture relay or the running
+/// proxy when there is one.
+async fn send_ordered(
+    request: &ordered::OrderedRequest<'_>,
+    wire_capture: Option<&WireCapture>,
+    accept_invalid_certs: bool,
+) -> Result<Exchange, String> {
+    let route = match wire_capture {
+        // The relay tunnels HTTPS untouched, so the real certificate is checked.
+        Some(capture) => ordered::Route {
+            proxy: Some(capture.proxy_addr()),
+            accept_invalid_certs,
+        },
+        None => match running_proxy_port() {
+            // Our proxy presents its own MITM certificate.
+            Some(port) => ordered::Route {
+                proxy: Some(std::net::SocketAddr::from(([127, 0, 0, 1], port))),
+                accept_invalid_certs: true,
+            },
+            None => ordered::Route {
+                proxy: None,
+                accept_invalid_certs,
+            },
+        },
+    };
+    let started = Instant::now();
+    let response = ordered::send(request, &route)
+        .await
+        .map_err(|e| format!("{e:#}"))?;
+    let total_ms = started.elapsed().as_millis() as u32;
+    Ok(Exchange {
+        status: response.status,
+        status_text: Some(response.reason).filter(|r| !r.is_empty()),
+        response_headers: response.headers,
+        response_body: response.body,
+        ttfb_ms: response.ttfb_ms,
+        download_ms: total_ms.saturating_sub(response.ttfb_ms),
+        blocked_redirect: None,
+    })
+}
+
+/// Port of the running proxy, if any
+fn running_proxy_port() -> Option<u16> {
+    std::panic::catch_unwind(get_proxy_status)
+        .ok()
+        .filter(|status| status.is_running)
+        .map(|status| status.port)
+}
+
+/// `Host` value for the target, with the port only when it is not the default
+fn host_header(scheme: &str, host: &str, port: u16) -> String {
+    if (scheme == "https" && port == 443) || (scheme == "http" && port == 80) {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// The original header list with `headers` (the final set after overrides and
+/// cleanup) applied in place: a header whose value changed keeps its position,
+/// removed headers are dropped and new ones are appended. `Host` and
+/// `Content-Length` are rewritten for the actual target and body.
+fn ordered_header_list(
+    original: &HttpTransaction,
+    headers: &HashMap<String, String>,
+    host: &str,
+    body: Option<&[u8]>,
+) -> Vec<(String, String)> {
+    let find = |map: &HashMap<String, String>, name: &str| -> Option<String> {
+        map.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    };
+    let body_len = body.map_or(0, <[u8]>::len);
+    let mut out: Vec<(String, String)> = Vec::new();
+    let mut placed: Vec<String> = Vec::new();
+    let mut has_length = false;
+
+    for (name, value) in &original.request_header_list {
+        let lower = name.to_ascii_lowercase();
+        match lower.as_str() {
+            "host" => out.push((name.clone(), host.to_string())),
+            "content-length" if !has_length && body_len > 0 => {
+                has_length = true;
+                out.push((name.clone(), body_len.to_string()));
+            }
+            "content-length" | "transfer-encoding" => {}
+            _ => {
+                let Some(current) = find(headers, name) else {
+                    continue;
+                };
+                let unchanged = find(&original.request_headers, name).as_ref() == Some(&current);
+                if unchanged {
+                    // Repeated headers (e.g. several Cookie lines) all stay.
+                    out.push((name.clone(), value.clone()));
+                } else if !placed.contains(&lower) {
+                    out.push((name.clone(), current));
+                }
+            }
+        }
+        placed.push(lower);
+    }
+    if !placed.iter().any(|name| name == "host") {
+        out.insert(0, ("Host".to_string(), host.to_string()));
+    }
+    let mut added: Vec<_> = headers
+        .iter()
+        .filter(|(name, _)| !placed.contains(&name.to_ascii_lowercase()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    added.sort();
+    out.extend(added);
+    if !has_length && body_len > 0 {
+        out.push(("Content-Length".to_string(), body_len.to_string()));
+    }
+    out
+}
+
+/// Replay a captured request against a saved environment: its base URL
+/// replaces the captured origin and its headers replace captured ones. Other
+/// captured credentials are forwarded unless the environment drops them.
+pub async fn replay_in_environment(
+    transaction_id: &str,
+    environment_name: &str,
+) -> Result<ReplayResult, String> {
+    let ReplayEnvironment {
+        base_url,
+        headers,
+        drop_credentials,
+        ..
+    } = crate::storage::get_environment(environment_name)
+        .map_err(|e| format!("Failed to load environments: {}", e))?
+        .ok_or_else(|| format!("Environment not found: {}", environment_name))?;
+    let params = ReplayParams {
+        headers: Some(headers),
+        base_url: Some(base_url),
+        drop_credentials,
+        ..Default::default()
+    };
+    replay_request(transaction_id, params).await
+}
+
+/// Scheme, host, port and path for sending `path` to `base_url`
+fn rebase(base_url: &str, path: &str) -> Result<(String, String, u16, String), String> {
+    let url = reqwest::Url::parse(base_url)
+        .map_err(|e| format!("Invalid base URL {}: {}", base_url, e))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("Base URL has no host: {}", base_url))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| format!("Base URL has no port: {}", base_url))?;
+    let prefix = url.path().trim_end_matches('/');
+    Ok((
+        url.scheme().to_string(),
+        host.to_string(),
+        port,
+        format!("{}{}", prefix, path),
+    ))
+}
+
+/// Stored transaction of an identical replay made within `window`, if any
+async fn recent_duplicate(fingerprint: &str, window: Duration) -> Option<HttpTransaction> {
+    let id = dedup::recent_match(fingerprint, window)?;
+    get_transaction_by_id(&id).await.ok().flatten()
+}
+
+/// Start a wire capture relay for `tx` if requested, linking its transcript.
+async fn start_wire_capture(
+    enabled: bool,
+    tx: &mut HttpTransaction,
+) -> Result<Option<WireCapture>, String> {
+    if !enabled {
+        return Ok(None);
+    }
+    let capture = WireCapture::start(&tx.id)
+        .await
+        .map_err(|e| format!("Failed to start wire capture: {}", e))?;
+    tx.transcript_id = Some(tx.id.clone());
+    Ok(Some(capture))
+}
+
+fn finish_wire_capture(capture: Option<WireCapture>) -> Option<String> {
+    match capture?.finish() {
+        Ok(path) => path.map(|p| p.to_string_lossy().into_owned()),
+        Err(err) => {
+            tracing::warn!("Failed to write wire capture: {}", err);
+            None
+        }
+    }
+}
+
+/// Replay captured requests in order, keeping their original relative timing.
+///
+/// Each request is started at the same offset from the first as in the original
+/// capture (gaps capped at [`MAX_SEQUENCE_GAP`]; out-of-order timestamps replay
+/// back to back). `params` is applied to every request.
+pub async fn replay_sequence(
+    transaction_ids: &[String],
+    params: ReplayParams,
+) -> Result<Vec<ReplayResult>, String> {
+    let mut start_times = Vec::with_capacity(transaction_ids.len());
+    for id in transaction_ids {
+        let original = get_transaction_by_id(id)
+            .await
+            .map_err(|e| format!("Failed to get transaction: {}", e))?
+            .ok_or_else(|| format!("Transaction not found: {}", id))?;
+        start_times.push(original.timing.start_time);
+    }
+
+    let sequence_start = tokio::time::Instant::now();
+    let mut offset = Duration::ZERO;
+    let mut results = Vec::with_capacity(transaction_ids.len());
+    for (i, id) in transaction_ids.iter().enumerate() {
+        if i > 0 {
+            let gap_ms = (start_times[i] - start_times[i - 1]).max(0) as u64;
+            offset += Duration::from_millis(gap_ms).min(MAX_SEQUENCE_GAP);
+            tokio::time::sleep_until(sequence_start + offset).await;
+        }
+        results.push(replay_request(id, params.clone()).await?);
+    }
+    Ok(results)
+}
+
+/// Fetch the pages after a captured response by following its detected
+/// pagination hint, up to `max_pages` requests.
+///
+/// Each page is a replay of the previous one with the path changed, so every
+/// page is stored as a new transaction noted as replayed from the page before
+/// it. Stops at the first response without a next hint, a failed request, or a
+/// next URL already seen. `max_pages` is capped at [`MAX_PAGES`].
+///
+/// A next URL on another origin is never followed, since the replayed
+/// credentials (Authorization, Cookie) would go with it: the walk stops and
+/// the URL is reported as `blocked_redirect` on the last page's result.
+pub async fn replay_all_pages(
+    transaction_id: &str,
+    max_pages: usize,
+) -> Result<Vec<ReplayResult>, String> {
+    let max_pages = max_pages.min(MAX_PAGES);
+    let mut current = get_transaction_by_id(transaction_id)
+        .await
+        .map_err(|e| format!("Failed to get transaction: {}", e))?
+        .ok_or_else(|| format!("Transaction not found: {}", transaction_id))?;
+    if current.pagination.is_none() {
+        return Err(format!(
+            "Transaction {} has no detected next page",
+            transaction_id
+        ));
+    }
+
+    let mut seen = Vec::new();
+    let mut results: Vec<ReplayResult> = Vec::new();
+    while results.len() < max_pages {
+        let Some(info) = current.pagination.as_ref() else {
+            break;
+        };
+        let next = next_page_url(&current, info)?;
+        if seen.contains(&next) {
+            break;
+        }
+        seen.push(next.clone());
+
+        let same_origin = next.scheme() == current.scheme
+            && next.host_str() == Some(current.host.as_str())
+            && next.port_or_known_default() == Some(current.port);
+        if !same_origin {
+            let Some(last) = results.last_mut() else {
+                return Err(format!(
+                    "Next page {} is on another origin; not following it",
+                    next
+                ));
+            };
+            last.blocked_redirect = Some(next.to_string());
+            break;
+        }
+        let params = ReplayParams {
+            path: Some(match next.query() {
+                Some(query) => format!("{}?{}", next.path(), query),
+                None => next.path().to_string(),
+            }),
+            ..Default::default()
+        };
+        let result = replay_request(&current.id, params).await?;
+        let page_id = result.transaction_id.clone();
+        let success = result.success;
+        results.push(result);
+        if !success {
+            break;
+        }
+        current = match get_transaction_by_id(&page_id).await {
+            Ok(Some(tx)) => tx,
+            _ => break,
+        };
+    }
+    Ok(results)
+}
+
+/// Absolute URL of the page after `tx`
+fn next_page_url(tx: &HttpTransaction, info: &PaginationInfo) -> Result<reqwest::Url, String> {
+    let current = format!(
+        "{}://{}{}",
+        tx.scheme,
+        host_header(&tx.scheme, &tx.host, tx.port),
+        tx.path
+    );
+    let mut url =
+        reqwest::Url::parse(&current).map_err(|e| format!("Invalid URL {}: {}", current, e))?;
+    match (info.style, &info.next_url, &info.next_cursor) {
+        (PaginationStyle::LinkHeader | PaginationStyle::BodyUrl, Some(next), _) => url
+            .join(next)
+            .map_err(|e| format!("Invalid next page URL {}: {}", next, e)),
+        (PaginationStyle::BodyCursor, _, Some(cursor)) => {
+            let param = info.cursor_param.as_deref().unwrap_or("cursor");
+            let kept: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(name, _)| name != param)
+                .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                .collect();
+            url.query_pairs_mut()
+                .clear()
+                .extend_pairs(kept)
+                .append_pair(param, cursor);
+            Ok(url)
+        }
+        _ => Err("Pagination hint has no next page".to_string()),
+    }
+}
+
+/// Request headers that let a server answer 304 Not Modified (or a cache serve
+/// a stored copy) instead of the full response
+const CACHE_VALIDATOR_HEADERS: &[&str] = &[
+    "if-none-match",
+    "if-modified-since",
+    "if-range",
+    "cache-control",
+    "pragma",
+];
+
+/// Remove conditional and caching headers and ask caches to revalidate
+fn strip_cache_validators(headers: &mut HashMap<String, String>) {
+    headers.retain(|name, _| {
+        !CACHE_VALIDATOR_HEADERS
+            .iter()
+            .any(|h| name.eq_ignore_ascii_case(h))
+    });
+    headers.insert("Cache-Control".to_string(), "no-cache".to_string());
+    headers.insert("Pragma".to_string(), "no-cache".to_string());
+}
+
+/// Make the body agree with its `Content-Encoding` header.
+///
+/// Bodies that already carry the declared encoding are left alone, as are
+/// encodings we cannot verify (e.g. `br`).
+fn reconcile_content_encoding(
+    headers: &mut HashMap<String, String>,
+    body: Option<Vec<u8>>,
+    mode: ReplayContentEncoding,
+) -> Result<Option<Vec<u8>>, String> {
+    let Some(key) = headers
+        .keys()
+        .find(|k| k.eq_ignore_ascii_case("content-encoding"))
+        .cloned()
+    else {
+        return Ok(body);
+    };
+    let Some(data) = body.filter(|b| !b.is_empty()) else {
+        return Ok(None);
+    };
+
+    // identity needs nothing; other encodings cannot be produced here
+    let Some(coding) = ContentCoding::from_token(&headers[&key]) else {
+        return Ok(Some(data));
+    };
+    if coding.is_encoded(&data) {
+        return Ok(Some(data));
+    }
+
+    match mode {
+        ReplayContentEncoding::Strip => {
+            headers.remove(&key);
+            Ok(Some(data))
+        }
+        ReplayContentEncoding::Recompress => coding
+            .encode(&data)
+            .map(Some)
+            .map_err(|e| format!("Failed to encode replay body: {}", e)),
+    }
+}
+
 /// Parameters for sending a direct (new) HTTP request
 #[derive(Debug, Clone)]
 pub struct DirectRequestParams {
@@ -268,6 +981,10 @@ pub struct DirectRequestParams {
     pub headers: HashMap<String, String>,
     /// Request body (optional)
     pub body: Option<Vec<u8>>,
+    /// Record the raw bytes exchanged with the upstream to a file
+    pub capture_wire: bool,
+    /// Give up after this many milliseconds (None = wait indefinitely)
+    pub timeout_ms: Option<u64>,
 }
 
 /// Send a new HTTP request directly (not a replay of existing transaction)
@@ -283,6 +1000,8 @@ pub async fn send_direct_request(params: DirectRequestParams) -> Result<ReplayRe
         method,
         headers,
         body,
+        capture_wire,
+        timeout_ms,
     } = params;
 
     // Parse the URL
@@ -343,87 +1062,39 @@ pub async fn send_direct_request(params: DirectRequestParams) -> Result<ReplayRe
     new_tx.request_body = body.clone();
     new_tx.notes = Some("Direct request from Composer".to_string());
 
+    let _slot = limit::acquire().await?;
+
     // Send initial state to UI
     send_transaction_to_sink(new_tx.clone());
 
     // Make the HTTP request
     let request_start = Instant::now();
-    let mut client_builder = reqwest::Client::builder();
-    // Route through our proxy if running so timing is captured consistently.
-    if let Ok(status) = std::panic::catch_unwind(get_proxy_status) {
-        if status.is_running {
-            let proxy_url = format!("http://127.0.0.1:{}", status.port);
-            if let Ok(proxy) = reqwest::Proxy::all(&proxy_url) {
-                // Route through our proxy; disable env/system proxies to avoid loops.
-                client_builder = client_builder.no_proxy().proxy(proxy);
-                // Trust our MITM cert for composer traffic as well.
-                client_builder = client_builder.danger_accept_invalid_certs(true);
-            }
-        }
-    }
-
-    let client = client_builder
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-    let reqwest_method = match http_method {
-        HttpMethod::Get => reqwest::Method::GET,
-        HttpMethod::Post => reqwest::Method::POST,
-        HttpMethod::Put => reqwest::Method::PUT,
-        HttpMethod::Patch => reqwest::Method::PATCH,
-        HttpMethod::Delete => reqwest::Method::DELETE,
-        HttpMethod::Head => reqwest::Method::HEAD,
-        HttpMethod::Options => reqwest::Method::OPTIONS,
-        HttpMethod::Connect => reqwest::Method::CONNECT,
-        HttpMethod::Trace => reqwest::Method::TRACE,
-    };
-
-    let mut request_builder = client.request(reqwest_method, &url);
-
-    // Add headers
-    for (key, value) in &clean_headers {
-        if let Ok(header_name) = reqwest::header::HeaderName::try_from(key.as_str()) {
-            if let Ok(header_value) = reqwest::header::HeaderValue::from_str(value) {
-                request_builder = request_builder.header(header_name, header_value);
-            }
-        }
-    }
-
-    // Add body if present
-    if let Some(body_bytes) = body {
-        request_builder = request_builder.body(body_bytes);
-    }
-
-    // Execute request
-    // Measure time until headers are received (TTFB)
-    let response_result = request_builder.send().await;
-    let ttfb = request_start.elapsed();
-    let ttfb_ms = ttfb.as_millis() as u32;
-
-    match response_result {
-        Ok(response) => {
-            let status = response.status().as_u16();
-            let status_text = response.status().canonical_reason().map(String::from);
-
-            // Collect response headers
-            let response_headers: HashMap<String, String> = response
-                .headers()
-                .iter()
-                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-                .collect();
+    let wire_capture = start_wire_capture(capture_wire, &mut new_tx).await?;
+    let exchange = send_with_reqwest(
+        http_method,
+        &url,
+        &clean_headers,
+        body,
+        wire_capture.as_ref(),
+        false,
+        timeout_ms,
+        false,
+    )
+    .await?;
 
+    match exchange {
+        Ok(Exchange {
+            status,
+            status_text,
+            response_headers,
+            response_body,
+            ttfb_ms,
+            download_ms,
+            blocked_redirect,
+        }) => {
             let content_type = response_headers.get("content-type").cloned();
-
-            // Get response body
-            // Measure download time
-            let download_start = Instant::now();
-            let response_body = response
-                .bytes()
-                .await
-                .map(|b| b.to_vec())
-                .unwrap_or_default();
-            let download_ms = download_start.elapsed().as_millis() as u32;
             let response_size = response_body.len() as u64;
+            let wire_capture_path = finish_wire_capture(wire_capture);
 
             // Total time is start to finish
             let total_ms = request_start.elapsed().as_millis() as u32;
@@ -436,6 +1107,7 @@ pub async fn send_direct_request(params: DirectRequestParams) -> Result<ReplayRe
             new_tx.response_content_type = content_type;
             new_tx.response_size = Some(response_size);
             new_tx.state = TransactionState::Completed;
+            new_tx.refresh_pagination();
 
             // Populate timing breakdown
             new_tx.timing = TransactionTiming {
@@ -457,15 +1129,18 @@ pub async fn send_direct_request(params: DirectRequestParams) -> Result<ReplayRe
                 status_code: Some(status),
                 success: true,
                 error: None,
+                wire_capture_path,
+                blocked_redirect,
             })
         }
         Err(e) => {
+            let wire_capture_path = finish_wire_capture(wire_capture);
             // Update transaction with error
             new_tx.state = TransactionState::Failed;
-            new_tx.notes = Some(format!("Request failed: {}", e));
+            new_tx.notes = Some(failure_note("Request failed", &e, timeout_ms));
             new_tx.timing = TransactionTiming {
                 start_time,
-                total_ms: Some(ttfb_ms),
+                total_ms: Some(request_start.elapsed().as_millis() as u32),
                 ..Default::default()
             };
 
@@ -480,7 +1155,966 @@ pub async fn send_direct_request(params: DirectRequestParams) -> Result<ReplayRe
                 status_code: None,
                 success: false,
                 error: Some(e.to_string()),
+                wire_capture_path,
+                blocked_redirect: None,
             })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use serial_test::serial;
+    use std::io::Read;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_recompresses_decoded_gzip_body() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&received).to_ascii_lowercase();
+                if let Some(head_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..head_end]
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length:"))
+                        .and_then(|v| v.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if received.len() >= head_end + 4 + content_length {
+                        socket
+                            .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                            .await
+                            .unwrap();
+                        return (
+                            text[..head_end].to_string(),
+                            received[head_end + 4..].to_vec(),
+                        );
+                    }
+                }
+            }
+        });
+
+        // The stored body is plain even though the captured header says gzip.
+        let mut headers = HashMap::new();
+        headers.insert("Content-Encoding".to_string(), "gzip".to_string());
+        let mut original = HttpTransaction::new(
+            HttpMethod::Post,
+            "http",
+            "127.0.0.1",
+            port,
+            "/ingest",
+            headers,
+        );
+        original.request_body = Some(b"{\"event\":\"click\"}".to_vec());
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let result = replay_request(&original_id, ReplayParams::default())
+            .await
+            .expect("replay runs");
+        assert!(result.success, "replay failed: {:?}", result.error);
+
+        let (head, body) = server.await.unwrap();
+        assert!(head.contains("content-encoding: gzip"));
+        let mut decoded = String::new();
+        GzDecoder::new(body.as_slice())
+            .read_to_string(&mut decoded)
+            .expect("body is valid gzip");
+        assert_eq!(decoded, r#"{"event":"click"}"#);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn wire_capture_writes_upstream_transcript() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "client closed before request head");
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+                )
+                .await
+                .unwrap();
+        });
+
+        let original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/wire?x=1",
+            HashMap::new(),
+        );
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let params = ReplayParams {
+            capture_wire: true,
+            ..Default::default()
+        };
+        let result = replay_request(&original_id, params)
+            .await
+            .expect("replay runs");
+        assert!(result.success, "replay failed: {:?}", result.error);
+
+        let path = result.wire_capture_path.expect("capture file written");
+        let capture = std::fs::read_to_string(&path).unwrap();
+        // The relay rewrites the proxy's absolute-form target to origin form.
+        assert!(capture.contains(&hex_of(b"GET /wire?x=1 HTTP/1.1")));
+        assert!(capture.contains(&hex_of(b"hello")));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn bypass_cache_strips_conditional_headers() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "client closed before request head");
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&received).to_ascii_lowercase()
+        });
+
+        let headers = HashMap::from([
+            ("If-None-Match".to_string(), "\"v1\"".to_string()),
+            ("Cache-Control".to_string(), "max-age=60".to_string()),
+            ("Accept".to_string(), "application/json".to_string()),
+        ]);
+        let original =
+            HttpTransaction::new(HttpMethod::Get, "http", "127.0.0.1", port, "/etag", headers);
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let params = ReplayParams {
+            bypass_cache: true,
+            ..Default::default()
+        };
+        let result = replay_request(&original_id, params)
+            .await
+            .expect("replay runs");
+        assert!(result.success, "replay failed: {:?}", result.error);
+        assert_eq!(result.status_code, Some(200));
+
+        let head = server.await.unwrap();
+        assert!(!head.contains("if-none-match"));
+        assert!(head.contains("cache-control: no-cache"));
+        assert!(!head.contains("max-age"));
+        assert!(head.contains("accept: application/json"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_fails_once_timeout_passes() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        // Accepts the request and never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+        });
+
+        let original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/slow",
+            HashMap::new(),
+        );
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let params = ReplayParams {
+            timeout_ms: Some(200),
+            ..Default::default()
+        };
+        let result = replay_request(&original_id, params)
+            .await
+            .expect("replay runs");
+        server.abort();
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("timed out after 200ms"));
+
+        let replayed = get_transaction_by_id(&result.transaction_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(replayed.state, TransactionState::Failed);
+        assert_eq!(replayed.notes.as_deref(), Some("timed out after 200ms"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn same_host_only_replay_stops_at_redirect_to_other_host() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        // /same redirects on this host, /away to the same server under
+        // another name
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    received.extend_from_slice(&buf[..n]);
+                }
+                let head = String::from_utf8_lossy(&received);
+                let response = if head.starts_with("GET /same ") {
+                    "HTTP/1.1 302 Found\r\nLocation: /done\r\n".to_string()
+                } else if head.starts_with("GET /away ") {
+                    format!("HTTP/1.1 302 Found\r\nLocation: http://localhost:{port}/done\r\n")
+                } else {
+                    "HTTP/1.1 200 OK\r\n".to_string()
+                };
+                let response = format!("{response}Content-Length: 0\r\nConnection: close\r\n\r\n");
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let mut ids = Vec::new();
+        for path in ["/same", "/away"] {
+            let original = HttpTransaction::new(
+                HttpMethod::Get,
+                "http",
+                "127.0.0.1",
+                port,
+                path,
+                HashMap::new(),
+            );
+            ids.push(original.id.clone());
+            persist_transaction(original).await.unwrap();
+        }
+        let params = ReplayParams {
+            redirect_same_host_only: true,
+            ..Default::default()
+        };
+
+        let followed = replay_request(&ids[0], params.clone()).await.unwrap();
+        assert_eq!(followed.status_code, Some(200));
+        assert_eq!(followed.blocked_redirect, None);
+
+        let blocked = replay_request(&ids[1], params).await.unwrap();
+        server.abort();
+        assert!(blocked.success);
+        assert_eq!(blocked.status_code, Some(302));
+        let location = format!("http://localhost:{port}/done");
+        assert_eq!(blocked.blocked_redirect.as_deref(), Some(location.as_str()));
+        let replayed = get_transaction_by_id(&blocked.transaction_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(replayed.notes.unwrap().ends_with(&location));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn preserve_header_order_sends_captured_sequence() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "client closed before request head");
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        let list: Vec<(String, String)> = [
+            ("X-Zeta", "1"),
+            ("accept", "*/*"),
+            ("Host", "example.test"),
+            ("X-Alpha", "a"),
+            ("Cookie", "a=1"),
+            ("Cookie", "b=2"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let headers = HashMap::from([
+            ("X-Zeta".to_string(), "1".to_string()),
+            ("accept".to_string(), "*/*".to_string()),
+            ("Host".to_string(), "example.test".to_string()),
+            ("X-Alpha".to_string(), "a".to_string()),
+            ("Cookie".to_string(), "a=1; b=2".to_string()),
+        ]);
+        let mut original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/order",
+            headers,
+        );
+        original.request_header_list = list;
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let params = ReplayParams {
+            headers: Some(HashMap::from([
+                ("x-alpha".to_string(), "b".to_string()),
+                ("X-New".to_string(), "n".to_string()),
+            ])),
+            preserve_header_order: true,
+            ..Default::default()
+        };
+        let result = replay_request(&original_id, params)
+            .await
+            .expect("replay runs");
+        assert!(result.success, "replay failed: {:?}", result.error);
+        assert_eq!(result.status_code, Some(200));
+
+        let head = server.await.unwrap();
+        let lines: Vec<&str> = head.trim_end().split("\r\n").collect();
+        let host = format!("Host: 127.0.0.1:{port}");
+        assert_eq!(
+            lines,
+            [
+                "GET /order HTTP/1.1",
+                "X-Zeta: 1",
+                "accept: */*",
+                host.as_str(),
+                "X-Alpha: b",
+                "Cookie: a=1",
+                "Cookie: b=2",
+                "X-New: n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn preserve_header_order_needs_a_recorded_order() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let original =
+            HttpTransaction::new(HttpMethod::Get, "http", "127.0.0.1", 9, "/", HashMap::new());
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let params = ReplayParams {
+            preserve_header_order: true,
+            ..Default::default()
+        };
+        let err = replay_request(&original_id, params).await.unwrap_err();
+        assert!(err.contains("no recorded header order"), "{err}");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_all_pages_follows_link_header_until_last_page() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut targets = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "client closed before request head");
+                    received.extend_from_slice(&buf[..n]);
+                }
+                let head = String::from_utf8_lossy(&received).to_string();
+                let target = head.split(' ').nth(1).unwrap().to_string();
+                let link = match target.as_str() {
+                    "/items?page=2" => "Link: </items?page=3>; rel=\"next\"\r\n",
+                    _ => "",
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n{link}Content-Length: 2\r\nConnection: close\r\n\r\n[]"
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                targets.push(target);
+            }
+            targets
+        });
+
+        let mut original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/items?page=1",
+            HashMap::new(),
+        );
+        original.status_code = Some(200);
+        original.response_headers = Some(HashMap::from([(
+            "Link".to_string(),
+            format!("<http://127.0.0.1:{port}/items?page=2>; rel=\"next\""),
+        )]));
+        original.refresh_pagination();
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let results = replay_all_pages(&original_id, DEFAULT_MAX_PAGES)
+            .await
+            .expect("pages replay");
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.success));
+        assert_eq!(server.await.unwrap(), ["/items?page=2", "/items?page=3"]);
+
+        let second = get_transaction_by_id(&results[0].transaction_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.notes, Some(format!("Replayed from {}", original_id)));
+        assert!(second.pagination.is_some());
+        let last = get_transaction_by_id(&results[1].transaction_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(last.path, "/items?page=3");
+        assert!(last.pagination.is_none());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_all_pages_stops_at_next_page_on_another_origin() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let elsewhere = format!("http://localhost:{port}/items?page=3");
+        let next_link = elsewhere.clone();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "client closed before request head");
+                received.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nLink: <{next_link}>; rel=\"next\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]"
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            // A second connection would mean the cross-origin page was fetched
+            tokio::time::timeout(Duration::from_millis(200), listener.accept())
+                .await
+                .is_err()
+        });
+
+        let mut original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/items?page=1",
+            HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]),
+        );
+        original.status_code = Some(200);
+        original.response_headers = Some(HashMap::from([(
+            "Link".to_string(),
+            "</items?page=2>; rel=\"next\"".to_string(),
+        )]));
+        original.refresh_pagination();
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let results = replay_all_pages(&original_id, u32::MAX as usize)
+            .await
+            .expect("pages replay");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].success);
+        assert_eq!(
+            results[0].blocked_redirect.as_deref(),
+            Some(elsewhere.as_str())
+        );
+        assert!(server.await.unwrap(), "cross-origin page was requested");
+
+        let err = replay_all_pages(&results[0].transaction_id, DEFAULT_MAX_PAGES)
+            .await
+            .unwrap_err();
+        assert!(err.contains("another origin"), "{err}");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn identical_replays_in_window_increment_counter() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "client closed before request head");
+                    received.extend_from_slice(&buf[..n]);
+                }
+                socket
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nsame",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/storm",
+            HashMap::new(),
+        );
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let params = ReplayParams {
+            dedup_window: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let result = replay_request(&original_id, params.clone())
+                .await
+                .expect("replay runs");
+            assert!(result.success, "replay failed: {:?}", result.error);
+            ids.push(result.transaction_id);
+        }
+
+        assert!(ids.iter().all(|id| *id == ids[0]), "{ids:?}");
+        assert_eq!(crate::storage::get_transaction_count().await.unwrap(), 2);
+        let collapsed = get_transaction_by_id(&ids[0]).await.unwrap().unwrap();
+        assert_eq!(collapsed.occurrence_count, 3);
+
+        // Without a window every replay is stored.
+        replay_request(&original_id, ReplayParams::default())
+            .await
+            .expect("replay runs");
+        assert_eq!(crate::storage::get_transaction_count().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_in_environment_retargets_and_merges_headers() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "client closed before request head");
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&received).to_ascii_lowercase()
+        });
+
+        crate::storage::save_environment(ReplayEnvironment {
+            name: "staging".to_string(),
+            base_url: format!("http://127.0.0.1:{port}/v2/"),
+            headers: HashMap::from([(
+                "Authorization".to_string(),
+                "Bearer staging-token".to_string(),
+            )]),
+            drop_credentials: false,
+        })
+        .unwrap();
+
+        let headers = HashMap::from([
+            ("authorization".to_string(), "Bearer prod-token".to_string()),
+            ("Accept".to_string(), "application/json".to_string()),
+        ]);
+        let original = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "api.example.com",
+            443,
+            "/users?id=7",
+            headers,
+        );
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let result = replay_in_environment(&original_id, "staging")
+            .await
+            .expect("replay runs");
+        assert!(result.success, "replay failed: {:?}", result.error);
+
+        let head = server.await.unwrap();
+        assert!(
+            head.starts_with("get /v2/users?id=7 http/1.1\r\n"),
+            "{head}"
+        );
+        assert!(head.contains(&format!("host: 127.0.0.1:{port}")));
+        assert!(head.contains("authorization: bearer staging-token"));
+        assert!(!head.contains("prod-token"));
+        assert!(head.contains("accept: application/json"));
+
+        let replayed = get_transaction_by_id(&result.transaction_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(replayed.host, "127.0.0.1");
+        assert_eq!(replayed.scheme, "http");
+
+        let missing = replay_in_environment(&original_id, "prod").await;
+        assert!(missing.unwrap_err().contains("Environment not found"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_in_environment_can_drop_captured_credentials() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 1024];
+            while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "client closed before request head");
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&received).to_ascii_lowercase()
+        });
+
+        crate::storage::save_environment(ReplayEnvironment {
+            name: "sandbox".to_string(),
+            base_url: format!("http://127.0.0.1:{port}"),
+            headers: HashMap::from([("X-Env".to_string(), "sandbox".to_string())]),
+            drop_credentials: true,
+        })
+        .unwrap();
+
+        let headers = HashMap::from([
+            ("Authorization".to_string(), "Bearer prod-token".to_string()),
+            ("Cookie".to_string(), "session=prod".to_string()),
+            ("Accept".to_string(), "application/json".to_string()),
+        ]);
+        let original = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "api.example.com",
+            443,
+            "/me",
+            headers,
+        );
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let result = replay_in_environment(&original_id, "sandbox")
+            .await
+            .expect("replay runs");
+        assert!(result.success, "replay failed: {:?}", result.error);
+
+        let head = server.await.unwrap();
+        assert!(!head.contains("authorization:"), "{head}");
+        assert!(!head.contains("cookie:"), "{head}");
+        assert!(head.contains("x-env: sandbox"));
+        assert!(head.contains("accept: application/json"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_of_redacted_query_is_flagged() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut targets = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "client closed before request head");
+                    received.extend_from_slice(&buf[..n]);
+                }
+                socket
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                    )
+                    .await
+                    .unwrap();
+                let head = String::from_utf8_lossy(&received).to_string();
+                targets.push(head.split(' ').nth(1).unwrap().to_string());
+            }
+            targets
+        });
+
+        let mut original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/cb?code=***",
+            HashMap::new(),
+        );
+        original.query_redacted = true;
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        let masked = replay_request(&original_id, ReplayParams::default())
+            .await
+            .expect("replay runs");
+        let full = replay_request(
+            &original_id,
+            ReplayParams {
+                path: Some("/cb?code=real".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("replay runs");
+        assert_eq!(server.await.unwrap(), ["/cb?code=***", "/cb?code=real"]);
+
+        let notes = |id: String| async move {
+            get_transaction_by_id(&id)
+                .await
+                .unwrap()
+                .unwrap()
+                .notes
+                .unwrap()
+        };
+        assert!(notes(masked.transaction_id)
+            .await
+            .contains("redacted query string"));
+        assert_eq!(
+            notes(full.transaction_id).await,
+            format!("Replayed from {}", original_id)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[serial]
+    async fn replays_beyond_the_cap_queue_for_a_slot() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (counter, high_water) = (in_flight.clone(), peak.clone());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (counter, high_water) = (counter.clone(), high_water.clone());
+                tokio::spawn(async move {
+                    let mut received = Vec::new();
+                    let mut buf = vec![0u8; 1024];
+                    while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        assert!(n > 0, "client closed before request head");
+                        received.extend_from_slice(&buf[..n]);
+                    }
+                    let now = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    high_water.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    counter.fetch_sub(1, Ordering::SeqCst);
+                    socket
+                        .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+
+        let original = HttpTransaction::new(
+            HttpMethod::Get,
+            "http",
+            "127.0.0.1",
+            port,
+            "/busy",
+            HashMap::new(),
+        );
+        let original_id = original.id.clone();
+        persist_transaction(original).await.unwrap();
+
+        set_limits(2, Duration::from_secs(10));
+        let replays = (0..6).map(|_| replay_request(&original_id, ReplayParams::default()));
+        let results = futures::future::join_all(replays).await;
+
+        // A queue that cannot drain in time fails instead of waiting forever.
+        set_limits(1, Duration::from_millis(50));
+        let (slow, queued) = tokio::join!(
+            replay_request(&original_id, ReplayParams::default()),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                replay_request(&original_id, ReplayParams::default()).await
+            }
+        );
+        set_limits(DEFAULT_MAX_CONCURRENT_REPLAYS, DEFAULT_QUEUE_TIMEOUT);
+
+        assert!(
+            results.iter().all(|r| matches!(r, Ok(r) if r.success)),
+            "{results:?}"
+        );
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(slow.unwrap().success);
+        assert!(queued.unwrap_err().contains("Too many concurrent replays"));
+    }
+
+    fn hex_of(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn replay_sequence_preserves_relative_timing() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        crate::storage::reset_store_for_tests(storage_dir.path().to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut arrivals = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = vec![0u8; 1024];
+                while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "client closed before request head");
+                    received.extend_from_slice(&buf[..n]);
+                }
+                arrivals.push(Instant::now());
+                socket
+                    .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+            arrivals
+        });
+
+        let base = 1_700_000_000_000i64;
+        let mut ids = Vec::new();
+        for (i, offset_ms) in [0i64, 100].into_iter().enumerate() {
+            let mut tx = HttpTransaction::new(
+                HttpMethod::Get,
+                "http",
+                "127.0.0.1",
+                port,
+                &format!("/step{i}"),
+                HashMap::new(),
+            );
+            tx.timing.start_time = base + offset_ms;
+            ids.push(tx.id.clone());
+            persist_transaction(tx).await.unwrap();
+        }
+
+        let started = Instant::now();
+        let results = replay_sequence(&ids, ReplayParams::default())
+            .await
+            .expect("sequence replays");
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.success), "{results:?}");
+
+        // Measured from the sequence start: the first request's connection
+        // setup time must not eat into the expected offset.
+        let arrivals = server.await.unwrap();
+        let offset = arrivals[1].duration_since(started);
+        assert!(
+            offset >= Duration::from_millis(100) && offset < Duration::from_millis(600),
+            "second replay started {offset:?} after the sequence began"
+        );
+    }
+
+    #[test]
+    fn strip_mode_drops_header_for_plain_body() {
+        let mut headers = HashMap::new();
+        headers.insert("content-encoding".to_string(), "gzip".to_string());
+        let body = reconcile_content_encoding(
+            &mut headers,
+            Some(b"plain".to_vec()),
+            ReplayContentEncoding::Strip,
+        )
+        .unwrap();
+        assert_eq!(body.as_deref(), Some(&b"plain"[..]));
+        assert!(headers.is_empty());
+    }
+}
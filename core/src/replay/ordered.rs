@@ -0,0 +1,248 @@
+//! Replay with the captured header order and casing
+//!
+//! reqwest (via hyper) keeps headers in a map: names are lowercased and the
+//! original order is lost. Some servers, anti-bot systems in particular, look
+//! at both, so an order-preserving replay writes the HTTP/1.1 request head
+//! itself and reads the response with the proxy's own response reader.
+
+use crate::proxy::server::{self, ResponseHead, MAX_BODY_CAPTURE_BYTES};
+use crate::proxy::upstream_tls;
+use anyhow::{anyhow, bail, Context};
+use rustls::pki_types::ServerName;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+/// Interim (1xx) responses skipped before giving up on a final one
+const MAX_INTERIM_RESPONSES: usize = 8;
+
+/// Where the request goes and how far to trust the certificate presented.
+pub(crate) struct Route {
+    /// Forward proxy to send through (absolute form for HTTP, CONNECT for HTTPS)
+    pub proxy: Option<SocketAddr>,
+    /// Skip certificate verification (invalid certs allowed, or our own MITM)
+    pub accept_invalid_certs: bool,
+}
+
+/// The request as it goes on the wire.
+pub(crate) struct OrderedRequest<'a> {
+    pub method: &'a str,
+    pub scheme: &'a str,
+    pub host: &'a str,
+    pub port: u16,
+    pub path: &'a str,
+    /// Every header to send, in order, including Host and Content-Length
+    pub headers: &'a [(String, String)],
+    pub body: &'a [u8],
+}
+
+pub(crate) struct OrderedResponse {
+    pub status: u16,
+    pub reason: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    /// Milliseconds from sending until the response head was read
+    pub ttfb_ms: u32,
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+pub(crate) async fn send(
+    request: &OrderedRequest<'_>,
+    route: &Route,
+) -> anyhow::Result<OrderedResponse> {
+    let authority = format!("{}:{}", request.host, request.port);
+    let https = request.scheme.eq_ignore_ascii_case("https");
+    let mut stream = match route.proxy {
+        Some(proxy) => TcpStream::connect(proxy)
+            .await
+            .with_context(|| format!("connecting to proxy {proxy}"))?,
+        None => TcpStream::connect(&authority)
+            .await
+            .with_context(|| format!("connecting to {authority}"))?,
+    };
+    if https && route.proxy.is_some() {
+        open_tunnel(&mut stream, &authority).await?;
+    }
+
+    let mut io: Box<dyn Io> = if https {
+        let mut config = if route.accept_invalid_certs {
+            upstream_tls::accept_any_certificate_config()?
+        } else {
+            upstream_tls::default_client_config()?
+        };
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let server_name = ServerName::try_from(request.host.to_string())
+            .map_err(|_| anyhow!("invalid server name {}", request.host))?;
+        let tls = TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+            .context("TLS handshake")?;
+        Box::new(tls)
+    } else {
+        Box::new(stream)
+    };
+
+    // A plain request through a forward proxy names the full URL.
+    let target = if !https && route.proxy.is_some() {
+        format!("http://{authority}{}", request.path)
+    } else {
+        request.path.to_string()
+    };
+    io.write_all(&encode_head(request.method, &target, request.headers))
+        .await?;
+    io.write_all(request.body).await?;
+    io.flush().await?;
+
+    let sent_at = std::time::Instant::now();
+    let mut head = read_head(&mut io).await?;
+    let ttfb_ms = sent_at.elapsed().as_millis() as u32;
+    let status = head.status_code;
+    let reason = std::mem::take(&mut head.reason);
+    let headers = head.headers.clone();
+    let body = read_body(&mut io, request.method, head).await?;
+    Ok(OrderedResponse {
+        status,
+        reason,
+        headers,
+        body,
+        ttfb_ms,
+    })
+}
+
+/// The request line and headers exactly as given, names and order untouched.
+fn encode_head(method: &str, target: &str, headers: &[(String, String)]) -> Vec<u8> {
+    let mut head = format!("{method} {target} HTTP/1.1\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    head.into_bytes()
+}
+
+async fn open_tunnel(stream: &mut TcpStream, authority: &str) -> anyhow::Result<()> {
+    stream
+        .write_all(format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n\r\n").as_bytes())
+        .await?;
+    let status = read_head(stream).await?.status_code;
+    if status != 200 {
+        bail!("proxy refused CONNECT to {authority} with status {status}");
+    }
+    Ok(())
+}
+
+/// Read the final response head, skipping interim (1xx) responses.
+async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<ResponseHead> {
+    let mut pending = Vec::new();
+    for _ in 0..=MAX_INTERIM_RESPONSES {
+        // Bytes read past an interim head belong to the next one.
+        let mut reader = pending.as_slice().chain(&mut *stream);
+        let mut head = server::read_response_head(&mut reader).await?;
+        let (unread, _) = reader.into_inner();
+        if !(100..200).contains(&head.status_code) || head.status_code == 101 {
+            head.body_prefix.extend_from_slice(unread);
+            return Ok(head);
+        }
+        let mut leftover = std::mem::take(&mut head.body_prefix);
+        leftover.extend_from_slice(unread);
+        pending = leftover;
+    }
+    bail!("more than {MAX_INTERIM_RESPONSES} interim responses")
+}
+
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Read the response body, keeping at most [`MAX_BODY_CAPTURE_BYTES`] of it
+/// as the proxy does for the bodies it captures.
+async fn read_body<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    method: &str,
+    head: ResponseHead,
+) -> anyhow::Result<Vec<u8>> {
+    if method.eq_ignore_ascii_case("HEAD") || head.status_code == 204 || head.status_code == 304 {
+        return Ok(Vec::new());
+    }
+    let chunked = header(&head.headers, "transfer-encoding")
+        .map(|v| v.to_ascii_lowercase().contains("chunked"))
+        .unwrap_or(false);
+    if chunked {
+        let (body, _) = server::decode_chunked_body(head.body_prefix, stream).await?;
+        return Ok(body);
+    }
+    let declared = header(&head.headers, "content-length").and_then(|v| v.parse::<u64>().ok());
+    let reader = head.body_prefix.as_slice().chain(stream);
+    let (body, total) = match declared {
+        Some(len) => read_capped(reader.take(len)).await?,
+        None => read_capped(reader).await?,
+    };
+    if let Some(len) = declared.filter(|&len| total < len) {
+        bail!("response body ended after {total} of {len} bytes");
+    }
+    Ok(body)
+}
+
+/// Read to EOF, keeping the first [`MAX_BODY_CAPTURE_BYTES`]. Returns the kept
+/// bytes and the total read.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R) -> anyhow::Result<(Vec<u8>, u64)> {
+    let mut body = Vec::new();
+    let mut total = 0u64;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok((body, total));
+        }
+        total += n as u64;
+        let keep = n.min(MAX_BODY_CAPTURE_BYTES - body.len());
+        body.extend_from_slice(&buf[..keep]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn chunked_body_is_reassembled() {
+        let mut wire: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n\
+            Transfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-T: 1\r\n\r\n";
+        let head = read_head(&mut wire).await.unwrap();
+        assert_eq!((head.status_code, head.reason.as_str()), (200, "OK"));
+        let body = read_body(&mut wire, "GET", head).await.unwrap();
+        assert_eq!(body, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_capped_not_trusted() {
+        // A chunk size near usize::MAX must fail on the short read, not allocate.
+        let mut wire: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            ffffffffffffffff\r\nabc";
+        let head = read_head(&mut wire).await.unwrap();
+        assert!(read_body(&mut wire, "GET", head).await.is_err());
+
+        let long = vec![b'x'; MAX_BODY_CAPTURE_BYTES + 10];
+        let mut wire =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", long.len()).into_bytes();
+        wire.extend_from_slice(&long);
+        let mut wire = wire.as_slice();
+        let head = read_head(&mut wire).await.unwrap();
+        let body = read_body(&mut wire, "GET", head).await.unwrap();
+        assert_eq!(body.len(), MAX_BODY_CAPTURE_BYTES);
+    }
+
+    #[tokio::test]
+    async fn endless_interim_responses_are_refused() {
+        let wire = b"HTTP/1.1 102 Processing\r\n\r\n".repeat(MAX_INTERIM_RESPONSES + 2);
+        let err = read_head(&mut wire.as_slice()).await.err().unwrap();
+        assert!(err.to_string().contains("interim"), "{err}");
+    }
+}
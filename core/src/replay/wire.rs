@@ -0,0 +1,145 @@
+//! Wire capture for replays
+//!
+//! reqwest hides the bytes it exchanges with the upstream, so a replay with wire
+//! capture is sent through a local single-purpose forward proxy. The relay opens
+//! the real upstream connection itself and records every byte on that leg into a
+//! transcript, which is written next to the traffic database when the replay ends.
+//! HTTPS requests are tunneled with CONNECT, so their capture holds TLS records.
+
+use crate::proxy::transcript::Recorded;
+use crate::storage;
+use anyhow::{anyhow, Context};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Largest request head the relay will buffer before giving up
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// A running relay recording one replay's upstream traffic under `transcript_id`.
+pub(crate) struct WireCapture {
+    transcript_id: String,
+    addr: SocketAddr,
+    proxy_url: String,
+    task: JoinHandle<()>,
+}
+
+impl WireCapture {
+    pub(crate) async fn start(transcript_id: &str) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let proxy_url = format!("http://{}", addr);
+        let id = transcript_id.to_string();
+        let task = tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                let id = id.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = relay(client, &id).await {
+                        tracing::debug!("Wire capture relay error: {err}");
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            transcript_id: transcript_id.to_string(),
+            addr,
+            proxy_url,
+            task,
+        })
+    }
+
+    /// URL to configure as the replay client's proxy
+    pub(crate) fn proxy_url(&self) -> &str {
+        &self.proxy_url
+    }
+
+    /// Address of the relay, for clients that speak to the proxy directly
+    pub(crate) fn proxy_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop relaying and write the transcript to disk. Returns the file path,
+    /// or `None` if nothing was captured.
+    pub(crate) fn finish(self) -> anyhow::Result<Option<PathBuf>> {
+        self.task.abort();
+        let Some(transcript) = storage::get_connection_transcript(&self.transcript_id) else {
+            return Ok(None);
+        };
+        let dir = storage::storage_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("wire_captures");
+        std::fs::create_dir_all(&dir).with_context(|| format!("creating {:?}", dir))?;
+        let path = dir.join(format!("{}.json", self.transcript_id));
+        std::fs::write(&path, serde_json::to_vec_pretty(&transcript)?)
+            .with_context(|| format!("writing {:?}", path))?;
+        Ok(Some(path))
+    }
+}
+
+async fn relay(mut client: TcpStream, transcript_id: &str) -> anyhow::Result<()> {
+    let (head, rest) = read_head(&mut client).await?;
+    let head_text = String::from_utf8_lossy(&head).into_owned();
+    let request_line = head_text.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let version = parts.next().unwrap_or("HTTP/1.1");
+
+    if method.eq_ignore_ascii_case("CONNECT") {
+        let upstream = TcpStream::connect(target).await?;
+        client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+        let mut upstream = Recorded::upstream(upstream, Some(transcript_id));
+        if !rest.is_empty() {
+            upstream.write_all(&rest).await?;
+        }
+        tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        return Ok(());
+    }
+
+    // Plain HTTP arrives in absolute form; send the origin form a direct
+    // request would have used.
+    let url = reqwest::Url::parse(target).map_err(|e| anyhow!("bad proxy target: {e}"))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("proxy target has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let origin_form = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let header_block = &head_text[request_line.len()..];
+    let rewritten = format!("{method} {origin_form} {version}{header_block}");
+
+    let upstream = TcpStream::connect((host, port)).await?;
+    let mut upstream = Recorded::upstream(upstream, Some(transcript_id));
+    upstream.write_all(rewritten.as_bytes()).await?;
+    if !rest.is_empty() {
+        upstream.write_all(&rest).await?;
+    }
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// Read up to and including the blank line ending the request head.
+async fn read_head(client: &mut TcpStream) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = buf.split_off(pos + 4);
+            return Ok((buf, rest));
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err(anyhow!("request head too large"));
+        }
+        let n = client.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("client closed before sending a request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
@@ -0,0 +1,68 @@
+//! Saved replay environments, kept as JSON next to the traffic database.
+
+use crate::models::ReplayEnvironment;
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const ENVIRONMENTS_FILE: &str = "environments.json";
+
+/// Serializes read-modify-write cycles on the environments file
+static FILE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn environments_path() -> anyhow::Result<PathBuf> {
+    let dir =
+        super::storage_dir().ok_or_else(|| anyhow::anyhow!("transaction store not initialized"))?;
+    Ok(dir.join(ENVIRONMENTS_FILE))
+}
+
+fn read_all(path: &PathBuf) -> anyhow::Result<Vec<ReplayEnvironment>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).with_context(|| format!("parsing {:?}", path)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("reading {:?}", path)),
+    }
+}
+
+fn write_all(path: &PathBuf, environments: &[ReplayEnvironment]) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_vec_pretty(environments)?)
+        .with_context(|| format!("writing {:?}", path))
+}
+
+/// All saved environments, in the order they were first saved
+pub fn list_environments() -> anyhow::Result<Vec<ReplayEnvironment>> {
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    read_all(&environments_path()?)
+}
+
+/// Saved environment called `name`, if any
+pub fn get_environment(name: &str) -> anyhow::Result<Option<ReplayEnvironment>> {
+    Ok(list_environments()?.into_iter().find(|e| e.name == name))
+}
+
+/// Save an environment, replacing any existing one with the same name
+pub fn save_environment(environment: ReplayEnvironment) -> anyhow::Result<()> {
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = environments_path()?;
+    let mut environments = read_all(&path)?;
+    match environments.iter_mut().find(|e| e.name == environment.name) {
+        Some(existing) => *existing = environment,
+        None => environments.push(environment),
+    }
+    write_all(&path, &environments)
+}
+
+/// Delete the environment called `name`. Returns whether it existed.
+pub fn delete_environment(name: &str) -> anyhow::Result<bool> {
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = environments_path()?;
+    let mut environments = read_all(&path)?;
+    let before = environments.len();
+    environments.retain(|e| e.name != name);
+    if environments.len() == before {
+        return Ok(false);
+    }
+    write_all(&path, &environments)?;
+    Ok(true)
+}
@@ -1,16 +1,25 @@
 use anyhow::{anyhow, Context};
 use base64::{engine::general_purpose, Engine as _};
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
 
-use crate::models::{HttpMethod, HttpTransaction, TransactionState, TransactionTiming};
+use crate::models::{
+    percent_decode, ExportTimezone, HttpMethod, HttpTransaction, TransactionState,
+    TransactionTiming,
+};
 
 const HAR_VERSION: &str = "1.2";
 const CREATOR_NAME: &str = "Cheddar Proxy";
 const CREATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
 struct ParsedUrlParts {
     scheme: String,
@@ -145,6 +154,18 @@ struct HarResponse {
     headers_size: i64,
     #[serde(rename = "bodySize")]
     body_size: i64,
+    #[serde(rename = "_serverTiming", skip_serializing_if = "Vec::is_empty")]
+    server_timing: Vec<HarServerTiming>,
+}
+
+/// Non-standard `_serverTiming` entry mirroring the Server-Timing header
+#[derive(Serialize)]
+struct HarServerTiming {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -160,6 +181,8 @@ struct HarPostData {
     text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     encoding: Option<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    params: Vec<HarHeader>,
 }
 
 #[derive(Serialize)]
@@ -175,13 +198,19 @@ struct HarContent {
 
 #[derive(Serialize)]
 struct HarTimings {
+    /// -1 when not measured, per the HAR spec
+    blocked: i64,
     send: i64,
     wait: i64,
     receive: i64,
 }
 
-pub fn transactions_to_har(transactions: &[HttpTransaction]) -> Value {
-    let entries = transactions.iter().map(HarEntry::from).collect();
+/// Build a HAR log, rendering `startedDateTime` in the given timezone.
+pub fn transactions_to_har(transactions: &[HttpTransaction], timezone: ExportTimezone) -> Value {
+    let entries = transactions
+        .iter()
+        .map(|tx| HarEntry::from(tx, timezone))
+        .collect();
     serde_json::to_value(HarLog {
         log: HarLogInner {
             version: HAR_VERSION,
@@ -196,14 +225,11 @@ pub fn transactions_to_har(transactions: &[HttpTransaction]) -> Value {
 }
 
 impl HarEntry {
-    fn from(tx: &HttpTransaction) -> Self {
+    fn from(tx: &HttpTransaction, timezone: ExportTimezone) -> Self {
         let url = tx.full_url();
-        let started_datetime = Utc
-            .timestamp_millis_opt(tx.timing.start_time)
-            .single()
-            .unwrap_or(Utc::now())
-            .to_rfc3339();
+        let started_datetime = timezone.format_millis(tx.timing.start_time);
         let time = tx.timing.total_ms.unwrap_or(0) as i64;
+        let blocked = tx.timing.blocked_ms.map(|ms| (ms as i64).min(time));
         Self {
             started_datetime,
             time,
@@ -211,8 +237,10 @@ impl HarEntry {
             response: HarResponse::from(tx),
             cache: HashMap::new(),
             timings: HarTimings {
+                blocked: blocked.unwrap_or(-1),
                 send: 0,
-                wait: time,
+                // Phases must add up to `time`
+                wait: time - blocked.unwrap_or(0),
                 receive: 0,
             },
         }
@@ -230,8 +258,11 @@ impl HarRequest {
             })
             .collect();
         let query_string = parse_query_pairs(url);
-        let (body_size, post_data) =
-            encode_body(&tx.request_body, tx.request_content_type.as_deref());
+        let (body_size, post_data) = encode_body(
+            &tx.request_body,
+            tx.request_content_type.as_deref(),
+            &tx.form_fields,
+        );
         Self {
             method: tx.method.to_string(),
             url: url.to_string(),
@@ -263,13 +294,25 @@ impl HarResponse {
         Self {
             status: tx.status_code.map(|c| c as i64).unwrap_or(0),
             status_text: tx.status_message.clone().unwrap_or_default(),
-            http_version: tx.http_version.clone(),
+            http_version: tx
+                .upstream_http_version
+                .clone()
+                .unwrap_or_else(|| tx.http_version.clone()),
             headers,
             cookies: Vec::new(),
             content,
             redirect_url: String::new(),
             headers_size: -1,
             body_size,
+            server_timing: tx
+                .server_timing
+                .iter()
+                .map(|(name, duration, description)| HarServerTiming {
+                    name: name.clone(),
+                    duration: *duration,
+                    description: description.clone(),
+                })
+                .collect(),
         }
     }
 }
@@ -315,7 +358,11 @@ impl HarContent {
     }
 }
 
-fn encode_body(body: &Option<Vec<u8>>, mime_type: Option<&str>) -> (i64, Option<HarPostData>) {
+fn encode_body(
+    body: &Option<Vec<u8>>,
+    mime_type: Option<&str>,
+    form_fields: &[(String, String)],
+) -> (i64, Option<HarPostData>) {
     if let Some(bytes) = body {
         let (text, encoding) = match String::from_utf8(bytes.clone()) {
             Ok(text) => (text, None),
@@ -327,6 +374,13 @@ fn encode_body(body: &Option<Vec<u8>>, mime_type: Option<&str>) -> (i64, Option<
                 mime_type: mime_type.unwrap_or("application/octet-stream").to_string(),
                 text,
                 encoding,
+                params: form_fields
+                    .iter()
+                    .map(|(name, value)| HarHeader {
+                        name: name.clone(),
+                        value: value.clone(),
+                    })
+                    .collect(),
             }),
         )
     } else {
@@ -349,50 +403,13 @@ fn split_query(query: &str) -> Vec<HarHeader> {
         .filter(|segment| !segment.is_empty())
         .map(|segment| {
             let mut parts = segment.splitn(2, '=');
-            let name = percent_decode(parts.next().unwrap_or_default());
-            let value = percent_decode(parts.next().unwrap_or_default());
+            let name = percent_decode(parts.next().unwrap_or_default().as_bytes(), true);
+            let value = percent_decode(parts.next().unwrap_or_default().as_bytes(), true);
             HarHeader { name, value }
         })
         .collect()
 }
 
-fn percent_decode(input: &str) -> String {
-    let bytes = input.as_bytes();
-    let mut output = String::with_capacity(bytes.len());
-    let mut i = 0;
-    while i < bytes.len() {
-        match bytes[i] {
-            b'%' if i + 2 < bytes.len() => {
-                if let (Some(h), Some(l)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
-                    output.push((h << 4 | l) as char);
-                    i += 3;
-                    continue;
-                }
-                output.push('%');
-                i += 1;
-            }
-            b'+' => {
-                output.push(' ');
-                i += 1;
-            }
-            ch => {
-                output.push(ch as char);
-                i += 1;
-            }
-        }
-    }
-    output
-}
-
-fn hex_value(byte: u8) -> Option<u8> {
-    match byte {
-        b'0'..=b'9' => Some(byte - b'0'),
-        b'a'..=b'f' => Some(byte - b'a' + 10),
-        b'A'..=b'F' => Some(byte - b'A' + 10),
-        _ => None,
-    }
-}
-
 #[derive(Deserialize)]
 struct RawHar {
     log: RawHarLog,
@@ -437,7 +454,7 @@ struct RawHarResponse {
     #[serde(rename = "statusText")]
     status_text: Option<String>,
     #[serde(rename = "httpVersion")]
-    _http_version: Option<String>,
+    http_version: Option<String>,
     headers: Option<Vec<HarHeader>>,
     content: Option<RawHarContent>,
 }
@@ -481,14 +498,23 @@ fn entry_to_transaction(entry: RawHarEntry) -> anyhow::Result<HttpTransaction> {
     let port = parts.port;
     let path = parts.path_and_query;
 
-    let request_headers = headers_to_map(entry.request.headers.unwrap_or_default());
+    let request_header_list: Vec<(String, String)> = entry
+        .request
+        .headers
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|h| !h.name.starts_with(':'))
+        .map(|h| (h.name, h.value))
+        .collect();
+    let request_headers = request_header_list.iter().cloned().collect();
     let (request_body, request_content_type) = decode_body(entry.request.post_data);
 
     let response = entry.response;
+    let upstream_http_version = response.as_ref().and_then(|r| r.http_version.clone());
     let (status_code, status_text, response_headers, response_body, response_mime, response_size) =
         match response {
             Some(resp) => {
-                let headers = resp.headers.map(headers_to_map).unwrap_or_default();
+                let headers = resp.headers.map(headers_to_list).unwrap_or_default();
                 let (body, mime, size) = decode_content(resp.content);
                 (
                     resp.status.map(|s| s as u16),
@@ -501,6 +527,8 @@ fn entry_to_transaction(entry: RawHarEntry) -> anyhow::Result<HttpTransaction> {
             }
             None => (None, None, None, None, None, None),
         };
+    let response_header_list = response_headers.clone().unwrap_or_default();
+    let response_headers = response_headers.map(|list| list.into_iter().collect());
 
     let start_time = parse_start_time(entry.started_datetime);
     let timing = TransactionTiming {
@@ -509,43 +537,82 @@ fn entry_to_transaction(entry: RawHarEntry) -> anyhow::Result<HttpTransaction> {
         ..TransactionTiming::default()
     };
 
-    Ok(HttpTransaction {
+    let mut tx = HttpTransaction {
         id: uuid::Uuid::new_v4().to_string(),
         method,
         scheme,
         host,
-        port: port as u16,
+        port,
         path,
+        query_redacted: false,
         http_version: entry
             .request
             .http_version
+            .clone()
             .unwrap_or_else(|| "HTTP/1.1".to_string()),
+        client_http_version: entry.request.http_version,
+        upstream_http_version,
         state: TransactionState::Completed,
         request_headers,
+        request_header_list,
         request_body,
         request_content_type,
+        request_body_sha256: None,
+        form_fields: Vec::new(),
         status_code,
         status_message: status_text,
         response_headers,
+        response_header_list,
         response_body,
         response_content_type: response_mime,
+        response_body_sha256: None,
+        response_body_encoding: None,
+        grpc_frames: Vec::new(),
+        grpc_status: None,
+        grpc_message: None,
+        request_body_entropy: None,
+        response_body_entropy: None,
+        server_timing: Vec::new(),
+        jsonrpc_method: None,
+        jsonrpc_id: None,
+        jsonrpc_error_code: None,
+        pagination: None,
         timing,
         response_size,
         has_breakpoint: false,
         notes: None,
+        pinned: false,
+        tags: Vec::new(),
+        occurrence_count: 1,
         server_ip: None,
+        server_geo: None,
         tls_version: None,
         tls_cipher: None,
+        tls_resumed: None,
         connection_reused: false,
+        upstream_connection_reused: false,
+        transcript_id: None,
+        url_credentials: None,
+        request_target_form: None,
+        security_warnings: Vec::new(),
+        conformance_notes: Vec::new(),
+        applied_actions: Vec::new(),
+        client_ja3: None,
+        client_ja4: None,
+        was_intercepted: false,
         is_websocket: false,
-    })
+        h2c_upgrade: None,
+    };
+    tx.refresh_form_fields();
+    tx.refresh_grpc_frames();
+    tx.refresh_server_timing();
+    tx.refresh_pagination();
+    tx.refresh_jsonrpc();
+    Ok(tx)
 }
 
-fn headers_to_map(headers: Vec<HarHeader>) -> HashMap<String, String> {
-    headers
-        .into_iter()
-        .map(|h| (h.name, h.value))
-        .collect::<HashMap<_, _>>()
+fn headers_to_list(headers: Vec<HarHeader>) -> Vec<(String, String)> {
+    headers.into_iter().map(|h| (h.name, h.value)).collect()
 }
 
 fn decode_body(post_data: Option<RawHarPostData>) -> (Option<Vec<u8>>, Option<String>) {
@@ -602,16 +669,40 @@ fn parse_start_time(value: Option<String>) -> i64 {
     Utc::now().timestamp_millis()
 }
 
+/// Returns true when the path should be read or written as gzip (`.gz` suffix).
+pub fn is_gzip_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("gz"))
+        .unwrap_or(false)
+}
+
+/// Export transactions as HAR. Paths ending in `.gz` are written gzip-compressed.
 pub async fn export_har_to_path(
     transactions: Vec<HttpTransaction>,
     output_path: impl AsRef<Path>,
+    timezone: ExportTimezone,
 ) -> anyhow::Result<usize> {
     if transactions.is_empty() {
         return Err(anyhow!("No transactions to export"));
     }
-    let value = transactions_to_har(&transactions);
-    let json = serde_json::to_string_pretty(&value)?;
-    std::fs::write(output_path, json).context("writing HAR file")?;
+    timezone.validate()?;
+    let output_path = output_path.as_ref();
+    let value = transactions_to_har(&transactions, timezone);
+    let file = File::create(output_path).context("creating HAR file")?;
+    let writer = BufWriter::new(file);
+    if is_gzip_path(output_path) {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        serde_json::to_writer_pretty(&mut encoder, &value).context("writing HAR file")?;
+        encoder
+            .finish()
+            .and_then(|mut inner| inner.flush())
+            .context("finishing gzip HAR file")?;
+    } else {
+        let mut writer = writer;
+        serde_json::to_writer_pretty(&mut writer, &value).context("writing HAR file")?;
+        writer.flush().context("writing HAR file")?;
+    }
     Ok(transactions.len())
 }
 
@@ -626,7 +717,122 @@ pub fn import_har_from_str(contents: &str) -> anyhow::Result<Vec<HttpTransaction
     }
 }
 
+/// Import a HAR file, transparently decompressing `.har.gz` files or any file
+/// that starts with the gzip magic header.
 pub fn import_har_from_path(path: impl AsRef<Path>) -> anyhow::Result<Vec<HttpTransaction>> {
-    let data = std::fs::read_to_string(path).context("reading HAR file")?;
+    let path = path.as_ref();
+    let raw = std::fs::read(path).context("reading HAR file")?;
+    let data = if is_gzip_path(path) || raw.starts_with(&GZIP_MAGIC) {
+        let mut decoded = String::new();
+        MultiGzDecoder::new(raw.as_slice())
+            .read_to_string(&mut decoded)
+            .context("decompressing gzip HAR file")?;
+        decoded
+    } else {
+        String::from_utf8(raw).context("HAR file is not valid UTF-8")?
+    };
     import_har_from_str(&data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::HttpMethod;
+    use tempfile::tempdir;
+
+    fn sample(path: &str, status: u16) -> HttpTransaction {
+        let mut headers = HashMap::new();
+        headers.insert("Accept".to_string(), "application/json".to_string());
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Get,
+            "https",
+            "api.example.com",
+            443,
+            path,
+            headers,
+        );
+        tx.status_code = Some(status);
+        tx.response_body = Some(br#"{"ok":true}"#.to_vec());
+        tx.response_content_type = Some("application/json".to_string());
+        tx.state = TransactionState::Completed;
+        tx
+    }
+
+    #[tokio::test]
+    async fn gzip_har_round_trip_preserves_entries() {
+        let dir = tempdir().expect("temp dir");
+        let path = dir.path().join("capture.har.gz");
+        let transactions = vec![sample("/a", 200), sample("/b?x=1", 404), sample("/c", 500)];
+
+        let exported = export_har_to_path(transactions, &path, ExportTimezone::Utc)
+            .await
+            .expect("export ok");
+        assert_eq!(exported, 3);
+
+        let raw = std::fs::read(&path).expect("read exported file");
+        assert!(raw.starts_with(&GZIP_MAGIC), "output is gzip compressed");
+
+        let imported = import_har_from_path(&path).expect("import ok");
+        assert_eq!(imported.len(), exported);
+        let mut paths: Vec<_> = imported.iter().map(|tx| tx.path.clone()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/a", "/b?x=1", "/c"]);
+        assert!(imported
+            .iter()
+            .all(|tx| tx.response_body.as_deref() == Some(br#"{"ok":true}"#.as_slice())));
+    }
+
+    #[tokio::test]
+    async fn gzip_magic_detected_without_extension() {
+        let dir = tempdir().expect("temp dir");
+        let gz_path = dir.path().join("capture.har.gz");
+        export_har_to_path(vec![sample("/magic", 200)], &gz_path, ExportTimezone::Utc)
+            .await
+            .expect("export ok");
+
+        let renamed = dir.path().join("capture.har");
+        std::fs::rename(&gz_path, &renamed).expect("rename");
+        let imported = import_har_from_path(&renamed).expect("import ok");
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].path, "/magic");
+    }
+
+    #[test]
+    fn started_datetime_uses_configured_offset() {
+        let mut tx = sample("/tz", 200);
+        // 2024-01-15T12:00:00Z
+        tx.timing.start_time = 1_705_320_000_000;
+
+        let har = transactions_to_har(&[tx.clone()], ExportTimezone::Offset { minutes: 330 });
+        let started = har["log"]["entries"][0]["startedDateTime"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(started, "2024-01-15T17:30:00+05:30");
+
+        let har_utc = transactions_to_har(&[tx.clone()], ExportTimezone::Utc);
+        assert_eq!(
+            har_utc["log"]["entries"][0]["startedDateTime"],
+            "2024-01-15T12:00:00+00:00"
+        );
+
+        // Storage stays in UTC ms regardless of the rendered offset.
+        assert_eq!(parse_start_time(Some(started)), tx.timing.start_time);
+    }
+
+    #[tokio::test]
+    async fn export_rejects_out_of_range_offset() {
+        let dir = tempdir().expect("temp dir");
+        let path = dir.path().join("capture.har");
+
+        let err = export_har_to_path(
+            vec![sample("/tz", 200)],
+            &path,
+            ExportTimezone::Offset { minutes: 24 * 60 },
+        )
+        .await
+        .expect_err("offset of a full day is rejected");
+        assert!(err.to_string().contains("out of range"));
+        assert!(!path.exists());
+    }
+}
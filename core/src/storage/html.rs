@@ -0,0 +1,219 @@
+//! Self-contained HTML rendering of a captured transaction, for sharing with
+//! people who would not open a HAR file.
+
+use crate::models::HttpTransaction;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Bodies larger than this are truncated in the rendered page.
+pub const MAX_HTML_BODY_BYTES: usize = 256 * 1024;
+
+const STYLE: &str = "body{font-family:-apple-system,Segoe UI,sans-serif;margin:2rem;color:#222}\
+h1{font-size:1.2rem;word-break:break-all}h2{font-size:1rem;margin-top:2rem}\
+table{border-collapse:collapse;font-family:monospace;font-size:.85rem}\
+td{border-bottom:1px solid #eee;padding:.2rem .8rem .2rem 0;vertical-align:top}\
+td:first-child{font-weight:bold;white-space:nowrap}\
+pre{background:#f6f8fa;padding:1rem;overflow:auto;font-size:.85rem}\
+.note{color:#777;font-style:italic}.k{color:#0550ae}.s{color:#0a3069}\
+.n{color:#953800}.b{color:#cf222e}.z{color:#6e7781}";
+
+/// Render a transaction's request and response as a standalone HTML page.
+/// JSON bodies are pretty-printed and highlighted; other text is escaped.
+pub fn render_transaction_html(tx: &HttpTransaction) -> String {
+    let method = tx.method.to_string();
+    let url = tx.full_url();
+    let status = match tx.status_code {
+        Some(code) => format!(
+            "{} {}",
+            code,
+            tx.status_message.as_deref().unwrap_or_default()
+        ),
+        None => format!("{:?}", tx.state),
+    };
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <title>{method} {url}</title><style>{STYLE}</style></head><body>\n\
+         <h1>{method} {url}</h1>\n<p><b>Status:</b> {status}",
+        method = escape(&method),
+        url = escape(&url),
+        status = escape(status.trim()),
+    );
+    if let Some(total) = tx.timing.total_ms {
+        let _ = write!(out, " &middot; {total} ms");
+    }
+    out.push_str("</p>\n");
+
+    out.push_str("<h2>Request headers</h2>\n");
+    out.push_str(&headers_table(&tx.request_headers));
+    out.push_str("<h2>Request body</h2>\n");
+    out.push_str(&body_block(
+        tx.request_body.as_deref(),
+        tx.request_content_type.as_deref(),
+    ));
+
+    if let Some(headers) = &tx.response_headers {
+        out.push_str("<h2>Response headers</h2>\n");
+        out.push_str(&headers_table(headers));
+    }
+    out.push_str("<h2>Response body</h2>\n");
+    out.push_str(&body_block(
+        tx.response_body.as_deref(),
+        tx.response_content_type.as_deref(),
+    ));
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn headers_table(headers: &HashMap<String, String>) -> String {
+    if headers.is_empty() {
+        return "<p class=\"note\">None</p>\n".to_string();
+    }
+    let mut sorted: Vec<_> = headers.iter().collect();
+    sorted.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+    let mut out = String::from("<table>\n");
+    for (name, value) in sorted {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            escape(name),
+            escape(value)
+        );
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+fn body_block(body: Option<&[u8]>, content_type: Option<&str>) -> String {
+    let Some(body) = body.filter(|b| !b.is_empty()) else {
+        return "<p class=\"note\">Empty</p>\n".to_string();
+    };
+    let Ok(text) = std::str::from_utf8(body) else {
+        return format!(
+            "<p class=\"note\">Binary body ({} bytes) omitted</p>\n",
+            body.len()
+        );
+    };
+
+    let mut note = String::new();
+    let mut shown = text;
+    if text.len() > MAX_HTML_BODY_BYTES {
+        let mut cut = MAX_HTML_BODY_BYTES;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        shown = &text[..cut];
+        note = format!(
+            "<p class=\"note\">Truncated to {} of {} bytes</p>\n",
+            cut,
+            text.len()
+        );
+    }
+
+    let looks_json = content_type.is_some_and(|ct| ct.to_ascii_lowercase().contains("json"))
+        || matches!(text.trim_start().chars().next(), Some('{' | '['));
+    let rendered = looks_json
+        .then(|| serde_json::from_str::<Value>(shown).ok())
+        .flatten()
+        .map(|value| {
+            let mut html = String::new();
+            highlight_json(&value, 0, &mut html);
+            html
+        })
+        .unwrap_or_else(|| escape(shown));
+    format!("<pre>{rendered}</pre>\n{note}")
+}
+
+fn highlight_json(value: &Value, indent: usize, out: &mut String) {
+    let pad = |level: usize| "  ".repeat(level);
+    match value {
+        Value::Null => out.push_str("<span class=\"z\">null</span>"),
+        Value::Bool(b) => {
+            let _ = write!(out, "<span class=\"b\">{b}</span>");
+        }
+        Value::Number(n) => {
+            let _ = write!(out, "<span class=\"n\">{n}</span>");
+        }
+        Value::String(s) => {
+            let _ = write!(out, "<span class=\"s\">{}</span>", escape(&json_string(s)));
+        }
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad(indent + 1));
+                highlight_json(item, indent + 1, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (i, (key, item)) in map.iter().enumerate() {
+                let _ = write!(
+                    out,
+                    "{}<span class=\"k\">{}</span>: ",
+                    pad(indent + 1),
+                    escape(&json_string(key))
+                );
+                highlight_json(item, indent + 1, out);
+                out.push_str(if i + 1 < map.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push('}');
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::HttpMethod;
+
+    #[test]
+    fn html_shows_method_url_status_and_escaped_body() {
+        let mut tx = HttpTransaction::new(
+            HttpMethod::Post,
+            "https",
+            "api.example.com",
+            443,
+            "/search?q=<b>",
+            HashMap::new(),
+        );
+        tx.status_code = Some(201);
+        tx.status_message = Some("Created".to_string());
+        tx.response_content_type = Some("application/json".to_string());
+        tx.response_body = Some(br#"{"name":"<script>","count":2}"#.to_vec());
+
+        let html = render_transaction_html(&tx);
+        assert!(html.contains("<h1>POST https://api.example.com/search?q=&lt;b&gt;</h1>"));
+        assert!(html.contains("<b>Status:</b> 201 Created"));
+        assert!(html.contains("<span class=\"s\">&quot;&lt;script&gt;&quot;</span>"));
+        assert!(html.contains("<span class=\"n\">2</span>"));
+        assert!(!html.contains("<script>"));
+    }
+}
@@ -1,15 +1,26 @@
 //! Transaction storage and pagination
 
+mod environment_store;
 mod har;
+mod html;
+mod snippet;
 mod transaction_store;
+mod transcript_store;
 mod websocket_store;
 
+pub use environment_store::*;
+pub use transcript_store::*;
 pub use websocket_store::*;
 
-use crate::models::{HttpTransaction, PaginatedTransactions, TransactionFilter};
+use crate::models::{
+    BodySearchHit, BodySource, ExtractedField, FilterExpr, GeoInfo, HttpTransaction,
+    PaginatedTransactions, TransactionFilter,
+};
 use once_cell::sync::Lazy;
 use std::sync::{Arc, Mutex};
+pub(crate) use transaction_store::host_regex_pattern;
 use transaction_store::TransactionStore;
+pub use transaction_store::{StoreConfig, ABANDONED_NOTE, MIN_BODY_SEARCH_LEN};
 
 static STORE: Lazy<Mutex<Option<Arc<TransactionStore>>>> = Lazy::new(|| Mutex::new(None));
 const DEFAULT_RING_SIZE: usize = 10_000;
@@ -59,6 +70,31 @@ fn store() -> anyhow::Result<Arc<TransactionStore>> {
         .ok_or_else(|| anyhow::anyhow!("transaction store not initialized"))
 }
 
+/// Current paging policy of the transaction store
+pub fn store_config() -> anyhow::Result<StoreConfig> {
+    Ok(store()?.config())
+}
+
+/// Apply the store's paging policy to a page size (0 picks the default) for
+/// readers that page outside the transaction store, e.g. WebSocket listings
+pub fn clamp_page_size(requested: u32) -> u32 {
+    store_config()
+        .unwrap_or_default()
+        .effective_page_size(requested)
+}
+
+/// Replace the store's paging and persistence policy
+pub fn set_store_config(config: StoreConfig) -> anyhow::Result<()> {
+    store()?.set_config(config);
+    Ok(())
+}
+
+/// Directory holding the traffic database, if the store is initialized
+pub fn storage_dir() -> Option<std::path::PathBuf> {
+    let store = store().ok()?;
+    store.db_path().parent().map(|p| p.to_path_buf())
+}
+
 /// Persist a completed transaction to the ring buffer and SQLite
 pub async fn persist_transaction(tx: HttpTransaction) -> anyhow::Result<()> {
     if let Ok(store) = store() {
@@ -78,6 +114,16 @@ pub async fn query_transactions(
     store.query(filter, page, page_size).await
 }
 
+/// Query transactions matching a boolean combination of filters
+pub async fn query_transactions_expr(
+    expr: &FilterExpr,
+    page: u32,
+    page_size: u32,
+) -> anyhow::Result<PaginatedTransactions> {
+    let store = store()?;
+    store.query_expr(expr, page, page_size).await
+}
+
 /// Query transactions with time range bounds (start_time_ms and end_time_ms are Unix timestamps in milliseconds)
 pub async fn query_transactions_with_time_range(
     filter: &TransactionFilter,
@@ -92,12 +138,32 @@ pub async fn query_transactions_with_time_range(
         .await
 }
 
+/// List transactions in a time range without the paging policy, newest first.
+/// `limit` of `None` reads every match; meant for internal bulk readers.
+pub async fn list_transactions_with_time_range(
+    filter: &TransactionFilter,
+    start_time_ms: i64,
+    end_time_ms: i64,
+    limit: Option<u32>,
+) -> anyhow::Result<Vec<HttpTransaction>> {
+    let store = store()?;
+    store
+        .list_with_time_range(filter, start_time_ms, end_time_ms, limit)
+        .await
+}
+
 /// List all transactions that match the filter without pagination (used for exports).
 pub async fn list_transactions(filter: &TransactionFilter) -> anyhow::Result<Vec<HttpTransaction>> {
     let store = store()?;
     store.list(filter).await
 }
 
+/// Like [`list_transactions`], but for a boolean filter expression (tags, pins, combinators).
+pub async fn list_transactions_expr(expr: &FilterExpr) -> anyhow::Result<Vec<HttpTransaction>> {
+    let store = store()?;
+    store.list_expr(expr).await
+}
+
 /// List recent transactions up to a limit (ordered by started_at DESC).
 pub async fn list_recent_transactions(limit: u32) -> anyhow::Result<Vec<HttpTransaction>> {
     let store = store()?;
@@ -113,21 +179,95 @@ pub async fn list_transactions_page(
     store.list_page(before_started_at_ms, limit).await
 }
 
+/// Extract a scalar JSON-pointer value from the bodies of matching transactions.
+pub async fn extract_json_field(
+    filter: &TransactionFilter,
+    pointer: &str,
+    source: BodySource,
+) -> anyhow::Result<Vec<ExtractedField>> {
+    let store = store()?;
+    store.extract_json_field(filter, pointer, source).await
+}
+
+/// Find transactions whose request or response body contains `query`.
+pub async fn search_bodies(query: &str, limit: u32) -> anyhow::Result<Vec<BodySearchHit>> {
+    let store = store()?;
+    store.search_bodies(query, limit).await
+}
+
 /// Get a single transaction by ID
 pub async fn get_transaction_by_id(id: &str) -> anyhow::Result<Option<HttpTransaction>> {
     let store = store()?;
     store.get_by_id(id).await
 }
 
+/// Open an async reader over the stored response body of a transaction.
+/// Returns `None` if no transaction has this ID.
+pub async fn open_response_body(
+    id: &str,
+) -> anyhow::Result<Option<impl tokio::io::AsyncRead + Unpin>> {
+    let store = store()?;
+    Ok(store.get_response_body(id).await?.map(std::io::Cursor::new))
+}
+
+/// Get several transactions by ID in one query, in the requested order.
+/// IDs that do not exist are omitted.
+pub async fn get_transactions_by_ids(ids: &[String]) -> anyhow::Result<Vec<HttpTransaction>> {
+    let store = store()?;
+    store.get_by_ids(ids).await
+}
+
 /// Delete transactions older than the specified number of days
 pub async fn prune_older_than(days: u32) -> anyhow::Result<u64> {
     let store = store()?;
     store.prune_older_than(days).await
 }
 
-/// Delete all transactions from both memory and database
+/// Count transactions that pruning with `days` would delete
+pub async fn prune_preview(days: u32) -> anyhow::Result<u64> {
+    let store = store()?;
+    store.prune_preview(days).await
+}
+
+/// Pin or unpin a transaction so pruning keeps it
+pub async fn set_transaction_pinned(id: &str, pinned: bool) -> anyhow::Result<bool> {
+    let store = store()?;
+    store.set_pinned(id, pinned).await
+}
+
+/// Replace the tags of a transaction
+pub async fn set_transaction_tags(id: &str, tags: Vec<String>) -> anyhow::Result<bool> {
+    let store = store()?;
+    store.set_tags(id, tags).await
+}
+
+/// Set a transaction's server GeoIP annotation without rewriting the rest of it
+pub async fn set_transaction_server_geo(id: &str, geo: GeoInfo) -> anyhow::Result<bool> {
+    let store = store()?;
+    store.set_server_geo(id, geo).await
+}
+
+/// Mark transactions a previous run left in Pending/Breakpointed for longer
+/// than `max_age_secs` as Failed. Returns the transactions that were updated.
+pub async fn fail_abandoned_transactions(
+    max_age_secs: u64,
+) -> anyhow::Result<Vec<HttpTransaction>> {
+    let store = store()?;
+    let cutoff_ms = chrono::Utc::now().timestamp_millis() - (max_age_secs as i64 * 1000);
+    store.fail_abandoned(cutoff_ms).await
+}
+
+/// Import the transactions (and their WebSocket messages) of another capture
+/// database, skipping ids already stored. Returns the number imported.
+pub async fn merge_database(other_db_path: &str) -> anyhow::Result<u64> {
+    let store = store()?;
+    store.merge_from(std::path::Path::new(other_db_path)).await
+}
+
+/// Delete all transactions and WebSocket messages from both memory and database
 pub async fn clear_all_transactions() -> anyhow::Result<u64> {
     let store = store()?;
+    websocket_store::clear_all_websocket_messages();
     store.clear_all().await
 }
 
@@ -147,11 +287,32 @@ pub async fn get_transaction_count() -> anyhow::Result<u64> {
     store.count().await
 }
 
-/// Get unique hosts with request counts, sorted by count descending
-pub async fn list_unique_hosts(limit: u32) -> anyhow::Result<Vec<(String, u64)>> {
+/// Transaction counts per status code (0 = no response), ascending by code
+pub async fn status_breakdown(
+    filter: &TransactionFilter,
+    time_range: Option<(i64, i64)>,
+) -> anyhow::Result<Vec<(u16, u64)>> {
+    let store = store()?;
+    store.status_breakdown(filter, time_range).await
+}
+
+/// Get unique hosts with request counts and last-seen time (ms), sorted by
+/// count descending
+pub async fn list_unique_hosts(limit: u32) -> anyhow::Result<Vec<(String, u64, i64)>> {
     let store = store()?;
     store.list_unique_hosts(limit).await
 }
 
-pub use har::{export_har_to_path, import_har_from_path, import_har_from_str, transactions_to_har};
+/// Get hosts with request counts and last-seen time (ms), most recent first
+pub async fn list_recent_hosts(limit: u32) -> anyhow::Result<Vec<(String, u64, i64)>> {
+    let store = store()?;
+    store.recent_hosts(limit).await
+}
+
+pub use har::{
+    export_har_to_path, import_har_from_path, import_har_from_str, is_gzip_path,
+    transactions_to_har,
+};
+pub use html::{render_transaction_html, MAX_HTML_BODY_BYTES};
+pub use snippet::{curl_command, fetch_snippet, MAX_SNIPPET_BODY_BYTES};
 pub use transaction_store::TransactionFilterExt;
@@ -0,0 +1,146 @@
+//! Reproduction snippets (cURL, JavaScript fetch) for captured requests
+
+use crate::models::HttpTransaction;
+use base64::Engine;
+
+/// Bodies larger than this are truncated in snippets.
+pub const MAX_SNIPPET_BODY_BYTES: usize = 16 * 1024;
+
+/// Headers that would be wrong once the body is re-sent (or truncated).
+const SKIPPED_HEADERS: &[&str] = &["content-length"];
+
+/// Render a request as a cURL command. Bodies are sent byte-for-byte with
+/// `--data-binary`; a binary body is piped in through `base64 --decode`.
+/// Requests the proxy decrypted get `--insecure`, so the command also works
+/// when pointed back through the proxy and its CA is not trusted.
+pub fn curl_command(tx: &HttpTransaction) -> String {
+    let mut out = String::new();
+    let binary = binary_body(tx);
+    if let Some(body) = &binary {
+        out.push_str(&format!(
+            "printf '%s' {} | base64 --decode | ",
+            shell_quote(&base64::engine::general_purpose::STANDARD.encode(body))
+        ));
+    }
+    out.push_str(&format!(
+        "curl -X {} {}",
+        tx.method.to_string(),
+        shell_quote(&tx.full_url())
+    ));
+    if tx.was_intercepted {
+        out.push_str(" \\\n  --insecure");
+    }
+    for (name, value) in sorted_headers(tx) {
+        out.push_str(&format!(
+            " \\\n  -H {}",
+            shell_quote(&format!("{name}: {value}"))
+        ));
+    }
+    if binary.is_some() {
+        out.push_str(" \\\n  --data-binary @-");
+        return out;
+    }
+    match snippet_body(tx) {
+        SnippetBody::None => {}
+        SnippetBody::Text { text, note } => {
+            out.push_str(&format!(" \\\n  --data-binary {}", shell_quote(&text)));
+            if let Some(note) = note {
+                out.push_str(&format!("\n# {note}"));
+            }
+        }
+        SnippetBody::Binary(note) => out.push_str(&format!("\n# {note}")),
+    }
+    out
+}
+
+/// A non-UTF-8 request body small enough to inline as base64.
+fn binary_body(tx: &HttpTransaction) -> Option<&[u8]> {
+    tx.request_body
+        .as_deref()
+        .filter(|body| !body.is_empty() && body.len() <= MAX_SNIPPET_BODY_BYTES)
+        .filter(|body| std::str::from_utf8(body).is_err())
+}
+
+/// Render a request as a JavaScript `fetch` call.
+pub fn fetch_snippet(tx: &HttpTransaction) -> String {
+    let mut out = String::new();
+    let mut options = vec![format!("  method: {}", js_string(&tx.method.to_string()))];
+    let headers = sorted_headers(tx);
+    if !headers.is_empty() {
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|(name, value)| format!("    {}: {}", js_string(name), js_string(value)))
+            .collect();
+        options.push(format!("  headers: {{\n{}\n  }}", lines.join(",\n")));
+    }
+    match snippet_body(tx) {
+        SnippetBody::None => {}
+        SnippetBody::Text { text, note } => {
+            if let Some(note) = note {
+                out.push_str(&format!("// {note}\n"));
+            }
+            options.push(format!("  body: {}", js_string(&text)));
+        }
+        SnippetBody::Binary(note) => out.push_str(&format!("// {note}\n")),
+    }
+    out.push_str(&format!(
+        "fetch({}, {{\n{}\n}});",
+        js_string(&tx.full_url()),
+        options.join(",\n")
+    ));
+    out
+}
+
+enum SnippetBody {
+    None,
+    Text { text: String, note: Option<String> },
+    Binary(String),
+}
+
+fn snippet_body(tx: &HttpTransaction) -> SnippetBody {
+    let Some(body) = tx.request_body.as_deref().filter(|b| !b.is_empty()) else {
+        return SnippetBody::None;
+    };
+    let Ok(text) = std::str::from_utf8(body) else {
+        return SnippetBody::Binary(format!(
+            "Binary request body ({} bytes) omitted",
+            body.len()
+        ));
+    };
+    if text.len() <= MAX_SNIPPET_BODY_BYTES {
+        return SnippetBody::Text {
+            text: text.to_string(),
+            note: None,
+        };
+    }
+    let mut cut = MAX_SNIPPET_BODY_BYTES;
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    SnippetBody::Text {
+        text: text[..cut].to_string(),
+        note: Some(format!(
+            "Request body truncated to {} of {} bytes",
+            cut,
+            text.len()
+        )),
+    }
+}
+
+fn sorted_headers(tx: &HttpTransaction) -> Vec<(&String, &String)> {
+    let mut headers: Vec<_> = tx
+        .request_headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+        .collect();
+    headers.sort();
+    headers
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn js_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
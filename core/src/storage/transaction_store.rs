@@ -1,18 +1,108 @@
-use crate::models::{HttpTransaction, PaginatedTransactions, TransactionFilter};
+use crate::models::{
+    BodySearchHit, BodySource, ExtractedField, FilterExpr, GeoInfo, HttpTransaction,
+    PaginatedTransactions, TransactionFilter, TransactionState,
+};
+use crate::proxy::content_coding::decode_body;
 use anyhow::Context;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
 use rusqlite::types::Value;
-use rusqlite::{params, Connection};
-use std::collections::VecDeque;
+use rusqlite::{params, Connection, ErrorCode, OpenFlags};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::RwLock;
 
+/// How long SQLite itself waits on a locked database before reporting BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+/// Total insert attempts made when the database stays busy or locked.
+const WRITE_MAX_ATTEMPTS: u32 = 5;
+/// Initial delay between insert attempts; doubled after every retry.
+const WRITE_RETRY_BASE_DELAY: Duration = Duration::from_millis(20);
+
+/// Note recorded on transactions the reaper marks as failed.
+pub const ABANDONED_NOTE: &str = "Abandoned (never reached a final state)";
+
+/// Shortest text [`TransactionStore::search_bodies`] accepts; the trigram
+/// index cannot look up anything shorter.
+pub const MIN_BODY_SEARCH_LEN: usize = 3;
+/// Bytes of each body (after decoding) that go into the search index.
+const MAX_INDEXED_BODY_BYTES: usize = 1024 * 1024;
+/// Bytes of context kept on either side of a match in search snippets.
+const SNIPPET_CONTEXT_BYTES: usize = 40;
+
+/// Compiled filter regexes keyed by pattern, so matching a page of rows (in
+/// Rust or through SQLite's `regexp()`) compiles each pattern once.
+static REGEX_CACHE: Lazy<Mutex<HashMap<String, Regex>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// Distinct patterns kept before the cache is emptied.
+const REGEX_CACHE_CAPACITY: usize = 64;
+
+/// Compile `pattern`, reusing an earlier compilation of the same pattern.
+pub(crate) fn cached_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let mut cache = REGEX_CACHE.lock().expect("regex cache poisoned");
+    if let Some(re) = cache.get(pattern) {
+        return Ok(re.clone());
+    }
+    let re = Regex::new(pattern)?;
+    if cache.len() >= REGEX_CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(pattern.to_string(), re.clone());
+    Ok(re)
+}
+
+/// Hosts are case-insensitive, so host regexes are too.
+pub(crate) fn host_regex_pattern(pattern: &str) -> String {
+    format!("(?i){pattern}")
+}
+
+/// `regexp(pattern, text)` for SQL filters; an invalid pattern fails the query.
+fn register_regexp(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let pattern: String = ctx.get(0)?;
+            let text: Option<String> = ctx.get(1)?;
+            let re = cached_regex(&pattern)
+                .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+            Ok(text.is_some_and(|text| re.is_match(&text)))
+        },
+    )
+}
+
+/// Whether an error is transient lock contention worth retrying.
+fn is_contention_error(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked)
+    )
+}
+
 pub trait TransactionFilterExt {
     fn matches(&self, tx: &HttpTransaction) -> bool;
+
+    /// Fail if a `host_regex` or `path_regex` does not compile, rather than
+    /// accept a filter that silently matches nothing.
+    fn validate(&self) -> anyhow::Result<()>;
 }
 
 impl TransactionFilterExt for TransactionFilter {
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(pattern) = &self.host_regex {
+            cached_regex(&host_regex_pattern(pattern))
+                .map_err(|e| anyhow::anyhow!("invalid host_regex: {e}"))?;
+        }
+        if let Some(pattern) = &self.path_regex {
+            cached_regex(pattern).map_err(|e| anyhow::anyhow!("invalid path_regex: {e}"))?;
+        }
+        Ok(())
+    }
+
     fn matches(&self, tx: &HttpTransaction) -> bool {
         if let Some(method) = self.method {
             if tx.method != method {
@@ -37,6 +127,22 @@ impl TransactionFilterExt for TransactionFilter {
                 return false;
             }
         }
+        if let Some(pattern) = &self.host_regex {
+            let matched = cached_regex(&host_regex_pattern(pattern))
+                .map(|re| re.is_match(&tx.host))
+                .unwrap_or(false);
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.path_regex {
+            let matched = cached_regex(pattern)
+                .map(|re| re.is_match(&tx.path))
+                .unwrap_or(false);
+            if !matched {
+                return false;
+            }
+        }
         if let Some(min) = self.status_min {
             if tx.status_code.unwrap_or(0) < min {
                 return false;
@@ -47,15 +153,400 @@ impl TransactionFilterExt for TransactionFilter {
                 return false;
             }
         }
+        if let Some(min) = self.min_response_size {
+            if tx.response_size.unwrap_or(0) < min {
+                return false;
+            }
+        }
+        if let Some(max) = self.max_response_size {
+            if tx.response_size.unwrap_or(0) > max {
+                return false;
+            }
+        }
+        if let Some(method) = &self.jsonrpc_method {
+            if tx.jsonrpc_method.as_deref() != Some(method.as_str()) {
+                return false;
+            }
+        }
+        if let Some(fingerprint) = &self.client_fingerprint {
+            if tx.client_ja3.as_deref() != Some(fingerprint.as_str())
+                && tx.client_ja4.as_deref() != Some(fingerprint.as_str())
+            {
+                return false;
+            }
+        }
+        if let Some(pinned) = self.pinned {
+            if tx.pinned != pinned {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !tx.tags.iter().any(|t| t == tag) {
+                return false;
+            }
+        }
+        if let Some(hash) = &self.body_sha256 {
+            let hash = hash.to_ascii_lowercase();
+            if tx.request_body_sha256.as_deref() != Some(hash.as_str())
+                && tx.response_body_sha256.as_deref() != Some(hash.as_str())
+            {
+                return false;
+            }
+        }
         true
     }
 }
 
+impl TransactionFilterExt for FilterExpr {
+    fn matches(&self, tx: &HttpTransaction) -> bool {
+        match self {
+            FilterExpr::And(children) => children.iter().all(|c| c.matches(tx)),
+            FilterExpr::Or(children) => children.iter().any(|c| c.matches(tx)),
+            FilterExpr::Not(child) => !child.matches(tx),
+            FilterExpr::Leaf(filter) => filter.matches(tx),
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            FilterExpr::And(children) | FilterExpr::Or(children) => {
+                children.iter().try_for_each(|c| c.validate())
+            }
+            FilterExpr::Not(child) => child.validate(),
+            FilterExpr::Leaf(filter) => filter.validate(),
+        }
+    }
+}
+
+/// SQL conditions (ANDed together) for the set fields of a flat filter.
+fn filter_conditions(filter: &TransactionFilter) -> (Vec<String>, Vec<Value>) {
+    let mut clauses = Vec::new();
+    let mut params = Vec::new();
+
+    if let Some(method) = filter.method {
+        clauses.push("method = ?".to_string());
+        params.push(Value::from(method.to_string()));
+    }
+    if let Some(host) = &filter.host_contains {
+        clauses.push("LOWER(host) LIKE ?".to_string());
+        params.push(Value::from(format!("%{}%", host.to_ascii_lowercase())));
+    }
+    if let Some(path) = &filter.path_contains {
+        clauses.push("LOWER(path) LIKE ?".to_string());
+        params.push(Value::from(format!("%{}%", path.to_ascii_lowercase())));
+    }
+    if let Some(pattern) = &filter.host_regex {
+        clauses.push("regexp(?, host)".to_string());
+        params.push(Value::from(host_regex_pattern(pattern)));
+    }
+    if let Some(pattern) = &filter.path_regex {
+        clauses.push("regexp(?, path)".to_string());
+        params.push(Value::from(pattern.clone()));
+    }
+    if let Some(min) = filter.status_min {
+        clauses.push("status >= ?".to_string());
+        params.push(Value::from(min as i64));
+    }
+    if let Some(max) = filter.status_max {
+        clauses.push("status <= ?".to_string());
+        params.push(Value::from(max as i64));
+    }
+    if let Some(min) = filter.min_response_size {
+        clauses.push("COALESCE(json_extract(data, '$.response_size'), 0) >= ?".to_string());
+        params.push(Value::from(min as i64));
+    }
+    if let Some(max) = filter.max_response_size {
+        clauses.push("COALESCE(json_extract(data, '$.response_size'), 0) <= ?".to_string());
+        params.push(Value::from(max as i64));
+    }
+    if let Some(hash) = &filter.body_sha256 {
+        clauses.push(
+            "(json_extract(data, '$.request_body_sha256') = ? \
+             OR json_extract(data, '$.response_body_sha256') = ?)"
+                .to_string(),
+        );
+        let hash = hash.to_ascii_lowercase();
+        params.push(Value::from(hash.clone()));
+        params.push(Value::from(hash));
+    }
+    if let Some(method) = &filter.jsonrpc_method {
+        clauses.push("json_extract(data, '$.jsonrpc_method') = ?".to_string());
+        params.push(Value::from(method.clone()));
+    }
+    if let Some(fingerprint) = &filter.client_fingerprint {
+        clauses.push(
+            "(json_extract(data, '$.client_ja3') = ? \
+             OR json_extract(data, '$.client_ja4') = ?)"
+                .to_string(),
+        );
+        params.push(Value::from(fingerprint.clone()));
+        params.push(Value::from(fingerprint.clone()));
+    }
+    if let Some(pinned) = filter.pinned {
+        clauses.push("COALESCE(json_extract(data, '$.pinned'), 0) = ?".to_string());
+        params.push(Value::from(pinned as i64));
+    }
+    if let Some(tag) = &filter.tag {
+        clauses.push(
+            "EXISTS (SELECT 1 FROM json_each(data, '$.tags') WHERE json_each.value = ?)"
+                .to_string(),
+        );
+        params.push(Value::from(tag.clone()));
+    }
+
+    (clauses, params)
+}
+
+/// Compile an expression to a SQL predicate. `None` means "matches everything".
+fn expr_condition(expr: &FilterExpr, params: &mut Vec<Value>) -> Option<String> {
+    match expr {
+        FilterExpr::Leaf(filter) => {
+            let (clauses, leaf_params) = filter_conditions(filter);
+            if clauses.is_empty() {
+                return None;
+            }
+            params.extend(leaf_params);
+            Some(format!("({})", clauses.join(" AND ")))
+        }
+        FilterExpr::And(children) => {
+            let parts: Vec<String> = children
+                .iter()
+                .filter_map(|c| expr_condition(c, params))
+                .collect();
+            if parts.is_empty() {
+                None
+            } else {
+                Some(format!("({})", parts.join(" AND ")))
+            }
+        }
+        FilterExpr::Or(children) => {
+            let mut parts = Vec::with_capacity(children.len());
+            let mut or_params = Vec::new();
+            for child in children {
+                // Any unconstrained branch makes the whole OR unconstrained.
+                parts.push(expr_condition(child, &mut or_params)?);
+            }
+            if parts.is_empty() {
+                return Some("0".to_string());
+            }
+            params.extend(or_params);
+            Some(format!("({})", parts.join(" OR ")))
+        }
+        // NULL columns compare as unknown; treat them as "no match" before
+        // negating so NOT agrees with the in-memory `matches`.
+        FilterExpr::Not(child) => match expr_condition(child, params) {
+            Some(part) => Some(format!("(NOT COALESCE({part}, 0))")),
+            None => Some("0".to_string()),
+        },
+    }
+}
+
+/// Narrow a `WHERE` clause (possibly empty) to `started_at` within the range.
+fn with_time_bounds(
+    clause: String,
+    params: &mut Vec<Value>,
+    start_time_ms: i64,
+    end_time_ms: i64,
+) -> String {
+    params.push(Value::from(start_time_ms));
+    params.push(Value::from(end_time_ms));
+    if clause.is_empty() {
+        "WHERE started_at >= ? AND started_at <= ?".to_string()
+    } else {
+        format!("{} AND started_at >= ? AND started_at <= ?", clause)
+    }
+}
+
+/// Resolve a JSON pointer in a body, rendering scalar results as text.
+fn extract_scalar(body: &[u8], pointer: &str) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_slice(body).ok()?;
+    match json.pointer(pointer)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => None,
+        scalar => Some(scalar.to_string()),
+    }
+}
+
+/// Rows eligible for age-based pruning; `?1` is the cutoff in epoch ms.
+const PRUNABLE_CLAUSE: &str = "started_at < ?1 AND COALESCE(json_extract(data, '$.pinned'), 0) = 0";
+
+fn prune_cutoff_ms(days: u32) -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    now_ms - (days as i64 * 24 * 60 * 60 * 1000)
+}
+
+/// Body text as indexed for search: undone `Content-Encoding` where possible,
+/// read as lossy UTF-8.
+fn body_text(headers: Option<&HashMap<String, String>>, body: Option<&[u8]>) -> String {
+    let Some(body) = body.filter(|body| !body.is_empty()) else {
+        return String::new();
+    };
+    let decoded = headers
+        .and_then(|headers| {
+            headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-encoding"))
+        })
+        .and_then(|(_, coding)| decode_body(coding, body, MAX_INDEXED_BODY_BYTES));
+    let body = decoded.as_deref().unwrap_or(body);
+    String::from_utf8_lossy(&body[..body.len().min(MAX_INDEXED_BODY_BYTES)]).into_owned()
+}
+
+/// Searchable (request, response) body text of a transaction.
+fn body_texts(tx: &HttpTransaction) -> (String, String) {
+    (
+        body_text(Some(&tx.request_headers), tx.request_body.as_deref()),
+        body_text(tx.response_headers.as_ref(), tx.response_body.as_deref()),
+    )
+}
+
+/// Store the body text of the `transactions` row `rowid` in the search index.
+fn index_bodies(conn: &Connection, rowid: i64, bodies: &(String, String)) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO transaction_bodies (rowid, request_body, response_body)
+         VALUES (?1, ?2, ?3)",
+        params![rowid, bodies.0, bodies.1],
+    )?;
+    Ok(())
+}
+
+/// Index the bodies of transactions missing from the search index: rows
+/// stored before the index existed, or merged in from another database.
+fn index_missing_bodies(conn: &Connection) -> anyhow::Result<u64> {
+    let rowids = conn
+        .prepare(
+            "SELECT rowid FROM transactions
+             WHERE rowid NOT IN (SELECT rowid FROM transaction_bodies)",
+        )?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if rowids.is_empty() {
+        return Ok(0);
+    }
+    let batch = conn.unchecked_transaction()?;
+    {
+        let mut select = batch.prepare("SELECT data FROM transactions WHERE rowid = ?")?;
+        for &rowid in &rowids {
+            let data: String = select.query_row(params![rowid], |row| row.get(0))?;
+            let bodies = serde_json::from_str(&data)
+                .map(|tx| body_texts(&tx))
+                .unwrap_or_default();
+            index_bodies(&batch, rowid, &bodies)?;
+        }
+    }
+    batch.commit()?;
+    Ok(rowids.len() as u64)
+}
+
+/// `text` around the first case-insensitive occurrence of `query`, on one
+/// line, with `…` marking where it was cut.
+fn match_snippet(text: &str, query: &str) -> Option<String> {
+    let at = text
+        .to_ascii_lowercase()
+        .find(&query.to_ascii_lowercase())?;
+    let mut start = at.saturating_sub(SNIPPET_CONTEXT_BYTES);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (at + query.len() + SNIPPET_CONTEXT_BYTES).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.extend(
+        text[start..end]
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c }),
+    );
+    if end < text.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+/// The metadata of `tx` that lean mode persists: identity, request line,
+/// lifecycle state, status and timing, plus every field [`filter_conditions`]
+/// reads (pin, tags, response size, body hashes, JSON-RPC method and client
+/// fingerprints) so filters keep working. Everything else, notably headers,
+/// bodies and the other fields derived from them, is left at its default.
+fn lean_copy(tx: &HttpTransaction) -> HttpTransaction {
+    let mut lean = HttpTransaction::new(
+        tx.method,
+        &tx.scheme,
+        &tx.host,
+        tx.port,
+        &tx.path,
+        HashMap::new(),
+    );
+    lean.id = tx.id.clone();
+    lean.state = tx.state;
+    lean.status_code = tx.status_code;
+    lean.timing = tx.timing.clone();
+    lean.pinned = tx.pinned;
+    lean.tags = tx.tags.clone();
+    lean.response_size = tx.response_size;
+    lean.request_body_sha256 = tx.request_body_sha256.clone();
+    lean.response_body_sha256 = tx.response_body_sha256.clone();
+    lean.jsonrpc_method = tx.jsonrpc_method.clone();
+    lean.client_ja3 = tx.client_ja3.clone();
+    lean.client_ja4 = tx.client_ja4.clone();
+    lean
+}
+
+/// Largest page the store hands out unless configured otherwise
+pub const DEFAULT_MAX_PAGE_SIZE: u32 = 100;
+
+/// Paging and persistence policy shared by every caller of the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreConfig {
+    /// Page size used when a caller passes 0
+    pub default_page_size: u32,
+    /// Larger requested page sizes are clamped to this
+    pub max_page_size: u32,
+    /// Persist only metadata; headers and bodies live in the ring buffer until evicted
+    pub lean: bool,
+    /// Also evict ring buffer entries that started more than this many seconds ago
+    pub ring_max_age_secs: Option<u64>,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            default_page_size: 50,
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            lean: false,
+            ring_max_age_secs: None,
+        }
+    }
+}
+
+impl StoreConfig {
+    /// 0 means the default page size, and nothing above the max
+    pub fn effective_page_size(&self, requested: u32) -> u32 {
+        if requested == 0 {
+            self.default_page_size.min(self.max_page_size)
+        } else {
+            requested.min(self.max_page_size)
+        }
+    }
+}
+
 pub struct TransactionStore {
     ring: RwLock<VecDeque<HttpTransaction>>,
     max_len: usize,
     db: Arc<Mutex<Connection>>,
     db_path: PathBuf,
+    config: Mutex<StoreConfig>,
+    /// When this store was opened; only transactions started earlier can be
+    /// left over from a previous run
+    opened_at_ms: i64,
 }
 
 impl TransactionStore {
@@ -68,6 +559,8 @@ impl TransactionStore {
         let db_path = dir.join("cheddarproxy_traffic.sqlite");
         let conn = Connection::open(&db_path)
             .with_context(|| format!("opening database at {:?}", db_path))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        register_regexp(&conn)?;
         conn.execute_batch(
             "
             CREATE TABLE IF NOT EXISTS transactions (
@@ -85,47 +578,132 @@ impl TransactionStore {
                 ON transactions(host);
             CREATE INDEX IF NOT EXISTS idx_transactions_status
                 ON transactions(status);
+            CREATE VIRTUAL TABLE IF NOT EXISTS transaction_bodies
+                USING fts5(request_body, response_body, tokenize = 'trigram');
             ",
         )?;
+        super::websocket_store::create_table(&conn)?;
+        let indexed = index_missing_bodies(&conn)?;
+        if indexed > 0 {
+            tracing::info!("Indexed the bodies of {} stored transactions", indexed);
+        }
 
         Ok(Self {
             ring: RwLock::new(VecDeque::with_capacity(max_len)),
             max_len,
             db: Arc::new(Mutex::new(conn)),
             db_path,
+            config: Mutex::new(StoreConfig::default()),
+            opened_at_ms: chrono::Utc::now().timestamp_millis(),
         })
     }
 
+    pub fn config(&self) -> StoreConfig {
+        *self.config.lock().expect("config mutex poisoned")
+    }
+
+    /// Replace the paging policy. A max of 0 is treated as 1.
+    pub fn set_config(&self, config: StoreConfig) {
+        *self.config.lock().expect("config mutex poisoned") = StoreConfig {
+            max_page_size: config.max_page_size.max(1),
+            ..config
+        };
+    }
+
+    /// Add a transaction to the ring buffer and persist it to SQLite, along
+    /// with its body text for [`Self::search_bodies`].
+    ///
+    /// Inserts that hit lock contention are retried with exponential backoff. If the
+    /// database is still unavailable afterwards the transaction stays in the ring
+    /// buffer (so it remains viewable by ID) and the error is returned.
     pub async fn add_transaction(&self, tx: HttpTransaction) -> anyhow::Result<()> {
+        let config = self.config();
         {
             let mut ring = self.ring.write().await;
             ring.push_back(tx.clone());
             while ring.len() > self.max_len {
                 ring.pop_front();
             }
+            if let Some(max_age) = config.ring_max_age_secs {
+                let cutoff = chrono::Utc::now().timestamp_millis() - (max_age as i64 * 1000);
+                while ring
+                    .front()
+                    .is_some_and(|oldest| oldest.timing.start_time < cutoff)
+                {
+                    ring.pop_front();
+                }
+            }
         }
 
         let db = Arc::clone(&self.db);
-        let payload = serde_json::to_string(&tx)?;
+        let payload = if config.lean {
+            serde_json::to_string(&lean_copy(&tx))?
+        } else {
+            serde_json::to_string(&tx)?
+        };
         tokio::task::spawn_blocking(move || {
             let started_at = tx.timing.start_time;
             let method = tx.method.to_string();
             let host = tx.host.clone();
             let path = tx.path.clone();
             let status = tx.status_code.map(|s| s as i64);
+            // Lean mode keeps bodies out of the database, index included.
+            let bodies = if config.lean {
+                Default::default()
+            } else {
+                body_texts(&tx)
+            };
 
-            let conn = db.lock().expect("db mutex poisoned");
-            conn.execute(
-                "INSERT OR REPLACE INTO transactions
-                   (id, started_at, method, host, path, status, data)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![tx.id, started_at, method, host, path, status, payload],
-            )
-            .context("inserting transaction")
+            let mut delay = WRITE_RETRY_BASE_DELAY;
+            let mut attempt = 1;
+            loop {
+                // Held per attempt only, so other reads and writes get the
+                // connection while this insert backs off.
+                let conn = db.lock().expect("db mutex poisoned");
+                let result = (|| {
+                    let batch = conn.unchecked_transaction()?;
+                    // Replacing the row gives it a new rowid, so drop the old index entry.
+                    batch.execute(
+                        "DELETE FROM transaction_bodies
+                         WHERE rowid = (SELECT rowid FROM transactions WHERE id = ?1)",
+                        params![tx.id],
+                    )?;
+                    batch.execute(
+                        "INSERT OR REPLACE INTO transactions
+                           (id, started_at, method, host, path, status, data)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![tx.id, started_at, method, host, path, status, payload],
+                    )?;
+                    index_bodies(&batch, batch.last_insert_rowid(), &bodies)?;
+                    batch.commit()
+                })();
+                drop(conn);
+                match result {
+                    Ok(_) => return Ok(()),
+                    Err(err) if is_contention_error(&err) && attempt < WRITE_MAX_ATTEMPTS => {
+                        tracing::debug!(
+                            "Database busy inserting transaction {} (attempt {}), retrying in {:?}",
+                            tx.id,
+                            attempt,
+                            delay
+                        );
+                        std::thread::sleep(delay);
+                        delay *= 2;
+                        attempt += 1;
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            "Giving up persisting transaction {} after {} attempt(s); kept in memory only: {}",
+                            tx.id,
+                            attempt,
+                            err
+                        );
+                        return Err(err).context("inserting transaction");
+                    }
+                }
+            }
         })
-        .await??;
-
-        Ok(())
+        .await?
     }
 
     pub async fn query(
@@ -134,8 +712,35 @@ impl TransactionStore {
         page: u32,
         page_size: u32,
     ) -> anyhow::Result<PaginatedTransactions> {
-        let offset = page as i64 * page_size as i64;
+        filter.validate()?;
         let (clause, params) = self.build_where_clause(filter);
+        self.query_where(clause, params, page, page_size).await
+    }
+
+    /// Query transactions matching a boolean filter expression
+    pub async fn query_expr(
+        &self,
+        expr: &FilterExpr,
+        page: u32,
+        page_size: u32,
+    ) -> anyhow::Result<PaginatedTransactions> {
+        let mut params = Vec::new();
+        expr.validate()?;
+        let clause = expr_condition(expr, &mut params)
+            .map(|condition| format!("WHERE {condition}"))
+            .unwrap_or_default();
+        self.query_where(clause, params, page, page_size).await
+    }
+
+    async fn query_where(
+        &self,
+        clause: String,
+        params: Vec<Value>,
+        page: u32,
+        page_size: u32,
+    ) -> anyhow::Result<PaginatedTransactions> {
+        let page_size = self.config().effective_page_size(page_size);
+        let offset = page as i64 * page_size as i64;
         let db = Arc::clone(&self.db);
 
         let (items, total) = tokio::task::spawn_blocking(move || {
@@ -188,20 +793,13 @@ impl TransactionStore {
         page: u32,
         page_size: u32,
     ) -> anyhow::Result<PaginatedTransactions> {
+        let page_size = self.config().effective_page_size(page_size);
         let offset = page as i64 * page_size as i64;
-        let (base_clause, mut params) = self.build_where_clause(filter);
-
-        // Add time bounds
-        let time_conditions = if base_clause.is_empty() {
-            "WHERE started_at >= ? AND started_at <= ?".to_string()
-        } else {
-            format!("{} AND started_at >= ? AND started_at <= ?", base_clause)
-        };
-        params.push(Value::from(start_time_ms));
-        params.push(Value::from(end_time_ms));
+        filter.validate()?;
+        let (clause, mut params) = self.build_where_clause(filter);
+        let clause = with_time_bounds(clause, &mut params, start_time_ms, end_time_ms);
 
         let db = Arc::clone(&self.db);
-        let clause = time_conditions;
 
         let (items, total) = tokio::task::spawn_blocking(move || {
             let conn = db.lock().expect("db mutex poisoned");
@@ -244,14 +842,58 @@ impl TransactionStore {
         })
     }
 
+    /// Every transaction in the time range that matches the filter, newest first.
+    ///
+    /// Not subject to the paging policy: `limit` of `None` reads them all. Meant
+    /// for internal bulk readers (capture sessions, summaries), not the UI.
+    pub async fn list_with_time_range(
+        &self,
+        filter: &TransactionFilter,
+        start_time_ms: i64,
+        end_time_ms: i64,
+        limit: Option<u32>,
+    ) -> anyhow::Result<Vec<HttpTransaction>> {
+        filter.validate()?;
+        let (clause, mut params) = self.build_where_clause(filter);
+        let clause = with_time_bounds(clause, &mut params, start_time_ms, end_time_ms);
+        self.list_where(clause, params, limit).await
+    }
+
     pub async fn list(&self, filter: &TransactionFilter) -> anyhow::Result<Vec<HttpTransaction>> {
+        filter.validate()?;
         let (clause, params) = self.build_where_clause(filter);
+        self.list_where(clause, params, None).await
+    }
+
+    /// Like [`Self::list`], but for a boolean filter expression.
+    pub async fn list_expr(&self, expr: &FilterExpr) -> anyhow::Result<Vec<HttpTransaction>> {
+        let mut params = Vec::new();
+        expr.validate()?;
+        let clause = expr_condition(expr, &mut params)
+            .map(|condition| format!("WHERE {condition}"))
+            .unwrap_or_default();
+        self.list_where(clause, params, None).await
+    }
+
+    async fn list_where(
+        &self,
+        clause: String,
+        mut params: Vec<Value>,
+        limit: Option<u32>,
+    ) -> anyhow::Result<Vec<HttpTransaction>> {
+        let limit_clause = match limit {
+            Some(limit) => {
+                params.push(Value::from(limit as i64));
+                " LIMIT ?"
+            }
+            None => "",
+        };
         let db = Arc::clone(&self.db);
         let rows = tokio::task::spawn_blocking(move || {
             let conn = db.lock().expect("db mutex poisoned");
             let sql = format!(
-                "SELECT data FROM transactions {} ORDER BY started_at DESC",
-                clause
+                "SELECT data FROM transactions {} ORDER BY started_at DESC{}",
+                clause, limit_clause
             );
             let mut stmt = conn.prepare(&sql)?;
             let mut rows = stmt.query(rusqlite::params_from_iter(params.iter()))?;
@@ -268,6 +910,104 @@ impl TransactionStore {
         Ok(rows)
     }
 
+    /// Evaluate a JSON pointer against the request or response body of every
+    /// transaction matching the filter, newest first.
+    ///
+    /// Only the selected body column is pulled out of the stored JSON (via
+    /// `json_extract`), so full transactions are never deserialized. Bodies that
+    /// aren't JSON, or where the pointer is missing or not a scalar, are skipped.
+    pub async fn extract_json_field(
+        &self,
+        filter: &TransactionFilter,
+        pointer: &str,
+        source: BodySource,
+    ) -> anyhow::Result<Vec<ExtractedField>> {
+        filter.validate()?;
+        let (clause, params) = self.build_where_clause(filter);
+        let body_path = match source {
+            BodySource::Request => "$.request_body",
+            BodySource::Response => "$.response_body",
+        };
+        let pointer = pointer.to_string();
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            let conn = db.lock().expect("db mutex poisoned");
+            let body_filter = format!("json_extract(data, '{body_path}') IS NOT NULL");
+            let clause = if clause.is_empty() {
+                format!("WHERE {body_filter}")
+            } else {
+                format!("{clause} AND {body_filter}")
+            };
+            let sql = format!(
+                "SELECT id, json_extract(data, '{body_path}') FROM transactions {clause}
+                 ORDER BY started_at DESC"
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query(rusqlite::params_from_iter(params.iter()))?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                let body_json: String = row.get(1)?;
+                let Ok(body) = serde_json::from_str::<Vec<u8>>(&body_json) else {
+                    continue;
+                };
+                if let Some(value) = extract_scalar(&body, &pointer) {
+                    out.push(ExtractedField {
+                        transaction_id: id,
+                        value,
+                    });
+                }
+            }
+            Ok::<_, anyhow::Error>(out)
+        })
+        .await?
+    }
+
+    /// Transactions whose request or response body contains `query`
+    /// (case-insensitive, at least [`MIN_BODY_SEARCH_LEN`] characters), newest
+    /// first, each with a snippet of the text around the match.
+    ///
+    /// Backed by a trigram full-text index, so large captures are not scanned.
+    pub async fn search_bodies(
+        &self,
+        query: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<BodySearchHit>> {
+        if query.chars().count() < MIN_BODY_SEARCH_LEN {
+            anyhow::bail!("search text must be at least {MIN_BODY_SEARCH_LEN} characters");
+        }
+        // One quoted phrase, so the query is matched literally.
+        let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+        let query = query.to_string();
+        let capped_limit = limit.clamp(1, 10_000) as i64;
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            let conn = db.lock().expect("db mutex poisoned");
+            let mut stmt = conn.prepare(
+                "SELECT t.data, transaction_bodies.request_body, transaction_bodies.response_body
+                 FROM transaction_bodies JOIN transactions t ON t.rowid = transaction_bodies.rowid
+                 WHERE transaction_bodies MATCH ?1
+                 ORDER BY t.started_at DESC LIMIT ?2",
+            )?;
+            let mut rows = stmt.query(params![phrase, capped_limit])?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                let data: String = row.get(0)?;
+                let request_body: String = row.get(1)?;
+                let response_body: String = row.get(2)?;
+                let snippet = match_snippet(&request_body, &query)
+                    .or_else(|| match_snippet(&response_body, &query))
+                    .unwrap_or_default();
+                out.push(BodySearchHit {
+                    transaction: serde_json::from_str(&data)?,
+                    snippet,
+                });
+            }
+            Ok::<_, anyhow::Error>(out)
+        })
+        .await?
+    }
+
     /// List recent transactions up to a limit (ordered by started_at DESC)
     pub async fn list_recent(&self, limit: u32) -> anyhow::Result<Vec<HttpTransaction>> {
         let db = Arc::clone(&self.db);
@@ -335,6 +1075,7 @@ impl TransactionStore {
         threshold_ms: Option<u64>,
         limit: u32,
     ) -> anyhow::Result<Vec<HttpTransaction>> {
+        filter.validate()?;
         let (base_clause, mut params) = self.build_where_clause(filter);
 
         let duration_condition =
@@ -385,10 +1126,11 @@ impl TransactionStore {
 
     /// Get a transaction by ID, first checking ring buffer then database
     pub async fn get_by_id(&self, id: &str) -> anyhow::Result<Option<HttpTransaction>> {
-        // First check the ring buffer for faster access
+        // First check the ring buffer for faster access. A transaction persisted
+        // more than once (e.g. updated later) is in the ring once per update.
         {
             let ring = self.ring.read().await;
-            if let Some(tx) = ring.iter().find(|tx| tx.id == id) {
+            if let Some(tx) = ring.iter().rev().find(|tx| tx.id == id) {
                 return Ok(Some(tx.clone()));
             }
         }
@@ -414,31 +1156,82 @@ impl TransactionStore {
         Ok(result)
     }
 
-    fn build_where_clause(&self, filter: &TransactionFilter) -> (String, Vec<Value>) {
-        let mut clauses = Vec::new();
-        let mut params = Vec::new();
-
-        if let Some(method) = filter.method {
-            clauses.push("method = ?".to_string());
-            params.push(Value::from(method.to_string()));
-        }
-        if let Some(host) = &filter.host_contains {
-            clauses.push("LOWER(host) LIKE ?".to_string());
-            params.push(Value::from(format!("%{}%", host.to_ascii_lowercase())));
-        }
-        if let Some(path) = &filter.path_contains {
-            clauses.push("LOWER(path) LIKE ?".to_string());
-            params.push(Value::from(format!("%{}%", path.to_ascii_lowercase())));
-        }
-        if let Some(min) = filter.status_min {
-            clauses.push("status >= ?".to_string());
-            params.push(Value::from(min as i64));
+    /// Fetch only the stored response body of a transaction, skipping the
+    /// rest of the record. Returns `None` if no transaction has this ID and an
+    /// empty body if the transaction has no response body.
+    pub async fn get_response_body(&self, id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        {
+            let ring = self.ring.read().await;
+            if let Some(tx) = ring.iter().rev().find(|tx| tx.id == id) {
+                return Ok(Some(tx.response_body.clone().unwrap_or_default()));
+            }
         }
-        if let Some(max) = filter.status_max {
-            clauses.push("status <= ?".to_string());
-            params.push(Value::from(max as i64));
+
+        let db = Arc::clone(&self.db);
+        let id_owned = id.to_string();
+        tokio::task::spawn_blocking(move || -> anyhow::Result<Option<Vec<u8>>> {
+            let conn = db.lock().expect("db mutex poisoned");
+            let mut stmt = conn.prepare(
+                "SELECT json_extract(data, '$.response_body') FROM transactions WHERE id = ?",
+            )?;
+            let mut rows = stmt.query(params![id_owned])?;
+            let Some(row) = rows.next()? else {
+                return Ok(None);
+            };
+            let body: Option<String> = row.get(0)?;
+            match body {
+                Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+                None => Ok(Some(Vec::new())),
+            }
+        })
+        .await?
+    }
+
+    /// Fetch several transactions in one query, in the order of `ids`.
+    /// Unknown ids are skipped.
+    pub async fn get_by_ids(&self, ids: &[String]) -> anyhow::Result<Vec<HttpTransaction>> {
+        let mut found: std::collections::HashMap<String, HttpTransaction> = {
+            let ring = self.ring.read().await;
+            ring.iter()
+                .filter(|tx| ids.contains(&tx.id))
+                .map(|tx| (tx.id.clone(), tx.clone()))
+                .collect()
+        };
+
+        let missing: Vec<Value> = ids
+            .iter()
+            .filter(|id| !found.contains_key(*id))
+            .map(|id| Value::Text(id.clone()))
+            .collect();
+        if !missing.is_empty() {
+            let db = Arc::clone(&self.db);
+            let from_db =
+                tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<HttpTransaction>> {
+                    let conn = db.lock().expect("db mutex poisoned");
+                    let mut out = Vec::new();
+                    // Stay under SQLite's bound-parameter limit for large selections.
+                    for chunk in missing.chunks(500) {
+                        let placeholders = vec!["?"; chunk.len()].join(", ");
+                        let mut stmt = conn.prepare(&format!(
+                            "SELECT data FROM transactions WHERE id IN ({placeholders})"
+                        ))?;
+                        let mut rows = stmt.query(rusqlite::params_from_iter(chunk.iter()))?;
+                        while let Some(row) = rows.next()? {
+                            let data: String = row.get(0)?;
+                            out.push(serde_json::from_str(&data)?);
+                        }
+                    }
+                    Ok(out)
+                })
+                .await??;
+            found.extend(from_db.into_iter().map(|tx| (tx.id.clone(), tx)));
         }
 
+        Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
+    }
+
+    fn build_where_clause(&self, filter: &TransactionFilter) -> (String, Vec<Value>) {
+        let (clauses, params) = filter_conditions(filter);
         let clause = if clauses.is_empty() {
             String::new()
         } else {
@@ -447,27 +1240,45 @@ impl TransactionStore {
         (clause, params)
     }
 
-    /// Delete transactions older than the specified number of days and reclaim space
-    pub async fn prune_older_than(&self, days: u32) -> anyhow::Result<u64> {
+    /// Count the transactions [`Self::prune_older_than`] would delete, without deleting.
+    pub async fn prune_preview(&self, days: u32) -> anyhow::Result<u64> {
         let db = Arc::clone(&self.db);
-        let cutoff_ms = {
-            use std::time::{SystemTime, UNIX_EPOCH};
-            let now_ms = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as i64;
-            now_ms - (days as i64 * 24 * 60 * 60 * 1000)
-        };
-
-        let deleted = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+        let cutoff_ms = prune_cutoff_ms(days);
+        let count = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
             let conn = db.lock().expect("db mutex poisoned");
-            let count = conn.execute(
-                "DELETE FROM transactions WHERE started_at < ?1",
-                params![cutoff_ms],
-            )?;
-            if count > 0 {
-                // Reclaim disk space after deleting rows
-                conn.execute_batch("VACUUM")?;
+            let sql = format!("SELECT COUNT(*) FROM transactions WHERE {PRUNABLE_CLAUSE}");
+            let count: i64 = conn.query_row(&sql, params![cutoff_ms], |row| row.get(0))?;
+            Ok(count.max(0) as u64)
+        })
+        .await??;
+        Ok(count)
+    }
+
+    /// Delete unpinned transactions older than the specified number of days and reclaim space
+    pub async fn prune_older_than(&self, days: u32) -> anyhow::Result<u64> {
+        let db = Arc::clone(&self.db);
+        let cutoff_ms = prune_cutoff_ms(days);
+
+        let deleted = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+            let conn = db.lock().expect("db mutex poisoned");
+            let sql = format!("DELETE FROM transactions WHERE {PRUNABLE_CLAUSE}");
+            let count = conn.execute(&sql, params![cutoff_ms])?;
+            conn.execute(
+                "DELETE FROM transaction_bodies
+                 WHERE rowid NOT IN (SELECT rowid FROM transactions)",
+                [],
+            )?;
+            let messages = super::websocket_store::prune_messages(&conn, cutoff_ms)?;
+            if messages > 0 {
+                tracing::info!(
+                    "Pruned {} WebSocket messages older than {} days",
+                    messages,
+                    days
+                );
+            }
+            if count > 0 || messages > 0 {
+                // Reclaim disk space after deleting rows
+                conn.execute_batch("VACUUM")?;
             }
             Ok(count as u64)
         })
@@ -480,7 +1291,150 @@ impl TransactionStore {
         Ok(deleted)
     }
 
-    /// Delete all transactions and reclaim space
+    /// Pin or unpin a transaction. Returns false if the ID is unknown.
+    pub async fn set_pinned(&self, id: &str, pinned: bool) -> anyhow::Result<bool> {
+        let in_ring = {
+            let mut ring = self.ring.write().await;
+            let mut found = false;
+            for tx in ring.iter_mut().filter(|tx| tx.id == id) {
+                tx.pinned = pinned;
+                found = true;
+            }
+            found
+        };
+
+        let db = Arc::clone(&self.db);
+        let id_owned = id.to_string();
+        let updated = tokio::task::spawn_blocking(move || -> anyhow::Result<usize> {
+            let conn = db.lock().expect("db mutex poisoned");
+            Ok(conn.execute(
+                "UPDATE transactions SET data = json_set(data, '$.pinned', json(?1)) WHERE id = ?2",
+                params![pinned.to_string(), id_owned],
+            )?)
+        })
+        .await??;
+
+        Ok(in_ring || updated > 0)
+    }
+
+    /// Replace the tags of a transaction. Returns false if no transaction has this ID.
+    pub async fn set_tags(&self, id: &str, tags: Vec<String>) -> anyhow::Result<bool> {
+        let in_ring = {
+            let mut ring = self.ring.write().await;
+            let mut found = false;
+            for tx in ring.iter_mut().filter(|tx| tx.id == id) {
+                tx.tags = tags.clone();
+                found = true;
+            }
+            found
+        };
+
+        let db = Arc::clone(&self.db);
+        let id_owned = id.to_string();
+        let tags_json = serde_json::to_string(&tags)?;
+        let updated = tokio::task::spawn_blocking(move || -> anyhow::Result<usize> {
+            let conn = db.lock().expect("db mutex poisoned");
+            Ok(conn.execute(
+                "UPDATE transactions SET data = json_set(data, '$.tags', json(?1)) WHERE id = ?2",
+                params![tags_json, id_owned],
+            )?)
+        })
+        .await??;
+
+        Ok(in_ring || updated > 0)
+    }
+
+    /// Set the GeoIP annotation of a transaction, leaving the rest of the stored
+    /// row as it is. Returns false if no transaction has this ID.
+    pub async fn set_server_geo(&self, id: &str, geo: GeoInfo) -> anyhow::Result<bool> {
+        let in_ring = {
+            let mut ring = self.ring.write().await;
+            let mut found = false;
+            for tx in ring.iter_mut().filter(|tx| tx.id == id) {
+                tx.server_geo = Some(geo.clone());
+                found = true;
+            }
+            found
+        };
+
+        let db = Arc::clone(&self.db);
+        let id_owned = id.to_string();
+        let geo_json = serde_json::to_string(&geo)?;
+        let updated = tokio::task::spawn_blocking(move || -> anyhow::Result<usize> {
+            let conn = db.lock().expect("db mutex poisoned");
+            Ok(conn.execute(
+                "UPDATE transactions SET data = json_set(data, '$.server_geo', json(?1)) WHERE id = ?2",
+                params![geo_json, id_owned],
+            )?)
+        })
+        .await??;
+
+        Ok(in_ring || updated > 0)
+    }
+
+    /// Mark transactions left Pending or Breakpointed by a previous run (started
+    /// before this store was opened) and before `cutoff_ms` as Failed with
+    /// [`ABANDONED_NOTE`]. Transactions of the current run are never touched,
+    /// however long they stay open. Returns the updated transactions so
+    /// listeners can refresh them.
+    pub async fn fail_abandoned(&self, cutoff_ms: i64) -> anyhow::Result<Vec<HttpTransaction>> {
+        let cutoff_ms = cutoff_ms.min(self.opened_at_ms);
+        let abandon = |tx: &mut HttpTransaction| {
+            tx.state = TransactionState::Failed;
+            tx.notes = Some(ABANDONED_NOTE.to_string());
+        };
+        let is_stale = |tx: &HttpTransaction| {
+            matches!(
+                tx.state,
+                TransactionState::Pending | TransactionState::Breakpointed
+            ) && tx.timing.start_time < cutoff_ms
+        };
+
+        let mut updated: Vec<HttpTransaction> = Vec::new();
+        {
+            let mut ring = self.ring.write().await;
+            for tx in ring.iter_mut().filter(|tx| is_stale(tx)) {
+                abandon(tx);
+                updated.push(tx.clone());
+            }
+        }
+
+        let db = Arc::clone(&self.db);
+        let from_db =
+            tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<HttpTransaction>> {
+                let conn = db.lock().expect("db mutex poisoned");
+                let mut stmt = conn.prepare(
+                    "SELECT data FROM transactions
+                 WHERE started_at < ?1
+                   AND json_extract(data, '$.state') IN ('Pending', 'Breakpointed')",
+                )?;
+                let stale = stmt
+                    .query_map(params![cutoff_ms], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut out = Vec::with_capacity(stale.len());
+                for data in stale {
+                    let mut tx: HttpTransaction = serde_json::from_str(&data)?;
+                    abandon(&mut tx);
+                    conn.execute(
+                        "UPDATE transactions SET data = ?1 WHERE id = ?2",
+                        params![serde_json::to_string(&tx)?, tx.id],
+                    )?;
+                    out.push(tx);
+                }
+                Ok(out)
+            })
+            .await??;
+
+        // Prefer the ring copy: in lean mode the database row has no bodies.
+        for tx in from_db {
+            if !updated.iter().any(|u| u.id == tx.id) {
+                updated.push(tx);
+            }
+        }
+        Ok(updated)
+    }
+
+    /// Delete all transactions and WebSocket messages and reclaim space
     pub async fn clear_all(&self) -> anyhow::Result<u64> {
         // Clear the in-memory ring buffer
         {
@@ -493,6 +1447,8 @@ impl TransactionStore {
         let deleted = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
             let conn = db.lock().expect("db mutex poisoned");
             let count = conn.execute("DELETE FROM transactions", [])?;
+            conn.execute("DELETE FROM transaction_bodies", [])?;
+            conn.execute("DELETE FROM websocket_messages", [])?;
             conn.execute_batch("VACUUM")?;
             Ok(count as u64)
         })
@@ -502,6 +1458,61 @@ impl TransactionStore {
         Ok(deleted)
     }
 
+    /// Copy every transaction from another capture database into this one,
+    /// with the WebSocket messages of the imported ones. The other file is
+    /// opened read-only; ids already present here are skipped.
+    /// Returns the number of transactions imported.
+    pub async fn merge_from(&self, other_db: &Path) -> anyhow::Result<u64> {
+        if fs::canonicalize(other_db).ok() == fs::canonicalize(&self.db_path).ok() {
+            anyhow::bail!("cannot merge a database into itself");
+        }
+        let other = Connection::open_with_flags(
+            other_db,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("opening database at {:?}", other_db))?;
+        let db = Arc::clone(&self.db);
+        let imported = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+            let mut stmt = other.prepare(
+                "SELECT id, started_at, method, host, path, status, data FROM transactions",
+            )?;
+            let mut rows = stmt.query([])?;
+            let conn = db.lock().expect("db mutex poisoned");
+            let batch = conn.unchecked_transaction()?;
+            let mut imported_ids = HashSet::new();
+            {
+                let mut insert = batch.prepare(
+                    "INSERT OR IGNORE INTO transactions
+                       (id, started_at, method, host, path, status, data)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )?;
+                while let Some(row) = rows.next()? {
+                    let values: [Value; 7] = [
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                    ];
+                    let id: String = row.get(0)?;
+                    if insert.execute(rusqlite::params_from_iter(values))? > 0 {
+                        imported_ids.insert(id);
+                    }
+                }
+            }
+            super::websocket_store::copy_messages(&other, &batch, &imported_ids)?;
+            batch.commit()?;
+            index_missing_bodies(&conn)?;
+            Ok(imported_ids.len() as u64)
+        })
+        .await??;
+
+        tracing::info!("Merged {} transactions from {:?}", imported, other_db);
+        Ok(imported)
+    }
+
     /// Get the count of transactions in the database
     pub async fn count(&self) -> anyhow::Result<u64> {
         let db = Arc::clone(&self.db);
@@ -515,43 +1526,105 @@ impl TransactionStore {
         Ok(count)
     }
 
-    /// Get unique hosts with request counts, sorted by count descending
-    pub async fn list_unique_hosts(&self, limit: u32) -> anyhow::Result<Vec<(String, u64)>> {
+    /// Count matching transactions per status code, ascending by code. Transactions
+    /// without a response (pending, failed before headers) are counted under 0.
+    /// `time_range` bounds `started_at` (ms, inclusive).
+    pub async fn status_breakdown(
+        &self,
+        filter: &TransactionFilter,
+        time_range: Option<(i64, i64)>,
+    ) -> anyhow::Result<Vec<(u16, u64)>> {
+        filter.validate()?;
+        let (mut clauses, mut params) = filter_conditions(filter);
+        if let Some((start, end)) = time_range {
+            clauses.push("started_at >= ? AND started_at <= ?".to_string());
+            params.push(Value::from(start));
+            params.push(Value::from(end));
+        }
+        let where_clause = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<(u16, u64)>> {
+            let conn = db.lock().expect("db mutex poisoned");
+            let mut stmt = conn.prepare(&format!(
+                "SELECT COALESCE(status, 0) AS code, COUNT(*) FROM transactions {where_clause}
+                 GROUP BY code
+                 ORDER BY code"
+            ))?;
+            let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                let status: i64 = row.get(0)?;
+                let count: i64 = row.get(1)?;
+                out.push((status as u16, count as u64));
+            }
+            Ok(out)
+        })
+        .await?
+    }
+
+    /// Get unique hosts with request counts and latest `started_at`, sorted by
+    /// count descending
+    pub async fn list_unique_hosts(&self, limit: u32) -> anyhow::Result<Vec<(String, u64, i64)>> {
+        self.host_summary("cnt DESC", limit).await
+    }
+
+    /// Hosts with their request count and latest `started_at`, most recently
+    /// active first
+    pub async fn recent_hosts(&self, limit: u32) -> anyhow::Result<Vec<(String, u64, i64)>> {
+        self.host_summary("last_seen DESC", limit).await
+    }
+
+    async fn host_summary(
+        &self,
+        order_by: &'static str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<(String, u64, i64)>> {
         let db = Arc::clone(&self.db);
         let capped_limit = limit.clamp(1, 500) as i64;
 
-        let results = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<(String, u64)>> {
+        tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<(String, u64, i64)>> {
             let conn = db.lock().expect("db mutex poisoned");
-            let mut stmt = conn.prepare(
-                "SELECT host, COUNT(*) as cnt FROM transactions 
-                 GROUP BY host 
-                 ORDER BY cnt DESC 
-                 LIMIT ?",
-            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT host, COUNT(*) AS cnt, MAX(started_at) AS last_seen FROM transactions
+                 GROUP BY host
+                 ORDER BY {order_by}, host
+                 LIMIT ?"
+            ))?;
             let mut rows = stmt.query(params![capped_limit])?;
             let mut out = Vec::new();
             while let Some(row) = rows.next()? {
                 let host: String = row.get(0)?;
                 let count: i64 = row.get(1)?;
-                out.push((host, count as u64));
+                let last_seen: Option<i64> = row.get(2)?;
+                out.push((host, count as u64, last_seen.unwrap_or(0)));
             }
             Ok(out)
         })
-        .await??;
-
-        Ok(results)
+        .await?
     }
 
-    #[allow(dead_code)]
     pub fn db_path(&self) -> &Path {
         &self.db_path
     }
+
+    /// The SQLite connection, shared with the stores keeping their own tables
+    /// in the traffic database
+    pub(super) fn db(&self) -> Arc<Mutex<Connection>> {
+        Arc::clone(&self.db)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{HttpMethod, TransactionFilter, TransactionTiming};
+    use crate::models::{
+        HttpMethod, PaginationInfo, PaginationStyle, TransactionFilter, TransactionTiming,
+    };
     use std::collections::HashMap;
     use tempfile::tempdir;
 
@@ -584,6 +1657,150 @@ mod tests {
         tx
     }
 
+    #[tokio::test]
+    async fn reaper_fails_stale_pending_transactions() {
+        let dir = tempdir().expect("temp dir");
+        let path = dir.path().to_str().unwrap();
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let hour_ago = now - 60 * 60 * 1000;
+        {
+            // The previous run stopped with one transaction still pending.
+            let previous = TransactionStore::new(path, 10).expect("store initializes");
+            previous
+                .add_transaction(make_tx("stale", hour_ago))
+                .await
+                .unwrap();
+            let mut done = make_tx("done", hour_ago);
+            done.state = TransactionState::Completed;
+            previous.add_transaction(done).await.unwrap();
+        }
+
+        let store = TransactionStore::new(path, 10).unwrap();
+        let reaped = store.fail_abandoned(now - 60_000).await.unwrap();
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].id, "stale");
+        let stale = store.get_by_id("stale").await.unwrap().unwrap();
+        assert_eq!(stale.state, TransactionState::Failed);
+        assert_eq!(stale.notes.as_deref(), Some(ABANDONED_NOTE));
+
+        // Reaped once; nothing is left to fail on the next pass.
+        assert!(store.fail_abandoned(now).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reaper_leaves_open_transactions_of_the_current_run_alone() {
+        let dir = tempdir().expect("temp dir");
+        let store = TransactionStore::new(dir.path().to_str().unwrap(), 10).unwrap();
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut stream = make_tx("sse", now);
+        stream.status_code = Some(200);
+        stream.response_headers = Some(HashMap::from([(
+            "content-type".to_string(),
+            "text/event-stream".to_string(),
+        )]));
+        store.add_transaction(stream).await.unwrap();
+        let mut held = make_tx("held", now);
+        held.state = TransactionState::Breakpointed;
+        store.add_transaction(held).await.unwrap();
+
+        // Even a cutoff past their start does not reap a live stream or a
+        // request held at a breakpoint.
+        let reaped = store.fail_abandoned(i64::MAX).await.unwrap();
+        assert!(reaped.is_empty());
+        let stream = store.get_by_id("sse").await.unwrap().unwrap();
+        assert_eq!(stream.state, TransactionState::Pending);
+        let held = store.get_by_id("held").await.unwrap().unwrap();
+        assert_eq!(held.state, TransactionState::Breakpointed);
+    }
+
+    #[tokio::test]
+    async fn merge_imports_other_database_and_skips_existing_ids() {
+        let here = tempdir().expect("temp dir");
+        let there = tempdir().expect("temp dir");
+        let store = TransactionStore::new(here.path().to_str().unwrap(), 10).unwrap();
+        let other = TransactionStore::new(there.path().to_str().unwrap(), 10).unwrap();
+        for id in ["a", "b"] {
+            store.add_transaction(make_tx(id, 1)).await.unwrap();
+        }
+        for id in ["b", "c", "d"] {
+            let mut tx = make_tx(id, 2);
+            tx.host = "other.example.com".to_string();
+            tx.response_body = Some(format!("merged body {id}").into_bytes());
+            other.add_transaction(tx).await.unwrap();
+        }
+        // Messages of "b" stay behind with the transaction that was skipped.
+        for (id, connection) in [("b-1", "b"), ("c-1", "c"), ("c-2", "c")] {
+            other
+                .db()
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO websocket_messages (id, connection_id, direction, opcode,
+                        payload, payload_length, timestamp)
+                     VALUES (?1, ?2, 'client_to_server', 'text', x'6869', 2, 1)",
+                    params![id, connection],
+                )
+                .unwrap();
+        }
+
+        let imported = store.merge_from(other.db_path()).await.unwrap();
+        assert_eq!(imported, 2);
+        assert_eq!(store.count().await.unwrap(), 4);
+        let kept = store.get_by_id("b").await.unwrap().unwrap();
+        assert_eq!(kept.host, "example.com");
+        let merged = store.get_by_id("c").await.unwrap().unwrap();
+        assert_eq!(merged.host, "other.example.com");
+        let hits = store.search_bodies("merged body", 10).await.unwrap();
+        assert_eq!(hits.len(), 2, "imported rows are indexed, skipped ones not");
+        let message_ids = |db: Arc<Mutex<Connection>>| -> Vec<String> {
+            let conn = db.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT id FROM websocket_messages ORDER BY seq")
+                .unwrap();
+            let ids = stmt.query_map([], |row| row.get(0)).unwrap();
+            ids.collect::<Result<_, _>>().unwrap()
+        };
+        assert_eq!(message_ids(store.db()), ["c-1", "c-2"]);
+        assert!(store.merge_from(store.db_path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn status_breakdown_counts_each_code_including_no_response() {
+        let dir = tempdir().expect("temp dir");
+        let store = TransactionStore::new(dir.path().to_str().unwrap(), 10).unwrap();
+        let base_time = 1_700_000_000_000i64;
+        let statuses = [200, 200, 404, 500, 200, 0];
+        for (i, status) in statuses.into_iter().enumerate() {
+            let mut tx = sample_transaction(
+                "api.example.com",
+                status,
+                base_time + i as i64,
+                HttpMethod::Get,
+            );
+            if status == 0 {
+                tx.status_code = None;
+            }
+            store.add_transaction(tx).await.unwrap();
+        }
+        let other = sample_transaction("cdn.other.com", 302, base_time, HttpMethod::Get);
+        store.add_transaction(other).await.unwrap();
+
+        let filter = TransactionFilter {
+            host_contains: Some("example.com".to_string()),
+            ..Default::default()
+        };
+        let breakdown = store.status_breakdown(&filter, None).await.unwrap();
+        assert_eq!(breakdown, vec![(0, 1), (200, 3), (404, 1), (500, 1)]);
+
+        let windowed = store
+            .status_breakdown(&filter, Some((base_time + 2, base_time + 3)))
+            .await
+            .unwrap();
+        assert_eq!(windowed, vec![(404, 1), (500, 1)]);
+    }
+
     #[tokio::test]
     async fn add_transaction_persists_and_queries() {
         let dir = tempdir().expect("temp dir");
@@ -642,6 +1859,171 @@ mod tests {
         assert_eq!(result.items[0].status_code, Some(502));
     }
 
+    #[tokio::test]
+    async fn query_filters_by_host_and_path_regex() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+
+        for (host, path) in [
+            ("api.example.com", "/api/v1/users/42"),
+            ("api.example.com", "/api/v1/users/me"),
+            ("cdn.example.com", "/api/v2/users/7"),
+        ] {
+            let mut tx = sample_transaction(host, 200, 1_700_000_000_000, HttpMethod::Get);
+            tx.path = path.to_string();
+            store.add_transaction(tx).await.expect("add ok");
+        }
+
+        let filter = TransactionFilter {
+            host_regex: Some(r"^API\.".into()),
+            path_regex: Some(r"^/api/v\d+/users/\d+$".into()),
+            ..Default::default()
+        };
+        let result = store.query(&filter, 0, 10).await.expect("query ok");
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].path, "/api/v1/users/42");
+        assert!(filter.matches(&result.items[0]));
+
+        let invalid = TransactionFilter {
+            path_regex: Some("/users/(".into()),
+            ..Default::default()
+        };
+        let err = store.query(&invalid, 0, 10).await.unwrap_err();
+        assert!(err.to_string().starts_with("invalid path_regex"), "{err:#}");
+        let invalid_host = TransactionFilter {
+            host_regex: Some("[a-".into()),
+            ..Default::default()
+        };
+        assert!(invalid_host.validate().is_err());
+        assert!(FilterExpr::Not(Box::new(invalid_host.into()))
+            .validate()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn query_filters_by_response_size_range() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+
+        let base = 1_700_000_000_000i64;
+        for (i, size) in [Some(512u64), Some(8 * 1024 * 1024), None]
+            .into_iter()
+            .enumerate()
+        {
+            let mut tx = make_tx(&format!("size-{i}"), base + i as i64);
+            tx.response_size = size;
+            store.add_transaction(tx).await.expect("add ok");
+        }
+
+        let filter = TransactionFilter {
+            min_response_size: Some(1024 * 1024),
+            ..Default::default()
+        };
+        let result = store.query(&filter, 0, 10).await.expect("query ok");
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].id, "size-1");
+        assert!(filter.matches(&result.items[0]));
+
+        let small = TransactionFilter {
+            max_response_size: Some(1024),
+            ..Default::default()
+        };
+        let result = store.list(&small).await.expect("list ok");
+        let ids: Vec<_> = result.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, vec!["size-2", "size-0"]);
+    }
+
+    #[tokio::test]
+    async fn filter_expr_supports_or_and_not() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+
+        let base = 1_700_000_000_000i64;
+        for (i, (host, status)) in [
+            ("a.test", 500),
+            ("b.test", 404),
+            ("b.test", 200),
+            ("c.test", 503),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut tx = sample_transaction(host, status, base + i as i64, HttpMethod::Get);
+            tx.id = format!("tx-{i}");
+            store.add_transaction(tx).await.expect("add ok");
+        }
+
+        let host = |h: &str| {
+            FilterExpr::Leaf(TransactionFilter {
+                host_contains: Some(h.into()),
+                ..Default::default()
+            })
+        };
+        let errors = FilterExpr::Leaf(TransactionFilter {
+            status_min: Some(400),
+            ..Default::default()
+        });
+
+        // (host a OR host b) AND status >= 400
+        let either_failing = FilterExpr::And(vec![
+            FilterExpr::Or(vec![host("a.test"), host("b.test")]),
+            errors.clone(),
+        ]);
+        let result = store
+            .query_expr(&either_failing, 0, 10)
+            .await
+            .expect("query ok");
+        let ids: Vec<_> = result.items.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, vec!["tx-1", "tx-0"]);
+        assert!(result.items.iter().all(|tx| either_failing.matches(tx)));
+
+        // NOT status >= 400
+        let ok_only = FilterExpr::Not(Box::new(errors));
+        let result = store.query_expr(&ok_only, 0, 10).await.expect("query ok");
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].id, "tx-2");
+        assert!(ok_only.matches(&result.items[0]));
+    }
+
+    #[tokio::test]
+    async fn prune_preview_matches_prune_and_keeps_pinned() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let old = now - 10 * 24 * 60 * 60 * 1000;
+        for i in 0..3 {
+            store
+                .add_transaction(make_tx(&format!("old-{i}"), old + i))
+                .await
+                .expect("add old");
+        }
+        store
+            .add_transaction(make_tx("recent", now))
+            .await
+            .expect("add recent");
+        assert!(store.set_pinned("old-1", true).await.expect("pin"));
+        assert!(!store.set_pinned("missing", true).await.expect("pin"));
+
+        let preview = store.prune_preview(5).await.expect("preview");
+        assert_eq!(preview, 2);
+        assert_eq!(
+            store.count().await.expect("count"),
+            4,
+            "preview deletes nothing"
+        );
+
+        let deleted = store.prune_older_than(5).await.expect("prune");
+        assert_eq!(deleted, preview);
+        let pinned = store.get_by_id("old-1").await.expect("get").expect("kept");
+        assert!(pinned.pinned);
+        assert_eq!(store.count().await.expect("count"), 2);
+    }
+
     #[tokio::test]
     async fn ring_buffer_enforces_max_length() {
         let dir = tempdir().expect("temp dir");
@@ -683,6 +2065,394 @@ mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_inserts_survive_external_lock() {
+        let dir = tempdir().expect("temp dir");
+        let store = Arc::new(
+            TransactionStore::new(dir.path().to_str().unwrap(), 1_000).expect("store initializes"),
+        );
+
+        // Another connection (e.g. a second process) holds the write lock for longer
+        // than the busy timeout, forcing the first inserts through the retry path.
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let db_path = store.db_path().to_path_buf();
+        let blocker = std::thread::spawn(move || {
+            let conn = Connection::open(db_path).expect("open second connection");
+            conn.execute_batch("BEGIN EXCLUSIVE").expect("take lock");
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(BUSY_TIMEOUT + Duration::from_millis(150));
+            conn.execute_batch("COMMIT").expect("release lock");
+        });
+        locked_rx.recv().unwrap();
+
+        let count = 50;
+        let handles: Vec<_> = (0..count)
+            .map(|i| {
+                let store = Arc::clone(&store);
+                tokio::spawn(async move {
+                    store
+                        .add_transaction(make_tx(&format!("tx-{i}"), 1_000 + i as i64))
+                        .await
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().expect("insert eventually succeeds");
+        }
+        blocker.join().unwrap();
+
+        let result = store
+            .query(&TransactionFilter::default(), 0, 100)
+            .await
+            .expect("query ok");
+        assert_eq!(result.total, count as u64);
+    }
+
+    #[tokio::test]
+    async fn extract_json_field_skips_missing_and_non_json_bodies() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+
+        let bodies: [(&str, &[u8]); 4] = [
+            ("a", br#"{"user":{"id":42}}"#),
+            ("b", br#"{"user":{"name":"no id"}}"#),
+            ("c", br#"{"user":{"id":"u-7"}}"#),
+            ("d", b"not json"),
+        ];
+        for (i, (id, body)) in bodies.iter().enumerate() {
+            let mut tx = make_tx(id, 1_000 + i as i64);
+            tx.response_body = Some(body.to_vec());
+            store.add_transaction(tx).await.expect("add ok");
+        }
+        store
+            .add_transaction(make_tx("no-body", 2_000))
+            .await
+            .expect("add ok");
+
+        let values = store
+            .extract_json_field(
+                &TransactionFilter::default(),
+                "/user/id",
+                BodySource::Response,
+            )
+            .await
+            .expect("extract ok");
+        let pairs: Vec<_> = values
+            .iter()
+            .map(|f| (f.transaction_id.as_str(), f.value.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("c", "u-7"), ("a", "42")]);
+
+        let request_values = store
+            .extract_json_field(
+                &TransactionFilter::default(),
+                "/user/id",
+                BodySource::Request,
+            )
+            .await
+            .expect("extract ok");
+        assert!(request_values.is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_clamps_page_size_to_configured_max() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+        for i in 0..5 {
+            store
+                .add_transaction(make_tx(&format!("page-{i}"), 1_700_000_000_000 + i))
+                .await
+                .expect("add ok");
+        }
+        store.set_config(StoreConfig {
+            default_page_size: 2,
+            max_page_size: 3,
+            ..StoreConfig::default()
+        });
+
+        let filter = TransactionFilter::default();
+        let result = store.query(&filter, 0, 1_000).await.expect("query ok");
+        assert_eq!(result.page_size, 3);
+        assert_eq!(result.items.len(), 3);
+        assert_eq!(result.total, 5);
+
+        let result = store
+            .query_with_time_range(&filter, 0, i64::MAX, 0, 0)
+            .await
+            .expect("query ok");
+        assert_eq!(result.page_size, 2);
+        assert_eq!(result.items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn list_with_time_range_ignores_page_limits() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+        for i in 0..5 {
+            store
+                .add_transaction(make_tx(&format!("bulk-{i}"), 1_700_000_000_000 + i))
+                .await
+                .expect("add ok");
+        }
+        store.set_config(StoreConfig {
+            default_page_size: 2,
+            max_page_size: 3,
+            ..StoreConfig::default()
+        });
+
+        let filter = TransactionFilter::default();
+        let all = store
+            .list_with_time_range(&filter, 1_700_000_000_001, i64::MAX, None)
+            .await
+            .expect("list ok");
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].id, "bulk-4");
+
+        let limited = store
+            .list_with_time_range(&filter, 0, i64::MAX, Some(2))
+            .await
+            .expect("list ok");
+        let ids: Vec<_> = limited.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, ["bulk-4", "bulk-3"]);
+    }
+
+    #[tokio::test]
+    async fn get_by_ids_preserves_order_and_skips_missing() {
+        let dir = tempdir().expect("temp dir");
+        // A ring of one keeps "first" in the database only.
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 1).expect("store initializes");
+        store
+            .add_transaction(make_tx("first", 1_000))
+            .await
+            .expect("add first");
+        store
+            .add_transaction(make_tx("second", 2_000))
+            .await
+            .expect("add second");
+
+        let ids = ["second", "missing", "first"].map(String::from);
+        let found: Vec<_> = store
+            .get_by_ids(&ids)
+            .await
+            .expect("lookup ok")
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect();
+        assert_eq!(found, vec!["second".to_string(), "first".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn ring_evicts_entries_older_than_max_age() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 100).expect("store initializes");
+        store.set_config(StoreConfig {
+            ring_max_age_secs: Some(60),
+            ..StoreConfig::default()
+        });
+
+        let now = chrono::Utc::now().timestamp_millis();
+        store
+            .add_transaction(make_tx("stale", now - 120_000))
+            .await
+            .expect("add stale");
+        store
+            .add_transaction(make_tx("fresh", now))
+            .await
+            .expect("add fresh");
+
+        let ring_ids: Vec<_> = store
+            .ring
+            .read()
+            .await
+            .iter()
+            .map(|tx| tx.id.clone())
+            .collect();
+        assert_eq!(ring_ids, vec!["fresh".to_string()]);
+
+        // Evicted from memory only; the database still has it.
+        let stale = store.get_by_id("stale").await.expect("lookup ok");
+        assert_eq!(stale.map(|tx| tx.id).as_deref(), Some("stale"));
+    }
+
+    #[tokio::test]
+    async fn search_bodies_finds_text_in_decoded_bodies() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(br#"{"user":{"email":"Alice@Example.com","role":"admin"}}"#)
+            .unwrap();
+        let mut older = make_tx("gzipped", 1);
+        older.response_headers = Some([("Content-Encoding".into(), "gzip".into())].into());
+        older.response_body = Some(gzip.finish().unwrap());
+        store.add_transaction(older).await.unwrap();
+
+        let mut newer = make_tx("plain", 2);
+        newer.request_body = Some(b"login=alice@example.com&remember=1".to_vec());
+        store.add_transaction(newer.clone()).await.unwrap();
+        // Persisting again replaces the index entry rather than adding one.
+        store.add_transaction(newer).await.unwrap();
+        store
+            .add_transaction(make_tx("unrelated", 3))
+            .await
+            .unwrap();
+
+        let hits = store.search_bodies("alice@example", 10).await.unwrap();
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.transaction.id.as_str()).collect();
+        assert_eq!(ids, ["plain", "gzipped"]);
+        assert_eq!(hits[0].snippet, "login=alice@example.com&remember=1");
+        assert_eq!(
+            hits[1].snippet,
+            r#"{"user":{"email":"Alice@Example.com","role":"admin"}}"#
+        );
+        assert_eq!(store.search_bodies("ALICE", 1).await.unwrap().len(), 1);
+        assert!(store.search_bodies("al", 10).await.is_err());
+
+        assert_eq!(
+            match_snippet(
+                &format!("{}needle{}", "a".repeat(50), "b".repeat(50)),
+                "needle"
+            )
+            .unwrap(),
+            format!("…{}needle{}…", "a".repeat(40), "b".repeat(40))
+        );
+
+        store.clear_all().await.unwrap();
+        assert!(store.search_bodies("alice", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn clear_all_also_deletes_websocket_messages() {
+        let dir = tempdir().expect("temp dir");
+        let store = TransactionStore::new(dir.path().to_str().unwrap(), 10).unwrap();
+        store.add_transaction(make_tx("ws", 1)).await.unwrap();
+        let count_messages = || {
+            store
+                .db()
+                .lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM websocket_messages", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .unwrap()
+        };
+        store
+            .db()
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO websocket_messages (id, connection_id, direction, opcode,
+                    payload, payload_length, timestamp)
+                 VALUES ('m1', 'ws', 'client_to_server', 'text', x'6869', 2, 1)",
+                [],
+            )
+            .unwrap();
+        assert_eq!(count_messages(), 1);
+
+        store.clear_all().await.unwrap();
+        assert_eq!(count_messages(), 0);
+    }
+
+    #[tokio::test]
+    async fn lean_mode_persists_metadata_only() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 10).expect("store initializes");
+        store.set_config(StoreConfig {
+            lean: true,
+            ..StoreConfig::default()
+        });
+
+        let mut tx = make_tx("lean", 1_700_000_000_000);
+        tx.status_code = Some(200);
+        tx.request_headers
+            .insert("Authorization".into(), "Bearer secret".into());
+        tx.request_header_list = vec![("Authorization".into(), "Bearer secret".into())];
+        tx.request_body = Some(b"request".to_vec());
+        tx.response_headers = Some([("Content-Type".into(), "text/plain".into())].into());
+        tx.response_body = Some(b"response".to_vec());
+        tx.response_body_encoding = Some("gzip".into());
+        tx.server_timing = vec![("db".into(), Some(12.5), None)];
+        tx.jsonrpc_method = Some("eth_call".into());
+        tx.jsonrpc_id = Some("1".into());
+        tx.jsonrpc_error_code = Some(-32000);
+        tx.pagination = Some(PaginationInfo {
+            style: PaginationStyle::LinkHeader,
+            next_url: Some("/items?page=2".into()),
+            next_cursor: None,
+            cursor_param: None,
+        });
+        tx.grpc_status = Some(0);
+        tx.grpc_message = Some("ok".into());
+        tx.request_body_entropy = Some(4.2);
+        tx.response_body_entropy = Some(7.9);
+        tx.timing.total_ms = Some(42);
+        tx.response_size = Some(8);
+        tx.response_body_sha256 = Some("ab12".into());
+        tx.client_ja3 = Some("ja3-hash".into());
+        store.add_transaction(tx).await.expect("add ok");
+
+        let result = store
+            .query(&TransactionFilter::default(), 0, 10)
+            .await
+            .expect("query ok");
+        let stored = &result.items[0];
+        assert!(stored.request_headers.is_empty());
+        assert!(stored.request_header_list.is_empty());
+        assert!(stored.response_headers.is_none());
+        assert!(stored.request_body.is_none());
+        assert!(stored.response_body.is_none());
+        assert!(stored.response_body_encoding.is_none());
+        assert!(stored.server_timing.is_empty());
+        assert!(stored.jsonrpc_id.is_none());
+        assert!(stored.jsonrpc_error_code.is_none());
+        assert!(stored.pagination.is_none());
+        assert!(stored.grpc_status.is_none());
+        assert!(stored.grpc_message.is_none());
+        assert!(stored.request_body_entropy.is_none());
+        assert!(stored.response_body_entropy.is_none());
+        assert_eq!(stored.id, "lean");
+        assert_eq!(stored.host, "example.com");
+        assert_eq!(stored.status_code, Some(200));
+        assert_eq!(stored.timing.total_ms, Some(42));
+
+        // Fields the filters read from the stored JSON are kept.
+        for filter in [
+            TransactionFilter {
+                min_response_size: Some(8),
+                ..Default::default()
+            },
+            TransactionFilter {
+                body_sha256: Some("AB12".into()),
+                ..Default::default()
+            },
+            TransactionFilter {
+                jsonrpc_method: Some("eth_call".into()),
+                ..Default::default()
+            },
+            TransactionFilter {
+                client_fingerprint: Some("ja3-hash".into()),
+                ..Default::default()
+            },
+        ] {
+            let matched = store.query(&filter, 0, 10).await.expect("query ok");
+            assert_eq!(matched.items.len(), 1, "{filter:?}");
+        }
+
+        // The in-memory copy keeps the full details until evicted.
+        let in_memory = store.get_by_id("lean").await.expect("lookup ok").unwrap();
+        assert_eq!(in_memory.response_body.as_deref(), Some(&b"response"[..]));
+    }
+
     #[tokio::test]
     async fn list_page_orders_and_limits() {
         let dir = tempdir().expect("temp dir");
@@ -708,4 +2478,35 @@ mod tests {
         assert_eq!(page2.len(), 1);
         assert_eq!(page2[0].id, "a");
     }
+
+    #[tokio::test]
+    async fn recent_hosts_orders_by_latest_activity() {
+        let dir = tempdir().expect("temp dir");
+        let store =
+            TransactionStore::new(dir.path().to_str().unwrap(), 100).expect("store initializes");
+
+        for tx in [
+            sample_transaction("busy.test", 200, 1_000, HttpMethod::Get),
+            sample_transaction("busy.test", 200, 2_000, HttpMethod::Get),
+            sample_transaction("busy.test", 200, 3_000, HttpMethod::Get),
+            sample_transaction("fresh.test", 200, 5_000, HttpMethod::Get),
+            sample_transaction("stale.test", 200, 500, HttpMethod::Get),
+        ] {
+            store.add_transaction(tx).await.expect("add tx");
+        }
+
+        let hosts = store.recent_hosts(10).await.expect("recent hosts");
+        assert_eq!(
+            hosts,
+            vec![
+                ("fresh.test".to_string(), 1, 5_000),
+                ("busy.test".to_string(), 3, 3_000),
+                ("stale.test".to_string(), 1, 500),
+            ]
+        );
+
+        let top = store.recent_hosts(1).await.expect("limited");
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, "fresh.test");
+    }
 }
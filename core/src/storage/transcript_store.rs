@@ -0,0 +1,114 @@
+//! Connection transcript storage
+//!
+//! In-memory ring of raw per-connection transcripts. Transcripts are large, so
+//! both the number of connections and the bytes kept per connection are capped.
+
+use crate::models::{ConnectionTranscript, TranscriptChunk, TranscriptDirection};
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::RwLock;
+
+/// Maximum raw bytes recorded per connection
+const MAX_TRANSCRIPT_BYTES: u64 = 256 * 1024;
+
+/// Maximum number of connections to keep transcripts for
+const MAX_TRANSCRIPTS: usize = 32;
+
+static TRANSCRIPT_STORE: Lazy<RwLock<TranscriptStore>> =
+    Lazy::new(|| RwLock::new(TranscriptStore::new()));
+
+struct TranscriptStore {
+    transcripts: HashMap<String, ConnectionTranscript>,
+    /// Order of connections for eviction
+    connection_order: VecDeque<String>,
+}
+
+impl TranscriptStore {
+    fn new() -> Self {
+        Self {
+            transcripts: HashMap::new(),
+            connection_order: VecDeque::new(),
+        }
+    }
+
+    fn append(&mut self, connection_id: &str, direction: TranscriptDirection, data: &[u8]) {
+        if !self.transcripts.contains_key(connection_id) {
+            if self.connection_order.len() >= MAX_TRANSCRIPTS {
+                if let Some(old) = self.connection_order.pop_front() {
+                    self.transcripts.remove(&old);
+                }
+            }
+            self.transcripts.insert(
+                connection_id.to_string(),
+                ConnectionTranscript {
+                    connection_id: connection_id.to_string(),
+                    chunks: Vec::new(),
+                    captured_bytes: 0,
+                    truncated: false,
+                },
+            );
+            self.connection_order.push_back(connection_id.to_string());
+        }
+
+        let Some(transcript) = self.transcripts.get_mut(connection_id) else {
+            return;
+        };
+        let remaining = MAX_TRANSCRIPT_BYTES.saturating_sub(transcript.captured_bytes) as usize;
+        let take = remaining.min(data.len());
+        if take < data.len() {
+            transcript.truncated = true;
+        }
+        if take == 0 {
+            return;
+        }
+
+        let mut data_hex = String::with_capacity(take * 2);
+        for byte in &data[..take] {
+            let _ = write!(data_hex, "{byte:02x}");
+        }
+        transcript.captured_bytes += take as u64;
+        transcript.chunks.push(TranscriptChunk {
+            direction,
+            timestamp_ms: Utc::now().timestamp_millis(),
+            data_hex,
+        });
+    }
+}
+
+/// Record bytes that crossed a connection
+pub fn append_transcript_chunk(connection_id: &str, direction: TranscriptDirection, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    if let Ok(mut store) = TRANSCRIPT_STORE.write() {
+        store.append(connection_id, direction, data);
+    }
+}
+
+/// Get the transcript recorded for a connection
+pub fn get_connection_transcript(connection_id: &str) -> Option<ConnectionTranscript> {
+    TRANSCRIPT_STORE
+        .read()
+        .ok()
+        .and_then(|store| store.transcripts.get(connection_id).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_is_capped_per_connection() {
+        let id = "transcript-cap-test";
+        let big = vec![0xabu8; MAX_TRANSCRIPT_BYTES as usize + 10];
+        append_transcript_chunk(id, TranscriptDirection::ClientToProxy, &big);
+        append_transcript_chunk(id, TranscriptDirection::ProxyToClient, b"more");
+
+        let transcript = get_connection_transcript(id).unwrap();
+        assert_eq!(transcript.captured_bytes, MAX_TRANSCRIPT_BYTES);
+        assert_eq!(transcript.chunks.len(), 1);
+        assert!(transcript.truncated);
+    }
+}
@@ -1,12 +1,23 @@
 //! WebSocket message storage
 //!
-//! Simple in-memory ring buffer for WebSocket messages.
-//! Messages are stored per-connection and can be queried by connection_id.
+//! Messages are persisted to the `websocket_messages` table of the traffic
+//! database, which is what queries read. Inserts go through a writer thread
+//! in batches, so relaying a frame never waits on SQLite. A bounded in-memory
+//! ring per connection backs the live throughput stats and serves reads when
+//! no database is open.
 
-use crate::models::WebSocketMessage;
+use crate::models::{
+    ExportTimezone, MessageDirection, WebSocketExportFormat, WebSocketMessage, WebSocketOpcode,
+    WebSocketStats,
+};
+use anyhow::Context;
+use base64::{engine::general_purpose, Engine as _};
 use once_cell::sync::Lazy;
-use std::collections::{HashMap, VecDeque};
-use std::sync::RwLock;
+use rusqlite::{params, Connection, Row};
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::sync::{mpsc, RwLock};
 
 /// Maximum messages to store per connection
 const MAX_MESSAGES_PER_CONNECTION: usize = 1000;
@@ -14,6 +25,9 @@ const MAX_MESSAGES_PER_CONNECTION: usize = 1000;
 /// Maximum number of connections to track
 const MAX_CONNECTIONS: usize = 100;
 
+/// Most messages inserted in one database transaction
+const MAX_WRITE_BATCH: usize = 256;
+
 /// Global WebSocket message store
 static WS_STORE: Lazy<RwLock<WebSocketStore>> = Lazy::new(|| RwLock::new(WebSocketStore::new()));
 
@@ -21,6 +35,8 @@ static WS_STORE: Lazy<RwLock<WebSocketStore>> = Lazy::new(|| RwLock::new(WebSock
 struct WebSocketStore {
     /// Messages indexed by connection_id
     messages: HashMap<String, VecDeque<WebSocketMessage>>,
+    /// Throughput counters indexed by connection_id
+    stats: HashMap<String, WebSocketStats>,
     /// Order of connections for LRU eviction
     connection_order: VecDeque<String>,
 }
@@ -29,6 +45,7 @@ impl WebSocketStore {
     fn new() -> Self {
         Self {
             messages: HashMap::new(),
+            stats: HashMap::new(),
             connection_order: VecDeque::new(),
         }
     }
@@ -42,12 +59,18 @@ impl WebSocketStore {
             if self.connection_order.len() >= MAX_CONNECTIONS {
                 if let Some(old_conn) = self.connection_order.pop_front() {
                     self.messages.remove(&old_conn);
+                    self.stats.remove(&old_conn);
                 }
             }
             self.messages.insert(conn_id.clone(), VecDeque::new());
             self.connection_order.push_back(conn_id.clone());
         }
 
+        self.stats
+            .entry(conn_id.clone())
+            .or_insert_with(|| WebSocketStats::new(conn_id.clone()))
+            .record(&msg);
+
         // Add message to the queue
         if let Some(queue) = self.messages.get_mut(&conn_id) {
             // Evict oldest message if at capacity
@@ -74,40 +97,394 @@ impl WebSocketStore {
 
     fn clear_connection(&mut self, connection_id: &str) {
         self.messages.remove(connection_id);
+        self.stats.remove(connection_id);
         self.connection_order.retain(|c| c != connection_id);
     }
 
     fn clear_all(&mut self) {
         self.messages.clear();
+        self.stats.clear();
         self.connection_order.clear();
     }
 }
 
-/// Add a WebSocket message to the store
+/// Create the message table and its indexes
+pub(super) fn create_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS websocket_messages (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            id TEXT NOT NULL,
+            connection_id TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            opcode TEXT NOT NULL,
+            payload BLOB NOT NULL,
+            payload_length INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            is_fragmented INTEGER NOT NULL DEFAULT 0,
+            is_final INTEGER NOT NULL DEFAULT 1,
+            injected INTEGER NOT NULL DEFAULT 0,
+            decoded_view TEXT,
+            compressed INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_websocket_messages_connection
+            ON websocket_messages(connection_id, seq);
+        CREATE INDEX IF NOT EXISTS idx_websocket_messages_timestamp
+            ON websocket_messages(timestamp);
+        ",
+    )
+}
+
+/// Delete messages captured before `cutoff_ms`, keeping those of pinned
+/// transactions like the transaction prune does. Returns the number deleted.
+pub(super) fn prune_messages(conn: &Connection, cutoff_ms: i64) -> rusqlite::Result<u64> {
+    let deleted = conn.execute(
+        "DELETE FROM websocket_messages WHERE timestamp < ?1 AND connection_id NOT IN (
+            SELECT id FROM transactions WHERE COALESCE(json_extract(data, '$.pinned'), 0) != 0
+        )",
+        params![cutoff_ms],
+    )?;
+    Ok(deleted as u64)
+}
+
+/// Copy the messages of `connection_ids` from another capture database, in
+/// capture order. Returns the number copied.
+pub(super) fn copy_messages(
+    from: &Connection,
+    to: &Connection,
+    connection_ids: &HashSet<String>,
+) -> rusqlite::Result<u64> {
+    // Files written before messages were persisted have no table.
+    let has_table: bool = from.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master
+                       WHERE type = 'table' AND name = 'websocket_messages')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table || connection_ids.is_empty() {
+        return Ok(0);
+    }
+    let mut stmt = from.prepare("SELECT * FROM websocket_messages ORDER BY seq")?;
+    let mut rows = stmt.query([])?;
+    let mut copied = 0;
+    while let Some(row) = rows.next()? {
+        let msg = message_from_row(row)?;
+        if connection_ids.contains(&msg.connection_id) {
+            insert_message(to, &msg)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+const DIRECTIONS: [(MessageDirection, &str); 2] = [
+    (MessageDirection::ClientToServer, "client_to_server"),
+    (MessageDirection::ServerToClient, "server_to_client"),
+];
+
+const OPCODES: [WebSocketOpcode; 6] = [
+    WebSocketOpcode::Continuation,
+    WebSocketOpcode::Text,
+    WebSocketOpcode::Binary,
+    WebSocketOpcode::Close,
+    WebSocketOpcode::Ping,
+    WebSocketOpcode::Pong,
+];
+
+fn direction_key(direction: MessageDirection) -> &'static str {
+    DIRECTIONS
+        .iter()
+        .find(|(d, _)| *d == direction)
+        .map(|(_, key)| *key)
+        .unwrap_or_default()
+}
+
+fn message_from_row(row: &Row) -> rusqlite::Result<WebSocketMessage> {
+    let direction: String = row.get("direction")?;
+    let opcode: String = row.get("opcode")?;
+    Ok(WebSocketMessage {
+        id: row.get("id")?,
+        connection_id: row.get("connection_id")?,
+        direction: DIRECTIONS
+            .iter()
+            .find(|(_, key)| *key == direction)
+            .map(|(d, _)| *d)
+            .unwrap_or(MessageDirection::ClientToServer),
+        opcode: OPCODES
+            .into_iter()
+            .find(|op| op.as_str() == opcode)
+            .unwrap_or(WebSocketOpcode::Binary),
+        payload: row.get("payload")?,
+        payload_length: row.get::<_, i64>("payload_length")? as u64,
+        timestamp: row.get("timestamp")?,
+        is_fragmented: row.get("is_fragmented")?,
+        is_final: row.get("is_final")?,
+        injected: row.get("injected")?,
+        decoded_view: row.get("decoded_view")?,
+        compressed: row.get("compressed")?,
+    })
+}
+
+fn insert_message(conn: &Connection, msg: &WebSocketMessage) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO websocket_messages (id, connection_id, direction, opcode, payload,
+            payload_length, timestamp, is_fragmented, is_final, injected, decoded_view,
+            compressed)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            msg.id,
+            msg.connection_id,
+            direction_key(msg.direction),
+            msg.opcode.as_str(),
+            msg.payload,
+            msg.payload_length as i64,
+            msg.timestamp,
+            msg.is_fragmented,
+            msg.is_final,
+            msg.injected,
+            msg.decoded_view,
+            msg.compressed,
+        ],
+    )?;
+    Ok(())
+}
+
+enum WriterCommand {
+    Insert(WebSocketMessage),
+    /// Answered once every message queued before it is written
+    Flush(mpsc::Sender<()>),
+}
+
+/// Queue of the thread that persists captured messages
+static WRITER: Lazy<mpsc::Sender<WriterCommand>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("websocket-writer".into())
+        .spawn(move || run_writer(receiver))
+        .expect("failed to spawn WebSocket writer thread");
+    sender
+});
+
+/// Insert queued messages, batching whatever arrived since the last write.
+fn run_writer(receiver: mpsc::Receiver<WriterCommand>) {
+    while let Ok(first) = receiver.recv() {
+        let mut batch = Vec::new();
+        let mut flushes = Vec::new();
+        let mut next = Some(first);
+        while let Some(command) = next {
+            match command {
+                WriterCommand::Insert(msg) => batch.push(msg),
+                WriterCommand::Flush(done) => flushes.push(done),
+            }
+            if batch.len() >= MAX_WRITE_BATCH {
+                break;
+            }
+            next = receiver.try_recv().ok();
+        }
+        if !batch.is_empty() {
+            if let Err(err) = insert_batch(&batch) {
+                tracing::warn!("Failed to persist WebSocket messages: {}", err);
+            }
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn insert_batch(batch: &[WebSocketMessage]) -> anyhow::Result<()> {
+    let Ok(store) = super::store() else {
+        return Ok(());
+    };
+    let db = store.db();
+    let conn = db.lock().unwrap_or_else(|e| e.into_inner());
+    let tx = conn.unchecked_transaction()?;
+    for msg in batch {
+        insert_message(&tx, msg)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Wait until every message queued so far is in the database
+fn flush_writes() {
+    let (done, written) = mpsc::channel();
+    if WRITER.send(WriterCommand::Flush(done)).is_ok() {
+        let _ = written.recv();
+    }
+}
+
+/// Run `f` against the traffic database, if a store is open, once queued
+/// messages are written
+fn with_db<T>(f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Option<anyhow::Result<T>> {
+    let db = super::store().ok()?.db();
+    flush_writes();
+    let conn = db.lock().unwrap_or_else(|e| e.into_inner());
+    Some(f(&conn).map_err(Into::into))
+}
+
+/// Add a WebSocket message to the store. The database insert is queued, so
+/// this never blocks on SQLite.
 pub fn add_websocket_message(msg: WebSocketMessage) {
+    if super::store().is_ok() && WRITER.send(WriterCommand::Insert(msg.clone())).is_err() {
+        tracing::warn!("WebSocket writer is gone; message not persisted");
+    }
     if let Ok(mut store) = WS_STORE.write() {
         store.add_message(msg);
     }
 }
 
-/// Get all messages for a WebSocket connection
-pub fn get_websocket_messages(connection_id: &str) -> Vec<WebSocketMessage> {
-    if let Ok(store) = WS_STORE.read() {
-        store.get_messages(connection_id)
-    } else {
-        Vec::new()
+/// Messages of a WebSocket connection in capture order, skipping `offset`
+/// and returning at most `limit` (all when `None`)
+pub fn get_websocket_messages(
+    connection_id: &str,
+    offset: u32,
+    limit: Option<u32>,
+) -> Vec<WebSocketMessage> {
+    let persisted = with_db(|conn| {
+        let mut stmt = conn.prepare_cached(
+            "SELECT * FROM websocket_messages WHERE connection_id = ?1
+             ORDER BY seq LIMIT ?2 OFFSET ?3",
+        )?;
+        let limit = limit.map_or(-1, i64::from);
+        let rows = stmt.query_map(params![connection_id, limit, offset], message_from_row)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+    });
+    match persisted {
+        Some(Ok(messages)) => messages,
+        Some(Err(err)) => {
+            tracing::warn!("Failed to read WebSocket messages: {}", err);
+            Vec::new()
+        }
+        None => WS_STORE
+            .read()
+            .map(|store| {
+                let messages = store.get_messages(connection_id).into_iter();
+                let messages = messages.skip(offset as usize);
+                match limit {
+                    Some(limit) => messages.take(limit as usize).collect(),
+                    None => messages.collect(),
+                }
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// A captured message by its id
+pub fn get_websocket_message(message_id: &str) -> Option<WebSocketMessage> {
+    let persisted = with_db(|conn| {
+        let mut stmt = conn.prepare_cached("SELECT * FROM websocket_messages WHERE id = ?1")?;
+        let mut rows = stmt.query_map(params![message_id], message_from_row)?;
+        rows.next().transpose()
+    });
+    match persisted {
+        Some(Ok(message)) => message,
+        Some(Err(err)) => {
+            tracing::warn!("Failed to read WebSocket message: {}", err);
+            None
+        }
+        None => WS_STORE.read().ok().and_then(|store| {
+            store
+                .messages
+                .values()
+                .flatten()
+                .find(|msg| msg.id == message_id)
+                .cloned()
+        }),
+    }
+}
+
+/// Write a connection's captured messages to `path`. Returns the number written.
+pub fn export_websocket_messages(
+    connection_id: &str,
+    path: &str,
+    format: WebSocketExportFormat,
+    timezone: ExportTimezone,
+) -> anyhow::Result<usize> {
+    timezone.validate()?;
+    let messages = get_websocket_messages(connection_id, 0, None);
+    let file = std::fs::File::create(path).with_context(|| format!("creating {path}"))?;
+    let mut out = std::io::BufWriter::new(file);
+    for msg in &messages {
+        let line = match format {
+            WebSocketExportFormat::Jsonl => jsonl_line(msg, timezone).to_string(),
+            WebSocketExportFormat::Text => text_line(msg, timezone),
+        };
+        writeln!(out, "{line}")?;
     }
+    out.flush()?;
+    Ok(messages.len())
+}
+
+fn jsonl_line(msg: &WebSocketMessage, timezone: ExportTimezone) -> serde_json::Value {
+    // Text frames stay readable; anything else is base64 so it round-trips.
+    let (payload, encoding) = match std::str::from_utf8(&msg.payload) {
+        Ok(text) if msg.opcode == WebSocketOpcode::Text => (text.to_string(), "utf8"),
+        _ => (general_purpose::STANDARD.encode(&msg.payload), "base64"),
+    };
+    let mut line = json!({
+        "timestamp": timezone.format_millis(msg.timestamp),
+        "direction": match msg.direction {
+            MessageDirection::ClientToServer => "client_to_server",
+            MessageDirection::ServerToClient => "server_to_client",
+        },
+        "opcode": msg.opcode.as_str(),
+        "final": msg.is_final,
+        "injected": msg.injected,
+        "compressed": msg.compressed,
+        "payload_length": msg.payload_length,
+        "payload_encoding": encoding,
+        "payload": payload,
+    });
+    if let Some(view) = msg
+        .decoded_view
+        .as_deref()
+        .and_then(|v| serde_json::from_str::<serde_json::Value>(v).ok())
+    {
+        line["decoded"] = view;
+    }
+    line
+}
+
+fn text_line(msg: &WebSocketMessage, timezone: ExportTimezone) -> String {
+    let body = match std::str::from_utf8(&msg.payload) {
+        Ok(text) if msg.opcode == WebSocketOpcode::Text => text.replace('\n', "\\n"),
+        _ => msg.payload_preview(),
+    };
+    format!(
+        "[{}] {} {} ({}){} {}",
+        timezone.format_millis(msg.timestamp),
+        msg.direction.as_str(),
+        msg.opcode.as_str(),
+        msg.size_str(),
+        if msg.injected { " [injected]" } else { "" },
+        body
+    )
 }
 
 /// Get the count of messages for a connection
 pub fn get_websocket_message_count(connection_id: &str) -> usize {
-    if let Ok(store) = WS_STORE.read() {
-        store.get_message_count(connection_id)
-    } else {
-        0
+    let persisted = with_db(|conn| {
+        conn.query_row(
+            "SELECT COUNT(*) FROM websocket_messages WHERE connection_id = ?1",
+            params![connection_id],
+            |row| row.get::<_, i64>(0),
+        )
+    });
+    match persisted {
+        Some(count) => count.map_or(0, |n| n as usize),
+        None => WS_STORE
+            .read()
+            .map(|store| store.get_message_count(connection_id))
+            .unwrap_or(0),
     }
 }
 
+/// Get throughput stats for a connection, if any message has been captured
+pub fn get_websocket_stats(connection_id: &str) -> Option<WebSocketStats> {
+    WS_STORE.read().ok()?.stats.get(connection_id).cloned()
+}
+
 /// WebSocket connection summary info
 #[derive(Debug, Clone)]
 pub struct WebSocketConnectionInfo {
@@ -147,6 +524,15 @@ pub fn get_websocket_connections(page: u32, page_size: u32) -> Vec<WebSocketConn
 
 /// Clear all messages for a connection
 pub fn clear_websocket_messages(connection_id: &str) {
+    let deleted = with_db(|conn| {
+        conn.execute(
+            "DELETE FROM websocket_messages WHERE connection_id = ?1",
+            params![connection_id],
+        )
+    });
+    if let Some(Err(err)) = deleted {
+        tracing::warn!("Failed to delete WebSocket messages: {}", err);
+    }
     if let Ok(mut store) = WS_STORE.write() {
         store.clear_connection(connection_id);
     }
@@ -154,7 +540,151 @@ pub fn clear_websocket_messages(connection_id: &str) {
 
 /// Clear all WebSocket messages
 pub fn clear_all_websocket_messages() {
+    if let Some(Err(err)) = with_db(|conn| conn.execute("DELETE FROM websocket_messages", [])) {
+        tracing::warn!("Failed to delete WebSocket messages: {}", err);
+    }
     if let Ok(mut store) = WS_STORE.write() {
         store.clear_all();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MessageDirection, WebSocketOpcode};
+    use serial_test::serial;
+
+    #[test]
+    fn stats_count_burst_of_frames() {
+        let mut store = WebSocketStore::new();
+        for i in 0..10 {
+            let (direction, payload) = if i % 2 == 0 {
+                (MessageDirection::ClientToServer, vec![b'x'; 10])
+            } else {
+                (MessageDirection::ServerToClient, vec![b'y'; 100 + i])
+            };
+            store.add_message(WebSocketMessage::new(
+                "ws-1".into(),
+                direction,
+                WebSocketOpcode::Text,
+                payload,
+                true,
+            ));
+        }
+
+        let stats = store.stats.get("ws-1").expect("stats tracked");
+        assert_eq!(stats.message_count(), 10);
+        assert_eq!(stats.client_to_server_messages, 5);
+        assert_eq!(stats.client_to_server_bytes, 50);
+        assert_eq!(stats.server_to_client_bytes, 101 + 103 + 105 + 107 + 109);
+        assert_eq!(stats.max_message_size, 109);
+        assert!(stats.messages_per_second() >= 10.0);
+
+        store.clear_connection("ws-1");
+        assert!(!store.stats.contains_key("ws-1"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn messages_persist_page_and_prune_from_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        super::super::reset_store_for_tests(dir.path().to_str().unwrap()).unwrap();
+        let id = "ws-persist-test";
+        for i in 0..5u8 {
+            let mut msg = WebSocketMessage::new(
+                id.into(),
+                MessageDirection::ServerToClient,
+                WebSocketOpcode::Binary,
+                vec![i],
+                true,
+            );
+            if i == 0 {
+                msg.timestamp = 1_000;
+            }
+            add_websocket_message(msg);
+        }
+
+        // A restart loses the in-memory ring; the table still has everything.
+        WS_STORE.write().unwrap().clear_all();
+        super::super::reset_store_for_tests(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(get_websocket_message_count(id), 5);
+        let page = get_websocket_messages(id, 1, Some(2));
+        let payloads: Vec<_> = page.iter().map(|m| m.payload.clone()).collect();
+        assert_eq!(payloads, [vec![1], vec![2]]);
+        assert_eq!(page[0].direction, MessageDirection::ServerToClient);
+        assert_eq!(page[0].opcode, WebSocketOpcode::Binary);
+
+        super::super::prune_older_than(5).await.unwrap();
+        assert_eq!(get_websocket_message_count(id), 4);
+        assert_eq!(get_websocket_messages(id, 0, None)[0].payload, vec![1]);
+
+        clear_websocket_messages(id);
+        assert_eq!(get_websocket_message_count(id), 0);
+    }
+
+    #[test]
+    #[serial]
+    fn adding_a_message_does_not_wait_for_the_database() {
+        let dir = tempfile::tempdir().unwrap();
+        super::super::reset_store_for_tests(dir.path().to_str().unwrap()).unwrap();
+        let id = "ws-queued-test";
+        let db = super::super::store().unwrap().db();
+        let busy = db.lock().unwrap();
+        add_websocket_message(WebSocketMessage::new(
+            id.into(),
+            MessageDirection::ClientToServer,
+            WebSocketOpcode::Text,
+            b"hello".to_vec(),
+            true,
+        ));
+        drop(busy);
+
+        assert_eq!(get_websocket_message_count(id), 1);
+        clear_websocket_messages(id);
+    }
+
+    #[test]
+    #[serial]
+    fn exports_connection_as_ndjson() {
+        let dir = tempfile::tempdir().unwrap();
+        super::super::reset_store_for_tests(dir.path().to_str().unwrap()).unwrap();
+        let id = "ws-export-test";
+        add_websocket_message(WebSocketMessage::new(
+            id.into(),
+            MessageDirection::ClientToServer,
+            WebSocketOpcode::Text,
+            b"{\"op\":\"subscribe\"}".to_vec(),
+            true,
+        ));
+        add_websocket_message(WebSocketMessage::new(
+            id.into(),
+            MessageDirection::ServerToClient,
+            WebSocketOpcode::Binary,
+            vec![0xff, 0x00],
+            true,
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.ndjson");
+        let written = export_websocket_messages(
+            id,
+            path.to_str().unwrap(),
+            WebSocketExportFormat::Jsonl,
+            ExportTimezone::Utc,
+        )
+        .unwrap();
+        clear_websocket_messages(id);
+        assert_eq!(written, 2);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["direction"], "client_to_server");
+        assert_eq!(lines[0]["payload"], r#"{"op":"subscribe"}"#);
+        assert_eq!(lines[1]["opcode"], "BINARY");
+        assert_eq!(lines[1]["payload"], "/wA=");
+    }
+}
@@ -34,6 +34,9 @@ class HttpTransaction {
   final String? serverIp;
   final String? tlsVersion;
   final String? tlsCipher;
+  final String? clientJa3;
+  final String? clientJa4;
+  final bool wasIntercepted;
   final bool connectionReused;
   final bool isWebsocket;
 
@@ -65,6 +68,9 @@ class HttpTransaction {
     this.serverIp,
     this.tlsVersion,
     this.tlsCipher,
+    this.clientJa3,
+    this.clientJa4,
+    this.wasIntercepted = false,
     this.connectionReused = false,
     this.isWebsocket = false,
   }) : timing = timing ?? TransactionTiming();
@@ -145,6 +151,9 @@ class HttpTransaction {
       serverIp: rustTx.serverIp,
       tlsVersion: rustTx.tlsVersion,
       tlsCipher: rustTx.tlsCipher,
+      clientJa3: rustTx.clientJa3,
+      clientJa4: rustTx.clientJa4,
+      wasIntercepted: rustTx.wasIntercepted,
       connectionReused: rustTx.connectionReused,
       isWebsocket: rustTx.isWebsocket,
     );
@@ -261,6 +270,9 @@ class HttpTransaction {
     String? serverIp,
     String? tlsVersion,
     String? tlsCipher,
+    String? clientJa3,
+    String? clientJa4,
+    bool? wasIntercepted,
     bool? connectionReused,
     bool? isWebsocket,
   }) {
@@ -292,6 +304,9 @@ class HttpTransaction {
       serverIp: serverIp ?? this.serverIp,
       tlsVersion: tlsVersion ?? this.tlsVersion,
       tlsCipher: tlsCipher ?? this.tlsCipher,
+      clientJa3: clientJa3 ?? this.clientJa3,
+      clientJa4: clientJa4 ?? this.clientJa4,
+      wasIntercepted: wasIntercepted ?? this.wasIntercepted,
       connectionReused: connectionReused ?? this.connectionReused,
       isWebsocket: isWebsocket ?? this.isWebsocket,
     );
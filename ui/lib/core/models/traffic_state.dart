@@ -8,6 +8,7 @@ import 'package:flutter/services.dart';
 
 import '../models/http_transaction.dart';
 import '../../src/rust/api/proxy_api.dart' as rust_api;
+import '../../src/rust/models/transaction.dart' as rust_models;
 import '../utils/system_proxy_service.dart';
 import '../utils/logger_service.dart';
 import '../utils/har_utils.dart';
@@ -458,6 +459,40 @@ class TrafficState extends ChangeNotifier {
           bindAddress: "127.0.0.1",
           enableHttps: true,
           storagePath: _storagePath ?? "./",
+          responseOnlyMode: false,
+          streamThresholdBytes: null,
+          bodyCaptureLimitBytes: null,
+          hopByHopHeaders: null,
+          recordTranscripts: false,
+          upstreamIdleTimeoutSecs: null,
+          sniInterceptInclude: null,
+          sniInterceptExclude: null,
+          captureInvalidResponses: false,
+          selfLoopAllowlist: null,
+          streamBodyPreviewBytes: 0,
+          streamFinalStateOnly: false,
+          leanStorage: false,
+          ringMaxAgeSecs: null,
+          blockTrace: true,
+          connectTlsPortsOnly: false,
+          strictHostValidation: false,
+          rejectAmbiguousFraming: false,
+          abandonedTransactionSecs: 3600,
+          bodyEntropy: false,
+          debugTimingHeaders: false,
+          tunnelOnCertFailure: true,
+          captureClientFingerprint: false,
+          queryRedaction: rust_models.QueryRedaction.off,
+          geoipDbPaths: null,
+          websocketKeepaliveSecs: null,
+          responseHeaderTimeoutSecs: 60,
+          enableH2: false,
+          recordConformanceNotes: false,
+          upstreamTlsResumption: true,
+          captureRequestBodies: true,
+          captureResponseBodies: true,
+          h2CUpgrade: rust_models.H2cUpgradeMode.downgrade,
+          throttle: null,
         );
         await rust_api.startProxy(config: config);
         // Get the actual port in case the backend fell back to a free one
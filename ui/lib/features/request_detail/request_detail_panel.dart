@@ -134,6 +134,9 @@ class _RequestDetailPanelState extends State<RequestDetailPanel>
         serverIp: full.serverIp,
         tlsVersion: full.tlsVersion,
         tlsCipher: full.tlsCipher,
+        clientJa3: full.clientJa3,
+        clientJa4: full.clientJa4,
+        wasIntercepted: full.wasIntercepted,
         connectionReused: full.connectionReused,
         isWebsocket: full.isWebsocket,
       );
@@ -879,6 +882,30 @@ class _TimingTab extends StatelessWidget {
                     isDark: isDark,
                   ),
                 ],
+                if (transaction.scheme == 'https') ...[
+                  const SizedBox(height: 8),
+                  _ConnectionInfoRow(
+                    label: 'TLS Intercepted',
+                    value: transaction.wasIntercepted ? 'Yes' : 'No (tunneled)',
+                    isDark: isDark,
+                  ),
+                ],
+                if (transaction.clientJa3 != null) ...[
+                  const SizedBox(height: 8),
+                  _ConnectionInfoRow(
+                    label: 'Client JA3',
+                    value: transaction.clientJa3!,
+                    isDark: isDark,
+                  ),
+                ],
+                if (transaction.clientJa4 != null) ...[
+                  const SizedBox(height: 8),
+                  _ConnectionInfoRow(
+                    label: 'Client JA4',
+                    value: transaction.clientJa4!,
+                    isDark: isDark,
+                  ),
+                ],
                 // Connection Reused - hidden until upstream connection pooling is implemented
                 // const SizedBox(height: 8),
                 // _ConnectionInfoRow(
@@ -888,6 +888,8 @@ class _TrafficListItem extends StatelessWidget {
           method: method,
           hostContains: hostContains,
           pathContains: pathContains,
+          hostRegex: null,
+          pathRegex: null,
         ),
       );
       ScaffoldMessenger.of(context).showSnackBar(